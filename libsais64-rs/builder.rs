@@ -56,7 +56,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     exit_status_to_result(
         "cmake",
         Command::new("cmake")
-            .args(["-DCMAKE_BUILD_TYPE=\"Release\"", "libsais", "-Blibsais"])
+            .args(["-DCMAKE_BUILD_TYPE=\"Release\"", "-DLIBSAIS_USE_OPENMP=ON", "libsais", "-Blibsais"])
             .status()?,
     )?;
     exit_status_to_result(
@@ -67,6 +67,12 @@ fn main() -> Result<(), Box<dyn Error>> {
     // link the c libsais library to rust
     println!("cargo:rustc-link-search=native=libsais64-rs/libsais");
     println!("cargo:rustc-link-lib=static=libsais");
+    // the OpenMP runtime used by the multithreaded entry points
+    if env::var("CARGO_CFG_TARGET_OS")? == "macos" {
+        println!("cargo:rustc-link-lib=omp");
+    } else {
+        println!("cargo:rustc-link-lib=gomp");
+    }
 
     // The bindgen::Builder is the main entry point
     // to bindgen, and lets you build up options for
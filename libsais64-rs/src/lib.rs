@@ -4,6 +4,56 @@
 #![allow(non_snake_case)]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// The error code libsais returns for invalid arguments, also used when the arguments are rejected before calling libsais
+pub const INVALID_ARGUMENT: i64 = -1;
+
+/// The error code libsais returns if it could not allocate memory
+pub const ALLOCATION_FAILED: i64 = -2;
+
+/// Error returned when a libsais routine fails
+///
+/// # Arguments
+/// * `code` - The error code returned by libsais, `INVALID_ARGUMENT` or `ALLOCATION_FAILED`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaisError {
+    pub code: i64,
+}
+
+impl Display for SaisError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reason = match self.code {
+            INVALID_ARGUMENT => "invalid argument",
+            ALLOCATION_FAILED => "memory allocation failed",
+            _ => "unknown error",
+        };
+        write!(f, "libsais failed with error code {} ({})", self.code, reason)
+    }
+}
+
+impl Error for SaisError {}
+
+/// Converts the exit code of a libsais routine to a Result
+///
+/// # Arguments
+/// * `exit_code` - The value returned by libsais, which is negative if an error occurred
+///
+/// # Returns
+///
+/// Returns the exit code if it is not negative
+///
+/// # Errors
+///
+/// Returns a SaisError with the exit code if it is negative
+fn check(exit_code: i64) -> Result<i64, SaisError> {
+    if exit_code < 0 {
+        Err(SaisError { code: exit_code })
+    } else {
+        Ok(exit_code)
+    }
+}
 
 /// Builds the suffix array over the `text` using the libsais64 algorithm
 ///
@@ -12,26 +62,470 @@ include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 ///
 /// # Returns
 ///
-/// Returns Some with the suffix array build over the text if construction succeeds
-/// Returns None if construction of the suffix array failed
-pub fn sais64(text: &[u8]) -> Option<Vec<i64>> {
+/// Returns the suffix array build over the text
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the suffix array failed
+pub fn sais64(text: &[u8]) -> Result<Vec<i64>, SaisError> {
+    sais64_omp(text, 1)
+}
+
+/// Builds the suffix array over the `text` using the multithreaded libsais64 algorithm
+///
+/// # Arguments
+/// * `text` - The text used for suffix array construction
+/// * `threads` - The number of threads used during construction, 0 to use the default number of OpenMP threads
+///
+/// # Returns
+///
+/// Returns the suffix array build over the text
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the suffix array failed
+pub fn sais64_omp(text: &[u8], threads: usize) -> Result<Vec<i64>, SaisError> {
     let mut sa = vec![0; text.len()];
-    let exit_code = unsafe { libsais64(text.as_ptr(), sa.as_mut_ptr(), text.len() as i64, 0, std::ptr::null_mut()) };
-    if exit_code == 0 {
-        Some(sa)
-    } else {
-        None
+    check(unsafe {
+        libsais64_omp(text.as_ptr(), sa.as_mut_ptr(), text.len() as i64, 0, std::ptr::null_mut(), threads as i64)
+    })?;
+    Ok(sa)
+}
+
+/// Builds the suffix array over a text of integers using the libsais64 algorithm
+/// The text is modified during construction, but restored when construction succeeds
+///
+/// # Arguments
+/// * `text` - The text used for suffix array construction, every value is in the range [0, `alphabet_size`)
+/// * `alphabet_size` - The number of distinct values that can occur in the text
+///
+/// # Returns
+///
+/// Returns the suffix array build over the text
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the suffix array failed
+pub fn sais64_long(text: &mut [i64], alphabet_size: i64) -> Result<Vec<i64>, SaisError> {
+    sais64_long_omp(text, alphabet_size, 1)
+}
+
+/// Builds the suffix array over a text of integers using the multithreaded libsais64 algorithm
+/// The text is modified during construction, but restored when construction succeeds
+///
+/// # Arguments
+/// * `text` - The text used for suffix array construction, every value is in the range [0, `alphabet_size`)
+/// * `alphabet_size` - The number of distinct values that can occur in the text
+/// * `threads` - The number of threads used during construction, 0 to use the default number of OpenMP threads
+///
+/// # Returns
+///
+/// Returns the suffix array build over the text
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the suffix array failed
+pub fn sais64_long_omp(text: &mut [i64], alphabet_size: i64, threads: usize) -> Result<Vec<i64>, SaisError> {
+    let mut sa = vec![0; text.len()];
+    check(unsafe {
+        libsais64_long_omp(text.as_mut_ptr(), sa.as_mut_ptr(), text.len() as i64, alphabet_size, 0, threads as i64)
+    })?;
+    Ok(sa)
+}
+
+/// Builds the suffix array over the `text` with 32-bit entries using the libsais algorithm
+/// The suffix array uses half the memory of the one built by `sais64`, but the text can be at most `i32::MAX` characters long
+///
+/// # Arguments
+/// * `text` - The text used for suffix array construction
+///
+/// # Returns
+///
+/// Returns the suffix array build over the text
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the suffix array failed,
+/// or with `INVALID_ARGUMENT` if the text is too long for 32-bit entries
+pub fn sais32(text: &[u8]) -> Result<Vec<i32>, SaisError> {
+    sais32_omp(text, 1)
+}
+
+/// Builds the suffix array over the `text` with 32-bit entries using the multithreaded libsais algorithm
+///
+/// # Arguments
+/// * `text` - The text used for suffix array construction
+/// * `threads` - The number of threads used during construction, 0 to use the default number of OpenMP threads
+///
+/// # Returns
+///
+/// Returns the suffix array build over the text
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the suffix array failed,
+/// or with `INVALID_ARGUMENT` if the text is too long for 32-bit entries
+pub fn sais32_omp(text: &[u8], threads: usize) -> Result<Vec<i32>, SaisError> {
+    let n = length_32(text.len())?;
+    let mut sa = vec![0; text.len()];
+    check(unsafe { libsais_omp(text.as_ptr(), sa.as_mut_ptr(), n, 0, std::ptr::null_mut(), threads as i32) } as i64)?;
+    Ok(sa)
+}
+
+/// Builds the suffix array with 32-bit entries over a text of integers using the libsais algorithm
+/// The text is modified during construction, but restored when construction succeeds
+///
+/// # Arguments
+/// * `text` - The text used for suffix array construction, every value is in the range [0, `alphabet_size`)
+/// * `alphabet_size` - The number of distinct values that can occur in the text
+///
+/// # Returns
+///
+/// Returns the suffix array build over the text
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the suffix array failed,
+/// or with `INVALID_ARGUMENT` if the text is too long for 32-bit entries
+pub fn sais32_int(text: &mut [i32], alphabet_size: i32) -> Result<Vec<i32>, SaisError> {
+    sais32_int_omp(text, alphabet_size, 1)
+}
+
+/// Builds the suffix array with 32-bit entries over a text of integers using the multithreaded libsais algorithm
+/// The text is modified during construction, but restored when construction succeeds
+///
+/// # Arguments
+/// * `text` - The text used for suffix array construction, every value is in the range [0, `alphabet_size`)
+/// * `alphabet_size` - The number of distinct values that can occur in the text
+/// * `threads` - The number of threads used during construction, 0 to use the default number of OpenMP threads
+///
+/// # Returns
+///
+/// Returns the suffix array build over the text
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the suffix array failed,
+/// or with `INVALID_ARGUMENT` if the text is too long for 32-bit entries
+pub fn sais32_int_omp(text: &mut [i32], alphabet_size: i32, threads: usize) -> Result<Vec<i32>, SaisError> {
+    let n = length_32(text.len())?;
+    let mut sa = vec![0; text.len()];
+    check(unsafe { libsais_int_omp(text.as_mut_ptr(), sa.as_mut_ptr(), n, alphabet_size, 0, threads as i32) } as i64)?;
+    Ok(sa)
+}
+
+/// Converts the length of a text to the length passed to the 32-bit libsais routines
+///
+/// # Arguments
+/// * `length` - The length of the text
+///
+/// # Returns
+///
+/// Returns the length as an i32
+///
+/// # Errors
+///
+/// Returns a SaisError with `INVALID_ARGUMENT` if the length does not fit in an i32
+fn length_32(length: usize) -> Result<i32, SaisError> {
+    i32::try_from(length).map_err(|_| SaisError { code: INVALID_ARGUMENT })
+}
+
+/// Builds the Burrows-Wheeler transform of the `text` using the libsais64 algorithm
+/// libsais appends a virtual sentinel to the text that is smaller than every character, this sentinel is not stored in the transform
+///
+/// # Arguments
+/// * `text` - The text that is transformed
+///
+/// # Returns
+///
+/// Returns the Burrows-Wheeler transform, together with the primary index needed to invert the transform
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the transform failed
+pub fn bwt64(text: &[u8]) -> Result<(Vec<u8>, i64), SaisError> {
+    bwt64_omp(text, 1)
+}
+
+/// Builds the Burrows-Wheeler transform of the `text` using the multithreaded libsais64 algorithm
+///
+/// # Arguments
+/// * `text` - The text that is transformed
+/// * `threads` - The number of threads used during construction, 0 to use the default number of OpenMP threads
+///
+/// # Returns
+///
+/// Returns the Burrows-Wheeler transform, together with the primary index needed to invert the transform
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the transform failed
+pub fn bwt64_omp(text: &[u8], threads: usize) -> Result<(Vec<u8>, i64), SaisError> {
+    let mut bwt = vec![0; text.len()];
+    let mut temporary = vec![0; text.len()];
+    let primary_index = check(unsafe {
+        libsais64_bwt_omp(
+            text.as_ptr(),
+            bwt.as_mut_ptr(),
+            temporary.as_mut_ptr(),
+            text.len() as i64,
+            0,
+            std::ptr::null_mut(),
+            threads as i64
+        )
+    })?;
+    Ok((bwt, primary_index))
+}
+
+/// Restores the text from its Burrows-Wheeler transform using the libsais64 algorithm
+///
+/// # Arguments
+/// * `bwt` - The Burrows-Wheeler transform built by `bwt64`
+/// * `primary_index` - The primary index returned together with the transform
+///
+/// # Returns
+///
+/// Returns the original text
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if the text could not be restored
+pub fn unbwt64(bwt: &[u8], primary_index: i64) -> Result<Vec<u8>, SaisError> {
+    unbwt64_omp(bwt, primary_index, 1)
+}
+
+/// Restores the text from its Burrows-Wheeler transform using the multithreaded libsais64 algorithm
+///
+/// # Arguments
+/// * `bwt` - The Burrows-Wheeler transform built by `bwt64`
+/// * `primary_index` - The primary index returned together with the transform
+/// * `threads` - The number of threads used, 0 to use the default number of OpenMP threads
+///
+/// # Returns
+///
+/// Returns the original text
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if the text could not be restored
+pub fn unbwt64_omp(bwt: &[u8], primary_index: i64, threads: usize) -> Result<Vec<u8>, SaisError> {
+    let mut text = vec![0; bwt.len()];
+    // libsais needs a temporary array that is one element longer than the text
+    let mut temporary = vec![0; bwt.len() + 1];
+    check(unsafe {
+        libsais64_unbwt_omp(
+            bwt.as_ptr(),
+            text.as_mut_ptr(),
+            temporary.as_mut_ptr(),
+            bwt.len() as i64,
+            std::ptr::null(),
+            primary_index,
+            threads as i64
+        )
+    })?;
+    Ok(text)
+}
+
+/// Builds the permuted longest common prefix array over the `text` using the libsais64 algorithm
+/// `plcp[i]` is the length of the longest common prefix of the suffix starting at `i` and the suffix before it in the suffix array
+///
+/// # Arguments
+/// * `text` - The text used for suffix array construction
+/// * `sa` - The (non-sparse) suffix array built over `text`
+///
+/// # Returns
+///
+/// Returns the permuted longest common prefix array
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the permuted longest common prefix array failed,
+/// or with `INVALID_ARGUMENT` if the text and the suffix array have a different length
+pub fn plcp64(text: &[u8], sa: &[i64]) -> Result<Vec<i64>, SaisError> {
+    plcp64_omp(text, sa, 1)
+}
+
+/// Builds the permuted longest common prefix array over the `text` using the multithreaded libsais64 algorithm
+///
+/// # Arguments
+/// * `text` - The text used for suffix array construction
+/// * `sa` - The (non-sparse) suffix array built over `text`
+/// * `threads` - The number of threads used during construction, 0 to use the default number of OpenMP threads
+///
+/// # Returns
+///
+/// Returns the permuted longest common prefix array
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the permuted longest common prefix array failed,
+/// or with `INVALID_ARGUMENT` if the text and the suffix array have a different length
+pub fn plcp64_omp(text: &[u8], sa: &[i64], threads: usize) -> Result<Vec<i64>, SaisError> {
+    if text.len() != sa.len() {
+        return Err(SaisError { code: INVALID_ARGUMENT });
     }
+    let mut plcp = vec![0; text.len()];
+    check(unsafe {
+        libsais64_plcp_omp(text.as_ptr(), sa.as_ptr(), plcp.as_mut_ptr(), text.len() as i64, threads as i64)
+    })?;
+    Ok(plcp)
+}
+
+/// Builds the longest common prefix array from the permuted longest common prefix array using the libsais64 algorithm
+/// `lcp[i]` is the length of the longest common prefix of the suffixes at index `i - 1` and `i` in the suffix array
+///
+/// # Arguments
+/// * `plcp` - The permuted longest common prefix array
+/// * `sa` - The (non-sparse) suffix array used to build `plcp`
+///
+/// # Returns
+///
+/// Returns the longest common prefix array
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the longest common prefix array failed,
+/// or with `INVALID_ARGUMENT` if the permuted longest common prefix array and the suffix array have a different length
+pub fn lcp64(plcp: &[i64], sa: &[i64]) -> Result<Vec<i64>, SaisError> {
+    lcp64_omp(plcp, sa, 1)
+}
+
+/// Builds the longest common prefix array from the permuted longest common prefix array using the multithreaded libsais64 algorithm
+///
+/// # Arguments
+/// * `plcp` - The permuted longest common prefix array
+/// * `sa` - The (non-sparse) suffix array used to build `plcp`
+/// * `threads` - The number of threads used during construction, 0 to use the default number of OpenMP threads
+///
+/// # Returns
+///
+/// Returns the longest common prefix array
+///
+/// # Errors
+///
+/// Returns a SaisError with the libsais error code if construction of the longest common prefix array failed,
+/// or with `INVALID_ARGUMENT` if the permuted longest common prefix array and the suffix array have a different length
+pub fn lcp64_omp(plcp: &[i64], sa: &[i64], threads: usize) -> Result<Vec<i64>, SaisError> {
+    if plcp.len() != sa.len() {
+        return Err(SaisError { code: INVALID_ARGUMENT });
+    }
+    let mut lcp = vec![0; sa.len()];
+    check(unsafe {
+        libsais64_lcp_omp(plcp.as_ptr(), sa.as_ptr(), lcp.as_mut_ptr(), sa.len() as i64, threads as i64)
+    })?;
+    Ok(lcp)
+}
+
+/// Builds the inverse suffix array, where `isa[sa[i]] = i`
+/// libsais has no routine for the inverse suffix array, so it is calculated from the suffix array
+///
+/// # Arguments
+/// * `sa` - The (non-sparse) suffix array
+///
+/// # Returns
+///
+/// Returns the inverse suffix array, which contains the rank of every suffix in the suffix array
+///
+/// # Errors
+///
+/// Returns a SaisError with `INVALID_ARGUMENT` if `sa` is not a permutation of the positions in the text
+pub fn isa64(sa: &[i64]) -> Result<Vec<i64>, SaisError> {
+    let mut isa = vec![-1; sa.len()];
+    for (rank, &suffix) in sa.iter().enumerate() {
+        if suffix < 0 || suffix as usize >= sa.len() || isa[suffix as usize] != -1 {
+            return Err(SaisError { code: INVALID_ARGUMENT });
+        }
+        isa[suffix as usize] = rank as i64;
+    }
+    Ok(isa)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{sais64};
+    use crate::{
+        bwt64, bwt64_omp, isa64, lcp64, lcp64_omp, plcp64, plcp64_omp, sais32, sais32_int, sais32_int_omp, sais32_omp,
+        sais64, sais64_long, sais64_long_omp, sais64_omp, unbwt64, unbwt64_omp, SaisError, INVALID_ARGUMENT
+    };
 
     #[test]
     fn check_build_sa_with_libsais64() {
         let text = "banana$";
         let sa = sais64(text.as_bytes());
-        assert_eq!(sa, Some(vec![6, 5, 3, 1, 0, 4, 2]));
+        assert_eq!(sa, Ok(vec![6, 5, 3, 1, 0, 4, 2]));
+    }
+
+    #[test]
+    fn check_build_sa_with_libsais64_long() {
+        // banana$ where $ = 0, a = 1, b = 2 and n = 3
+        let mut text = vec![2, 1, 3, 1, 3, 1, 0];
+        let sa = sais64_long(&mut text, 4);
+        assert_eq!(sa, Ok(vec![6, 5, 3, 1, 0, 4, 2]));
+        assert_eq!(text, vec![2, 1, 3, 1, 3, 1, 0]);
+    }
+
+    #[test]
+    fn check_build_sa_with_libsais32() {
+        let text = "banana$";
+        assert_eq!(sais32(text.as_bytes()), Ok(vec![6, 5, 3, 1, 0, 4, 2]));
+
+        // banana$ where $ = 0, a = 1, b = 2 and n = 3
+        let mut text = vec![2, 1, 3, 1, 3, 1, 0];
+        assert_eq!(sais32_int(&mut text, 4), Ok(vec![6, 5, 3, 1, 0, 4, 2]));
+        assert_eq!(text, vec![2, 1, 3, 1, 3, 1, 0]);
+    }
+
+    #[test]
+    fn check_build_lcp_with_libsais64() {
+        let text = "banana$";
+        let sa = sais64(text.as_bytes()).unwrap();
+        let plcp = plcp64(text.as_bytes(), &sa).unwrap();
+        assert_eq!(plcp, vec![0, 3, 2, 1, 0, 0, 0]);
+        let lcp = lcp64(&plcp, &sa);
+        assert_eq!(lcp, Ok(vec![0, 0, 1, 3, 0, 0, 2]));
+    }
+
+    #[test]
+    fn check_build_lcp_invalid_length() {
+        let text = "banana$";
+        let sa = sais64(text.as_bytes()).unwrap();
+        assert_eq!(plcp64(&text.as_bytes()[1..], &sa), Err(SaisError { code: INVALID_ARGUMENT }));
+        assert_eq!(lcp64(&[0, 3, 2], &sa), Err(SaisError { code: INVALID_ARGUMENT }));
+    }
+
+    #[test]
+    fn check_bwt_with_libsais64() {
+        let text = "banana$";
+        // the row of the virtual sentinel is left out of the transform, the primary index is the position of that row
+        let (bwt, primary_index) = bwt64(text.as_bytes()).unwrap();
+        assert_eq!(bwt, b"$annbaa".to_vec());
+        assert_eq!(primary_index, 5);
+        assert_eq!(unbwt64(&bwt, primary_index), Ok(text.as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn check_isa() {
+        assert_eq!(isa64(&[6, 5, 3, 1, 0, 4, 2]), Ok(vec![4, 3, 6, 2, 5, 1, 0]));
+        assert_eq!(isa64(&[0, 0, 1]), Err(SaisError { code: INVALID_ARGUMENT }));
+        assert_eq!(isa64(&[0, 3, 1]), Err(SaisError { code: INVALID_ARGUMENT }));
+    }
+
+    #[test]
+    fn check_build_with_libsais64_omp() {
+        let text = "banana$";
+        for threads in [0, 1, 4] {
+            let sa = sais64_omp(text.as_bytes(), threads).unwrap();
+            assert_eq!(sa, vec![6, 5, 3, 1, 0, 4, 2]);
+            let mut integer_text = vec![2, 1, 3, 1, 3, 1, 0];
+            assert_eq!(sais64_long_omp(&mut integer_text, 4, threads), Ok(sa.clone()));
+            let sa_32: Vec<i32> = sa.iter().map(|&suffix| suffix as i32).collect();
+            assert_eq!(sais32_omp(text.as_bytes(), threads), Ok(sa_32.clone()));
+            let mut integer_text = vec![2, 1, 3, 1, 3, 1, 0];
+            assert_eq!(sais32_int_omp(&mut integer_text, 4, threads), Ok(sa_32));
+            let plcp = plcp64_omp(text.as_bytes(), &sa, threads).unwrap();
+            assert_eq!(plcp, vec![0, 3, 2, 1, 0, 0, 0]);
+            assert_eq!(lcp64_omp(&plcp, &sa, threads), Ok(vec![0, 0, 1, 3, 0, 0, 2]));
+            let (bwt, primary_index) = bwt64_omp(text.as_bytes(), threads).unwrap();
+            assert_eq!(unbwt64_omp(&bwt, primary_index, threads), Ok(text.as_bytes().to_vec()));
+        }
     }
 }
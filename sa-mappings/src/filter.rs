@@ -0,0 +1,412 @@
+//! This module contains the `ProteinFilter` struct, which selects the proteins of a database that
+//! are used to build an index.
+
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    fmt::{
+        Display,
+        Formatter
+    },
+    fs::File,
+    io::{
+        BufRead,
+        BufReader
+    }
+};
+
+use umgap::taxon::TaxonId;
+
+use crate::taxonomy::TaxonAggregator;
+
+/// The filters that select the proteins of a database that are used to build an index, and how
+/// their sequences are stored in the text of the index
+/// Proteins of which the taxon is not in the taxonomy are never used, regardless of the filters
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProteinFilter {
+    /// Only keep the proteins of which the taxon is in the subtree of one of these taxa, all taxa
+    /// are kept if this is empty
+    pub include_taxa: Vec<TaxonId>,
+
+    /// Remove the proteins of which the taxon is in the subtree of one of these taxa
+    pub exclude_taxa: Vec<TaxonId>,
+
+    /// Remove the proteins with a shorter sequence
+    pub min_length: Option<usize>,
+
+    /// Remove the proteins with a longer sequence
+    pub max_length: Option<usize>,
+
+    /// Only keep the proteins with one of these accessions, or all accessions if None
+    pub allowed_accessions: Option<BTreeSet<String>>,
+
+    /// Remove the proteins with one of these accessions
+    pub denied_accessions: BTreeSet<String>,
+
+    /// Store the proteins with identical sequences as one entry in the text, see
+    /// `Proteins::deduplicate`
+    pub deduplicate: bool
+}
+
+impl ProteinFilter {
+    /// Creates a filter that keeps all proteins
+    pub const fn new() -> Self {
+        Self {
+            include_taxa:       Vec::new(),
+            exclude_taxa:       Vec::new(),
+            min_length:         None,
+            max_length:         None,
+            allowed_accessions: None,
+            denied_accessions:  BTreeSet::new(),
+            deduplicate:        false
+        }
+    }
+
+    /// Returns true if the filter keeps all proteins, each with its own sequence in the text
+    pub fn is_empty(&self) -> bool {
+        *self == Self::new()
+    }
+
+    /// Checks if a protein passes all filters
+    /// Empty sequences never match a peptide, so they are not removed by the length filters. A
+    /// sub-index uses them to pad the gaps left by the removed proteins.
+    ///
+    /// # Arguments
+    /// * `uniprot_id` - The accession of the protein
+    /// * `taxon_id` - The taxon id of the protein
+    /// * `sequence_length` - The length of the sequence of the protein
+    /// * `taxon_aggregator` - The taxonomy used to check the taxon subtrees
+    ///
+    /// # Returns
+    ///
+    /// Returns true if the protein is kept
+    pub fn keeps(
+        &self,
+        uniprot_id: &str,
+        taxon_id: TaxonId,
+        sequence_length: usize,
+        taxon_aggregator: &TaxonAggregator
+    ) -> bool {
+        if sequence_length > 0
+            && (self.min_length.is_some_and(|min_length| sequence_length < min_length)
+                || self.max_length.is_some_and(|max_length| sequence_length > max_length))
+        {
+            return false;
+        }
+        if self.allowed_accessions.as_ref().is_some_and(|allowed| !allowed.contains(uniprot_id))
+            || self.denied_accessions.contains(uniprot_id)
+        {
+            return false;
+        }
+
+        let in_subtree_of = |taxa: &[TaxonId]| {
+            taxa.iter().any(|&ancestor| taxon_aggregator.in_subtree(taxon_id, ancestor))
+        };
+        (self.include_taxa.is_empty() || in_subtree_of(&self.include_taxa))
+            && !in_subtree_of(&self.exclude_taxa)
+    }
+
+    /// Restricts the filter to the proteins of a taxon subtree, the other filters stay the same
+    ///
+    /// # Arguments
+    /// * `taxon_id` - The root of the taxon subtree
+    /// * `taxon_aggregator` - The taxonomy used to check the taxon subtrees
+    ///
+    /// # Returns
+    ///
+    /// Returns the filter of which the included taxa are the intersection of the included taxa of
+    /// this filter with the taxon subtree, or None if that intersection is empty
+    pub fn restrict_to_subtree(
+        &self,
+        taxon_id: TaxonId,
+        taxon_aggregator: &TaxonAggregator
+    ) -> Option<ProteinFilter> {
+        let include_taxa = if self.include_taxa.is_empty()
+            || self.include_taxa.iter().any(|&ancestor| taxon_aggregator.in_subtree(taxon_id, ancestor))
+        {
+            vec![taxon_id]
+        } else {
+            // only the included subtrees that lie within the taxon subtree remain
+            self.include_taxa
+                .iter()
+                .copied()
+                .filter(|&included| taxon_aggregator.in_subtree(included, taxon_id))
+                .collect()
+        };
+        if include_taxa.is_empty() {
+            return None;
+        }
+
+        Some(ProteinFilter { include_taxa, ..self.clone() })
+    }
+
+    /// Serializes the filter, so it can be stored in the metadata of an index
+    /// Every filter that is set is stored on its own line as `name=values`, where multiple values
+    /// are separated by a comma
+    ///
+    /// # Returns
+    ///
+    /// Returns the serialized filter, which is empty if the filter keeps all proteins
+    pub fn serialize(&self) -> String {
+        let join = |values: Vec<String>| values.join(",");
+        let mut lines = vec![];
+        if !self.include_taxa.is_empty() {
+            lines.push(format!("include_taxa={}", join(self.include_taxa.iter().map(ToString::to_string).collect())));
+        }
+        if !self.exclude_taxa.is_empty() {
+            lines.push(format!("exclude_taxa={}", join(self.exclude_taxa.iter().map(ToString::to_string).collect())));
+        }
+        if let Some(min_length) = self.min_length {
+            lines.push(format!("min_length={}", min_length));
+        }
+        if let Some(max_length) = self.max_length {
+            lines.push(format!("max_length={}", max_length));
+        }
+        if let Some(allowed_accessions) = &self.allowed_accessions {
+            lines.push(format!("allowed_accessions={}", join(allowed_accessions.iter().cloned().collect())));
+        }
+        if !self.denied_accessions.is_empty() {
+            lines.push(format!("denied_accessions={}", join(self.denied_accessions.iter().cloned().collect())));
+        }
+        if self.deduplicate {
+            lines.push("deduplicate=true".to_string());
+        }
+        lines.join("\n")
+    }
+
+    /// Parses a filter that was serialized with `serialize`
+    ///
+    /// # Arguments
+    /// * `serialized` - The serialized filter
+    ///
+    /// # Returns
+    ///
+    /// Returns the parsed filter
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if a line contains an unknown filter or an invalid value
+    pub fn deserialize(serialized: &str) -> Result<Self, Box<dyn Error>> {
+        let mut filter = Self::new();
+        for line in serialized.lines().filter(|line| !line.is_empty()) {
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid protein filter: {}", line))?;
+            let values = value.split(',').filter(|value| !value.is_empty());
+            match name {
+                "include_taxa" => filter.include_taxa = values.map(str::parse::<TaxonId>).collect::<Result<_, _>>()?,
+                "exclude_taxa" => filter.exclude_taxa = values.map(str::parse::<TaxonId>).collect::<Result<_, _>>()?,
+                "min_length" => filter.min_length = Some(value.parse()?),
+                "max_length" => filter.max_length = Some(value.parse()?),
+                "allowed_accessions" => filter.allowed_accessions = Some(values.map(String::from).collect()),
+                "denied_accessions" => filter.denied_accessions = values.map(String::from).collect(),
+                "deduplicate" => filter.deduplicate = value.parse()?,
+                _ => return Err(format!("Unknown protein filter: {}", name).into())
+            }
+        }
+        Ok(filter)
+    }
+}
+
+impl Display for ProteinFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no filters");
+        }
+
+        let taxa = |taxa: &[TaxonId]| taxa.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ");
+        let mut filters = vec![];
+        if !self.include_taxa.is_empty() {
+            filters.push(format!("taxa {}", taxa(&self.include_taxa)));
+        }
+        if !self.exclude_taxa.is_empty() {
+            filters.push(format!("not taxa {}", taxa(&self.exclude_taxa)));
+        }
+        if let Some(min_length) = self.min_length {
+            filters.push(format!("at least {} residues", min_length));
+        }
+        if let Some(max_length) = self.max_length {
+            filters.push(format!("at most {} residues", max_length));
+        }
+        if let Some(allowed_accessions) = &self.allowed_accessions {
+            filters.push(format!("{} allowed accessions", allowed_accessions.len()));
+        }
+        if !self.denied_accessions.is_empty() {
+            filters.push(format!("{} denied accessions", self.denied_accessions.len()));
+        }
+        if self.deduplicate {
+            filters.push("identical sequences deduplicated".to_string());
+        }
+        write!(f, "{}", filters.join(", "))
+    }
+}
+
+/// Reads a file with an accession on every line
+///
+/// # Arguments
+/// * `file` - The path to the file
+///
+/// # Returns
+///
+/// Returns the accessions in the file, empty lines are skipped
+///
+/// # Errors
+///
+/// Returns a `Box<dyn Error>` if the file could not be read
+pub fn read_accessions_file(file: &str) -> Result<BTreeSet<String>, Box<dyn Error>> {
+    let mut accessions = BTreeSet::new();
+    for line in BufReader::new(File::open(file)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            accessions.insert(line.trim().to_string());
+        }
+    }
+    Ok(accessions)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        io::Write,
+        path::PathBuf
+    };
+
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::taxonomy::AggregationMethod;
+
+    fn create_taxonomy_file(tmp_dir: &TempDir) -> PathBuf {
+        let taxonomy_file = tmp_dir.path().join("taxonomy.tsv");
+        let mut file = File::create(&taxonomy_file).unwrap();
+
+        writeln!(file, "1\troot\tno rank\t1\t\x01").unwrap();
+        writeln!(file, "2\tBacteria\tsuperkingdom\t1\t\x01").unwrap();
+        writeln!(file, "6\tAzorhizobium\tgenus\t1\t\x01").unwrap();
+        writeln!(file, "7\tAzorhizobium caulinodans\tspecies\t6\t\x01").unwrap();
+        writeln!(file, "9\tBuchnera aphidicola\tspecies\t6\t\x01").unwrap();
+
+        taxonomy_file
+    }
+
+    fn create_filter() -> ProteinFilter {
+        ProteinFilter {
+            include_taxa:       vec![6],
+            exclude_taxa:       vec![9],
+            min_length:         Some(5),
+            max_length:         Some(10),
+            allowed_accessions: None,
+            denied_accessions:  BTreeSet::from(["P12345".to_string()]),
+            deduplicate:        false
+        }
+    }
+
+    #[test]
+    fn test_keeps() {
+        let tmp_dir = TempDir::new("test_keeps").unwrap();
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::LcaStar
+        )
+        .unwrap();
+
+        let filter = create_filter();
+        assert!(filter.keeps("P54321", 7, 5, &taxon_aggregator));
+        assert!(filter.keeps("P54321", 6, 10, &taxon_aggregator));
+        assert!(!filter.keeps("P54321", 2, 8, &taxon_aggregator));
+        assert!(!filter.keeps("P54321", 9, 8, &taxon_aggregator));
+        assert!(!filter.keeps("P54321", 7, 4, &taxon_aggregator));
+        assert!(!filter.keeps("P54321", 7, 11, &taxon_aggregator));
+        assert!(!filter.keeps("P12345", 7, 8, &taxon_aggregator));
+        // empty sequences are only removed by the other filters
+        assert!(filter.keeps("P54321", 7, 0, &taxon_aggregator));
+        assert!(!filter.keeps("P54321", 9, 0, &taxon_aggregator));
+
+        let filter = ProteinFilter {
+            allowed_accessions: Some(BTreeSet::from(["P12345".to_string()])),
+            ..ProteinFilter::new()
+        };
+        assert!(filter.keeps("P12345", 2, 1, &taxon_aggregator));
+        assert!(!filter.keeps("P54321", 2, 1, &taxon_aggregator));
+        assert!(ProteinFilter::new().keeps("P54321", 2, 1, &taxon_aggregator));
+    }
+
+    #[test]
+    fn test_restrict_to_subtree() {
+        let tmp_dir = TempDir::new("test_restrict_to_subtree").unwrap();
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::LcaStar
+        )
+        .unwrap();
+
+        // the subtree lies within an included subtree
+        let filter = create_filter();
+        let restricted = filter.restrict_to_subtree(7, &taxon_aggregator).unwrap();
+        assert_eq!(restricted, ProteinFilter { include_taxa: vec![7], ..create_filter() });
+
+        // the included subtree lies within the subtree
+        let restricted = filter.restrict_to_subtree(1, &taxon_aggregator).unwrap();
+        assert_eq!(restricted, create_filter());
+
+        // the subtree and the included subtree do not overlap
+        let filter = ProteinFilter { include_taxa: vec![7], ..ProteinFilter::new() };
+        assert_eq!(filter.restrict_to_subtree(9, &taxon_aggregator), None);
+
+        // all taxa are included
+        let restricted = ProteinFilter::new().restrict_to_subtree(6, &taxon_aggregator).unwrap();
+        assert_eq!(restricted.include_taxa, vec![6]);
+    }
+
+    #[test]
+    fn test_serialize_deserialize() {
+        let filter = create_filter();
+        assert_eq!(filter.serialize(), "include_taxa=6\nexclude_taxa=9\nmin_length=5\nmax_length=10\ndenied_accessions=P12345");
+        assert_eq!(ProteinFilter::deserialize(&filter.serialize()).unwrap(), filter);
+
+        let filter = ProteinFilter {
+            include_taxa:       vec![2, 6],
+            allowed_accessions: Some(BTreeSet::new()),
+            deduplicate:        true,
+            ..ProteinFilter::new()
+        };
+        assert_eq!(ProteinFilter::deserialize(&filter.serialize()).unwrap(), filter);
+
+        assert_eq!(ProteinFilter::new().serialize(), "");
+        assert!(ProteinFilter::deserialize("").unwrap().is_empty());
+        assert!(ProteinFilter::deserialize("min_length=five").is_err());
+        assert!(ProteinFilter::deserialize("deduplicate=yes").is_err());
+        assert!(ProteinFilter::deserialize("reviewed=true").is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(ProteinFilter::new().to_string(), "no filters");
+        assert_eq!(
+            create_filter().to_string(),
+            "taxa 6, not taxa 9, at least 5 residues, at most 10 residues, 1 denied accessions"
+        );
+        assert_eq!(
+            ProteinFilter { deduplicate: true, ..ProteinFilter::new() }.to_string(),
+            "identical sequences deduplicated"
+        );
+    }
+
+    #[test]
+    fn test_read_accessions_file() {
+        let tmp_dir = TempDir::new("test_read_accessions_file").unwrap();
+        let accessions_file = tmp_dir.path().join("accessions.txt");
+        let mut file = File::create(&accessions_file).unwrap();
+        writeln!(file, "P12345").unwrap();
+        writeln!(file).unwrap();
+        writeln!(file, " P54321 ").unwrap();
+
+        assert_eq!(
+            read_accessions_file(accessions_file.to_str().unwrap()).unwrap(),
+            BTreeSet::from(["P12345".to_string(), "P54321".to_string()])
+        );
+    }
+}
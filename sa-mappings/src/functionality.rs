@@ -8,7 +8,7 @@ use serde::Serialize;
 use crate::proteins::Protein;
 
 /// A struct that represents the functional annotations once aggregated
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct FunctionalAggregation {
     /// A HashMap representing how many GO, EC and IPR terms were found
     pub counts: HashMap<String, usize>,
@@ -28,7 +28,7 @@ impl FunctionAggregator {
     /// # Returns
     ///
     /// Returns a JSON string containing the aggregated functional annotations
-    pub fn aggregate(&self, proteins: Vec<&Protein>) -> FunctionalAggregation {
+    pub fn aggregate(&self, proteins: Vec<Protein>) -> FunctionalAggregation {
         // Keep track of the proteins that have a certain annotation
         let mut proteins_with_ec: HashSet<String> = HashSet::new();
         let mut proteins_with_go: HashSet<String> = HashSet::new();
@@ -40,9 +40,9 @@ impl FunctionAggregator {
         for protein in proteins.iter() {
             for annotation in protein.get_functional_annotations().split(';') {
                 match annotation.chars().next() {
-                    Some('E') => proteins_with_ec.insert(protein.uniprot_id.clone()),
-                    Some('G') => proteins_with_go.insert(protein.uniprot_id.clone()),
-                    Some('I') => proteins_with_ipr.insert(protein.uniprot_id.clone()),
+                    Some('E') => proteins_with_ec.insert(protein.uniprot_id.to_string()),
+                    Some('G') => proteins_with_go.insert(protein.uniprot_id.to_string()),
+                    Some('I') => proteins_with_ipr.insert(protein.uniprot_id.to_string()),
                     _ => false
                 };
 
@@ -70,7 +70,7 @@ impl FunctionAggregator {
     /// # Returns
     ///
     /// Returns a list of lists with all the functional annotations per protein
-    pub fn get_all_functional_annotations(&self, proteins: &[&Protein]) -> Vec<Vec<String>> {
+    pub fn get_all_functional_annotations(&self, proteins: &[Protein]) -> Vec<Vec<String>> {
         proteins
             .iter()
             .map(
@@ -2,18 +2,30 @@
 //! and collections of proteins, respectively.
 
 use std::{
+    collections::HashMap,
     error::Error,
     fs::File,
-    io::BufReader,
-    ops::Index,
+    io::{
+        BufRead,
+        BufReader,
+        Read,
+        Write
+    },
+    ops::Range,
     str::from_utf8
 };
 
 use bytelines::ByteLines;
-use fa_compression::algorithm1::decode;
+use fa_compression::algorithm1::{
+    decode,
+    encode
+};
 use umgap::taxon::TaxonId;
 
-use crate::taxonomy::TaxonAggregator;
+use crate::{
+    filter::ProteinFilter,
+    taxonomy::TaxonAggregator
+};
 
 /// The separation character used in the input string
 pub static SEPARATION_CHARACTER: u8 = b'-';
@@ -22,16 +34,45 @@ pub static SEPARATION_CHARACTER: u8 = b'-';
 /// This character should be smaller than the separation character
 pub static TERMINATION_CHARACTER: u8 = b'$';
 
-/// A struct that represents a protein and its linked information
-pub struct Protein {
+/// The character that starts the header line of every protein in a FASTA file
+pub static FASTA_HEADER_CHARACTER: u8 = b'>';
+
+/// A struct that represents a protein and its linked information, borrowed from the flat arrays
+/// of the `Proteins` it is part of
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Protein<'a> {
     /// The id of the protein
-    pub uniprot_id: String,
+    pub uniprot_id: &'a str,
 
     /// the taxon id of the protein
     pub taxon_id: TaxonId,
 
     /// The encoded functional annotations of the protein
-    pub functional_annotations: Vec<u8>
+    pub functional_annotations: &'a [u8]
+}
+
+/// A flat array of bytes, either in memory or for example memory mapped from a file
+pub type ProteinSection = Box<dyn AsRef<[u8]> + Send + Sync>;
+
+/// The flat arrays in which the information of all proteins is stored, all integers are stored in
+/// little endian
+pub struct ProteinArrays {
+    /// The taxon id of every protein (u32)
+    pub taxa: ProteinSection,
+
+    /// The start of the accession of every protein in `accessions`, followed by the total length
+    /// (u64)
+    pub accession_offsets: ProteinSection,
+
+    /// The accessions of all proteins, concatenated
+    pub accessions: ProteinSection,
+
+    /// The start of the annotations of every protein in `annotations`, followed by the total
+    /// length (u64)
+    pub annotation_offsets: ProteinSection,
+
+    /// The encoded functional annotations of all proteins, concatenated
+    pub annotations: ProteinSection
 }
 
 /// A struct that represents a collection of proteins
@@ -39,14 +80,96 @@ pub struct Proteins {
     /// The input string containing all proteins
     pub input_string: Vec<u8>,
 
-    /// The proteins in the input string
-    pub proteins: Vec<Protein>
+    /// The flat arrays with the information of the proteins in the input string
+    arrays: ProteinArrays,
+
+    /// The number of proteins in the input string
+    len: usize,
+
+    /// The index of the first protein of every entry in the input string, followed by the number
+    /// of proteins. Empty if every protein has its own entry in the input string
+    pub entry_starts: Vec<u32>
+}
+
+/// An iterator over consecutive proteins of a `Proteins`
+#[derive(Clone)]
+pub struct ProteinIter<'a> {
+    /// The proteins that are iterated over
+    proteins: &'a Proteins,
+
+    /// The indices of the proteins that are not yet returned
+    range: Range<usize>
+}
+
+/// Collects proteins into the flat arrays of a `ProteinArrays` in memory
+#[derive(Default)]
+struct ProteinArraysBuilder {
+    taxa:               Vec<u8>,
+    accession_offsets:  Vec<u8>,
+    accessions:         Vec<u8>,
+    annotation_offsets: Vec<u8>,
+    annotations:        Vec<u8>
 }
 
-impl Protein {
+impl Protein<'_> {
     /// Returns the decoded functional annotations of the protein
     pub fn get_functional_annotations(&self) -> String {
-        decode(&self.functional_annotations)
+        decode(self.functional_annotations)
+    }
+}
+
+impl ProteinArrays {
+    /// Stores proteins in flat arrays in memory
+    ///
+    /// # Arguments
+    /// * `proteins` - The proteins that are stored
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the flat arrays with the proteins in the given order
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if the taxon id of a protein does not fit in 32 bits
+    pub fn from_proteins<'a>(proteins: impl IntoIterator<Item = Protein<'a>>) -> Result<Self, Box<dyn Error>> {
+        let mut builder = ProteinArraysBuilder::default();
+        for protein in proteins {
+            builder.push(protein.uniprot_id, stored_taxon_id(protein.taxon_id)?, protein.functional_annotations);
+        }
+        Ok(builder.finish())
+    }
+}
+
+impl ProteinArraysBuilder {
+    /// Appends a protein to the flat arrays
+    ///
+    /// # Arguments
+    /// * `uniprot_id` - The id of the protein
+    /// * `taxon_id` - The taxon id of the protein
+    /// * `functional_annotations` - The encoded functional annotations of the protein
+    fn push(&mut self, uniprot_id: &str, taxon_id: u32, functional_annotations: &[u8]) {
+        self.taxa.extend_from_slice(&taxon_id.to_le_bytes());
+        self.accession_offsets.extend_from_slice(&(self.accessions.len() as u64).to_le_bytes());
+        self.accessions.extend_from_slice(uniprot_id.as_bytes());
+        self.annotation_offsets.extend_from_slice(&(self.annotations.len() as u64).to_le_bytes());
+        self.annotations.extend_from_slice(functional_annotations);
+    }
+
+    /// Returns the flat arrays with all appended proteins
+    fn finish(mut self) -> ProteinArrays {
+        self.accession_offsets.extend_from_slice(&(self.accessions.len() as u64).to_le_bytes());
+        self.annotation_offsets.extend_from_slice(&(self.annotations.len() as u64).to_le_bytes());
+        let section = |mut data: Vec<u8>| -> ProteinSection {
+            data.shrink_to_fit();
+            Box::new(data)
+        };
+        ProteinArrays {
+            taxa:               section(self.taxa),
+            accession_offsets:  section(self.accession_offsets),
+            accessions:         section(self.accessions),
+            annotation_offsets: section(self.annotation_offsets),
+            annotations:        section(self.annotations)
+        }
     }
 }
 
@@ -56,6 +179,7 @@ impl Proteins {
     /// # Arguments
     /// * `file` - The path to the database file
     /// * `taxon_aggregator` - The `TaxonAggregator` to use
+    /// * `filter` - The filter that selects the proteins that are kept
     ///
     /// # Returns
     ///
@@ -66,10 +190,16 @@ impl Proteins {
     /// Returns a `Box<dyn Error>` if an error occurred while reading the database file
     pub fn try_from_database_file(
         file: &str,
-        taxon_aggregator: &TaxonAggregator
+        taxon_aggregator: &TaxonAggregator,
+        filter: &ProteinFilter
     ) -> Result<Self, Box<dyn Error>> {
+        // FASTA files are read without functional annotations
+        if is_fasta_file(file)? {
+            return Self::try_from_fasta_file(file, None, taxon_aggregator, filter);
+        }
+
         let mut input_string: String = String::new();
-        let mut proteins: Vec<Protein> = Vec::new();
+        let mut proteins = ProteinArraysBuilder::default();
 
         let file = File::open(file)?;
         
@@ -84,31 +214,126 @@ impl Proteins {
             let uniprot_id = from_utf8(fields.next().unwrap())?;
             let taxon_id = from_utf8(fields.next().unwrap())?.parse::<TaxonId>()?;
             let sequence = from_utf8(fields.next().unwrap())?;
-            let functional_annotations: Vec<u8> = fields.next().unwrap().to_vec();
+            let functional_annotations = fields.next().unwrap();
 
-            if !taxon_aggregator.taxon_exists(taxon_id) {
+            if !taxon_aggregator.taxon_exists(taxon_id)
+                || !filter.keeps(uniprot_id, taxon_id, sequence.len(), taxon_aggregator)
+            {
                 continue;
             }
 
             input_string.push_str(&sequence.to_uppercase());
             input_string.push(SEPARATION_CHARACTER.into());
 
-            proteins.push(Protein {
-                uniprot_id: uniprot_id.to_string(),
-                taxon_id,
-                functional_annotations
-            });
+            proteins.push(uniprot_id, stored_taxon_id(taxon_id)?, functional_annotations);
+        }
+
+        input_string.pop();
+        input_string.push(TERMINATION_CHARACTER.into());
+        input_string.shrink_to_fit();
+        let mut proteins = Self::from_arrays(input_string.into_bytes(), proteins.finish(), Vec::new())?;
+        if filter.deduplicate {
+            proteins.deduplicate();
+        }
+        Ok(proteins)
+    }
 
+    /// Creates a new `Proteins` struct from a database file, with the functional annotations of a
+    /// separate annotations file if the database file is a FASTA file
+    ///
+    /// # Arguments
+    /// * `file` - The path to the database file
+    /// * `annotations_file` - The path to the annotations file of a FASTA database file, see
+    ///   `try_from_fasta_file`, or None to read the database file without a separate annotations
+    ///   file
+    /// * `taxon_aggregator` - The `TaxonAggregator` to use
+    /// * `filter` - The filter that selects the proteins that are kept
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `Proteins` struct
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if an annotations file is given for a tsv database file, which
+    /// contains the functional annotations itself, or if an error occurred while reading the files
+    pub fn try_from_database_file_with_annotations(
+        file: &str,
+        annotations_file: Option<&str>,
+        taxon_aggregator: &TaxonAggregator,
+        filter: &ProteinFilter
+    ) -> Result<Self, Box<dyn Error>> {
+        match annotations_file {
+            None => Self::try_from_database_file(file, taxon_aggregator, filter),
+            Some(_) if !is_fasta_file(file)? => Err(
+                "An annotations file can only be used with a FASTA database file, a tsv database file contains the functional annotations itself".into()
+            ),
+            Some(annotations_file) => {
+                Self::try_from_fasta_file(file, Some(annotations_file), taxon_aggregator, filter)
+            }
         }
+    }
+
+    /// Creates a new `Proteins` struct from a UniProt FASTA file and a `TaxonAggregator`
+    /// The accession is taken from the identifier in the header (`>sp|P12345|NAME_HUMAN ...`) and
+    /// the taxon id from the `OX=` field of the header
+    ///
+    /// # Arguments
+    /// * `file` - The path to the FASTA file
+    /// * `annotations_file` - The path to a tsv file with the accession and the functional
+    ///   annotations (e.g. `GO:0009279;IPR:IPR016364;EC:1.1.1.-`) of a protein on every line, or
+    ///   None if the proteins have no functional annotations
+    /// * `taxon_aggregator` - The `TaxonAggregator` to use
+    /// * `filter` - The filter that selects the proteins that are kept
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `Proteins` struct, proteins without a line in the
+    /// annotations file have no functional annotations
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if an error occurred while reading the FASTA file or the
+    /// annotations file, or if a header does not contain a taxon id
+    pub fn try_from_fasta_file(
+        file: &str,
+        annotations_file: Option<&str>,
+        taxon_aggregator: &TaxonAggregator,
+        filter: &ProteinFilter
+    ) -> Result<Self, Box<dyn Error>> {
+        let annotations = match annotations_file {
+            Some(annotations_file) => read_annotations_file(annotations_file)?,
+            None => HashMap::new()
+        };
+        let mut input_string: String = String::new();
+        let mut proteins = ProteinArraysBuilder::default();
+        let mut result = Ok(());
+
+        read_fasta_file(file, |uniprot_id, taxon_id, sequence| {
+            if result.is_err()
+                || !taxon_aggregator.taxon_exists(taxon_id)
+                || !filter.keeps(uniprot_id, taxon_id, sequence.len(), taxon_aggregator)
+            {
+                return;
+            }
+
+            input_string.push_str(&sequence.to_uppercase());
+            input_string.push(SEPARATION_CHARACTER.into());
+
+            let functional_annotations = annotations.get(uniprot_id).map_or(&[][..], Vec::as_slice);
+            result = stored_taxon_id(taxon_id)
+                .map(|taxon_id| proteins.push(uniprot_id, taxon_id, functional_annotations));
+        })?;
+        result?;
 
         input_string.pop();
         input_string.push(TERMINATION_CHARACTER.into());
         input_string.shrink_to_fit();
-        proteins.shrink_to_fit();
-        Ok(Self {
-            input_string: input_string.into_bytes(),
-            proteins
-        })
+        let mut proteins = Self::from_arrays(input_string.into_bytes(), proteins.finish(), Vec::new())?;
+        if filter.deduplicate {
+            proteins.deduplicate();
+        }
+        Ok(proteins)
     }
 
     /// Creates a `vec<u8>` which represents all the proteins concatenated from the database file
@@ -116,6 +341,7 @@ impl Proteins {
     /// # Arguments
     /// * `file` - The path to the database file
     /// * `taxon_aggregator` - The `TaxonAggregator` to use
+    /// * `filter` - The filter that selects the proteins that are kept
     ///
     /// # Returns
     ///
@@ -124,47 +350,513 @@ impl Proteins {
     /// # Errors
     ///
     /// Returns a `Box<dyn Error>` if an error occurred while reading the database file
-    pub fn try_from_database_file_without_annotations(database_file: &str, taxon_aggregator: &TaxonAggregator) -> Result<Vec<u8>, Box<dyn Error>> {
-        let mut input_string: String = String::new();
+    pub fn try_from_database_file_without_annotations(
+        database_file: &str,
+        taxon_aggregator: &TaxonAggregator,
+        filter: &ProteinFilter
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut input_string = Vec::new();
+        Self::write_database_text(database_file, taxon_aggregator, filter, &mut input_string)?;
+        Ok(finish_input_string(input_string, filter))
+    }
+
+    /// Writes all the proteins concatenated from the database file to a writer, without keeping
+    /// them in memory
+    /// The sequences are never deduplicated, even if the filter deduplicates them
+    ///
+    /// # Arguments
+    /// * `file` - The path to the database file
+    /// * `taxon_aggregator` - The `TaxonAggregator` to use
+    /// * `filter` - The filter that selects the proteins that are kept
+    /// * `writer` - The writer the text is written to
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if an error occurred while reading the database file or while
+    /// writing the text
+    pub fn write_database_text(
+        database_file: &str,
+        taxon_aggregator: &TaxonAggregator,
+        filter: &ProteinFilter,
+        writer: &mut impl Write
+    ) -> Result<(), Box<dyn Error>> {
+        // every protein but the first is preceded by a separator, so the last one can be followed by the terminator
+        let mut first = true;
+        let mut write_sequence = |sequence: &str| -> Result<(), std::io::Error> {
+            if !first {
+                writer.write_all(&[SEPARATION_CHARACTER])?;
+            }
+            first = false;
+            writer.write_all(sequence.to_uppercase().as_bytes())
+        };
 
-        let file = File::open(database_file)?;
+        if is_fasta_file(database_file)? {
+            let mut result = Ok(());
+            read_fasta_file(database_file, |uniprot_id, taxon_id, sequence| {
+                if result.is_ok()
+                    && taxon_aggregator.taxon_exists(taxon_id)
+                    && filter.keeps(uniprot_id, taxon_id, sequence.len(), taxon_aggregator)
+                {
+                    result = write_sequence(sequence);
+                }
+            })?;
+            result?;
+        } else {
+            let file = File::open(database_file)?;
 
-        // Read the lines as bytes, since the input string is not guaranteed to be utf8
-        // because of the encoded functional annotations
-        let mut lines = ByteLines::new(BufReader::new(file));
+            // Read the lines as bytes, since the input string is not guaranteed to be utf8
+            // because of the encoded functional annotations
+            let mut lines = ByteLines::new(BufReader::new(file));
 
-        while let Some(Ok(line)) = lines.next() {
-            let mut fields = line.split(|b| *b == b'\t');
+            while let Some(Ok(line)) = lines.next() {
+                let mut fields = line.split(|b| *b == b'\t');
 
-            // only get the taxon id and sequence from each line, we don't need the other parts
-            fields.next();
-            let taxon_id = from_utf8(fields.next().unwrap())?.parse::<TaxonId>()?;
-            let sequence = from_utf8(fields.next().unwrap())?;
-            fields.next();
+                // only get the accession, taxon id and sequence from each line, we don't need the other parts
+                let uniprot_id = from_utf8(fields.next().unwrap())?;
+                let taxon_id = from_utf8(fields.next().unwrap())?.parse::<TaxonId>()?;
+                let sequence = from_utf8(fields.next().unwrap())?;
+                fields.next();
 
-            if !taxon_aggregator.taxon_exists(taxon_id) {
-                continue;
+                if !taxon_aggregator.taxon_exists(taxon_id)
+                    || !filter.keeps(uniprot_id, taxon_id, sequence.len(), taxon_aggregator)
+                {
+                    continue;
+                }
+
+                write_sequence(sequence)?;
             }
+        }
 
-            input_string.push_str(&sequence.to_uppercase());
-            input_string.push(SEPARATION_CHARACTER.into());
+        writer.write_all(&[TERMINATION_CHARACTER])?;
+        Ok(())
+    }
+
+    /// Creates a new `Proteins` struct from the input string and proteins that each have their own
+    /// entry in the input string
+    ///
+    /// # Arguments
+    /// * `input_string` - The input string containing all proteins
+    /// * `proteins` - The proteins in the input string, in the order of their sequences
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `Proteins` struct
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if the taxon id of a protein does not fit in 32 bits
+    pub fn new<'a>(
+        input_string: Vec<u8>,
+        proteins: impl IntoIterator<Item = Protein<'a>>
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::from_arrays(input_string, ProteinArrays::from_proteins(proteins)?, Vec::new())
+    }
+
+    /// Creates a new `Proteins` struct from the input string and the flat arrays of its proteins,
+    /// without copying the proteins out of the arrays
+    ///
+    /// # Arguments
+    /// * `input_string` - The input string containing all proteins
+    /// * `arrays` - The flat arrays with the information of the proteins
+    /// * `entry_starts` - The index of the first protein of every entry in the input string,
+    ///   followed by the number of proteins, or empty if every protein has its own entry
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `Proteins` struct
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if the arrays do not contain the same number of proteins, if the
+    /// offsets in the arrays are not increasing or point outside of the arrays, if an accession is
+    /// not valid utf8, or if the entries do not match the proteins
+    pub fn from_arrays(
+        input_string: Vec<u8>,
+        arrays: ProteinArrays,
+        entry_starts: Vec<u32>
+    ) -> Result<Self, Box<dyn Error>> {
+        let taxa = section_bytes(&arrays.taxa);
+        let len = taxa.len() / 4;
+        let offsets_match = |data: &ProteinSection, offsets: &ProteinSection| {
+            let offsets = section_bytes(offsets);
+            offsets.len() == 8 * (len + 1) && offset_at(offsets, len) == section_bytes(data).len()
+        };
+        if !taxa.len().is_multiple_of(4)
+            || !offsets_match(&arrays.accessions, &arrays.accession_offsets)
+            || !offsets_match(&arrays.annotations, &arrays.annotation_offsets)
+        {
+            return Err("The arrays of the proteins do not contain the same number of proteins".into());
         }
 
-        input_string.pop();
-        input_string.push(TERMINATION_CHARACTER.into());
+        // the arrays can come from a memory mapped file of which the checksums are not verified,
+        // so every part of a protein has to lie within its array before the proteins are used
+        let offsets_increasing = |offsets: &ProteinSection| {
+            let offsets = section_bytes(offsets);
+            offset_at(offsets, 0) == 0 && (0..len).all(|index| offset_at(offsets, index) <= offset_at(offsets, index + 1))
+        };
+        if !offsets_increasing(&arrays.accession_offsets) || !offsets_increasing(&arrays.annotation_offsets) {
+            return Err("The offsets of the proteins in their arrays are corrupt".into());
+        }
+        if (0..len).any(|index| from_utf8(section_part(&arrays.accessions, &arrays.accession_offsets, index)).is_err()) {
+            return Err("The accession of a protein is not valid utf8".into());
+        }
+        if !entry_starts.is_empty()
+            && (entry_starts[0] != 0
+                || entry_starts.last().is_some_and(|&count| count as usize != len)
+                || entry_starts.windows(2).any(|bounds| bounds[0] >= bounds[1]))
+        {
+            return Err("The entries do not match the proteins".into());
+        }
 
-        input_string.shrink_to_fit();
-        Ok(input_string.into_bytes())
+        Ok(Self {
+            input_string,
+            arrays,
+            len,
+            entry_starts
+        })
+    }
+
+    /// Returns the number of proteins
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if there are no proteins
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the protein at the given index
+    ///
+    /// # Arguments
+    /// * `index` - The index of the protein, in the order of the entries in the input string
+    ///
+    /// # Returns
+    ///
+    /// Returns the protein, borrowed from the flat arrays
+    pub fn get(&self, index: usize) -> Protein<'_> {
+        let taxa = section_bytes(&self.arrays.taxa);
+        let taxon_id = u32::from_le_bytes(taxa[4 * index..4 * index + 4].try_into().unwrap());
+        let uniprot_id = section_part(&self.arrays.accessions, &self.arrays.accession_offsets, index);
+        Protein {
+            // the accessions are checked in `from_arrays`, they are only invalid if a memory mapped
+            // file is changed while it is in use
+            uniprot_id:             from_utf8(uniprot_id).unwrap_or_default(),
+            taxon_id:               taxon_id as TaxonId,
+            functional_annotations: section_part(&self.arrays.annotations, &self.arrays.annotation_offsets, index)
+        }
+    }
+
+    /// Returns an iterator over all proteins, in the order of the entries in the input string
+    pub fn iter(&self) -> ProteinIter<'_> {
+        self.range(0..self.len)
+    }
+
+    /// Returns an iterator over the proteins with the given indices
+    ///
+    /// # Arguments
+    /// * `range` - The indices of the proteins, in the order of the entries in the input string
+    ///
+    /// # Returns
+    ///
+    /// Returns an iterator over the proteins in the range, which is empty if the range is empty
+    pub fn range(&self, range: Range<usize>) -> ProteinIter<'_> {
+        ProteinIter {
+            proteins: self,
+            range
+        }
+    }
+
+    /// Returns the flat arrays in which the information of the proteins is stored
+    pub fn arrays(&self) -> &ProteinArrays {
+        &self.arrays
+    }
+
+    /// Collapses the proteins with identical sequences into one entry of the input string
+    /// The entries are ordered by the first protein with their sequence, and every entry keeps the
+    /// proteins with its sequence in their original order
+    pub fn deduplicate(&mut self) {
+        if self.is_empty() || !self.entry_starts.is_empty() {
+            return;
+        }
+
+        let (input_string, protein_entries) = deduplicate_sequences(&self.input_string);
+        let mut order: Vec<usize> = (0..self.len).collect();
+        // the sort is stable, so the proteins of an entry keep their order
+        order.sort_by_key(|&protein| protein_entries[protein]);
+
+        let entry_count = protein_entries.iter().max().map_or(0, |&entry| entry as usize + 1);
+        let mut entry_starts = vec![0_u32; entry_count + 1];
+        for &entry in &protein_entries {
+            entry_starts[entry as usize + 1] += 1;
+        }
+        for entry in 1..entry_starts.len() {
+            entry_starts[entry] += entry_starts[entry - 1];
+        }
+
+        let mut proteins = ProteinArraysBuilder::default();
+        for protein in order.into_iter().map(|index| self.get(index)) {
+            // the taxon ids were already stored in 32 bits
+            proteins.push(protein.uniprot_id, protein.taxon_id as u32, protein.functional_annotations);
+        }
+
+        self.input_string = input_string;
+        self.input_string.shrink_to_fit();
+        self.arrays = proteins.finish();
+        self.entry_starts = entry_starts;
+    }
+
+    /// Returns the proteins of an entry in the input string
+    ///
+    /// # Arguments
+    /// * `entry` - The index of the entry in the input string
+    ///
+    /// # Returns
+    ///
+    /// Returns the proteins with the sequence of the entry, which is a single protein if the
+    /// sequences are not deduplicated
+    pub fn entry(&self, entry: usize) -> ProteinIter<'_> {
+        if self.entry_starts.is_empty() {
+            return self.range(entry..entry + 1);
+        }
+        self.range(self.entry_starts[entry] as usize..self.entry_starts[entry + 1] as usize)
+    }
+}
+
+impl<'a> Iterator for ProteinIter<'a> {
+    type Item = Protein<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.range.next().map(|index| self.proteins.get(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.range.nth(n).map(|index| self.proteins.get(index))
+    }
+}
+
+impl DoubleEndedIterator for ProteinIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.range.next_back().map(|index| self.proteins.get(index))
+    }
+}
+
+impl ExactSizeIterator for ProteinIter<'_> {}
+
+impl<'a> IntoIterator for &'a Proteins {
+    type Item = Protein<'a>;
+    type IntoIter = ProteinIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Converts a taxon id to the 32 bits in which it is stored in the flat arrays
+///
+/// # Arguments
+/// * `taxon_id` - The taxon id of a protein
+///
+/// # Returns
+///
+/// Returns the taxon id as a u32
+///
+/// # Errors
+///
+/// Returns a `Box<dyn Error>` if the taxon id does not fit in 32 bits
+fn stored_taxon_id(taxon_id: TaxonId) -> Result<u32, Box<dyn Error>> {
+    Ok(u32::try_from(taxon_id).map_err(|_| "The taxon id of a protein does not fit in 32 bits")?)
+}
+
+/// Returns the bytes of a flat array
+fn section_bytes(section: &ProteinSection) -> &[u8] {
+    (**section).as_ref()
+}
+
+/// Reads the little endian u64 offset at the given index of an array of offsets
+fn offset_at(offsets: &[u8], index: usize) -> usize {
+    u64::from_le_bytes(offsets[8 * index..8 * index + 8].try_into().unwrap()) as usize
+}
+
+/// Returns the part of a protein in a flat array of concatenated parts
+///
+/// # Arguments
+/// * `data` - The concatenated parts of all proteins
+/// * `offsets` - The start of every part in `data`, followed by the length of `data` (u64)
+/// * `index` - The index of the protein
+///
+/// # Returns
+///
+/// Returns the bytes of the part of the protein
+fn section_part<'a>(data: &'a ProteinSection, offsets: &ProteinSection, index: usize) -> &'a [u8] {
+    let offsets = section_bytes(offsets);
+    &section_bytes(data)[offset_at(offsets, index)..offset_at(offsets, index + 1)]
+}
+
+/// Deduplicates the sequences of an input string if the filter requires it
+///
+/// # Arguments
+/// * `input_string` - The input string with all proteins
+/// * `filter` - The filter the proteins were selected with
+///
+/// # Returns
+///
+/// Returns the input string in which every sequence occurs once if the filter deduplicates the
+/// sequences, otherwise the input string itself
+fn finish_input_string(mut input_string: Vec<u8>, filter: &ProteinFilter) -> Vec<u8> {
+    if filter.deduplicate {
+        input_string = deduplicate_sequences(&input_string).0;
+    }
+    input_string.shrink_to_fit();
+    input_string
+}
+
+/// Collapses the identical sequences in an input string into one entry
+///
+/// # Arguments
+/// * `input_string` - The input string with all proteins, which ends with the termination character
+///
+/// # Returns
+///
+/// Returns the input string in which every sequence occurs once, in the order of their first
+/// occurrence, together with the index of the entry of every protein in the original input string
+fn deduplicate_sequences(input_string: &[u8]) -> (Vec<u8>, Vec<u32>) {
+    let mut entries: HashMap<&[u8], u32> = HashMap::new();
+    let mut deduplicated = Vec::with_capacity(input_string.len());
+    let mut protein_entries = Vec::new();
+
+    for sequence in input_string[..input_string.len() - 1].split(|&b| b == SEPARATION_CHARACTER) {
+        let entry_count = entries.len() as u32;
+        let entry = *entries.entry(sequence).or_insert_with(|| {
+            deduplicated.extend_from_slice(sequence);
+            deduplicated.push(SEPARATION_CHARACTER);
+            entry_count
+        });
+        protein_entries.push(entry);
+    }
+
+    deduplicated.pop();
+    deduplicated.push(TERMINATION_CHARACTER);
+    (deduplicated, protein_entries)
+}
+
+/// Checks if a database file is a FASTA file instead of a tsv file
+///
+/// # Arguments
+/// * `file` - The path to the database file
+///
+/// # Returns
+///
+/// Returns true if the file starts with the FASTA header character
+///
+/// # Errors
+///
+/// Returns a `Box<dyn Error>` if the file could not be read
+pub fn is_fasta_file(file: &str) -> Result<bool, Box<dyn Error>> {
+    let mut first_character = [0_u8; 1];
+    let read = File::open(file)?.read(&mut first_character)?;
+    Ok(read == 1 && first_character[0] == FASTA_HEADER_CHARACTER)
+}
+
+/// Reads the proteins from a FASTA file and passes the accession, the taxon id and the sequence
+/// of every protein to `handle_protein`
+///
+/// # Arguments
+/// * `file` - The path to the FASTA file
+/// * `handle_protein` - The function that is called for every protein in the file
+///
+/// # Errors
+///
+/// Returns a `Box<dyn Error>` if the file could not be read, if it does not start with a header
+/// or if a header is invalid
+fn read_fasta_file(
+    file: &str,
+    mut handle_protein: impl FnMut(&str, TaxonId, &str)
+) -> Result<(), Box<dyn Error>> {
+    let mut lines = ByteLines::new(BufReader::new(File::open(file)?));
+    let mut header: Option<(String, TaxonId)> = None;
+    let mut sequence = String::new();
+
+    while let Some(line) = lines.next() {
+        let line = from_utf8(line?)?.trim();
+        if let Some(header_line) = line.strip_prefix(FASTA_HEADER_CHARACTER as char) {
+            if let Some((uniprot_id, taxon_id)) = header.take() {
+                handle_protein(&uniprot_id, taxon_id, &sequence);
+            }
+            header = Some(parse_fasta_header(header_line)?);
+            sequence.clear();
+        } else if header.is_some() {
+            // the sequence of a protein is spread over multiple lines
+            sequence.push_str(line);
+        } else if !line.is_empty() {
+            return Err("The FASTA file does not start with a header".into());
+        }
+    }
+    if let Some((uniprot_id, taxon_id)) = header {
+        handle_protein(&uniprot_id, taxon_id, &sequence);
     }
-    
+
+    Ok(())
 }
 
-impl Index<usize> for Proteins {
-    type Output = Protein;
+/// Parses the accession and the taxon id from the header of a protein in a UniProt FASTA file
+///
+/// # Arguments
+/// * `header` - The header line without the FASTA header character, e.g. `sp|P12345|NAME_HUMAN
+///   Protein name OS=Homo sapiens OX=9606 GN=NAME PE=1 SV=1`
+///
+/// # Returns
+///
+/// Returns the accession and the taxon id of the protein. The accession is the second part of a
+/// UniProt identifier (`db|accession|entry name`), or the complete identifier otherwise
+///
+/// # Errors
+///
+/// Returns a `Box<dyn Error>` if the header has no identifier or no valid taxon id
+fn parse_fasta_header(header: &str) -> Result<(String, TaxonId), Box<dyn Error>> {
+    let identifier = header.split_whitespace().next().ok_or("The FASTA file contains an empty header")?;
+    let parts: Vec<&str> = identifier.split('|').collect();
+    let uniprot_id = if parts.len() == 3 { parts[1] } else { identifier };
+
+    let taxon_id = header
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("OX="))
+        .ok_or_else(|| format!("The FASTA header of {} does not contain a taxon id (OX=)", uniprot_id))?
+        .parse::<TaxonId>()?;
 
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.proteins[index]
+    Ok((uniprot_id.to_string(), taxon_id))
+}
+
+/// Reads the functional annotations of the proteins in a FASTA file from a tsv file
+///
+/// # Arguments
+/// * `file` - The path to the tsv file with the accession and the functional annotations of a
+///   protein on every line, the annotations are separated by a `;`
+///
+/// # Returns
+///
+/// Returns the encoded functional annotations of every accession in the file
+///
+/// # Errors
+///
+/// Returns a `Box<dyn Error>` if the file could not be read or contains a line without a tab
+fn read_annotations_file(file: &str) -> Result<HashMap<String, Vec<u8>>, Box<dyn Error>> {
+    let mut annotations = HashMap::new();
+    for line in BufReader::new(File::open(file)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (uniprot_id, functional_annotations) = line
+            .split_once('\t')
+            .ok_or_else(|| format!("The annotations file contains a line without a tab: {}", line))?;
+        annotations.insert(uniprot_id.to_string(), encode(functional_annotations.trim()));
     }
+    Ok(annotations)
 }
 
 #[cfg(test)]
@@ -209,6 +901,34 @@ mod tests {
         database_file
     }
 
+    fn create_fasta_file(tmp_dir: &TempDir) -> PathBuf {
+        let fasta_file = tmp_dir.path().join("database.fasta");
+        let mut file = File::create(&fasta_file).unwrap();
+
+        writeln!(file, ">sp|P12345|PROT1_ROOT Protein 1 OS=root OX=1 PE=1 SV=1").unwrap();
+        writeln!(file, "MLPGLALLLL").unwrap();
+        writeln!(file, "AAWTARALEV").unwrap();
+        writeln!(file, ">tr|P54321|PROT2_BACTE Protein 2 OS=Bacteria OX=2 GN=prot2 PE=4 SV=1").unwrap();
+        writeln!(file, "PTDGNAGLLAEPQIAMFCGRLNMHMNVQNG").unwrap();
+        writeln!(file, ">P67890 OX=6").unwrap();
+        writeln!(file, "kwdsdpsgtktcidt").unwrap();
+        writeln!(file).unwrap();
+        writeln!(file, ">sp|P13579|PROT4_METME Protein 4 OS=Methylophilus methylotrophus OX=17").unwrap();
+        writeln!(file, "KEGILQYCQEVYPELQITNVVEANQPVTIQNWCKRGRKQCKTHPH").unwrap();
+
+        fasta_file
+    }
+
+    fn create_annotations_file(tmp_dir: &TempDir) -> PathBuf {
+        let annotations_file = tmp_dir.path().join("annotations.tsv");
+        let mut file = File::create(&annotations_file).unwrap();
+
+        writeln!(file, "P12345\tGO:0009279;IPR:IPR016364;IPR:IPR008816").unwrap();
+        writeln!(file, "P13579\tEC:1.1.1.-").unwrap();
+
+        annotations_file
+    }
+
     fn create_taxonomy_file(tmp_dir: &TempDir) -> PathBuf {
         let taxonomy_file = tmp_dir.path().join("taxonomy.tsv");
         let mut file = File::create(&taxonomy_file).unwrap();
@@ -234,47 +954,49 @@ mod tests {
     #[test]
     fn test_new_protein() {
         let protein = Protein {
-            uniprot_id:             "P12345".to_string(),
+            uniprot_id:             "P12345",
             taxon_id:               1,
-            functional_annotations: vec![0xD1, 0x11]
+            functional_annotations: &[0xD1, 0x11]
         };
 
         assert_eq!(protein.uniprot_id, "P12345");
         assert_eq!(protein.taxon_id, 1);
-        assert_eq!(protein.functional_annotations, vec![0xD1, 0x11]);
+        assert_eq!(protein.functional_annotations, [0xD1, 0x11]);
     }
 
     #[test]
     fn test_new_proteins() {
-        let proteins = Proteins {
-            input_string: "MLPGLALLLLAAWTARALEV-PTDGNAGLLAEPQIAMFCGRLNMHMNVQNG"
+        let proteins = Proteins::new(
+            "MLPGLALLLLAAWTARALEV-PTDGNAGLLAEPQIAMFCGRLNMHMNVQNG"
                 .as_bytes()
                 .to_vec(),
-            proteins:     vec![
+            [
                 Protein {
-                    uniprot_id:             "P12345".to_string(),
+                    uniprot_id:             "P12345",
                     taxon_id:               1,
-                    functional_annotations: vec![0xD1, 0x11]
+                    functional_annotations: &[0xD1, 0x11]
                 },
                 Protein {
-                    uniprot_id:             "P54321".to_string(),
+                    uniprot_id:             "P54321",
                     taxon_id:               2,
-                    functional_annotations: vec![0xD1, 0x11]
+                    functional_annotations: &[0xD1, 0x11]
                 },
             ]
-        };
+        )
+        .unwrap();
 
         assert_eq!(
             proteins.input_string,
             "MLPGLALLLLAAWTARALEV-PTDGNAGLLAEPQIAMFCGRLNMHMNVQNG".as_bytes()
         );
-        assert_eq!(proteins.proteins.len(), 2);
-        assert_eq!(proteins.proteins[0].uniprot_id, "P12345");
-        assert_eq!(proteins.proteins[0].taxon_id, 1);
-        assert_eq!(proteins.proteins[0].functional_annotations, vec![0xD1, 0x11]);
-        assert_eq!(proteins.proteins[1].uniprot_id, "P54321");
-        assert_eq!(proteins.proteins[1].taxon_id, 2);
-        assert_eq!(proteins.proteins[1].functional_annotations, vec![0xD1, 0x11]);
+        assert_eq!(proteins.len(), 2);
+        assert_eq!(proteins.get(0).uniprot_id, "P12345");
+        assert_eq!(proteins.get(0).taxon_id, 1);
+        assert_eq!(proteins.get(0).functional_annotations, [0xD1, 0x11]);
+        assert_eq!(proteins.get(1).uniprot_id, "P54321");
+        assert_eq!(proteins.get(1).taxon_id, 2);
+        assert_eq!(proteins.get(1).functional_annotations, [0xD1, 0x11]);
+        assert!(Proteins::new(vec![], [Protein { taxon_id: 1 << 32, ..proteins.get(0) }]).is_err());
     }
 
     #[test]
@@ -291,11 +1013,11 @@ mod tests {
         )
         .unwrap();
         let proteins =
-            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator)
+            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator, &ProteinFilter::new())
                 .unwrap();
 
         let taxa = vec![1, 2, 6, 17];
-        for (i, protein) in proteins.proteins.iter().enumerate() {
+        for (i, protein) in proteins.iter().enumerate() {
             assert_eq!(protein.taxon_id, taxa[i]);
         }
     }
@@ -314,12 +1036,12 @@ mod tests {
         )
         .unwrap();
         let proteins =
-            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator)
+            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator, &ProteinFilter::new())
                 .unwrap();
 
-        for protein in proteins.proteins.iter() {
+        for protein in proteins.iter() {
             assert_eq!(
-                decode(&protein.functional_annotations),
+                decode(protein.functional_annotations),
                 "GO:0009279;IPR:IPR016364;IPR:IPR008816"
             );
         }
@@ -339,7 +1061,7 @@ mod tests {
         )
             .unwrap();
         let proteins =
-            Proteins::try_from_database_file_without_annotations(database_file.to_str().unwrap(), &taxon_aggregator)
+            Proteins::try_from_database_file_without_annotations(database_file.to_str().unwrap(), &taxon_aggregator, &ProteinFilter::new())
                 .unwrap();
         
         let sep_char = SEPARATION_CHARACTER as char;
@@ -347,4 +1069,240 @@ mod tests {
         let expected = format!("MLPGLALLLLAAWTARALEV{}PTDGNAGLLAEPQIAMFCGRLNMHMNVQNG{}KWDSDPSGTKTCIDT{}KEGILQYCQEVYPELQITNVVEANQPVTIQNWCKRGRKQCKTHPH{}", sep_char, sep_char, sep_char, end_char);
         assert_eq!(proteins, expected.as_bytes());
     }
+
+    #[test]
+    fn test_is_fasta_file() {
+        let tmp_dir = TempDir::new("test_is_fasta_file").unwrap();
+
+        let database_file = create_database_file(&tmp_dir);
+        let fasta_file = create_fasta_file(&tmp_dir);
+
+        assert!(!is_fasta_file(database_file.to_str().unwrap()).unwrap());
+        assert!(is_fasta_file(fasta_file.to_str().unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_parse_fasta_header() {
+        assert_eq!(
+            parse_fasta_header("sp|P12345|PROT1_HUMAN Protein 1 OS=Homo sapiens OX=9606 PE=1 SV=1").unwrap(),
+            ("P12345".to_string(), 9606)
+        );
+        assert_eq!(parse_fasta_header("P12345 OX=1").unwrap(), ("P12345".to_string(), 1));
+        assert!(parse_fasta_header("sp|P12345|PROT1_HUMAN Protein 1 OS=Homo sapiens").is_err());
+        assert!(parse_fasta_header("sp|P12345|PROT1_HUMAN OX=human").is_err());
+        assert!(parse_fasta_header("").is_err());
+    }
+
+    #[test]
+    fn test_try_from_fasta_file() {
+        let tmp_dir = TempDir::new("test_try_from_fasta_file").unwrap();
+
+        let database_file = create_database_file(&tmp_dir);
+        let fasta_file = create_fasta_file(&tmp_dir);
+        let annotations_file = create_annotations_file(&tmp_dir);
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+        .unwrap();
+        let proteins = Proteins::try_from_fasta_file(
+            fasta_file.to_str().unwrap(),
+            Some(annotations_file.to_str().unwrap()),
+            &taxon_aggregator,
+            &ProteinFilter::new()
+        )
+        .unwrap();
+        let tsv_proteins =
+            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator, &ProteinFilter::new())
+                .unwrap();
+
+        assert_eq!(proteins.input_string, tsv_proteins.input_string);
+        let uniprot_ids: Vec<&str> = proteins.iter().map(|protein| protein.uniprot_id).collect();
+        assert_eq!(uniprot_ids, vec!["P12345", "P54321", "P67890", "P13579"]);
+        let taxa: Vec<TaxonId> = proteins.iter().map(|protein| protein.taxon_id).collect();
+        assert_eq!(taxa, vec![1, 2, 6, 17]);
+        assert_eq!(
+            proteins.get(0).get_functional_annotations(),
+            "GO:0009279;IPR:IPR016364;IPR:IPR008816"
+        );
+        assert_eq!(proteins.get(1).get_functional_annotations(), "");
+        assert_eq!(proteins.get(3).get_functional_annotations(), "EC:1.1.1.-");
+    }
+
+    #[test]
+    fn test_try_from_database_file_with_annotations() {
+        let tmp_dir = TempDir::new("test_try_from_database_file_with_annotations").unwrap();
+
+        let database_file = create_database_file(&tmp_dir);
+        let fasta_file = create_fasta_file(&tmp_dir);
+        let annotations_file = create_annotations_file(&tmp_dir);
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+        .unwrap();
+        let proteins = Proteins::try_from_database_file_with_annotations(
+            fasta_file.to_str().unwrap(),
+            Some(annotations_file.to_str().unwrap()),
+            &taxon_aggregator,
+            &ProteinFilter::new()
+        )
+        .unwrap();
+        assert_eq!(
+            proteins.get(0).get_functional_annotations(),
+            "GO:0009279;IPR:IPR016364;IPR:IPR008816"
+        );
+
+        let tsv_proteins = Proteins::try_from_database_file_with_annotations(
+            database_file.to_str().unwrap(),
+            None,
+            &taxon_aggregator,
+            &ProteinFilter::new()
+        )
+        .unwrap();
+        assert_eq!(tsv_proteins.input_string, proteins.input_string);
+
+        // a tsv database file contains the functional annotations itself
+        assert!(Proteins::try_from_database_file_with_annotations(
+            database_file.to_str().unwrap(),
+            Some(annotations_file.to_str().unwrap()),
+            &taxon_aggregator,
+            &ProteinFilter::new()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_database_file_detects_fasta() {
+        let tmp_dir = TempDir::new("test_database_file_detects_fasta").unwrap();
+
+        let fasta_file = create_fasta_file(&tmp_dir);
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+        .unwrap();
+        let proteins =
+            Proteins::try_from_database_file(fasta_file.to_str().unwrap(), &taxon_aggregator, &ProteinFilter::new())
+                .unwrap();
+        let input_string = Proteins::try_from_database_file_without_annotations(
+            fasta_file.to_str().unwrap(),
+            &taxon_aggregator,
+            &ProteinFilter::new()
+        )
+        .unwrap();
+
+        assert_eq!(proteins.len(), 4);
+        assert!(proteins.iter().all(|protein| protein.functional_annotations.is_empty()));
+        assert_eq!(input_string, proteins.input_string);
+    }
+
+    #[test]
+    fn test_filter_proteins() {
+        let tmp_dir = TempDir::new("test_filter_proteins").unwrap();
+
+        let database_file = create_database_file(&tmp_dir);
+        let fasta_file = create_fasta_file(&tmp_dir);
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+        .unwrap();
+        // only the proteins in the subtrees of taxa 2 and 6 with at least 16 residues
+        let filter = ProteinFilter {
+            include_taxa: vec![2, 6],
+            min_length:   Some(16),
+            ..ProteinFilter::new()
+        };
+
+        for file in [database_file, fasta_file] {
+            let proteins =
+                Proteins::try_from_database_file(file.to_str().unwrap(), &taxon_aggregator, &filter)
+                    .unwrap();
+            let input_string = Proteins::try_from_database_file_without_annotations(
+                file.to_str().unwrap(),
+                &taxon_aggregator,
+                &filter
+            )
+            .unwrap();
+
+            let uniprot_ids: Vec<&str> = proteins.iter().map(|protein| protein.uniprot_id).collect();
+            assert_eq!(uniprot_ids, vec!["P54321", "P13579"]);
+            assert_eq!(
+                input_string,
+                b"PTDGNAGLLAEPQIAMFCGRLNMHMNVQNG-KEGILQYCQEVYPELQITNVVEANQPVTIQNWCKRGRKQCKTHPH$".to_vec()
+            );
+            assert_eq!(input_string, proteins.input_string);
+        }
+    }
+
+    #[test]
+    fn test_deduplicate() {
+        let mut proteins = Proteins::new(
+            b"MLPG-PTDG-MLPG-KWDS-PTDG$".to_vec(),
+            ["P1", "P2", "P3", "P4", "P5"]
+                .into_iter()
+                .zip([1, 2, 6, 17, 2])
+                .map(|(uniprot_id, taxon_id)| Protein {
+                    uniprot_id,
+                    taxon_id,
+                    functional_annotations: &[]
+                })
+        )
+        .unwrap();
+        assert_eq!(proteins.entry(1).next().unwrap().uniprot_id, "P2");
+
+        proteins.deduplicate();
+        assert_eq!(proteins.input_string, b"MLPG-PTDG-KWDS$".to_vec());
+        assert_eq!(proteins.entry_starts, vec![0, 2, 4, 5]);
+        let entries: Vec<Vec<&str>> = (0..3)
+            .map(|entry| proteins.entry(entry).map(|protein| protein.uniprot_id).collect())
+            .collect();
+        assert_eq!(entries, vec![vec!["P1", "P3"], vec!["P2", "P5"], vec!["P4"]]);
+    }
+
+    #[test]
+    fn test_deduplicate_database_file() {
+        let tmp_dir = TempDir::new("test_deduplicate_database_file").unwrap();
+
+        let database_file = tmp_dir.path().join("database.tsv");
+        let mut file = File::create(&database_file).unwrap();
+        writeln!(file, "P12345\t1\tMLPGLALLLLAAWTARALEV\t").unwrap();
+        writeln!(file, "P54321\t2\tKWDSDPSGTKTCIDT\t").unwrap();
+        writeln!(file, "P67890\t6\tMLPGLALLLLAAWTARALEV\t").unwrap();
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+        .unwrap();
+        let filter = ProteinFilter {
+            deduplicate: true,
+            ..ProteinFilter::new()
+        };
+        let proteins =
+            Proteins::try_from_database_file(database_file.to_str().unwrap(), &taxon_aggregator, &filter)
+                .unwrap();
+        let input_string = Proteins::try_from_database_file_without_annotations(
+            database_file.to_str().unwrap(),
+            &taxon_aggregator,
+            &filter
+        )
+        .unwrap();
+
+        assert_eq!(input_string, b"MLPGLALLLLAAWTARALEV-KWDSDPSGTKTCIDT$".to_vec());
+        assert_eq!(input_string, proteins.input_string);
+        let taxa: Vec<TaxonId> = proteins.entry(0).map(|protein| protein.taxon_id).collect();
+        assert_eq!(taxa, vec![1, 6]);
+        assert_eq!(proteins.entry(1).next().unwrap().uniprot_id, "P54321");
+    }
 }
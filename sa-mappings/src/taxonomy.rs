@@ -13,8 +13,10 @@ use umgap::{
         lca::LCACalculator,
         mix::MixCalculator
     },
+    rank::Rank,
     taxon::{
         read_taxa_file,
+        Taxon,
         TaxonId,
         TaxonList,
         TaxonTree
@@ -30,7 +32,83 @@ pub struct TaxonAggregator {
     aggregator: Box<dyn MultiThreadSafeAggregator>,
 
     /// The taxon list.
-    taxon_list: TaxonList
+    taxon_list: TaxonList,
+
+    /// A vector that contains the parent of every taxon ID, 0 if the taxon does not exist.
+    parents: Vec<TaxonId>,
+
+    /// A vector that contains the depth of every taxon ID in the taxonomic tree.
+    depths: Vec<u32>,
+
+    /// True if the LCA* aggregation method is used, false for the LCA.
+    lca_star: bool
+}
+
+/// A summary of a set of taxa, from which the aggregation of the set can be read and which can be joined with the summary of another set.
+/// The highest bit is set if the taxa of the set are not all on a single lineage, the other bits store the aggregated taxon ID.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TaxonSummary(u32);
+
+impl TaxonSummary {
+    /// The summary of an empty set of taxa.
+    pub const EMPTY: TaxonSummary = TaxonSummary(0);
+
+    /// The bit that is set if the taxa of the set are not all on a single lineage.
+    const FORK: u32 = 1 << 31;
+
+    /// Creates the summary of a set of taxa that are all on the lineage of a taxon.
+    ///
+    /// # Arguments
+    ///
+    /// * `taxon` - The deepest taxon of the set, 0 for an empty set.
+    ///
+    /// # Returns
+    ///
+    /// Returns the summary of the set.
+    pub fn lineage(taxon: TaxonId) -> Self {
+        TaxonSummary(taxon as u32)
+    }
+
+    /// Creates the summary of a set of taxa that are not all on a single lineage.
+    ///
+    /// # Arguments
+    ///
+    /// * `taxon` - The LCA of the set.
+    ///
+    /// # Returns
+    ///
+    /// Returns the summary of the set.
+    fn fork(taxon: TaxonId) -> Self {
+        TaxonSummary(taxon as u32 | Self::FORK)
+    }
+
+    /// Creates a summary from its stored representation.
+    ///
+    /// # Arguments
+    ///
+    /// * `bits` - The value returned by `to_bits`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the summary that was stored.
+    pub fn from_bits(bits: u32) -> Self {
+        TaxonSummary(bits)
+    }
+
+    /// Returns the representation of the summary that can be stored.
+    pub fn to_bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns the aggregated taxon ID of the set, 0 for an empty set.
+    pub fn taxon(self) -> TaxonId {
+        (self.0 & !Self::FORK) as TaxonId
+    }
+
+    /// Returns true if the taxa of the set are not all on a single lineage.
+    fn is_fork(self) -> bool {
+        self.0 & Self::FORK != 0
+    }
 }
 
 /// An enum that specifies the aggregation method to use.
@@ -62,20 +140,176 @@ impl TaxonAggregator {
         method: AggregationMethod
     ) -> Result<Self, Box<dyn Error>> {
         let taxons = read_taxa_file(file)?;
+        Ok(Self::from_taxa(taxons, method))
+    }
+
+    /// Creates a new `TaxonAggregator` from the contents of a taxonomy file and an aggregation method.
+    /// This is used when the taxonomy is embedded in an index bundle instead of stored in a separate file.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The contents of a taxonomy file.
+    /// * `method` - An `AggregationMethod` enum that specifies the aggregation method to use.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `TaxonAggregator`
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if the contents are not a valid taxonomy.
+    pub fn try_from_taxonomy_bytes(
+        data: &[u8],
+        method: AggregationMethod
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut taxons = Vec::new();
+        for line in std::str::from_utf8(data)?.lines() {
+            taxons.push(line.parse::<Taxon>()?);
+        }
+        Ok(Self::from_taxa(taxons, method))
+    }
+
+    /// Creates a new `TaxonAggregator` from the binary form of a taxonomy written by
+    /// `taxonomy_file_to_binary` and an aggregation method.
+    /// This is used when the taxonomy is embedded in an index bundle, so the text of the taxonomy
+    /// file does not have to be parsed again.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The binary form of a taxonomy.
+    /// * `method` - An `AggregationMethod` enum that specifies the aggregation method to use.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the `TaxonAggregator`
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Box<dyn Error>` if the data is truncated or contains an invalid taxon.
+    pub fn try_from_taxonomy_binary(
+        data: &[u8],
+        method: AggregationMethod
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut position = 0;
+        let mut take = |length: usize| -> Result<&[u8], Box<dyn Error>> {
+            let bytes = data
+                .get(position .. position + length)
+                .ok_or("The binary taxonomy is truncated")?;
+            position += length;
+            Ok(bytes)
+        };
+
+        let count = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        // a corrupt count does not allocate a huge vector up front, every taxon takes at least 14 bytes
+        let mut taxons = Vec::with_capacity((count as usize).min(data.len() / 14));
+        for _ in 0 .. count {
+            let id = u32::from_le_bytes(take(4)?.try_into().unwrap()) as TaxonId;
+            let parent = u32::from_le_bytes(take(4)?.try_into().unwrap()) as TaxonId;
+            let rank = match take(1)?[0] {
+                0 => Rank::NoRank,
+                index => Rank::ranks()
+                    .nth(index as usize - 1)
+                    .ok_or("The binary taxonomy contains an invalid rank")?
+            };
+            let valid = take(1)?[0] != 0;
+            let name_length = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+            let name = std::str::from_utf8(take(name_length)?)?.to_string();
+            taxons.push(Taxon::new(id, name, rank, parent, valid));
+        }
+        if position != data.len() {
+            return Err("The binary taxonomy contains trailing bytes".into());
+        }
+
+        Ok(Self::from_taxa(taxons, method))
+    }
+
+    /// Creates a new `TaxonAggregator` from a list of taxa and an aggregation method.
+    ///
+    /// # Arguments
+    ///
+    /// * `taxons` - All the taxa in the taxonomy.
+    /// * `method` - An `AggregationMethod` enum that specifies the aggregation method to use.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `TaxonAggregator`
+    fn from_taxa(taxons: Vec<Taxon>, method: AggregationMethod) -> Self {
+        let max_taxon_id = taxons.iter().map(|taxon| taxon.id).max().unwrap_or(0);
+        let mut parents = vec![0; max_taxon_id + 1];
+        for taxon in taxons.iter() {
+            parents[taxon.id] = taxon.parent;
+        }
+        let depths = Self::calculate_depths(&parents);
+
         let taxon_tree = TaxonTree::new(&taxons);
         let taxon_list = TaxonList::new(taxons);
         let snapping = taxon_tree.snapping(&taxon_list, true);
 
+        let lca_star = matches!(method, AggregationMethod::LcaStar);
         let aggregator: Box<dyn MultiThreadSafeAggregator> = match method {
             AggregationMethod::Lca => Box::new(MixCalculator::new(taxon_tree, 1.0)),
             AggregationMethod::LcaStar => Box::new(LCACalculator::new(taxon_tree))
         };
 
-        Ok(Self {
+        Self {
             snapping,
             aggregator,
-            taxon_list
-        })
+            taxon_list,
+            parents,
+            depths,
+            lca_star
+        }
+    }
+
+    /// Calculates the depth of every taxon in the taxonomic tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `parents` - The parent of every taxon ID, 0 if the taxon does not exist.
+    ///
+    /// # Returns
+    ///
+    /// Returns a vector with the depth of every taxon ID, where the root and the taxa of which the parent is missing have depth 0.
+    fn calculate_depths(parents: &[TaxonId]) -> Vec<u32> {
+        let mut depths = vec![u32::MAX; parents.len()];
+        let mut path = vec![];
+
+        for taxon in 0 .. parents.len() {
+            if parents[taxon] == 0 {
+                continue;
+            }
+
+            // walk up until we find a taxon of which the depth is already known, the root, or a taxon of which the parent is missing
+            let mut current = taxon;
+            while depths[current] == u32::MAX
+                && parents[current] != current
+                && parents[current] < parents.len()
+                && parents[parents[current]] != 0
+            {
+                path.push(current);
+                current = parents[current];
+            }
+            if depths[current] == u32::MAX {
+                depths[current] = 0;
+            }
+
+            let mut depth = depths[current];
+            while let Some(descendant) = path.pop() {
+                depth += 1;
+                depths[descendant] = depth;
+            }
+        }
+
+        depths
+    }
+
+    /// Checks if the LCA* aggregation method is used.
+    ///
+    /// # Returns
+    ///
+    /// Returns true if the taxa are aggregated with the LCA*, false if they are aggregated with the LCA.
+    pub fn is_lca_star(&self) -> bool {
+        self.lca_star
     }
 
     /// Checks if a taxon exists in the taxon list.
@@ -121,6 +355,116 @@ impl TaxonAggregator {
         self.snapping[taxon].unwrap_or_else(|| panic!("Could not snap taxon with id {taxon}"))
     }
 
+    /// Checks if a taxon is part of the taxonomic tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `taxon` - The taxon ID to check.
+    ///
+    /// # Returns
+    ///
+    /// Returns true if the taxon has a parent, which is the case for every taxon in the taxonomy file.
+    fn in_tree(&self, taxon: TaxonId) -> bool {
+        taxon < self.parents.len() && self.parents[taxon] != 0
+    }
+
+    /// Calculates the lowest common ancestor (LCA) of two taxa in the taxonomic tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `taxon1` - The first taxon ID, 0 and taxa that are not in the taxonomy are used as a neutral element.
+    /// * `taxon2` - The second taxon ID, 0 and taxa that are not in the taxonomy are used as a neutral element.
+    ///
+    /// # Returns
+    ///
+    /// Returns the taxon ID of the lowest common ancestor, or the other taxon if one of both is a neutral element.
+    /// Returns 0 if both taxa are neutral elements, or if they are in different trees of the taxonomy.
+    pub fn lca(&self, taxon1: TaxonId, taxon2: TaxonId) -> TaxonId {
+        if !self.in_tree(taxon1) {
+            return if self.in_tree(taxon2) { taxon2 } else { 0 };
+        }
+        if !self.in_tree(taxon2) {
+            return taxon1;
+        }
+
+        let mut taxon1 = taxon1;
+        let mut taxon2 = taxon2;
+        while self.depths[taxon1] > self.depths[taxon2] {
+            taxon1 = self.parents[taxon1];
+        }
+        while self.depths[taxon2] > self.depths[taxon1] {
+            taxon2 = self.parents[taxon2];
+        }
+        while taxon1 != taxon2 {
+            // a taxon of which the parent is missing is the root of a separate tree, which has no common ancestor with other trees
+            if self.parents[taxon1] == taxon1 || !self.in_tree(self.parents[taxon1]) {
+                return 0;
+            }
+            taxon1 = self.parents[taxon1];
+            taxon2 = self.parents[taxon2];
+        }
+
+        taxon1
+    }
+
+    /// Checks if a taxon is part of the subtree of another taxon in the taxonomic tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `taxon` - The taxon ID to check.
+    /// * `ancestor` - The taxon ID of the root of the subtree.
+    ///
+    /// # Returns
+    ///
+    /// Returns true if both taxa exist and `ancestor` is `taxon` itself or one of its ancestors.
+    pub fn in_subtree(&self, taxon: TaxonId, ancestor: TaxonId) -> bool {
+        if !self.in_tree(taxon) || !self.in_tree(ancestor) {
+            return false;
+        }
+
+        let mut taxon = taxon;
+        while self.depths[taxon] > self.depths[ancestor] {
+            taxon = self.parents[taxon];
+        }
+
+        taxon == ancestor
+    }
+
+    /// Joins the summaries of two sets of taxa using the specified aggregation method.
+    /// Folding `join` over the summaries of single taxa gives the same taxon as `aggregate`, in any order and grouping.
+    ///
+    /// # Arguments
+    ///
+    /// * `summary1` - The summary of the first set, taxa that are not in the taxonomy are ignored.
+    /// * `summary2` - The summary of the second set, taxa that are not in the taxonomy are ignored.
+    ///
+    /// # Returns
+    ///
+    /// Returns the summary of the union of both sets.
+    pub fn join(&self, summary1: TaxonSummary, summary2: TaxonSummary) -> TaxonSummary {
+        let (taxon1, taxon2) = (summary1.taxon(), summary2.taxon());
+        if !self.in_tree(taxon1) {
+            return if self.in_tree(taxon2) { summary2 } else { TaxonSummary::EMPTY };
+        }
+        if !self.in_tree(taxon2) {
+            return summary1;
+        }
+
+        let lca = self.lca(taxon1, taxon2);
+        if !self.lca_star {
+            return TaxonSummary::lineage(lca);
+        }
+
+        // the LCA* ignores the taxa that are an ancestor of another taxon in the set
+        match (summary1.is_fork(), summary2.is_fork()) {
+            (false, false) if lca == taxon1 => summary2,
+            (false, false) if lca == taxon2 => summary1,
+            (false, true) if lca == taxon1 => summary2,
+            (true, false) if lca == taxon2 => summary1,
+            _ => TaxonSummary::fork(lca)
+        }
+    }
+
     /// Aggregates a list of taxon IDs using the specified aggregation method.
     ///
     /// # Arguments
@@ -145,6 +489,40 @@ impl TaxonAggregator {
     }
 }
 
+/// Converts a taxonomy file to the binary form in which it is embedded in an index bundle.
+/// All integers are stored in little endian: the number of taxa (u64), followed by the id (u32),
+/// the parent (u32), the index of the rank in `Rank` (u8), the validity (u8), the length of the
+/// name (u32) and the name of every taxon.
+///
+/// # Arguments
+///
+/// * `file` - A string slice that represents the path to the taxonomy file.
+///
+/// # Returns
+///
+/// Returns a `Result` containing the binary form of the taxonomy.
+///
+/// # Errors
+///
+/// Returns a `Box<dyn Error>` if an error occurred while reading the taxonomy file, or if a taxon
+/// id does not fit in 32 bits.
+pub fn taxonomy_file_to_binary(file: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let taxons = read_taxa_file(file)?;
+    let to_u32 = |taxon: TaxonId| u32::try_from(taxon).map_err(|_| "A taxon id does not fit in 32 bits");
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&(taxons.len() as u64).to_le_bytes());
+    for taxon in taxons.iter() {
+        data.extend_from_slice(&to_u32(taxon.id)?.to_le_bytes());
+        data.extend_from_slice(&to_u32(taxon.parent)?.to_le_bytes());
+        data.push(taxon.rank.index() as u8);
+        data.push(taxon.valid as u8);
+        data.extend_from_slice(&(taxon.name.len() as u32).to_le_bytes());
+        data.extend_from_slice(taxon.name.as_bytes());
+    }
+    Ok(data)
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -199,6 +577,49 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn test_try_from_taxonomy_bytes() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_try_from_taxonomy_bytes").unwrap();
+
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+        let data = std::fs::read(taxonomy_file).unwrap();
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_bytes(
+            &data,
+            AggregationMethod::LcaStar
+        )
+        .unwrap();
+
+        assert!(taxon_aggregator.taxon_exists(14));
+        assert!(!taxon_aggregator.taxon_exists(15));
+        assert_eq!(taxon_aggregator.lca(13, 14), 10);
+    }
+
+    #[test]
+    fn test_try_from_taxonomy_binary() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_try_from_taxonomy_binary").unwrap();
+
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+        let data = taxonomy_file_to_binary(taxonomy_file.to_str().unwrap()).unwrap();
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_binary(
+            &data,
+            AggregationMethod::LcaStar
+        )
+        .unwrap();
+
+        assert!(taxon_aggregator.taxon_exists(14));
+        assert!(!taxon_aggregator.taxon_exists(15));
+        assert!(!taxon_aggregator.taxon_valid(21));
+        assert_eq!(taxon_aggregator.lca(13, 14), 10);
+        assert_eq!(taxon_aggregator.lca(20, 21), 19);
+
+        assert!(TaxonAggregator::try_from_taxonomy_binary(&data[.. data.len() - 1], AggregationMethod::LcaStar).is_err());
+        assert!(TaxonAggregator::try_from_taxonomy_binary(&[data.as_slice(), &[0]].concat(), AggregationMethod::LcaStar).is_err());
+    }
+
     #[test]
     fn test_taxon_exists() {
         // Create a temporary directory for this test
@@ -241,6 +662,81 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_lca() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_lca").unwrap();
+
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::LcaStar
+        )
+        .unwrap();
+
+        assert_eq!(taxon_aggregator.lca(7, 9), 6);
+        assert_eq!(taxon_aggregator.lca(13, 14), 10);
+        assert_eq!(taxon_aggregator.lca(17, 19), 17);
+        assert_eq!(taxon_aggregator.lca(20, 21), 19);
+        assert_eq!(taxon_aggregator.lca(2, 20), 1);
+        assert_eq!(taxon_aggregator.lca(0, 14), 14);
+        assert_eq!(taxon_aggregator.lca(14, 0), 14);
+        // taxa that are not in the taxonomy are ignored
+        assert_eq!(taxon_aggregator.lca(3, 14), 14);
+        assert_eq!(taxon_aggregator.lca(14, 1000), 14);
+        assert_eq!(taxon_aggregator.lca(1000, 3), 0);
+    }
+
+    #[test]
+    fn test_lca_missing_parent() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_lca_missing_parent").unwrap();
+
+        // the parent of taxon 4 is not in the taxonomy, so taxon 4 is the root of a separate tree
+        let taxonomy_file = tmp_dir.path().join("taxonomy.tsv");
+        let mut file = File::create(&taxonomy_file).unwrap();
+        writeln!(file, "1\troot\tno rank\t1\t\x01").unwrap();
+        writeln!(file, "2\tBacteria\tsuperkingdom\t1\t\x01").unwrap();
+        writeln!(file, "4\tArchaea\tsuperkingdom\t3\t\x01").unwrap();
+        writeln!(file, "5\tHalobacteria\tclass\t4\t\x01").unwrap();
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::LcaStar
+        )
+        .unwrap();
+
+        assert_eq!(taxon_aggregator.lca(2, 1), 1);
+        assert_eq!(taxon_aggregator.lca(4, 5), 4);
+        assert_eq!(taxon_aggregator.lca(2, 5), 0);
+        assert_eq!(taxon_aggregator.lca(1, 4), 0);
+    }
+
+    #[test]
+    fn test_in_subtree() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_in_subtree").unwrap();
+
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::LcaStar
+        )
+        .unwrap();
+
+        assert!(taxon_aggregator.in_subtree(7, 6));
+        assert!(taxon_aggregator.in_subtree(20, 10));
+        assert!(taxon_aggregator.in_subtree(14, 14));
+        assert!(taxon_aggregator.in_subtree(2, 1));
+        assert!(!taxon_aggregator.in_subtree(6, 7));
+        assert!(!taxon_aggregator.in_subtree(9, 10));
+        assert!(!taxon_aggregator.in_subtree(3, 1));
+        assert!(!taxon_aggregator.in_subtree(7, 3));
+        assert!(!taxon_aggregator.in_subtree(7, 100));
+    }
+
     #[test]
     fn test_aggregate_lca() {
         // Create a temporary directory for this test
@@ -276,4 +772,55 @@ mod tests {
         assert_eq!(taxon_aggregator.aggregate(vec![11, 14]), Some(10));
         assert_eq!(taxon_aggregator.aggregate(vec![17, 19]), Some(19));
     }
+
+    fn check_join(taxon_aggregator: &TaxonAggregator) {
+        let taxa = [1, 2, 6, 7, 9, 10, 11, 13, 14, 17, 19, 20];
+        for subset in 1_usize .. 1 << taxa.len() {
+            let set: Vec<TaxonId> = (0 .. taxa.len()).filter(|i| subset & (1 << i) != 0).map(|i| taxa[i]).collect();
+            let aggregated = taxon_aggregator.aggregate(set.clone());
+
+            // the summaries are joined from left to right, and in two halves
+            let summaries: Vec<TaxonSummary> = set.iter().map(|&taxon| TaxonSummary::lineage(taxon)).collect();
+            let fold = |summaries: &[TaxonSummary]| {
+                summaries.iter().fold(TaxonSummary::EMPTY, |current, &summary| taxon_aggregator.join(current, summary))
+            };
+            let (left, right) = summaries.split_at(summaries.len() / 2);
+            assert_eq!(Some(fold(&summaries).taxon()), aggregated, "{:?}", set);
+            assert_eq!(Some(taxon_aggregator.join(fold(right), fold(left)).taxon()), aggregated, "{:?}", set);
+        }
+    }
+
+    #[test]
+    fn test_join_lca() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_join").unwrap();
+
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::Lca
+        )
+        .unwrap();
+
+        check_join(&taxon_aggregator);
+    }
+
+    #[test]
+    fn test_join_lca_star() {
+        // Create a temporary directory for this test
+        let tmp_dir = TempDir::new("test_join").unwrap();
+
+        let taxonomy_file = create_taxonomy_file(&tmp_dir);
+
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            taxonomy_file.to_str().unwrap(),
+            AggregationMethod::LcaStar
+        )
+        .unwrap();
+
+        check_join(&taxon_aggregator);
+        assert_eq!(taxon_aggregator.join(TaxonSummary::lineage(17), TaxonSummary::lineage(19)).taxon(), 19);
+        assert_eq!(taxon_aggregator.join(TaxonSummary::lineage(3), TaxonSummary::EMPTY), TaxonSummary::EMPTY);
+    }
 }
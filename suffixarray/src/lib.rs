@@ -3,42 +3,72 @@ use std::num::NonZeroUsize;
 
 use clap::{arg, Parser, ValueEnum};
 
+use sa_mappings::filter::ProteinFilter;
 use sa_mappings::functionality::FunctionAggregator;
 use sa_mappings::proteins::Proteins;
 use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
-use suffixarray_builder::{build_sa, SAConstructionAlgorithm};
-use suffixarray_builder::binary::{load_suffix_array, write_suffix_array};
+use suffixarray_builder::{build_fm_index, build_sa, build_sa_with_lcp_lr, IndexType, SAConstructionAlgorithm};
+use suffixarray_builder::binary::{
+    load_fm_index, load_lcp_lr, load_suffix_array, load_taxon_index, map_suffix_array, map_taxon_index, read_protein_filter,
+    write_fm_index,
+    write_suffix_array, DatabaseFingerprint,
+};
+use suffixarray_builder::bundle::load_bundle;
+use suffixarray_builder::lcp_lr::LcpLr;
+use suffixarray_builder::suffix_array::{
+    required_bits_per_value, BitPackedSuffixArray, BuiltSuffixArray, SuffixArray, SuffixEntry,
+};
+use suffixarray_builder::taxon_lca_index::TaxonLcaIndex;
 
-use crate::peptide_search::{analyse_all_peptides, search_all_peptides};
+use crate::peptide_index::PeptideIndex;
+use crate::peptide_search::{analyse_all_peptides, analyse_all_peptides_batch, count_all_peptides, search_all_peptides};
 use crate::sa_searcher::Searcher;
+use crate::sampling::{Sampling, SamplingPolicy};
 use crate::suffix_to_protein_index::{
     DenseSuffixToProtein, SparseSuffixToProtein, SuffixToProteinIndex, SuffixToProteinMappingStyle,
 };
+use crate::suffix_tree_index::SuffixTreeIndex;
 use crate::util::{get_time_ms, read_lines};
 
+pub mod peptide_index;
 pub mod peptide_search;
+pub mod residue_equivalence;
 pub mod sa_searcher;
+pub mod sampling;
 pub mod suffix_to_protein_index;
+pub mod suffix_tree_index;
 pub mod util;
 
-/// Enum that represents the 2 kinds of search that are supported
+/// The suffix array (or FM-index) that is loaded or built, together with its LCP-LR arrays if they are used
+type LoadedIndex = (Box<dyn SuffixArray>, Option<LcpLr>);
+
+/// Enum that represents the 3 kinds of search that are supported
+/// - Search the matching proteins and retrieve their annotations
+/// - Search the matching proteins and perform the taxonomic and functional analyses
+/// - Only count the matches and the matching proteins, without retrieving them
 #[derive(ValueEnum, Clone, Debug, PartialEq)]
 pub enum SearchMode {
     Search,
     Analysis,
+    Count,
 }
 
 /// Enum that represents all possible commandline arguments
 #[derive(Parser, Debug)]
 pub struct Arguments {
     /// File with the proteins used to build the suffix tree. All the proteins are expected to be concatenated using a `#`.
-    #[arg(short, long)]
-    database_file: String,
+    /// Both tsv database files and UniProt FASTA files are accepted, the taxon of a protein in a FASTA file is read from the `OX=` field of its header.
+    #[arg(short, long, required_unless_present = "load_bundle")]
+    database_file: Option<String>,
+    /// Tsv file with the accession and the functional annotations of a protein on every line, used for the proteins of a FASTA database file
+    #[arg(long, requires = "database_file")]
+    annotations_file: Option<String>,
     #[arg(short, long)]
     search_file: Option<String>,
     #[arg(short, long)]
     /// The taxonomy to be used as a tsv file. This is a preprocessed version of the NCBI taxonomy.
-    taxonomy: String,
+    /// Only optional if the taxonomy is embedded in the loaded bundle
+    taxonomy: Option<String>,
     /// This will only build the tree and stop after that is completed. Used during benchmarking.
     #[arg(long)]
     build_only: bool,
@@ -48,6 +78,16 @@ pub struct Arguments {
     /// The sparseness factor used on the suffix array (default value 1, which means every value in the SA is used)
     #[arg(long, default_value_t = 1)]
     sparseness_factor: u8,
+    /// The number of bits used to store every entry of the built suffix array (default: the minimum number of bits needed for the input text, use 64 to store plain integers)
+    #[arg(long)]
+    bits_per_value: Option<u8>,
+    /// The kind of index that is built or loaded. The FM-index uses less memory than the suffix array, but is slower to search.
+    /// The suffix tree is always built from the database file and can not be stored.
+    #[arg(long, value_enum, default_value_t = IndexType::SuffixArray)]
+    index_type: IndexType,
+    /// The FM-index stores the suffix array value of every suffix starting at a multiple of this sample rate
+    #[arg(long, default_value_t = 32)]
+    fm_sample_rate: usize,
     /// Set the style used to map back from the suffix to the protein. 2 options <sparse> or <dense>. Dense is default
     /// Dense uses O(n) memory with n the size of the input text, and takes O(1) time to find the mapping
     /// Sparse uses O(m) memory with m the number of proteins, and takes O(log m) to find the mapping
@@ -55,11 +95,40 @@ pub struct Arguments {
     suffix_to_protein_mapping: SuffixToProteinMappingStyle,
     #[arg(long)]
     load_index: Option<String>,
+    /// Bundle file written by the builder, which contains the proteins and the suffix array, so the database file does not have to be read
+    #[arg(long, conflicts_with_all = ["database_file", "load_index"])]
+    load_bundle: Option<String>,
+    /// Memory map the loaded index instead of reading it into memory, for a bundle the proteins are memory mapped as well
+    #[arg(long)]
+    memory_map: bool,
+    /// Verify the checksums of a memory mapped index when it is loaded, which reads the complete index.
+    /// An index that is read into memory is always verified.
+    #[arg(long, requires = "memory_map")]
+    verify_checksums: bool,
+    /// File with the LCP-LR arrays of the loaded index, used to speed up the search
+    #[arg(long)]
+    load_lcp_lr: Option<String>,
+    /// Also build the LCP-LR arrays when the suffix array is built, used to speed up the search
+    #[arg(long)]
+    build_lcp_lr: bool,
+    /// File with the taxon index of the loaded index, used to aggregate the taxa of the matches without retrieving the proteins.
+    /// The taxon index is built when it is first used if no file is provided.
+    #[arg(long)]
+    load_taxon_index: Option<String>,
     #[arg(short, long, value_enum, default_value_t = SAConstructionAlgorithm::LibSais)]
     construction_algorithm: SAConstructionAlgorithm,
-    /// Assume the resulting taxon ID is root (1) whenever a peptide matches >= cutoff proteins
+    /// The maximum number of proteins retrieved per peptide. The LCA is still calculated over all the matches of the peptide.
+    /// A match of a deduplicated sequence counts once for every protein with that sequence
     #[arg(long, default_value_t = 10000)]
     cutoff: usize,
+    /// The policy used to select the retrieved proteins when a peptide matches more proteins than the cutoff.
+    /// First keeps the first proteins in the index, the other policies keep a random sample of all the matching proteins
+    #[arg(long, value_enum, default_value_t = SamplingPolicy::First)]
+    sampling_policy: SamplingPolicy,
+    /// The seed used by the random sampling policies
+    #[arg(long, default_value_t = 0)]
+    sampling_seed: u64,
+    /// The number of threads used to build the index and to search the peptides (default: all available cores)
     #[arg(long)]
     threads: Option<NonZeroUsize>,
     #[arg(long)]
@@ -67,9 +136,44 @@ pub struct Arguments {
     #[arg(long)]
     clean_taxa: bool,
     #[arg(long, value_enum, default_value_t = SearchMode::Analysis)]
-    search_mode: SearchMode
+    search_mode: SearchMode,
+    /// Sort and deduplicate the peptides before the analysis, so the search work for a prefix is shared by the peptides that start with it.
+    /// The results are still printed in the order of the search file
+    #[arg(long)]
+    batch_search: bool,
 }
 
+impl Arguments {
+
+    /// Returns the database file and the taxonomy file, which are both required if no bundle is loaded
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database file or the taxonomy file was not provided
+    fn database_files(&self) -> Result<(String, String), Box<dyn Error>> {
+        let database_file = self.database_file.clone().ok_or("No database file provided")?;
+        let taxonomy = self.taxonomy.clone().ok_or("No taxonomy file provided")?;
+        Ok((database_file, taxonomy))
+    }
+
+    /// Reads the proteins from the database file, with the functional annotations from the annotations file if one was provided
+    ///
+    /// # Arguments
+    /// * `taxon_id_calculator` - The taxonomy used to read the database file
+    /// * `filter` - The filters that select the proteins that are read
+    ///
+    /// # Returns
+    ///
+    /// Returns the proteins in the database file that pass the filters
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no database file was provided, if an annotations file is given for a tsv database file, or if the database file or the annotations file could not be read
+    fn read_proteins(&self, taxon_id_calculator: &TaxonAggregator, filter: &ProteinFilter) -> Result<Proteins, Box<dyn Error>> {
+        let (database_file, _) = self.database_files()?;
+        Proteins::try_from_database_file_with_annotations(&database_file, self.annotations_file.as_deref(), taxon_id_calculator, filter)
+    }
+}
 
 /// Run the suffix array program
 ///
@@ -84,41 +188,116 @@ pub struct Arguments {
 /// 
 /// Returns all possible errors that occurred during the program
 pub fn run(mut args: Arguments) -> Result<(), Box<dyn Error>> {
-    let taxon_id_calculator =
-        TaxonAggregator::try_from_taxonomy_file(&args.taxonomy, AggregationMethod::LcaStar)?;
+    let index: Box<dyn PeptideIndex> = if let Some(bundle_file_name) = args.load_bundle.clone() {
+        Box::new(create_searcher_from_bundle(&mut args, &bundle_file_name)?)
+    } else {
+        let (_, taxonomy) = args.database_files()?;
+        let taxon_id_calculator = TaxonAggregator::try_from_taxonomy_file(&taxonomy, AggregationMethod::LcaStar)?;
+        if args.index_type == IndexType::SuffixTree {
+            Box::new(create_suffix_tree_index(&args, taxon_id_calculator)?)
+        } else {
+            Box::new(create_searcher(&mut args, taxon_id_calculator)?)
+        }
+    };
+
+    // option that only builds the tree, but does not allow for querying (easy for benchmark purposes)
+    if args.build_only {
+        return Ok(());
+    }
+
+    execute_search(index.as_ref(), &args)?;
+    Ok(())
+}
+
+/// Creates the Searcher over the suffix array (or FM-index) that is loaded or built as set with the commandline arguments
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program, the sparseness factor is updated to the one of a loaded index
+/// * `taxon_id_calculator` - The taxonomy used by the searcher
+///
+/// # Returns
+///
+/// Returns the Searcher which contains the protein database
+///
+/// # Errors
+///
+/// Returns any error that occurred while loading or building the index
+fn create_searcher(args: &mut Arguments, taxon_id_calculator: TaxonAggregator) -> Result<Searcher, Box<dyn Error>> {
+    let (database_file, taxonomy) = args.database_files()?;
+    // a loaded suffix array only contains the proteins that pass the filters it was built with
+    let filter = match &args.load_index {
+        Some(index_file_name) if args.index_type != IndexType::FmIndex => read_protein_filter(index_file_name)?,
+        _ => ProteinFilter::new(),
+    };
+    let proteins = args.read_proteins(&taxon_id_calculator, &filter)?;
+    let database_fingerprint = DatabaseFingerprint::new(&proteins.input_string, &[&database_file, &taxonomy], &filter)?;
 
-    let sa = match &args.load_index {
+    let (sa, lcp_lr) = match args.load_index.clone() {
         // load SA from file
-        Some(index_file_name) => {
-            let (sparseness_factor, sa) = load_suffix_array(index_file_name)?;
-            args.sparseness_factor = sparseness_factor;
-            // println!("Loading the SA took {} ms and loading the proteins + SA took {} ms", end_loading_ms - start_loading_ms, end_loading_ms - start_reading_proteins_ms);
-            // TODO: some kind of security check that the loaded database file and SA match
-            sa
-        }
+        Some(index_file_name) => load_index(args, &index_file_name, &database_fingerprint)?,
         // build the SA
-        None => {
-            let protein_sequences =
-                Proteins::try_from_database_file(&args.database_file, &taxon_id_calculator)?;
-            build_sa(
-                &mut protein_sequences.input_string.clone(),
-                &args.construction_algorithm,
-                args.sparseness_factor,
-            )?
-        }
+        None => build_index(args, &proteins.input_string, &database_fingerprint)?,
     };
 
-    let proteins = Proteins::try_from_database_file(&args.database_file, &taxon_id_calculator)?;
+    let taxon_index = load_taxon_index_file(args, &database_fingerprint)?;
+    assemble_searcher(args, sa, lcp_lr, taxon_index, proteins, taxon_id_calculator)
+}
 
-    if let Some(output) = &args.output {
-        write_suffix_array(args.sparseness_factor, &sa, output)?;
+/// Creates the Searcher over the proteins and the suffix array stored in a bundle
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program, the sparseness factor is updated to the one of the bundle
+/// * `bundle_file_name` - The name of the bundle file
+///
+/// # Returns
+///
+/// Returns the Searcher which contains the protein database
+///
+/// # Errors
+///
+/// Returns any error that occurred while loading the bundle or the taxonomy
+fn create_searcher_from_bundle(args: &mut Arguments, bundle_file_name: &str) -> Result<Searcher, Box<dyn Error>> {
+    if args.index_type != IndexType::SuffixArray {
+        return Err("A bundle always contains a suffix array".into());
     }
 
-    // option that only builds the tree, but does not allow for querying (easy for benchmark purposes)
-    if args.build_only {
-        return Ok(());
-    }
+    let bundle = load_bundle(bundle_file_name, args.memory_map, args.verify_checksums)?;
+    let taxon_id_calculator = bundle.taxon_aggregator(args.taxonomy.as_deref(), AggregationMethod::LcaStar)?;
+    args.sparseness_factor = bundle.sparseness_factor;
+    let lcp_lr = match &args.load_lcp_lr {
+        Some(lcp_lr_file_name) => Some(load_lcp_lr(lcp_lr_file_name, bundle.sparseness_factor, &bundle.database)?),
+        None => None,
+    };
+
+    let taxon_index = load_taxon_index_file(args, &bundle.database)?;
+    assemble_searcher(args, bundle.suffix_array, lcp_lr, taxon_index, bundle.proteins, taxon_id_calculator)
+}
 
+/// Creates the Searcher over a loaded or built suffix array (or FM-index)
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program
+/// * `sa` - The suffix array (or FM-index) built over the proteins
+/// * `lcp_lr` - The LCP-LR arrays of the suffix array, if they are used
+/// * `taxon_index` - The taxon index of the suffix array, if it was loaded
+/// * `proteins` - List of all the proteins where the suffix array is build on
+/// * `taxon_id_calculator` - The taxonomy used by the searcher
+///
+/// # Returns
+///
+/// Returns the Searcher which contains the protein database
+///
+/// # Errors
+///
+/// Returns an error if the LCP-LR arrays or the taxon index do not belong to the suffix array
+fn assemble_searcher(
+    args: &Arguments,
+    sa: Box<dyn SuffixArray>,
+    lcp_lr: Option<LcpLr>,
+    taxon_index: Option<TaxonLcaIndex>,
+    proteins: Proteins,
+    taxon_id_calculator: TaxonAggregator,
+) -> Result<Searcher, Box<dyn Error>> {
     // build the right mapping index, use box to be able to store both types in this variable
     let suffix_index_to_protein: Box<dyn SuffixToProteinIndex> =
         match args.suffix_to_protein_mapping {
@@ -132,7 +311,7 @@ pub fn run(mut args: Arguments) -> Result<(), Box<dyn Error>> {
 
     let functional_aggregator = FunctionAggregator {};
 
-    let searcher = Searcher::new(
+    let mut searcher = Searcher::new(
         sa,
         args.sparseness_factor,
         suffix_index_to_protein,
@@ -140,15 +319,205 @@ pub fn run(mut args: Arguments) -> Result<(), Box<dyn Error>> {
         taxon_id_calculator,
         functional_aggregator,
     );
+    if let Some(lcp_lr) = lcp_lr {
+        searcher = searcher.with_lcp_lr(lcp_lr)?;
+    }
+    if let Some(taxon_index) = taxon_index {
+        searcher = searcher.with_taxon_lca_index(taxon_index)?;
+    }
+    Ok(searcher)
+}
 
-    execute_search(&searcher, &args)?;
-    Ok(())
+/// Loads the taxon index from the file provided in the arguments, it is memory mapped if the index is memory mapped
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program, with the sparseness factor of the loaded suffix array
+/// * `database_fingerprint` - The fingerprint of the database, which has to match the one stored in the taxon index file
+///
+/// # Returns
+///
+/// Returns the taxon index, or None if no file was provided
+///
+/// # Errors
+///
+/// Returns any error that occurred while loading the taxon index, or if it was built for another suffix array
+fn load_taxon_index_file(
+    args: &Arguments,
+    database_fingerprint: &DatabaseFingerprint,
+) -> Result<Option<TaxonLcaIndex>, Box<dyn Error>> {
+    let taxon_index = match &args.load_taxon_index {
+        Some(file_name) if args.memory_map => {
+            map_taxon_index(file_name, args.sparseness_factor, database_fingerprint, args.verify_checksums)?
+        }
+        Some(file_name) => load_taxon_index(file_name, args.sparseness_factor, database_fingerprint)?,
+        None => return Ok(None),
+    };
+    Ok(Some(taxon_index))
+}
+
+/// Builds the suffix tree over the proteins in the database file
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program
+/// * `taxon_id_calculator` - The taxonomy used by the index
+///
+/// # Returns
+///
+/// Returns the SuffixTreeIndex which contains the protein database
+///
+/// # Errors
+///
+/// Returns an error if the suffix tree had to be loaded from or stored in a file, or if the database file could not be read
+fn create_suffix_tree_index(args: &Arguments, taxon_id_calculator: TaxonAggregator) -> Result<SuffixTreeIndex, Box<dyn Error>> {
+    if args.load_index.is_some() || args.output.is_some() {
+        return Err("The suffix tree can not be loaded from or stored in a file".into());
+    }
+
+    let proteins = args.read_proteins(&taxon_id_calculator, &ProteinFilter::new())?;
+    Ok(SuffixTreeIndex::new(proteins, taxon_id_calculator, FunctionAggregator {}))
+}
+
+/// Loads the index from the file with the given name
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program, the sparseness factor is updated to the one of the loaded index
+/// * `index_file_name` - The name of the file where the index is stored
+/// * `database_fingerprint` - The fingerprint of the database, which has to match the one stored in the index files
+///
+/// # Returns
+///
+/// Returns the loaded suffix array (or FM-index), together with the LCP-LR arrays if they were provided
+///
+/// # Errors
+///
+/// Returns any error that occurred while loading the index, or if the suffix array was built on another database
+fn load_index(
+    args: &mut Arguments,
+    index_file_name: &str,
+    database_fingerprint: &DatabaseFingerprint,
+) -> Result<LoadedIndex, Box<dyn Error>> {
+    if args.index_type == IndexType::FmIndex {
+        // the FM-index always represents the complete suffix array
+        args.sparseness_factor = 1;
+        return Ok((Box::new(load_fm_index(index_file_name, database_fingerprint)?), None));
+    }
+
+    let (sparseness_factor, sa) = if args.memory_map {
+        let (sparseness_factor, sa) = map_suffix_array(index_file_name, database_fingerprint, args.verify_checksums)?;
+        (sparseness_factor, Box::new(sa) as Box<dyn SuffixArray>)
+    } else {
+        load_suffix_array(index_file_name, database_fingerprint)?
+    };
+    args.sparseness_factor = sparseness_factor;
+    let lcp_lr = match &args.load_lcp_lr {
+        Some(lcp_lr_file_name) => Some(load_lcp_lr(lcp_lr_file_name, sparseness_factor, database_fingerprint)?),
+        None => None,
+    };
+    Ok((sa, lcp_lr))
+}
+
+/// Builds the index over the text of the proteins that were read from the database file, and writes it to the output file if one was provided
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program
+/// * `text` - The text of the proteins, which is copied since it is translated during construction
+/// * `database_fingerprint` - The fingerprint of the database, which is stored in the index files
+///
+/// # Returns
+///
+/// Returns the built suffix array (or FM-index), together with the LCP-LR arrays if they had to be built
+///
+/// # Errors
+///
+/// Returns any error that occurred while building or writing the index
+fn build_index(
+    args: &Arguments,
+    text: &[u8],
+    database_fingerprint: &DatabaseFingerprint,
+) -> Result<LoadedIndex, Box<dyn Error>> {
+    // libsais uses all available cores if the number of threads is 0
+    let threads = args.threads.map_or(0, NonZeroUsize::get);
+
+    if args.index_type == IndexType::FmIndex {
+        // the FM-index always represents the complete suffix array
+        if args.sparseness_factor != 1 || args.build_lcp_lr {
+            return Err("The FM-index can not be built with a sparseness factor or LCP-LR arrays".into());
+        }
+        let fm_index = build_fm_index(
+            &mut text.to_vec(),
+            &args.construction_algorithm,
+            args.fm_sample_rate,
+            threads,
+        )?;
+        if let Some(output) = &args.output {
+            write_fm_index(&fm_index, database_fingerprint, output)?;
+        }
+        return Ok((Box::new(fm_index), None));
+    }
+
+    let (sa, lcp_lr) = if args.build_lcp_lr {
+        let (sa, lcp_lr) = build_sa_with_lcp_lr(
+            &mut text.to_vec(),
+            &args.construction_algorithm,
+            args.sparseness_factor,
+            threads,
+        )?;
+        (BuiltSuffixArray::Entries64(sa), Some(lcp_lr))
+    } else {
+        let sa = build_sa(
+            &mut text.to_vec(),
+            &args.construction_algorithm,
+            args.sparseness_factor,
+            threads,
+        )?;
+        (sa, None)
+    };
+
+    let bits_per_value = args
+        .bits_per_value
+        .unwrap_or_else(|| required_bits_per_value(text.len()));
+    let sa = match sa {
+        BuiltSuffixArray::Entries32(sa) => store_suffix_array(args, bits_per_value, database_fingerprint, sa)?,
+        BuiltSuffixArray::Entries64(sa) => store_suffix_array(args, bits_per_value, database_fingerprint, sa)?,
+    };
+    Ok((sa, lcp_lr))
+}
+
+/// Writes the built suffix array to the output file if one was provided, and stores it in the representation used during search
+///
+/// # Arguments
+/// * `args` - The arguments used to start the program
+/// * `bits_per_value` - The number of bits used to store every entry of the suffix array
+/// * `database_fingerprint` - The fingerprint of the database, which is stored in the suffix array file
+/// * `sa` - The built suffix array
+///
+/// # Returns
+///
+/// Returns the suffix array, bit-packed unless every entry is stored in 64 bits
+///
+/// # Errors
+///
+/// Returns any error that occurred while writing the suffix array, or if the entries do not fit in `bits_per_value` bits
+fn store_suffix_array<T: SuffixEntry>(
+    args: &Arguments,
+    bits_per_value: u8,
+    database_fingerprint: &DatabaseFingerprint,
+    sa: Vec<T>,
+) -> Result<Box<dyn SuffixArray>, Box<dyn Error>> {
+    if let Some(output) = &args.output {
+        write_suffix_array(args.sparseness_factor, bits_per_value, database_fingerprint, &sa, output)?;
+    }
+    if bits_per_value == 64 {
+        Ok(Box::new(sa))
+    } else {
+        Ok(Box::new(BitPackedSuffixArray::new(&sa, bits_per_value)?))
+    }
 }
 
 /// Execute the search using the provided programs
 ///
 /// # Arguments
-/// * `searcher` - The Searcher which contains the protein database
+/// * `searcher` - The index which contains the protein database
 /// * `args` - The arguments used to start the program
 ///
 /// # Returns
@@ -158,8 +527,12 @@ pub fn run(mut args: Arguments) -> Result<(), Box<dyn Error>> {
 /// # Errors
 ///
 /// Returns possible errors that occurred during search
-fn execute_search(searcher: &Searcher, args: &Arguments) -> Result<(), Box<dyn Error>> {
+fn execute_search(searcher: &dyn PeptideIndex, args: &Arguments) -> Result<(), Box<dyn Error>> {
     let cutoff = args.cutoff;
+    let sampling = Sampling {
+        policy: args.sampling_policy,
+        seed: args.sampling_seed,
+    };
     let search_file = args
         .search_file
         .as_ref()
@@ -181,21 +554,44 @@ fn execute_search(searcher: &Searcher, args: &Arguments) -> Result<(), Box<dyn E
             let search_result = search_all_peptides(
                 searcher,
                 &all_peptides,
+                &[],
                 cutoff,
                 args.equalize_i_and_l,
                 args.clean_taxa,
+                &sampling,
             );
             println!("{}", serde_json::to_string(&search_result)?);
         }
         SearchMode::Analysis => {
-            let search_result = analyse_all_peptides(
+            let search_result = if args.batch_search {
+                analyse_all_peptides_batch(
+                    searcher,
+                    &all_peptides,
+                    cutoff,
+                    args.equalize_i_and_l,
+                    args.clean_taxa,
+                    &sampling,
+                )
+            } else {
+                analyse_all_peptides(
+                    searcher,
+                    &all_peptides,
+                    cutoff,
+                    args.equalize_i_and_l,
+                    args.clean_taxa,
+                    &sampling,
+                )
+            };
+            println!("{}", serde_json::to_string(&search_result)?);
+        }
+        SearchMode::Count => {
+            let count_result = count_all_peptides(
                 searcher,
                 &all_peptides,
-                cutoff,
                 args.equalize_i_and_l,
                 args.clean_taxa,
             );
-            println!("{}", serde_json::to_string(&search_result)?);
+            println!("{}", serde_json::to_string(&count_result)?);
         }
     }
         
@@ -0,0 +1,210 @@
+use sa_mappings::functionality::{FunctionAggregator, FunctionalAggregation};
+use sa_mappings::proteins::{Protein, ProteinIter};
+use sa_mappings::taxonomy::TaxonAggregator;
+use serde::{Deserialize, Serialize};
+use umgap::taxon::TaxonId;
+
+use crate::sa_searcher::SearchAllSuffixesResult;
+
+/// Position in the matches of a peptide from where the iteration over the matches can be resumed
+/// The meaning of the fields depends on the index that returned the cursor, so a cursor can only be used with the same index and peptide
+///
+/// # Arguments
+/// * `skip` - The skip offset of a sparse suffix array where the iteration continues
+/// * `interval` - The index of the suffix array interval where the iteration continues
+/// * `position` - The position in the index where the iteration continues
+/// * `protein` - The number of proteins of the match at the cursor that were already returned, only set if the cutoff was reached
+///   within the proteins of a deduplicated sequence
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchCursor {
+    pub skip: usize,
+    pub interval: usize,
+    pub position: usize,
+    #[serde(default)]
+    pub protein: usize,
+}
+
+/// Lazy iterator over the start positions of the matches of a peptide in the text
+pub trait MatchIterator: Iterator<Item = i64> {
+
+    /// Returns the cursor from where the iteration continues
+    /// Passing this cursor to `PeptideIndex::matches` resumes the iteration with the next match
+    fn cursor(&self) -> MatchCursor;
+}
+
+/// Trait implemented by every index in which peptides can be searched
+/// The peptide search functions, the CLI and the server only use this trait, so they can run on any backend (suffix array, sparse suffix array, FM-index or suffix tree)
+pub trait PeptideIndex: Send + Sync {
+
+    /// Returns the length of the shortest peptide that can be searched in the index
+    /// A sparse suffix array can only find peptides that are at least as long as its sparseness factor
+    fn min_peptide_length(&self) -> usize;
+
+    /// Returns a lazy iterator over the start positions in the text of all occurrences of a peptide
+    /// During search I and L can be equated
+    /// The peptide can contain the ambiguity codes B (D or N), Z (E or Q), J (I or L) and X (any residue)
+    ///
+    /// # Arguments
+    /// * `peptide` - The peptide we are searching in the index
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    /// * `cursor` - The position from where the iteration starts, `MatchCursor::default()` to start at the first match
+    ///
+    /// # Returns
+    ///
+    /// Returns an iterator over the matches from `cursor` on
+    fn matches<'a>(&'a self, peptide: &[u8], equalize_i_and_l: bool, cursor: MatchCursor) -> Box<dyn MatchIterator + 'a>;
+
+    /// Returns lazy iterators over the matches of every peptide of a batch
+    /// Indexes can reuse the work for the prefix a peptide shares with the previous peptide, so the peptides should be sorted lexicographically
+    /// By default every peptide is searched independently
+    ///
+    /// # Arguments
+    /// * `peptides` - The peptides we are searching in the index, every peptide at least `min_peptide_length` long
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    ///
+    /// # Returns
+    ///
+    /// Returns an iterator over the matches of every peptide, in the order of `peptides`
+    fn matches_batch<'a>(&'a self, peptides: &[&[u8]], equalize_i_and_l: bool) -> Vec<Box<dyn MatchIterator + 'a>> {
+        peptides
+            .iter()
+            .map(|peptide| self.matches(peptide, equalize_i_and_l, MatchCursor::default()))
+            .collect()
+    }
+
+    /// Searches the start positions in the text of all occurrences of a peptide
+    ///
+    /// # Arguments
+    /// * `peptide` - The peptide we are searching in the index
+    /// * `max_matches` - The maximum amount of matches processed, if more matches are found we don't process them
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    ///
+    /// # Returns
+    ///
+    /// Returns all the matching suffixes
+    fn locate(&self, peptide: &[u8], max_matches: usize, equalize_i_and_l: bool) -> SearchAllSuffixesResult {
+        let matching_suffixes: Vec<i64> = self
+            .matches(peptide, equalize_i_and_l, MatchCursor::default())
+            .take(max_matches)
+            .collect();
+        SearchAllSuffixesResult::from_matches(matching_suffixes, max_matches)
+    }
+
+    /// Counts the number of occurrences of a peptide in the text
+    ///
+    /// # Arguments
+    /// * `peptide` - The peptide we are searching in the index
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of matching suffixes
+    fn count(&self, peptide: &[u8], equalize_i_and_l: bool) -> usize {
+        self.matches(peptide, equalize_i_and_l, MatchCursor::default()).count()
+    }
+
+    /// Counts the distinct proteins that contain an occurrence of a peptide
+    ///
+    /// # Arguments
+    /// * `peptide` - The peptide we are searching in the index
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    /// * `clean_taxa` - If set to true, only the proteins with a taxon which is stored as "valid" are counted
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of distinct matching proteins
+    fn count_proteins(&self, peptide: &[u8], equalize_i_and_l: bool, clean_taxa: bool) -> usize;
+
+    /// Returns all the proteins that correspond with the provided suffixes
+    ///
+    /// # Arguments
+    /// * `suffixes` - List of start positions in the text
+    ///
+    /// # Returns
+    ///
+    /// Returns the proteins that every suffix is a part of, suffixes that are not part of a protein are skipped
+    /// If the sequences are deduplicated, all proteins with the sequence of a suffix are returned
+    fn retrieve_proteins(&self, suffixes: &[i64]) -> Vec<Protein<'_>>;
+
+    /// Returns the proteins that correspond with a single match
+    ///
+    /// # Arguments
+    /// * `suffix` - The start position of the match in the text
+    ///
+    /// # Returns
+    ///
+    /// Returns the protein the match is a part of, or all proteins with its sequence if the sequences are deduplicated
+    /// Returns no proteins if the match is not part of a protein
+    fn match_proteins(&self, suffix: i64) -> ProteinIter<'_>;
+
+    /// Calculates the LCA of all the proteins that match the peptide, without retrieving these proteins
+    ///
+    /// # Arguments
+    /// * `peptide` - The peptide we are searching in the index
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    /// * `clean_taxa` - If set to true, only the taxa which are stored as "valid" are used
+    ///
+    /// # Returns
+    ///
+    /// Returns the taxonomic analysis result for all matches of the peptide, or None if there are no matches
+    fn search_lca(&self, peptide: &[u8], equalize_i_and_l: bool, clean_taxa: bool) -> Option<TaxonId>;
+
+    /// Returns the taxonomy used by the index
+    fn taxon_aggregator(&self) -> &TaxonAggregator;
+
+    /// Returns the object used to retrieve the functional annotations of the proteins in the index
+    fn function_aggregator(&self) -> &FunctionAggregator;
+
+    /// Retrieves the taxonomic analysis for a collection of proteins
+    ///
+    /// # Arguments
+    /// * `proteins` - A collection of proteins
+    ///
+    /// # Returns
+    ///
+    /// Returns the taxonomic analysis result for the given list of proteins
+    fn retrieve_lca(&self, proteins: &[Protein]) -> Option<TaxonId> {
+        let taxon_ids: Vec<TaxonId> = proteins.iter().map(|prot| prot.taxon_id).collect();
+
+        self.taxon_aggregator()
+            .aggregate(taxon_ids)
+            .map(|id| self.taxon_aggregator().snap_taxon(id))
+    }
+
+    /// Returns true if the protein is considered valid by the provided taxonomy file
+    ///
+    /// # Arguments
+    /// * `protein` - A protein of which we want to check the validity
+    ///
+    /// # Returns
+    ///
+    ///  Returns true if the protein is considered valid by the provided taxonomy file
+    fn taxon_valid(&self, protein: &Protein) -> bool {
+        self.taxon_aggregator().taxon_valid(protein.taxon_id)
+    }
+
+    /// Retrieves the functional analysis for a collection of proteins
+    ///
+    /// # Arguments
+    /// * `proteins` - A collection of proteins
+    ///
+    /// # Returns
+    ///
+    /// Returns the functional analysis result for the given list of proteins
+    fn retrieve_function(&self, proteins: &[Protein]) -> Option<FunctionalAggregation> {
+        let res = self.function_aggregator().aggregate(proteins.to_vec());
+        Some(res)
+    }
+
+    /// Retrieves the all the functional annotations for a collection of proteins
+    ///
+    /// # Arguments
+    /// * `proteins` - A collection of proteins
+    ///
+    /// # Returns
+    ///
+    /// Returns all functional annotations for a collection of proteins
+    fn get_all_functional_annotations(&self, proteins: &[Protein]) -> Vec<Vec<String>> {
+        self.function_aggregator().get_all_functional_annotations(proteins)
+    }
+}
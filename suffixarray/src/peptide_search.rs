@@ -1,8 +1,13 @@
-use crate::sa_searcher::{SearchAllSuffixesResult, Searcher};
+use crate::peptide_index::{MatchCursor, MatchIterator, PeptideIndex};
+use crate::sampling::{sample_proteins, Sampling, SamplingPolicy};
 use rayon::prelude::*;
 use sa_mappings::functionality::FunctionalAggregation;
 use sa_mappings::proteins::Protein;
 use serde::Serialize;
+use std::cmp::min;
+
+/// The number of sorted peptides that are searched incrementally by the same thread in a batch search
+const BATCH_CHUNK_SIZE: usize = 1024;
 
 /// Struct representing a collection of `SearchResultWithAnalysis` or `SearchOnlyResult` results
 #[derive(Debug, Serialize)]
@@ -11,7 +16,7 @@ pub struct OutputData<T: Serialize> {
 }
 
 /// Struct representing the search result of the `sequence` in the index, including the analyses
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SearchResultWithAnalysis {
     sequence: String,
     lca: Option<usize>,
@@ -22,11 +27,22 @@ pub struct SearchResultWithAnalysis {
 }
 
 /// Struct representing the search result of the `sequence` in the index (without the analyses)
+/// When the cutoff is used with the `first` sampling policy, `next_cursor` can be used to retrieve the next page of proteins
 #[derive(Debug, Serialize)]
 pub struct SearchOnlyResult {
     sequence: String,
     proteins: Vec<ProteinInfo>,
     cutoff_used: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<MatchCursor>,
+}
+
+/// Struct representing the number of matches of the `sequence` in the index
+#[derive(Debug, Serialize)]
+pub struct CountResult {
+    sequence: String,
+    count: usize,
+    protein_count: usize,
 }
 
 /// Struct that represents all information known about a certain protein in our database
@@ -38,54 +54,108 @@ pub struct ProteinInfo {
 }
 
 /// Searches the `peptide` in the index multithreaded and retrieves the matching proteins
+/// When the cutoff is reached, the proteins that are retained are selected with the sampling policy
 ///
 /// # Arguments
-/// * `searcher` - The Searcher which contains the protein database
+/// * `searcher` - The index which contains the protein database
 /// * `peptide` - The peptide that is being searched in the index
-/// * `cutoff` - The maximum amount of matches we want to process from the index
+/// * `cursor` - The position in the matches from where the search continues, `MatchCursor::default()` to start at the first match
+/// * `cutoff` - The maximum amount of proteins we want to retrieve from the index
 /// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
 /// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `sampling` - The policy used to select the retained proteins when the cutoff is reached
 ///
 /// # Returns
 ///
 /// Returns Some if matches are found.
-/// The first argument is true if the cutoff is used, otherwise false
-/// The second argument is a list of all matching proteins for the peptide
+/// The first argument is true if the cutoff was used
+/// The second argument is the cursor from where the next matches can be retrieved if the cutoff is used, otherwise None
+/// With a sampling policy other than `first` the sample is taken from all matching proteins from the cursor on, so no cursor is returned
+/// The third argument is a list of all matching proteins for the peptide
 /// Returns None if the peptides does not have any matches, or if the peptide is shorter than the sparseness factor k used in the index
 pub fn search_proteins_for_peptide<'a>(
-    searcher: &'a Searcher,
+    searcher: &'a dyn PeptideIndex,
     peptide: &str,
+    cursor: MatchCursor,
     cutoff: usize,
     equalize_i_and_l: bool,
     clean_taxa: bool,
-) -> Option<(bool, Vec<&'a Protein>)> {
+    sampling: &Sampling,
+) -> Option<(bool, Option<MatchCursor>, Vec<Protein<'a>>)> {
     let peptide = peptide.strip_suffix('\n').unwrap_or(peptide).to_uppercase();
 
     // words that are shorter than the sample rate are not searchable
-    if peptide.len() < searcher.sparseness_factor as usize {
+    if peptide.len() < searcher.min_peptide_length() {
         return None;
     }
 
-    let suffix_search =
-        searcher.search_matching_suffixes(peptide.as_bytes(), cutoff, equalize_i_and_l);
-    let mut cutoff_used = false;
-    let suffixes = match suffix_search {
-        SearchAllSuffixesResult::MaxMatches(matched_suffixes) => {
-            cutoff_used = true;
-            matched_suffixes
-        }
-        SearchAllSuffixesResult::SearchResult(matched_suffixes) => matched_suffixes,
-        SearchAllSuffixesResult::NoMatches => {
-            return None;
+    let matches = searcher.matches(peptide.as_bytes(), equalize_i_and_l, cursor);
+    retrieve_proteins_for_matches(searcher, &peptide, matches, cursor.protein, cutoff, equalize_i_and_l, clean_taxa, sampling)
+}
+
+/// Retrieves the matching proteins from the matches of a peptide in the index
+/// The cutoff counts the retrieved proteins, so a match of a deduplicated sequence counts once for every protein with that sequence
+///
+/// # Arguments
+/// * `searcher` - The index which contains the protein database
+/// * `search_string` - The peptide that is being searched in the index, without trailing newline and in uppercase
+/// * `matches` - The iterator over the matches of the peptide in the index
+/// * `skip_proteins` - The number of proteins of the first match that were already returned, see `MatchCursor`
+/// * `cutoff` - The maximum amount of proteins we want to retrieve from the index
+/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
+/// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `sampling` - The policy used to select the retained proteins when the cutoff is reached
+///
+/// # Returns
+///
+/// Returns the same as `search_proteins_for_peptide`
+#[allow(clippy::too_many_arguments)]
+fn retrieve_proteins_for_matches<'a>(
+    searcher: &'a dyn PeptideIndex,
+    search_string: &str,
+    mut matches: Box<dyn MatchIterator + 'a>,
+    mut skip_proteins: usize,
+    cutoff: usize,
+    equalize_i_and_l: bool,
+    clean_taxa: bool,
+    sampling: &Sampling,
+) -> Option<(bool, Option<MatchCursor>, Vec<Protein<'a>>)> {
+    // the sample is taken from the same proteins as the page that is retrieved
+    let start = MatchCursor { protein: skip_proteins, ..matches.cursor() };
+    let mut proteins: Vec<Protein> = vec![];
+    let mut next_cursor = None;
+    let mut has_matches = false;
+    loop {
+        let cursor = matches.cursor();
+        let Some(suffix) = matches.next() else { break };
+        has_matches = true;
+
+        let match_proteins = searcher.match_proteins(suffix);
+        let match_count = match_proteins.len();
+        let first = min(std::mem::take(&mut skip_proteins), match_count);
+        let taken = min(cutoff - proteins.len(), match_count - first);
+        proteins.extend(match_proteins.skip(first).take(taken));
+        // the cutoff is only used if there are proteins left after the retrieved ones
+        if first + taken < match_count {
+            next_cursor = Some(MatchCursor { protein: first + taken, ..cursor });
+            break;
         }
-    };
+    }
+    if !has_matches {
+        return None;
+    }
+    let cutoff_used = next_cursor.is_some();
+
+    if cutoff_used && sampling.policy != SamplingPolicy::First {
+        proteins = sample_proteins(searcher, search_string.as_bytes(), equalize_i_and_l, start, cutoff, sampling);
+        next_cursor = None;
+    }
 
-    let mut proteins = searcher.retrieve_proteins(&suffixes);
     if clean_taxa {
         proteins.retain(|protein| searcher.taxon_valid(protein))
     }
 
-    Some((cutoff_used, proteins))
+    Some((cutoff_used, next_cursor, proteins))
 }
 
 
@@ -93,25 +163,29 @@ pub fn search_proteins_for_peptide<'a>(
 /// This does NOT perform any of the analyses, it only retrieves the functional and taxonomic annotations
 ///
 /// # Arguments
-/// * `searcher` - The Searcher which contains the protein database
+/// * `searcher` - The index which contains the protein database
 /// * `peptide` - The peptide that is being searched in the index
-/// * `cutoff` - The maximum amount of matches we want to process from the index
+/// * `cursor` - The position in the matches from where the search continues, `MatchCursor::default()` to start at the first match
+/// * `cutoff` - The maximum amount of proteins we want to retrieve from the index
 /// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
 /// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `sampling` - The policy used to select the retained proteins when the cutoff is reached
 ///
 /// # Returns
 ///
 /// Returns Some(SearchOnlyResult) if the peptide has matches
 /// Returns None if the peptides does not have any matches, or if the peptide is shorter than the sparseness factor k used in the index
 pub fn search_peptide_retrieve_annotations(
-    searcher: &Searcher,
+    searcher: &dyn PeptideIndex,
     peptide: &str,
+    cursor: MatchCursor,
     cutoff: usize,
     equalize_i_and_l: bool,
     clean_taxa: bool,
+    sampling: &Sampling,
 ) -> Option<SearchOnlyResult> {
-    let (cutoff_used, proteins) =
-        search_proteins_for_peptide(searcher, peptide, cutoff, equalize_i_and_l, clean_taxa)?;
+    let (cutoff_used, next_cursor, proteins) =
+        search_proteins_for_peptide(searcher, peptide, cursor, cutoff, equalize_i_and_l, clean_taxa, sampling)?;
 
     let annotations = searcher.get_all_functional_annotations(&proteins);
 
@@ -119,7 +193,7 @@ pub fn search_peptide_retrieve_annotations(
     for (&protein, annotations) in proteins.iter().zip(annotations) {
         protein_info.push(ProteinInfo {
             taxon: protein.taxon_id,
-            uniprot_accession: protein.uniprot_id.clone(),
+            uniprot_accession: protein.uniprot_id.to_string(),
             functional_annotations: annotations,
         })
     }
@@ -128,6 +202,7 @@ pub fn search_peptide_retrieve_annotations(
         sequence: peptide.to_string(),
         proteins: protein_info,
         cutoff_used,
+        next_cursor,
     })
 }
 
@@ -135,33 +210,80 @@ pub fn search_peptide_retrieve_annotations(
 /// Searches the `peptide` in the index multithreaded and performs the taxonomic and functional analyses
 ///
 /// # Arguments
-/// * `searcher` - The Searcher which contains the protein database
+/// * `searcher` - The index which contains the protein database
 /// * `peptide` - The peptide that is being searched in the index
-/// * `cutoff` - The maximum amount of matches we want to process from the index
+/// * `cutoff` - The maximum amount of proteins we want to retrieve from the index
 /// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
 /// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `sampling` - The policy used to select the retained proteins when the cutoff is reached
 ///
 /// # Returns
 ///
 /// Returns Some(SearchResultWithAnalysis) if the peptide has matches
 /// Returns None if the peptides does not have any matches, or if the peptide is shorter than the sparseness factor k used in the index
 pub fn analyse_peptide(
-    searcher: &Searcher,
+    searcher: &dyn PeptideIndex,
     peptide: &str,
     cutoff: usize,
     equalize_i_and_l: bool,
     clean_taxa: bool,
+    sampling: &Sampling,
+) -> Option<SearchResultWithAnalysis> {
+    let search_string = peptide.strip_suffix('\n').unwrap_or(peptide).to_uppercase();
+
+    // words that are shorter than the sample rate are not searchable
+    if search_string.len() < searcher.min_peptide_length() {
+        return None;
+    }
+
+    let matches = searcher.matches(search_string.as_bytes(), equalize_i_and_l, MatchCursor::default());
+    let mut result = analyse_matches(searcher, &search_string, matches, cutoff, equalize_i_and_l, clean_taxa, sampling)?;
+    result.sequence = peptide.to_string();
+    Some(result)
+}
+
+/// Performs the taxonomic and functional analyses on the matches of a peptide in the index
+///
+/// # Arguments
+/// * `searcher` - The index which contains the protein database
+/// * `search_string` - The peptide that is being searched in the index, without trailing newline and in uppercase
+/// * `matches` - The iterator over the matches of the peptide in the index
+/// * `cutoff` - The maximum amount of proteins we want to retrieve from the index
+/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
+/// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `sampling` - The policy used to select the retained proteins when the cutoff is reached
+///
+/// # Returns
+///
+/// Returns Some(SearchResultWithAnalysis) with `search_string` as sequence if the peptide has matches
+/// Returns None if the peptides does not have any matches
+fn analyse_matches<'a>(
+    searcher: &'a dyn PeptideIndex,
+    search_string: &str,
+    matches: Box<dyn MatchIterator + 'a>,
+    cutoff: usize,
+    equalize_i_and_l: bool,
+    clean_taxa: bool,
+    sampling: &Sampling,
 ) -> Option<SearchResultWithAnalysis> {
-    let (cutoff_used, mut proteins) =
-        search_proteins_for_peptide(searcher, peptide, cutoff, equalize_i_and_l, clean_taxa)?;
+    let (cutoff_used, _, mut proteins) = retrieve_proteins_for_matches(
+        searcher,
+        search_string,
+        matches,
+        0,
+        cutoff,
+        equalize_i_and_l,
+        clean_taxa,
+        sampling,
+    )?;
 
     if clean_taxa {
         proteins.retain(|protein| searcher.taxon_valid(protein))
     }
 
-    // calculate the lca
+    // calculate the lca, when the cutoff is used the proteins are incomplete so the lca is calculated over all matches in the index
     let lca = if cutoff_used {
-        Some(1)
+        searcher.search_lca(search_string.as_bytes(), equalize_i_and_l, clean_taxa)
     } else {
         searcher.retrieve_lca(&proteins)
     };
@@ -174,13 +296,13 @@ pub fn analyse_peptide(
 
     for protein in &proteins {
         taxa.push(protein.taxon_id);
-        uniprot_accession_numbers.push(protein.uniprot_id.clone());
+        uniprot_accession_numbers.push(protein.uniprot_id.to_string());
     }
 
     let fa = searcher.retrieve_function(&proteins);
     // output the result
     Some(SearchResultWithAnalysis {
-        sequence: peptide.to_string(),
+        sequence: search_string.to_string(),
         lca,
         cutoff_used,
         uniprot_accession_numbers,
@@ -193,26 +315,28 @@ pub fn analyse_peptide(
 /// Searches the list of `peptides` in the index multithreaded and performs the functional and taxonomic analyses
 ///
 /// # Arguments
-/// * `searcher` - The Searcher which contains the protein database
+/// * `searcher` - The index which contains the protein database
 /// * `peptides` - List of peptides we want to search in the index
-/// * `cutoff` - The maximum amount of matches we want to process from the index
+/// * `cutoff` - The maximum amount of proteins we want to retrieve from the index
 /// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
 /// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `sampling` - The policy used to select the retained proteins when the cutoff is reached
 ///
 /// # Returns
 ///
 /// Returns an `OutputData<SearchResultWithAnalysis>` object with the search and analyses results for the peptides
 pub fn analyse_all_peptides(
-    searcher: &Searcher,
+    searcher: &dyn PeptideIndex,
     peptides: &Vec<String>,
     cutoff: usize,
     equalize_i_and_l: bool,
     clean_taxa: bool,
+    sampling: &Sampling,
 ) -> OutputData<SearchResultWithAnalysis> {
     let res: Vec<SearchResultWithAnalysis> = peptides
         .par_iter()
         // calculate the results
-        .map(|peptide| analyse_peptide(searcher, peptide, cutoff, equalize_i_and_l, clean_taxa))
+        .map(|peptide| analyse_peptide(searcher, peptide, cutoff, equalize_i_and_l, clean_taxa, sampling))
         // remove the None's
         .filter_map(|search_result| search_result)
         .collect();
@@ -220,33 +344,174 @@ pub fn analyse_all_peptides(
     OutputData { result: res }
 }
 
+/// Searches the list of `peptides` in the index and performs the functional and taxonomic analyses, reusing the search work between peptides that share a prefix
+/// The peptides are deduplicated and sorted lexicographically, after which chunks of `BATCH_CHUNK_SIZE` sorted peptides are searched incrementally in parallel
+///
+/// # Arguments
+/// * `searcher` - The index which contains the protein database
+/// * `peptides` - List of peptides we want to search in the index
+/// * `cutoff` - The maximum amount of proteins we want to retrieve from the index
+/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
+/// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `sampling` - The policy used to select the retained proteins when the cutoff is reached
+///
+/// # Returns
+///
+/// Returns an `OutputData<SearchResultWithAnalysis>` object with the same results as `analyse_all_peptides`, in the order of `peptides`
+pub fn analyse_all_peptides_batch(
+    searcher: &dyn PeptideIndex,
+    peptides: &[String],
+    cutoff: usize,
+    equalize_i_and_l: bool,
+    clean_taxa: bool,
+    sampling: &Sampling,
+) -> OutputData<SearchResultWithAnalysis> {
+    let search_strings: Vec<String> = peptides
+        .iter()
+        .map(|peptide| peptide.strip_suffix('\n').unwrap_or(peptide).to_uppercase())
+        .collect();
+
+    // words that are shorter than the sample rate are not searchable
+    let mut unique_search_strings: Vec<&str> = search_strings
+        .iter()
+        .map(String::as_str)
+        .filter(|search_string| search_string.len() >= searcher.min_peptide_length())
+        .collect();
+    unique_search_strings.sort_unstable();
+    unique_search_strings.dedup();
+
+    let unique_results: Vec<Option<SearchResultWithAnalysis>> = unique_search_strings
+        .par_chunks(BATCH_CHUNK_SIZE)
+        .flat_map_iter(|chunk| {
+            let chunk_search_strings: Vec<&[u8]> = chunk.iter().map(|search_string| search_string.as_bytes()).collect();
+            searcher
+                .matches_batch(&chunk_search_strings, equalize_i_and_l)
+                .into_iter()
+                .zip(chunk)
+                .map(|(matches, search_string)| {
+                    analyse_matches(searcher, search_string, matches, cutoff, equalize_i_and_l, clean_taxa, sampling)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    // restore the input order, duplicate peptides get a copy of the same result
+    let res: Vec<SearchResultWithAnalysis> = peptides
+        .iter()
+        .zip(&search_strings)
+        .filter_map(|(peptide, search_string)| {
+            let index = unique_search_strings.binary_search(&search_string.as_str()).ok()?;
+            let mut result = unique_results[index].clone()?;
+            result.sequence = peptide.to_string();
+            Some(result)
+        })
+        .collect();
+
+    OutputData { result: res }
+}
+
 /// Searches the list of `peptides` in the index and retrieves all related information about the found proteins
 /// This does NOT perform any of the analyses
 /// 
 /// # Arguments
-/// * `searcher` - The Searcher which contains the protein database
+/// * `searcher` - The index which contains the protein database
 /// * `peptides` - List of peptides we want to search in the index
-/// * `cutoff` - The maximum amount of matches we want to process from the index
+/// * `cursors` - The cursor from where the search continues for every peptide, or an empty list to start at the first match of every peptide
+/// * `cutoff` - The maximum amount of proteins we want to retrieve from the index
 /// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
 /// * `clean_taxa` - Boolean indicating if we want to filter out proteins that are invalid in the taxonomy
+/// * `sampling` - The policy used to select the retained proteins when the cutoff is reached
 ///
 /// # Returns
 ///
 /// Returns an `OutputData<SearchOnlyResult>` object with the search results for the peptides
 pub fn search_all_peptides(
-    searcher: &Searcher,
+    searcher: &dyn PeptideIndex,
     peptides: &Vec<String>,
+    cursors: &[MatchCursor],
     cutoff: usize,
     equalize_i_and_l: bool,
     clean_taxa: bool,
+    sampling: &Sampling,
 ) -> OutputData<SearchOnlyResult> {
     let res: Vec<SearchOnlyResult> = peptides
         .par_iter()
+        .enumerate()
         // calculate the results
-        .map(|peptide| search_peptide_retrieve_annotations(searcher, peptide, cutoff, equalize_i_and_l, clean_taxa))
+        .map(|(i, peptide)| {
+            let cursor = cursors.get(i).copied().unwrap_or_default();
+            search_peptide_retrieve_annotations(searcher, peptide, cursor, cutoff, equalize_i_and_l, clean_taxa, sampling)
+        })
         // remove None's
         .filter_map(|search_result| search_result)
         .collect();
 
     OutputData { result: res }
 }
+
+/// Counts the occurrences of the `peptide` in the index and the distinct proteins it occurs in, without retrieving the matches
+///
+/// # Arguments
+/// * `searcher` - The index which contains the protein database
+/// * `peptide` - The peptide that is being searched in the index
+/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
+/// * `clean_taxa` - Boolean indicating if we only want to count proteins that are valid in the taxonomy
+///
+/// # Returns
+///
+/// Returns Some(CountResult) with the exact number of occurrences, which is 0 if the peptide does not occur
+/// Returns None if the peptide is shorter than the sparseness factor k used in the index
+pub fn count_peptide(
+    searcher: &dyn PeptideIndex,
+    peptide: &str,
+    equalize_i_and_l: bool,
+    clean_taxa: bool,
+) -> Option<CountResult> {
+    let search_string = peptide.strip_suffix('\n').unwrap_or(peptide).to_uppercase();
+
+    // words that are shorter than the sample rate are not searchable
+    if search_string.len() < searcher.min_peptide_length() {
+        return None;
+    }
+
+    let count = searcher.count(search_string.as_bytes(), equalize_i_and_l);
+    let protein_count = if count == 0 {
+        0
+    } else {
+        searcher.count_proteins(search_string.as_bytes(), equalize_i_and_l, clean_taxa)
+    };
+
+    Some(CountResult {
+        sequence: peptide.to_string(),
+        count,
+        protein_count,
+    })
+}
+
+/// Counts the occurrences of the list of `peptides` in the index multithreaded
+///
+/// # Arguments
+/// * `searcher` - The index which contains the protein database
+/// * `peptides` - List of peptides we want to count in the index
+/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
+/// * `clean_taxa` - Boolean indicating if we only want to count proteins that are valid in the taxonomy
+///
+/// # Returns
+///
+/// Returns an `OutputData<CountResult>` object with the counts for the peptides
+pub fn count_all_peptides(
+    searcher: &dyn PeptideIndex,
+    peptides: &Vec<String>,
+    equalize_i_and_l: bool,
+    clean_taxa: bool,
+) -> OutputData<CountResult> {
+    let res: Vec<CountResult> = peptides
+        .par_iter()
+        // calculate the counts
+        .map(|peptide| count_peptide(searcher, peptide, equalize_i_and_l, clean_taxa))
+        // remove the None's
+        .filter_map(|count_result| count_result)
+        .collect();
+
+    OutputData { result: res }
+}
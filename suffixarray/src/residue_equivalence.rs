@@ -0,0 +1,144 @@
+use sa_mappings::proteins::{SEPARATION_CHARACTER, TERMINATION_CHARACTER};
+
+/// Returns the character that represents `text_character` in the suffix array
+/// The suffix array is built with I == L, so every L in the text is sorted as if it were an I
+///
+/// # Arguments
+/// * `text_character` - A character from the protein text
+///
+/// # Returns
+///
+/// Returns I if `text_character` is L, otherwise `text_character` itself
+#[inline]
+pub fn fold_text_character(text_character: u8) -> u8 {
+    if text_character == b'L' {
+        b'I'
+    } else {
+        text_character
+    }
+}
+
+/// Returns the character that represents `query_character` in the suffix array
+/// Next to L, also the ambiguity code J (I or L) is sorted as an I
+///
+/// # Arguments
+/// * `query_character` - A character from the searched peptide
+///
+/// # Returns
+///
+/// Returns I if `query_character` is L or J, otherwise `query_character` itself
+#[inline]
+pub fn fold_query_character(query_character: u8) -> u8 {
+    if query_character == b'L' || query_character == b'J' {
+        b'I'
+    } else {
+        query_character
+    }
+}
+
+/// Returns true if `query_character` is an ambiguity code that matches residues which are not adjacent in the suffix array
+/// Peptides containing such a code can not be found with a single binary search
+///
+/// # Arguments
+/// * `query_character` - A character from the searched peptide
+///
+/// # Returns
+///
+/// Returns true if `query_character` is B (D or N), Z (E or Q) or X (any residue)
+#[inline]
+pub fn is_ambiguous(query_character: u8) -> bool {
+    matches!(query_character, b'B' | b'Z' | b'X')
+}
+
+/// Returns true if `query_character` matches `text_character`
+/// The ambiguity codes B (D or N), Z (E or Q), J (I or L) and X (any residue) are supported in the query
+/// A separation or termination character in the text is never matched by an ambiguity code
+///
+/// # Arguments
+/// * `query_character` - A character from the searched peptide
+/// * `text_character` - A character from the protein text
+/// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+///
+/// # Returns
+///
+/// Returns true if `query_character` and `text_character` are considered the same residue
+#[inline]
+pub fn residue_matches(query_character: u8, text_character: u8, equalize_i_and_l: bool) -> bool {
+    if query_character == text_character {
+        return true;
+    }
+
+    match query_character {
+        b'B' => text_character == b'D' || text_character == b'N',
+        b'Z' => text_character == b'E' || text_character == b'Q',
+        b'J' => text_character == b'I' || text_character == b'L',
+        b'X' => text_character != SEPARATION_CHARACTER && text_character != TERMINATION_CHARACTER,
+        b'I' => equalize_i_and_l && text_character == b'L',
+        b'L' => equalize_i_and_l && text_character == b'I',
+        _ => false,
+    }
+}
+
+/// Returns true if `query_character` can match a suffix of which the next character in the suffix array is `folded_text_character`
+/// Since I and L are equal in the suffix array, this check is less strict than `residue_matches` for I and L
+///
+/// # Arguments
+/// * `query_character` - A character from the searched peptide
+/// * `folded_text_character` - A character from the protein text, folded with `fold_text_character`
+///
+/// # Returns
+///
+/// Returns true if a suffix starting with `folded_text_character` can match `query_character`
+#[inline]
+pub fn residue_matches_folded(query_character: u8, folded_text_character: u8) -> bool {
+    fold_query_character(query_character) == folded_text_character
+        || residue_matches(query_character, folded_text_character, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::residue_equivalence::{
+        fold_query_character, fold_text_character, is_ambiguous, residue_matches,
+        residue_matches_folded,
+    };
+
+    #[test]
+    fn test_fold() {
+        assert_eq!(fold_text_character(b'L'), b'I');
+        assert_eq!(fold_text_character(b'J'), b'J');
+        assert_eq!(fold_query_character(b'J'), b'I');
+        assert_eq!(fold_query_character(b'A'), b'A');
+    }
+
+    #[test]
+    fn test_is_ambiguous() {
+        assert!(is_ambiguous(b'B'));
+        assert!(is_ambiguous(b'Z'));
+        assert!(is_ambiguous(b'X'));
+        assert!(!is_ambiguous(b'J'));
+        assert!(!is_ambiguous(b'A'));
+    }
+
+    #[test]
+    fn test_residue_matches() {
+        assert!(residue_matches(b'B', b'D', false));
+        assert!(residue_matches(b'B', b'N', false));
+        assert!(!residue_matches(b'B', b'E', false));
+        assert!(residue_matches(b'Z', b'Q', false));
+        assert!(residue_matches(b'J', b'L', false));
+        assert!(residue_matches(b'X', b'W', false));
+        assert!(!residue_matches(b'X', b'-', false));
+        assert!(!residue_matches(b'X', b'$', false));
+        assert!(!residue_matches(b'I', b'L', false));
+        assert!(residue_matches(b'I', b'L', true));
+        assert!(!residue_matches(b'D', b'B', true));
+    }
+
+    #[test]
+    fn test_residue_matches_folded() {
+        assert!(residue_matches_folded(b'L', b'I'));
+        assert!(residue_matches_folded(b'J', b'I'));
+        assert!(residue_matches_folded(b'B', b'N'));
+        assert!(!residue_matches_folded(b'A', b'I'));
+    }
+}
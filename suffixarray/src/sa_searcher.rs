@@ -1,11 +1,22 @@
 use std::cmp::min;
+use std::collections::HashSet;
+use std::error::Error;
+use std::sync::OnceLock;
 
 
-use sa_mappings::functionality::{FunctionAggregator, FunctionalAggregation};
-use sa_mappings::proteins::{Protein, Proteins};
-use sa_mappings::taxonomy::TaxonAggregator;
+use sa_mappings::functionality::FunctionAggregator;
+use sa_mappings::proteins::{Protein, ProteinIter, Proteins, SEPARATION_CHARACTER, TERMINATION_CHARACTER};
+use sa_mappings::taxonomy::{TaxonAggregator, TaxonSummary};
+use suffixarray_builder::lcp_lr::{LcpLr, LCP_LR_CAP};
+use suffixarray_builder::suffix_array::SuffixArray;
+use suffixarray_builder::taxon_lca_index::TaxonLcaIndex;
 use umgap::taxon::TaxonId;
 
+use crate::peptide_index::{MatchCursor, MatchIterator, PeptideIndex};
+use crate::residue_equivalence::{
+    fold_query_character, fold_text_character, is_ambiguous, residue_matches,
+    residue_matches_folded,
+};
 use crate::sa_searcher::BoundSearch::{Maximum, Minimum};
 use crate::suffix_to_protein_index::SuffixToProteinIndex;
 use crate::Nullable;
@@ -33,6 +44,27 @@ pub enum SearchAllSuffixesResult {
     SearchResult(Vec<i64>),
 }
 
+impl SearchAllSuffixesResult {
+    /// Creates the result of a search from the found matches
+    ///
+    /// # Arguments
+    /// * `matching_suffixes` - The found matches, at most `max_matches`
+    /// * `max_matches` - The maximum amount of matches that was processed
+    ///
+    /// # Returns
+    ///
+    /// Returns `MaxMatches` if the maximum amount of matches is reached, `NoMatches` if nothing was found, otherwise `SearchResult`
+    pub(crate) fn from_matches(matching_suffixes: Vec<i64>, max_matches: usize) -> Self {
+        if matching_suffixes.is_empty() {
+            SearchAllSuffixesResult::NoMatches
+        } else if matching_suffixes.len() >= max_matches {
+            SearchAllSuffixesResult::MaxMatches(matching_suffixes)
+        } else {
+            SearchAllSuffixesResult::SearchResult(matching_suffixes)
+        }
+    }
+}
+
 /// Custom implementation of partialEq for SearchAllSuffixesResult
 /// We consider 2 SearchAllSuffixesResult equal if they exist of the same key, and the Vec contains the same values, but the order can be different
 impl PartialEq for SearchAllSuffixesResult {
@@ -72,6 +104,27 @@ impl PartialEq for SearchAllSuffixesResult {
     }
 }
 
+/// Struct representing a suffix that matches the searched peptide with a limited number of mismatches
+///
+/// # Arguments
+/// * `suffix` - The start of the match in the text
+/// * `mismatches` - The number of positions where the match differs from the searched peptide (Hamming distance),
+///   or the number of substitutions, insertions and deletions (edit distance) when searching with edits
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ApproximateMatch {
+    pub suffix: i64,
+    pub mismatches: usize,
+}
+
+/// Enum representing the matching suffixes after searching a peptide with mismatches in the suffix array
+/// Both the MaxMatches and SearchResult indicate found suffixes, but MaxMatches is used when the cutoff is reached.
+#[derive(Debug, PartialEq)]
+pub enum SearchAllApproximateSuffixesResult {
+    NoMatches,
+    MaxMatches(Vec<ApproximateMatch>),
+    SearchResult(Vec<ApproximateMatch>),
+}
+
 /// Struct that contains all the elements needed to search a peptide in the suffix array
 /// This struct also contains all the functions used for search
 ///
@@ -81,13 +134,17 @@ impl PartialEq for SearchAllSuffixesResult {
 /// * `suffix_index_to_protein` - Mapping from a suffix to the proteins to know which a suffix is part of
 /// * `taxon_id_calculator` - Object representing the used taxonomy and that calculates the taxonomic analysis provided by Unipept
 /// * `function_aggregator` - Object used to retrieve the functional annotations and to calculate the functional analysis provided by Unipept
+/// * `taxon_lca_index` - Index used to calculate the LCA of an interval in the suffix array without retrieving the proteins, built when it is first used if it was not loaded
+/// * `lcp_lr` - Optional LCP-LR arrays of the suffix array, used to speed up the binary search
 pub struct Searcher {
-    sa: Vec<i64>,
+    sa: Box<dyn SuffixArray>,
     pub sparseness_factor: u8,
     suffix_index_to_protein: Box<dyn SuffixToProteinIndex>,
     proteins: Proteins,
     taxon_id_calculator: TaxonAggregator,
-    function_aggregator: FunctionAggregator
+    function_aggregator: FunctionAggregator,
+    taxon_lca_index: OnceLock<TaxonLcaIndex>,
+    lcp_lr: Option<LcpLr>
 }
 
 impl Searcher {
@@ -106,7 +163,7 @@ impl Searcher {
     ///
     /// Returns a new Searcher object
     pub fn new(
-        sa: Vec<i64>,
+        sa: Box<dyn SuffixArray>,
         sparseness_factor: u8,
         suffix_index_to_protein: Box<dyn SuffixToProteinIndex>,
         proteins: Proteins,
@@ -119,12 +176,65 @@ impl Searcher {
             suffix_index_to_protein,
             proteins,
             taxon_id_calculator,
-            function_aggregator
+            function_aggregator,
+            taxon_lca_index: OnceLock::new(),
+            lcp_lr: None
+        }
+    }
+
+    /// Adds the LCP-LR arrays of the suffix array to the Searcher, which are used to speed up the binary search
+    ///
+    /// # Arguments
+    /// * `lcp_lr` - The LCP-LR arrays built for the suffix array of this Searcher
+    ///
+    /// # Returns
+    ///
+    /// Returns the Searcher that uses the LCP-LR arrays during search
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the LCP-LR arrays were not built for a suffix array of the same size
+    pub fn with_lcp_lr(mut self, lcp_lr: LcpLr) -> Result<Self, Box<dyn Error>> {
+        if lcp_lr.len() != self.sa.len() || lcp_lr.right.len() != self.sa.len() {
+            return Err("The LCP-LR arrays were not built for this suffix array".into());
+        }
+        self.lcp_lr = Some(lcp_lr);
+        Ok(self)
+    }
+
+    /// Adds the taxon LCA index of the suffix array to the Searcher, so it does not have to be built when it is first used
+    ///
+    /// # Arguments
+    /// * `taxon_lca_index` - The taxon LCA index built for the suffix array of this Searcher
+    ///
+    /// # Returns
+    ///
+    /// Returns the Searcher that uses the taxon LCA index to aggregate the taxa of the matches
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index was not built for a suffix array of the same size, or with another aggregation method
+    pub fn with_taxon_lca_index(self, taxon_lca_index: TaxonLcaIndex) -> Result<Self, Box<dyn Error>> {
+        if taxon_lca_index.len() != self.sa.len() {
+            return Err("The taxon index was not built for this suffix array".into());
+        }
+        if taxon_lca_index.is_lca_star() != self.taxon_id_calculator.is_lca_star() {
+            return Err("The taxon index was built with another aggregation method than the one used by the taxonomy".into());
         }
+        // the index was not used yet, so it can not have been built
+        let _ = self.taxon_lca_index.set(taxon_lca_index);
+        Ok(self)
+    }
+
+    /// Returns the taxon LCA index of the suffix array, which is built over the proteins if it was not loaded
+    fn taxon_lca_index(&self) -> &TaxonLcaIndex {
+        self.taxon_lca_index
+            .get_or_init(|| TaxonLcaIndex::new(self.sa.as_ref(), &self.proteins, &self.taxon_id_calculator))
     }
     
     /// Compares the `search_string` to the `suffix`
     /// During search this function performs extra logic since the suffix array is build with I == L, while ` self.proteins.input_string` is the original text where I != L
+    /// The ambiguity code J (I or L) is handled in the same way, the other ambiguity codes can not be used in a binary search
     ///
     /// # Arguments
     /// * `search_string` - The string/peptide being searched in the suffix array
@@ -156,12 +266,8 @@ impl Searcher {
         // match as long as possible
         while index_in_search_string < search_string.len()
             && index_in_suffix < self.proteins.input_string.len()
-            && (search_string[index_in_search_string]
-                == self.proteins.input_string[index_in_suffix]
-                || (search_string[index_in_search_string] == b'L'
-                    && self.proteins.input_string[index_in_suffix] == b'I')
-                || (search_string[index_in_search_string] == b'I'
-                    && self.proteins.input_string[index_in_suffix] == b'L'))
+            && fold_query_character(search_string[index_in_search_string])
+                == fold_text_character(self.proteins.input_string[index_in_suffix])
         {
             index_in_suffix += 1;
             index_in_search_string += 1;
@@ -172,17 +278,8 @@ impl Searcher {
                 is_cond_or_equal = true
             } else if index_in_suffix < self.proteins.input_string.len() {
                 // in our index every L was replaced by a I, so we need to replace them if we want to search in the right direction
-                let peptide_char = if search_string[index_in_search_string] == b'L' {
-                    b'I'
-                } else {
-                    search_string[index_in_search_string]
-                };
-
-                let protein_char = if self.proteins.input_string[index_in_suffix] == b'L' {
-                    b'I'
-                } else {
-                    self.proteins.input_string[index_in_suffix]
-                };
+                let peptide_char = fold_query_character(search_string[index_in_search_string]);
+                let protein_char = fold_text_character(self.proteins.input_string[index_in_suffix]);
 
                 is_cond_or_equal = condition_check(peptide_char, protein_char);
             }
@@ -191,6 +288,59 @@ impl Searcher {
         (is_cond_or_equal, index_in_search_string)
     }
     
+    /// Compares the `search_string` to the suffix at `center` in the suffix array, using the LCP-LR arrays to avoid comparing characters that are already known
+    /// This is the binary search of Manber and Myers, which compares every character of the `search_string` at most once during the whole search
+    ///
+    /// If the `search_string` shares more characters with the left bound than with the right bound, the longest common prefix of the left bound and `center` decides:
+    /// if it is larger, `center` compares the same as the left bound; if it is smaller, `center` compares the same as the right bound; only if it is equal the characters have to be compared
+    /// The other case is symmetrical, using the longest common prefix of `center` and the right bound
+    ///
+    /// # Arguments
+    /// * `lcp_lr` - The LCP-LR arrays of the suffix array
+    /// * `search_string` - The string/peptide being searched in the suffix array
+    /// * `center` - The index in the suffix array we are comparing with in the binary search
+    /// * `lcp_left` - How far the `search_string` matched the suffix at the left bound of the search window
+    /// * `lcp_right` - How far the `search_string` matched the suffix at the right bound of the search window
+    /// * `bound` - Indicates if we are searching for the min of max bound
+    ///
+    /// # Returns
+    ///
+    /// The same result as `compare` for the suffix at `center`
+    fn compare_with_lcp_lr(
+        &self,
+        lcp_lr: &LcpLr,
+        search_string: &[u8],
+        center: usize,
+        lcp_left: usize,
+        lcp_right: usize,
+        bound: BoundSearch,
+    ) -> (bool, usize) {
+        // the result of `compare` that moves the left or the right bound to the center
+        let same_as_left = |lcp_center| (bound == Maximum, lcp_center);
+        let same_as_right = |lcp_center| (bound == Minimum, lcp_center);
+
+        if lcp_left >= lcp_right {
+            let lcp_left_center = lcp_lr.left[center] as usize;
+            if lcp_left_center > lcp_left {
+                return same_as_left(lcp_left);
+            }
+            // a capped value is only a lower bound, so it can not be used to decide
+            if lcp_left_center < lcp_left && lcp_left_center < LCP_LR_CAP {
+                return same_as_right(lcp_left_center);
+            }
+            self.compare(search_string, self.sa.get(center), lcp_left_center, bound)
+        } else {
+            let lcp_center_right = lcp_lr.right[center] as usize;
+            if lcp_center_right > lcp_right {
+                return same_as_right(lcp_right);
+            }
+            if lcp_center_right < lcp_right && lcp_center_right < LCP_LR_CAP {
+                return same_as_left(lcp_center_right);
+            }
+            self.compare(search_string, self.sa.get(center), lcp_center_right, bound)
+        }
+    }
+
     /// Searches for the minimum or maximum bound for a string in the suffix array
     ///
     /// # Arguments
@@ -211,8 +361,16 @@ impl Searcher {
         // repeat until search window is minimum size OR we matched the whole search string last iteration
         while right - left > 1 {
             let center = (left + right) / 2;
-            let skip = min(lcp_left, lcp_right);
-            let (retval, lcp_center) = self.compare(search_string, self.sa[center], skip, bound);
+            let (retval, lcp_center) = match &self.lcp_lr {
+                // the initial bounds are never compared, so the LCP-LR values can only be used once a bound matched a character
+                Some(lcp_lr) if !search_string.is_empty() && (lcp_left > 0 || lcp_right > 0) => {
+                    self.compare_with_lcp_lr(lcp_lr, search_string, center, lcp_left, lcp_right, bound)
+                }
+                _ => {
+                    let skip = min(lcp_left, lcp_right);
+                    self.compare(search_string, self.sa.get(center), skip, bound)
+                }
+            };
 
             found |= lcp_center == search_string.len();
 
@@ -229,7 +387,7 @@ impl Searcher {
         // handle edge case to search at index 0
         if right == 1 && left == 0 {
             let (retval, lcp_center) =
-                self.compare(search_string, self.sa[0], min(lcp_left, lcp_right), bound);
+                self.compare(search_string, self.sa.get(0), min(lcp_left, lcp_right), bound);
 
             found |= lcp_center == search_string.len();
 
@@ -253,6 +411,16 @@ impl Searcher {
     ///
     /// Returns the minimum and maximum bound of all matches in the suffix array, or `NoMatches` if no matches were found
     pub fn search_bounds(&self, search_string: &[u8]) -> BoundSearchResult {
+        // some representations of the suffix array (like the FM-index) can find the bounds without a binary search
+        let folded_search_string: Vec<u8> = search_string.iter().map(|&character| fold_query_character(character)).collect();
+        if let Some((min_bound, max_bound)) = self.sa.backward_search(&folded_search_string) {
+            return if min_bound < max_bound {
+                BoundSearchResult::SearchResult((min_bound, max_bound))
+            } else {
+                BoundSearchResult::NoMatches
+            };
+        }
+
         let (found_min, min_bound) = self.binary_search_bound(Minimum, search_string);
 
         if !found_min {
@@ -266,6 +434,7 @@ impl Searcher {
 
     /// Searches for the suffixes matching a search string
     /// During search I and L can be equated
+    /// The search string can contain the ambiguity codes B (D or N), Z (E or Q), J (I or L) and X (any residue)
     ///
     /// # Arguments
     /// * `search_string` - The string/peptide we are searching in the suffix array
@@ -275,312 +444,1168 @@ impl Searcher {
     /// # Returns
     ///
     /// Returns all the matching suffixes
-    #[inline]
     pub fn search_matching_suffixes(
         &self,
         search_string: &[u8],
         max_matches: usize,
         equalize_i_and_l: bool,
     ) -> SearchAllSuffixesResult {
-        let mut matching_suffixes: Vec<i64> = vec![];
-        let mut il_locations = vec![];
-        for (i, &character) in search_string.iter().enumerate() {
-            if character == b'I' || character == b'L' {
-                il_locations.push(i);
-            }
-        }
-
-        let mut skip: usize = 0;
-        while skip < self.sparseness_factor as usize {
-            let mut il_locations_start = 0;
-            while il_locations_start < il_locations.len() && il_locations[il_locations_start] < skip {
-                il_locations_start += 1;
-            }
-            let il_locations_current_suffix = &il_locations[il_locations_start..];
-            let current_search_string_prefix = &search_string[..skip];
-            let current_search_string_suffix = &search_string[skip..];
-            let search_bound_result = self.search_bounds(&search_string[skip..]);
-            // if the shorter part is matched, see if what goes before the matched suffix matches the unmatched part of the prefix
-            if let BoundSearchResult::SearchResult((min_bound, max_bound)) = search_bound_result {
-                // try all the partially matched suffixes and store the matching suffixes in an array (stop when our max number of matches is reached)
-                let mut sa_index = min_bound;
-                while sa_index < max_bound {
-                    let suffix = self.sa[sa_index] as usize;
-                    // filter away matches where I was wrongfully equalized to L, and check the unmatched prefix
-                    // when I and L equalized, we only need to check the prefix, not the whole match, when the prefix is 0, we don't need to check at all
-                    if suffix >= skip
-                        && ((skip == 0
-                            || Self::check_prefix(
-                        current_search_string_prefix,
-                                &self.proteins.input_string[suffix - skip..suffix],
-                                equalize_i_and_l,
-                            ))
-                            && Self::check_suffix(
-                                skip,
-                                il_locations_current_suffix,
-                                current_search_string_suffix,
-                                &self.proteins.input_string
-                                    [suffix..suffix + search_string.len() - skip],
-                                equalize_i_and_l,
-                            ))
-                    {
-                        matching_suffixes.push((suffix - skip) as i64);
-
-                        // return if max number of matches is reached
-                        if matching_suffixes.len() >= max_matches {
-                            return SearchAllSuffixesResult::MaxMatches(matching_suffixes);
-                        }
-                    }
-                    sa_index += 1;
-                }
-            }
-            skip += 1;
-        }
-
-        if matching_suffixes.is_empty() {
-            SearchAllSuffixesResult::NoMatches
-        } else {
-            SearchAllSuffixesResult::SearchResult(matching_suffixes)
-        }
+        let matching_suffixes: Vec<i64> = self
+            .matching_suffixes(search_string, equalize_i_and_l, MatchCursor::default())
+            .take(max_matches)
+            .collect();
+        SearchAllSuffixesResult::from_matches(matching_suffixes, max_matches)
     }
 
-    /// Returns true of the prefixes are the same
-    /// if `equalize_i_and_l` is set to true, L and I are considered the same
+    /// Returns a lazy iterator over the suffixes matching a search string
+    /// The matches are only searched when they are requested, so the iteration can be stopped (and resumed) at any point
     ///
     /// # Arguments
-    /// * `search_string_prefix` - The unchecked prefix of the string/peptide that is searched
-    /// * `index_prefix` - The unchecked prefix from the protein from the suffix array
+    /// * `search_string` - The string/peptide we are searching in the suffix array
     /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    /// * `cursor` - The position from where the iteration starts, `MatchCursor::default()` to start at the first match
     ///
     /// # Returns
     ///
-    /// Returns true if `search_string_prefix` and `index_prefix` are considered the same, otherwise false
-    #[inline]
-    fn check_prefix(
-        search_string_prefix: &[u8],
-        index_prefix: &[u8],
+    /// Returns an iterator over the start positions of the matches in the text
+    pub fn matching_suffixes(
+        &self,
+        search_string: &[u8],
         equalize_i_and_l: bool,
-    ) -> bool {
-        if equalize_i_and_l {
-            search_string_prefix.iter().zip(index_prefix).all(
-                |(&search_character, &index_character)| {
-                    search_character == index_character
-                        || (search_character == b'I' && index_character == b'L')
-                        || (search_character == b'L' && index_character == b'I')
-                },
-            )
-        } else {
-            search_string_prefix == index_prefix
-        }
+        cursor: MatchCursor,
+    ) -> SuffixArrayMatches<'_> {
+        SuffixArrayMatches::new(self, search_string, equalize_i_and_l, cursor, vec![])
     }
 
-    /// Returns true of the search_string and index_string are equal
-    /// This is automatically true if `equalize_i_and_l` is set to true, since there matched during search where I = L
-    /// If `equalize_i_and_l` is set to false, we need to check if the I and L locations have the same character
+    /// Returns lazy iterators over the suffixes matching every search string of a batch
+    /// The intervals in the suffix array are searched incrementally: the intervals of the prefix a search string shares with the previous search string are reused, and only narrowed for the remaining characters
+    /// The search strings should be sorted lexicographically, since sorted search strings share the longest prefixes with their predecessor
+    /// Search strings with the ambiguity codes B, Z or X are searched independently
     ///
     /// # Arguments
-    /// * `skip` - The used skip factor during the search iteration
-    /// * `il_locations` - The locations of the I's and L's in the **original** peptide
-    /// * `search_string` - The peptide that is being searched, but already with the skipped prefix removed from it
-    /// * `index_string` - The suffix that search_string matches with when I and L were equalized during search
+    /// * `search_strings` - The strings/peptides we are searching in the suffix array
     /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
     ///
     /// # Returns
     ///
-    /// Returns true if `search_string` and `index_string` are considered the same, otherwise false
-    fn check_suffix(
-        skip: usize,
-        il_locations: &[usize],
-        search_string: &[u8],
-        index_string: &[u8],
-        equalize_i_and_l: bool,
-    ) -> bool {
-        if equalize_i_and_l {
-            true
-        } else {
-            for &il_location in il_locations {
-                let index = il_location - skip;
-                if search_string[index] != index_string[index] {
-                    return false;
-                }
-            }
-            true
-        }
+    /// Returns an iterator over the matches of every search string, in the order of `search_strings`
+    pub fn matching_suffixes_batch(&self, search_strings: &[&[u8]], equalize_i_and_l: bool) -> Vec<SuffixArrayMatches<'_>> {
+        // representations with backward search find the bounds without comparing suffixes, so there is nothing to reuse
+        let backward_search = self.sa.backward_search(&[]).is_some();
+
+        // the search strings are searched without their skipped prefix, so every skip offset has its own incremental search
+        let mut incremental_searches: Vec<IncrementalSearch> = (0..self.sparseness_factor)
+            .map(|_| IncrementalSearch::new(self.sa.len()))
+            .collect();
+
+        search_strings
+            .iter()
+            .map(|&search_string| {
+                let intervals = incremental_searches
+                    .iter_mut()
+                    .enumerate()
+                    .map(|(skip, incremental_search)| {
+                        if skip >= search_string.len() {
+                            vec![]
+                        } else if backward_search || search_string.iter().any(|&character| is_ambiguous(character)) {
+                            self.search_bound_intervals(&search_string[skip..])
+                        } else {
+                            self.narrow_bounds(incremental_search, &search_string[skip..])
+                        }
+                    })
+                    .collect();
+                SuffixArrayMatches::new(self, search_string, equalize_i_and_l, MatchCursor::default(), intervals)
+            })
+            .collect()
     }
 
-    /// Returns all the proteins that correspond with the provided suffixes
+    /// Searches the interval in the suffix array of which the suffixes match the search string when I and L are equalized, reusing the intervals of the previous search string
+    /// For every character after the prefix shared with the previous search string, the interval is narrowed to the suffixes with that character at that depth
     ///
     /// # Arguments
-    /// * `suffixes` - List of suffix indices
+    /// * `incremental_search` - The intervals of the previous search string, which are updated to the intervals of `search_string`
+    /// * `search_string` - The string/peptide we are searching in the suffix array, without ambiguity codes B, Z or X
     ///
     /// # Returns
     ///
-    /// Returns the proteins that every suffix is a part of 
-    #[inline]
-    pub fn retrieve_proteins(&self, suffixes: &Vec<i64>) -> Vec<&Protein> {
-        let mut res = vec![];
-        for &suffix in suffixes {
-            let protein_index = self.suffix_index_to_protein.suffix_to_protein(suffix);
-            if !protein_index.is_null() {
-                res.push(&self.proteins[protein_index as usize]);
-            }
+    /// Returns the minimum (inclusive) and maximum (exclusive) bound of the matching interval, or no intervals if there are no matches
+    fn narrow_bounds(&self, incremental_search: &mut IncrementalSearch, search_string: &[u8]) -> Vec<(usize, usize)> {
+        let folded_search_string: Vec<u8> = search_string.iter().map(|&character| fold_query_character(character)).collect();
+
+        // only the intervals of the prefix shared with the previous search string are still valid
+        let shared_prefix = incremental_search
+            .search_string
+            .iter()
+            .zip(&folded_search_string)
+            .take_while(|(previous, current)| previous == current)
+            .count();
+        incremental_search.search_string.truncate(shared_prefix);
+        incremental_search.bounds.truncate(shared_prefix + 1);
+
+        for (depth, &character) in folded_search_string.iter().enumerate().skip(shared_prefix) {
+            let (min_bound, max_bound) = incremental_search.bounds[depth];
+            let start = self.partition_point(min_bound, max_bound, |suffix| {
+                self.folded_character_at(suffix as usize + depth) < character
+            });
+            let end = self.partition_point(start, max_bound, |suffix| {
+                self.folded_character_at(suffix as usize + depth) <= character
+            });
+            incremental_search.search_string.push(character);
+            incremental_search.bounds.push((start, end));
+        }
+
+        let (min_bound, max_bound) = incremental_search.bounds[folded_search_string.len()];
+        if folded_search_string.is_empty() || min_bound >= max_bound {
+            vec![]
+        } else {
+            vec![(min_bound, max_bound)]
         }
-        res
     }
 
-    /// Searches all the matching proteins for a search_string/peptide in the suffix array
+    /// Searches the intervals in the suffix array of which the suffixes match the search string when I and L are equalized
+    /// B, Z and X match residues that are spread over the suffix array, so search strings with these can not be found with a single binary search
     ///
     /// # Arguments
-    /// * `search_string` - The string/peptide being searched
-    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    /// * `search_string` - The string/peptide we are searching in the suffix array
     ///
     /// # Returns
     ///
-    /// Returns the matching proteins for the search_string
-    pub fn search_proteins_for_peptide(&self, search_string: &[u8], equalize_i_and_l: bool) -> Vec<&Protein> {
-        let mut matching_suffixes = vec![];
-        if let SearchAllSuffixesResult::SearchResult(suffixes) =
-            self.search_matching_suffixes(search_string, usize::MAX, equalize_i_and_l)
-        {
-            matching_suffixes = suffixes;
+    /// Returns the minimum (inclusive) and maximum (exclusive) bound of every matching interval
+    fn search_bound_intervals(&self, search_string: &[u8]) -> Vec<(usize, usize)> {
+        if search_string.iter().any(|&character| is_ambiguous(character)) {
+            let mut intervals = vec![];
+            self.search_intervals_with_mismatches(search_string, (0, self.sa.len()), 0, 0, &mut intervals);
+            intervals
+        } else {
+            match self.search_bounds(search_string) {
+                BoundSearchResult::SearchResult(bounds) => vec![bounds],
+                BoundSearchResult::NoMatches => vec![],
+            }
         }
-        self.retrieve_proteins(&matching_suffixes)
     }
 
-    /// Retrieves the taxonomic analysis for a collection of proteins
+    /// Returns the locations of the I's and L's in the search string
     ///
     /// # Arguments
-    /// * `proteins` - A collection of proteins
+    /// * `search_string` - The string/peptide we are searching in the suffix array
     ///
     /// # Returns
     ///
-    /// Returns the taxonomic analysis result for the given list of proteins
-    #[inline]
-    pub fn retrieve_lca(&self, proteins: &[&Protein]) -> Option<TaxonId> {
-        let taxon_ids: Vec<TaxonId> = proteins.iter().map(|prot| prot.taxon_id).collect();
-        
-        self.taxon_id_calculator
-            .aggregate(taxon_ids)
-            .map(|id| self.taxon_id_calculator
-                .snap_taxon(id)
-            )
+    /// Returns the indices of all I's and L's in the search string
+    fn il_locations(search_string: &[u8]) -> Vec<usize> {
+        let mut il_locations = vec![];
+        for (i, &character) in search_string.iter().enumerate() {
+            if character == b'I' || character == b'L' {
+                il_locations.push(i);
+            }
+        }
+        il_locations
     }
 
-    /// Returns true if the protein is considered valid by the provided taxonomy file
+    /// Returns the locations of the I's and L's that are not part of the skipped prefix
     ///
     /// # Arguments
-    /// * `protein` - A protein of which we want to check the validity
+    /// * `il_locations` - The locations of the I's and L's in the search string
+    /// * `skip` - The used skip factor during the search iteration
     ///
     /// # Returns
     ///
-    ///  Returns true if the protein is considered valid by the provided taxonomy file
-    pub fn taxon_valid(&self, protein: &Protein) -> bool {
-        self.taxon_id_calculator.taxon_valid(protein.taxon_id)
+    /// Returns the locations of the I's and L's at index `skip` or later
+    fn il_locations_from(il_locations: &[usize], skip: usize) -> &[usize] {
+        let mut il_locations_start = 0;
+        while il_locations_start < il_locations.len() && il_locations[il_locations_start] < skip {
+            il_locations_start += 1;
+        }
+        &il_locations[il_locations_start..]
     }
 
-    /// Retrieves the functional analysis for a collection of proteins
+    /// Returns true if the search string matches the text at `suffix - skip`, knowing that the part after the skip matches when I and L are equalized
     ///
     /// # Arguments
-    /// * `proteins` - A collection of proteins
+    /// * `search_string` - The string/peptide we are searching in the suffix array
+    /// * `suffix` - The suffix from the suffix array that matches `search_string[skip..]` when I and L are equalized
+    /// * `skip` - The used skip factor during the search iteration
+    /// * `il_locations_current_suffix` - The locations of the I's and L's in the search string at index `skip` or later
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
     ///
     /// # Returns
     ///
-    /// Returns the functional analysis result for the given list of proteins
-    pub fn retrieve_function(&self, proteins: &[&Protein]) -> Option<FunctionalAggregation> {
-        let res = self.function_aggregator.aggregate(proteins.to_vec());
-        Some(res)
+    /// Returns true if the search string matches the text at `suffix - skip`
+    #[inline]
+    fn check_match(
+        &self,
+        search_string: &[u8],
+        suffix: usize,
+        skip: usize,
+        il_locations_current_suffix: &[usize],
+        equalize_i_and_l: bool,
+    ) -> bool {
+        // filter away matches where I was wrongfully equalized to L, and check the unmatched prefix
+        // when I and L equalized, we only need to check the prefix, not the whole match, when the prefix is 0, we don't need to check at all
+        suffix >= skip
+            && ((skip == 0
+                || Self::check_prefix(
+                    &search_string[..skip],
+                    &self.proteins.input_string[suffix - skip..suffix],
+                    equalize_i_and_l,
+                ))
+                && Self::check_suffix(
+                    skip,
+                    il_locations_current_suffix,
+                    &search_string[skip..],
+                    &self.proteins.input_string[suffix..suffix + search_string.len() - skip],
+                    equalize_i_and_l,
+                ))
     }
 
-    /// Retrieves the all the functional annotations for a collection of proteins
+    /// Searches for the suffixes matching a search string with at most `max_mismatches` substitutions (Hamming distance)
+    /// Instead of a single binary search, the search branches over all suffix array intervals that can still lead to a match
+    /// Insertions and deletions are not counted here, use `search_matching_suffixes_with_edits` for those
+    /// During search I and L can be equated
     ///
     /// # Arguments
-    /// * `proteins` - A collection of proteins
+    /// * `search_string` - The string/peptide we are searching in the suffix array
+    /// * `max_mismatches` - The maximum number of mismatches allowed between the search string and a match
+    /// * `max_matches` - The maximum amount of matches processed, if more matches are found we don't process them
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
     ///
     /// # Returns
     ///
-    /// Returns all functional annotations for a collection of proteins
-    pub fn get_all_functional_annotations(&self, proteins: &[&Protein]) -> Vec<Vec<String>> {
-        self.function_aggregator.get_all_functional_annotations(proteins)
-    }
-    
-}
+    /// Returns all the matching suffixes, together with the number of mismatches for every match
+    pub fn search_matching_suffixes_with_mismatches(
+        &self,
+        search_string: &[u8],
+        max_mismatches: usize,
+        max_matches: usize,
+        equalize_i_and_l: bool,
+    ) -> SearchAllApproximateSuffixesResult {
+        let mut matching_suffixes: Vec<ApproximateMatch> = vec![];
 
+        let mut skip: usize = 0;
+        while skip < self.sparseness_factor as usize && skip < search_string.len() {
+            // find all the intervals in the suffix array of which the suffixes match the unskipped part of the search string
+            let mut intervals = vec![];
+            self.search_intervals_with_mismatches(
+                &search_string[skip..],
+                (0, self.sa.len()),
+                0,
+                max_mismatches,
+                &mut intervals,
+            );
+
+            for (min_bound, max_bound) in intervals {
+                for sa_index in min_bound..max_bound {
+                    let suffix = self.sa.get(sa_index) as usize;
+                    if suffix < skip {
+                        continue;
+                    }
+                    // the intervals are found with I == L and without checking the skipped prefix, so count the actual mismatches
+                    if let Some(mismatches) = self.count_mismatches(
+                        search_string,
+                        suffix - skip,
+                        max_mismatches,
+                        equalize_i_and_l,
+                    ) {
+                        matching_suffixes.push(ApproximateMatch {
+                            suffix: (suffix - skip) as i64,
+                            mismatches,
+                        });
 
-#[cfg(test)]
-mod tests {
-    use sa_mappings::functionality::FunctionAggregator;
-    use sa_mappings::proteins::{Protein, Proteins};
-    use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
-    use crate::sa_searcher::{
-        BoundSearchResult, SearchAllSuffixesResult, Searcher,
-    };
-    use crate::suffix_to_protein_index::SparseSuffixToProtein;
+                        // return if max number of matches is reached
+                        if matching_suffixes.len() >= max_matches {
+                            return SearchAllApproximateSuffixesResult::MaxMatches(
+                                matching_suffixes,
+                            );
+                        }
+                    }
+                }
+            }
+            skip += 1;
+        }
 
-    fn get_example_proteins() -> Proteins {
-        let text = "AI-BLACVAA-AC-KCRLZ$".to_string().into_bytes();
-        Proteins {
-            input_string: text,
-            proteins: vec![
-                Protein {
-                    uniprot_id: String::new(),
-                    taxon_id: 0,
-                    functional_annotations: vec![],
-                },
-                Protein {
-                    uniprot_id: String::new(),
-                    taxon_id: 0,
-                    functional_annotations: vec![],
-                },
-                Protein {
-                    uniprot_id: String::new(),
-                    taxon_id: 0,
-                    functional_annotations: vec![],
-                },
-                Protein {
-                    uniprot_id: String::new(),
-                    taxon_id: 0,
-                    functional_annotations: vec![],
-                },
-            ],
+        if matching_suffixes.is_empty() {
+            SearchAllApproximateSuffixesResult::NoMatches
+        } else {
+            SearchAllApproximateSuffixesResult::SearchResult(matching_suffixes)
         }
     }
 
-    #[test]
-    fn test_search_simple() {
-        let proteins = get_example_proteins();
-        let sa = vec![
-            19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18,
-        ];
-
-        let searcher = Searcher::new(
-            sa,
-            1,
-            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
-            proteins,
-            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
-            FunctionAggregator {}
-        );
-
-        // search bounds 'A'
-        let bounds_res = searcher.search_bounds(&[b'A']);
-        assert_eq!(bounds_res, BoundSearchResult::SearchResult((4, 9)));
+    /// Recursively collects the suffix array intervals of which the suffixes match `search_string` with at most `mismatches_left` mismatches
+    /// All suffixes in the interval `bounds` are known to match the first `depth` characters of the search string
+    /// This comparison happens with I == L, since the suffix array is built that way
+    /// Ambiguity codes in the search string branch over all the residues they match
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide we are searching in the suffix array
+    /// * `bounds` - The minimum (inclusive) and maximum (exclusive) bound of the current interval in the suffix array
+    /// * `depth` - The number of characters of the search string that are already matched by the current interval
+    /// * `mismatches_left` - The number of mismatches that can still be used
+    /// * `intervals` - The list in which the found intervals are stored
+    fn search_intervals_with_mismatches(
+        &self,
+        search_string: &[u8],
+        bounds: (usize, usize),
+        depth: usize,
+        mismatches_left: usize,
+        intervals: &mut Vec<(usize, usize)>,
+    ) {
+        let (min_bound, max_bound) = bounds;
+        if search_string.is_empty() {
+            return;
+        }
+        if depth == search_string.len() {
+            intervals.push(bounds);
+            return;
+        }
 
-        // search bounds '$'
-        let bounds_res = searcher.search_bounds(&[b'$']);
-        assert_eq!(bounds_res, BoundSearchResult::SearchResult((0, 1)));
+        let query_character = search_string[depth];
+        let expected_character = fold_query_character(query_character);
+
+        // without mismatches left, only the interval of the expected character has to be considered
+        if mismatches_left == 0 && !is_ambiguous(query_character) {
+            let start = self.partition_point(min_bound, max_bound, |suffix| {
+                self.folded_character_at(suffix as usize + depth) < expected_character
+            });
+            let end = self.partition_point(start, max_bound, |suffix| {
+                self.folded_character_at(suffix as usize + depth) <= expected_character
+            });
+            if start < end {
+                self.search_intervals_with_mismatches(search_string, (start, end), depth + 1, 0, intervals);
+            }
+            return;
+        }
 
-        // search bounds 'AC'
-        let bounds_res = searcher.search_bounds(&[b'A', b'C']);
-        assert_eq!(bounds_res, BoundSearchResult::SearchResult((6, 8)));
+        // all suffixes in the interval share the first `depth` characters, so they are sorted on the character at `depth`
+        // this allows us to split the interval in a sub interval per character
+        let mut start = min_bound;
+        while start < max_bound {
+            let character = self.folded_character_at(self.sa.get(start) as usize + depth);
+            let end = self.partition_point(start, max_bound, |suffix| {
+                self.folded_character_at(suffix as usize + depth) <= character
+            });
+
+            if residue_matches_folded(query_character, character) {
+                self.search_intervals_with_mismatches(search_string, (start, end), depth + 1, mismatches_left, intervals);
+            } else if mismatches_left > 0 && character != SEPARATION_CHARACTER && character != TERMINATION_CHARACTER {
+                // a match can never span multiple proteins, so we can never substitute a separation or termination character
+                self.search_intervals_with_mismatches(search_string, (start, end), depth + 1, mismatches_left - 1, intervals);
+            }
+            start = end;
+        }
     }
 
-    #[test]
-    fn test_search_sparse() {
-        let proteins = get_example_proteins();
-        let sa = vec![9, 0, 3, 12, 15, 6, 18];
-
+    /// Counts the mismatches between the `search_string` and the text starting at `start`
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide that is searched
+    /// * `start` - The start of the possible match in the text
+    /// * `max_mismatches` - The maximum number of allowed mismatches
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of mismatches, or None if there are more than `max_mismatches` mismatches or if the match crosses the border of a protein
+    fn count_mismatches(
+        &self,
+        search_string: &[u8],
+        start: usize,
+        max_mismatches: usize,
+        equalize_i_and_l: bool,
+    ) -> Option<usize> {
+        let text = &self.proteins.input_string;
+        if start + search_string.len() > text.len() {
+            return None;
+        }
+
+        let mut mismatches = 0;
+        for (&search_character, &index_character) in search_string.iter().zip(&text[start..start + search_string.len()]) {
+            if index_character == SEPARATION_CHARACTER || index_character == TERMINATION_CHARACTER {
+                return None;
+            }
+
+            if !residue_matches(search_character, index_character, equalize_i_and_l) {
+                mismatches += 1;
+                if mismatches > max_mismatches {
+                    return None;
+                }
+            }
+        }
+
+        Some(mismatches)
+    }
+
+    /// Searches for the suffixes matching a search string with at most `max_edits` substitutions, insertions and deletions (edit distance)
+    /// Like the search with mismatches, the search branches over all suffix array intervals that can still lead to a match
+    /// With a sparse suffix array, a match is only found if it contains one of the sampled suffixes,
+    /// which is always the case if the search string is at least `sparseness_factor + max_edits` long
+    /// During search I and L can be equated
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide we are searching in the suffix array
+    /// * `max_edits` - The maximum edit distance allowed between the search string and a match
+    /// * `max_matches` - The maximum amount of matches processed, if more matches are found we don't process them
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    ///
+    /// # Returns
+    ///
+    /// Returns all the starts of matches in the text, together with the lowest edit distance of a match at that start.
+    /// No matches are returned if the search string is not longer than `max_edits`, since it would match everywhere
+    pub fn search_matching_suffixes_with_edits(
+        &self,
+        search_string: &[u8],
+        max_edits: usize,
+        max_matches: usize,
+        equalize_i_and_l: bool,
+    ) -> SearchAllApproximateSuffixesResult {
+        if search_string.len() <= max_edits {
+            return SearchAllApproximateSuffixesResult::NoMatches;
+        }
+
+        let sparseness_factor = self.sparseness_factor as usize;
+        let mut matching_suffixes: Vec<ApproximateMatch> = vec![];
+        // the same start can be reached from multiple sampled suffixes and alignments, but only has to be checked once
+        let mut checked_starts: HashSet<usize> = HashSet::new();
+
+        // the sampled suffix inside a match can be aligned with any of the first `sparseness_factor + max_edits` characters
+        let mut skip: usize = 0;
+        while skip < sparseness_factor + max_edits && skip < search_string.len() {
+            let mut intervals = vec![];
+            self.search_intervals_with_edits(
+                &search_string[skip..],
+                (0, self.sa.len()),
+                (0, 0),
+                max_edits,
+                &mut intervals,
+            );
+
+            for (min_bound, max_bound) in intervals {
+                for sa_index in min_bound..max_bound {
+                    let suffix = self.sa.get(sa_index) as usize;
+                    // the skipped prefix spans `skip` characters of the text, give or take the edits,
+                    // and the match starts after the previous sampled suffix
+                    let first_start = suffix.saturating_sub((skip + max_edits).min(sparseness_factor - 1));
+                    let last_start = suffix.saturating_sub(skip.saturating_sub(max_edits));
+
+                    for start in first_start..=last_start {
+                        if !checked_starts.insert(start) {
+                            continue;
+                        }
+                        // the intervals are found with I == L and only for a part of the search string, so align the whole string
+                        if let Some(edits) = self.count_edits(search_string, start, max_edits, equalize_i_and_l) {
+                            matching_suffixes.push(ApproximateMatch {
+                                suffix: start as i64,
+                                mismatches: edits,
+                            });
+
+                            // return if max number of matches is reached
+                            if matching_suffixes.len() >= max_matches {
+                                return SearchAllApproximateSuffixesResult::MaxMatches(
+                                    matching_suffixes,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            skip += 1;
+        }
+
+        if matching_suffixes.is_empty() {
+            SearchAllApproximateSuffixesResult::NoMatches
+        } else {
+            SearchAllApproximateSuffixesResult::SearchResult(matching_suffixes)
+        }
+    }
+
+    /// Recursively collects the suffix array intervals of which the suffixes match `search_string` with at most `edits_left` edits
+    /// All suffixes in the interval `bounds` share the first `text_depth` characters, which are aligned with the first `query_depth` characters of the search string
+    /// This comparison happens with I == L, since the suffix array is built that way
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide we are searching in the suffix array
+    /// * `bounds` - The minimum (inclusive) and maximum (exclusive) bound of the current interval in the suffix array
+    /// * `depths` - The number of characters of the text and of the search string that are already aligned
+    /// * `edits_left` - The number of edits that can still be used
+    /// * `intervals` - The list in which the found intervals are stored
+    fn search_intervals_with_edits(
+        &self,
+        search_string: &[u8],
+        bounds: (usize, usize),
+        depths: (usize, usize),
+        edits_left: usize,
+        intervals: &mut Vec<(usize, usize)>,
+    ) {
+        let (min_bound, max_bound) = bounds;
+        let (text_depth, query_depth) = depths;
+        if query_depth == search_string.len() {
+            intervals.push(bounds);
+            return;
+        }
+
+        let query_character = search_string[query_depth];
+        let expected_character = fold_query_character(query_character);
+
+        // without edits left, only the interval of the expected character has to be considered
+        if edits_left == 0 && !is_ambiguous(query_character) {
+            let start = self.partition_point(min_bound, max_bound, |suffix| {
+                self.folded_character_at(suffix as usize + text_depth) < expected_character
+            });
+            let end = self.partition_point(start, max_bound, |suffix| {
+                self.folded_character_at(suffix as usize + text_depth) <= expected_character
+            });
+            if start < end {
+                self.search_intervals_with_edits(search_string, (start, end), (text_depth + 1, query_depth + 1), 0, intervals);
+            }
+            return;
+        }
+
+        // deletion: the character of the search string does not occur in the text
+        if edits_left > 0 {
+            self.search_intervals_with_edits(search_string, bounds, (text_depth, query_depth + 1), edits_left - 1, intervals);
+        }
+
+        // all suffixes in the interval share the first `text_depth` characters, so they are sorted on the character at `text_depth`
+        let mut start = min_bound;
+        while start < max_bound {
+            let character = self.folded_character_at(self.sa.get(start) as usize + text_depth);
+            let end = self.partition_point(start, max_bound, |suffix| {
+                self.folded_character_at(suffix as usize + text_depth) <= character
+            });
+
+            // a match can never span multiple proteins, so a separation or termination character can never be part of it
+            if character != SEPARATION_CHARACTER && character != TERMINATION_CHARACTER {
+                if residue_matches_folded(query_character, character) {
+                    self.search_intervals_with_edits(search_string, (start, end), (text_depth + 1, query_depth + 1), edits_left, intervals);
+                } else if edits_left > 0 {
+                    // substitution
+                    self.search_intervals_with_edits(search_string, (start, end), (text_depth + 1, query_depth + 1), edits_left - 1, intervals);
+                }
+                if edits_left > 0 {
+                    // insertion: the character of the text does not occur in the search string
+                    self.search_intervals_with_edits(search_string, (start, end), (text_depth + 1, query_depth), edits_left - 1, intervals);
+                }
+            }
+            start = end;
+        }
+    }
+
+    /// Computes the lowest edit distance between the `search_string` and a part of the text starting at `start`
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide that is searched
+    /// * `start` - The start of the possible match in the text
+    /// * `max_edits` - The maximum number of allowed edits
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    ///
+    /// # Returns
+    ///
+    /// Returns the lowest edit distance over all the ends of the match within the protein, or None if it is larger than `max_edits`
+    fn count_edits(
+        &self,
+        search_string: &[u8],
+        start: usize,
+        max_edits: usize,
+        equalize_i_and_l: bool,
+    ) -> Option<usize> {
+        let text = &self.proteins.input_string;
+        // a match is at most `max_edits` characters longer than the search string and ends before the end of the protein
+        let end = min(start + search_string.len() + max_edits, text.len()).max(start);
+        let protein_end = text[start..end]
+            .iter()
+            .position(|&character| character == SEPARATION_CHARACTER || character == TERMINATION_CHARACTER)
+            .map_or(end, |offset| start + offset);
+
+        // `edits[j]` is the edit distance between the first `j` characters of the search string and the aligned text so far
+        let mut edits: Vec<usize> = (0..=search_string.len()).collect();
+        let mut lowest_edits = edits[search_string.len()];
+        for &index_character in &text[start..protein_end] {
+            let mut diagonal = edits[0];
+            edits[0] += 1;
+            for (j, &search_character) in search_string.iter().enumerate() {
+                let substitution = diagonal + usize::from(!residue_matches(search_character, index_character, equalize_i_and_l));
+                diagonal = edits[j + 1];
+                edits[j + 1] = substitution.min(edits[j + 1] + 1).min(edits[j] + 1);
+            }
+            lowest_edits = lowest_edits.min(edits[search_string.len()]);
+
+            // the edit distance can only grow once every prefix needs too many edits
+            if edits.iter().all(|&e| e > max_edits) {
+                break;
+            }
+        }
+
+        (lowest_edits <= max_edits).then_some(lowest_edits)
+    }
+
+    /// Returns the first index in the interval [`min_bound`, `max_bound`) of the suffix array for which `predicate` is false
+    /// The predicate has to be true for a prefix of the interval and false for the rest, like `slice::partition_point`
+    ///
+    /// # Arguments
+    /// * `min_bound` - The start of the interval in the suffix array (inclusive)
+    /// * `max_bound` - The end of the interval in the suffix array (exclusive)
+    /// * `predicate` - Function on the suffixes in the interval
+    ///
+    /// # Returns
+    ///
+    /// Returns the index of the first suffix for which `predicate` is false, or `max_bound` if it is true for all suffixes
+    fn partition_point(&self, min_bound: usize, max_bound: usize, predicate: impl Fn(i64) -> bool) -> usize {
+        let mut left = min_bound;
+        let mut right = max_bound;
+        while left < right {
+            let center = (left + right) / 2;
+            if predicate(self.sa.get(center)) {
+                left = center + 1;
+            } else {
+                right = center;
+            }
+        }
+        left
+    }
+
+    /// Returns the character at `index` in the text, where L is replaced by I like in the suffix array
+    ///
+    /// # Arguments
+    /// * `index` - The index in the text
+    ///
+    /// # Returns
+    ///
+    /// Returns the character at `index` with L replaced by I, or the termination character if the index is outside the text
+    #[inline]
+    fn folded_character_at(&self, index: usize) -> u8 {
+        self.proteins
+            .input_string
+            .get(index)
+            .map_or(TERMINATION_CHARACTER, |&character| fold_text_character(character))
+    }
+
+    /// Returns true of the prefixes are the same
+    /// if `equalize_i_and_l` is set to true, L and I are considered the same
+    /// Ambiguity codes in the search string match all the residues they represent
+    ///
+    /// # Arguments
+    /// * `search_string_prefix` - The unchecked prefix of the string/peptide that is searched
+    /// * `index_prefix` - The unchecked prefix from the protein from the suffix array
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    ///
+    /// # Returns
+    ///
+    /// Returns true if `search_string_prefix` and `index_prefix` are considered the same, otherwise false
+    #[inline]
+    fn check_prefix(
+        search_string_prefix: &[u8],
+        index_prefix: &[u8],
+        equalize_i_and_l: bool,
+    ) -> bool {
+        search_string_prefix.iter().zip(index_prefix).all(
+            |(&search_character, &index_character)| {
+                residue_matches(search_character, index_character, equalize_i_and_l)
+            },
+        )
+    }
+
+    /// Returns true of the search_string and index_string are equal
+    /// This is automatically true if `equalize_i_and_l` is set to true, since there matched during search where I = L
+    /// If `equalize_i_and_l` is set to false, we need to check if the I and L locations have the same character
+    ///
+    /// # Arguments
+    /// * `skip` - The used skip factor during the search iteration
+    /// * `il_locations` - The locations of the I's and L's in the **original** peptide
+    /// * `search_string` - The peptide that is being searched, but already with the skipped prefix removed from it
+    /// * `index_string` - The suffix that search_string matches with when I and L were equalized during search
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    ///
+    /// # Returns
+    ///
+    /// Returns true if `search_string` and `index_string` are considered the same, otherwise false
+    fn check_suffix(
+        skip: usize,
+        il_locations: &[usize],
+        search_string: &[u8],
+        index_string: &[u8],
+        equalize_i_and_l: bool,
+    ) -> bool {
+        if equalize_i_and_l {
+            true
+        } else {
+            for &il_location in il_locations {
+                let index = il_location - skip;
+                if search_string[index] != index_string[index] {
+                    return false;
+                }
+            }
+            true
+        }
+    }
+
+    /// Returns all the proteins that correspond with the provided suffixes
+    ///
+    /// # Arguments
+    /// * `suffixes` - List of suffix indices
+    ///
+    /// # Returns
+    ///
+    /// Returns the proteins that every suffix is a part of, all proteins with the same sequence if the sequences are deduplicated
+    #[inline]
+    pub fn retrieve_proteins(&self, suffixes: &[i64]) -> Vec<Protein<'_>> {
+        suffixes.iter().flat_map(|&suffix| self.match_proteins(suffix)).collect()
+    }
+
+    /// Returns the proteins that correspond with a single suffix
+    ///
+    /// # Arguments
+    /// * `suffix` - The start position of the suffix in the text
+    ///
+    /// # Returns
+    ///
+    /// Returns the protein the suffix is a part of, all proteins with the same sequence if the sequences are deduplicated,
+    /// or no proteins if the suffix is not part of a protein
+    #[inline]
+    pub fn match_proteins(&self, suffix: i64) -> ProteinIter<'_> {
+        let protein_index = self.suffix_index_to_protein.suffix_to_protein(suffix);
+        if protein_index.is_null() {
+            return self.proteins.range(0..0);
+        }
+        self.proteins.entry(protein_index as usize)
+    }
+
+    /// Searches all the matching proteins for a search_string/peptide in the suffix array
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide being searched
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    ///
+    /// # Returns
+    ///
+    /// Returns the matching proteins for the search_string
+    pub fn search_proteins_for_peptide(&self, search_string: &[u8], equalize_i_and_l: bool) -> Vec<Protein<'_>> {
+        let mut matching_suffixes = vec![];
+        if let SearchAllSuffixesResult::SearchResult(suffixes) =
+            self.search_matching_suffixes(search_string, usize::MAX, equalize_i_and_l)
+        {
+            matching_suffixes = suffixes;
+        }
+        self.retrieve_proteins(&matching_suffixes)
+    }
+
+    /// Counts the suffixes matching a search string, without storing the matches
+    /// For the suffix array intervals where every suffix is a match, the size of the interval is used directly
+    /// Only the suffixes that have to be checked (sparse suffix array or I and L not equalized) are enumerated
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide being searched
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    ///
+    /// # Returns
+    ///
+    /// Returns the exact number of matches of the search string
+    pub fn count_matching_suffixes(&self, search_string: &[u8], equalize_i_and_l: bool) -> usize {
+        let il_locations = Self::il_locations(search_string);
+        let mut count = 0;
+
+        let mut skip: usize = 0;
+        while skip < self.sparseness_factor as usize && skip < search_string.len() {
+            let il_locations_current_suffix = Self::il_locations_from(&il_locations, skip);
+            for (min_bound, max_bound) in self.search_bound_intervals(&search_string[skip..]) {
+                if skip == 0 && (equalize_i_and_l || il_locations_current_suffix.is_empty()) {
+                    // every suffix in this interval is a match
+                    count += max_bound - min_bound;
+                    continue;
+                }
+
+                count += (min_bound..max_bound)
+                    .filter(|&sa_index| {
+                        let suffix = self.sa.get(sa_index) as usize;
+                        self.check_match(search_string, suffix, skip, il_locations_current_suffix, equalize_i_and_l)
+                    })
+                    .count();
+            }
+            skip += 1;
+        }
+
+        count
+    }
+
+    /// Counts the distinct proteins that contain a match of the search string, without storing the matches
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide being searched
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    /// * `clean_taxa` - If set to true, only the proteins with a taxon which is stored as "valid" are counted
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of distinct proteins that match the search string
+    pub fn count_matching_proteins(&self, search_string: &[u8], equalize_i_and_l: bool, clean_taxa: bool) -> usize {
+        let il_locations = Self::il_locations(search_string);
+        let mut protein_indices: HashSet<u32> = HashSet::new();
+
+        let mut skip: usize = 0;
+        while skip < self.sparseness_factor as usize && skip < search_string.len() {
+            let il_locations_current_suffix = Self::il_locations_from(&il_locations, skip);
+            // every suffix in the intervals is a match if nothing has to be checked
+            let check_needed = skip != 0 || (!equalize_i_and_l && !il_locations_current_suffix.is_empty());
+            for (min_bound, max_bound) in self.search_bound_intervals(&search_string[skip..]) {
+                for sa_index in min_bound..max_bound {
+                    let suffix = self.sa.get(sa_index) as usize;
+                    if check_needed && !self.check_match(search_string, suffix, skip, il_locations_current_suffix, equalize_i_and_l) {
+                        continue;
+                    }
+
+                    let protein_index = self.suffix_index_to_protein.suffix_to_protein((suffix - skip) as i64);
+                    if !protein_index.is_null() {
+                        protein_indices.insert(protein_index);
+                    }
+                }
+            }
+            skip += 1;
+        }
+
+        // every entry in the text contains all proteins with its sequence
+        protein_indices
+            .into_iter()
+            .flat_map(|protein_index| self.proteins.entry(protein_index as usize))
+            .filter(|protein| !clean_taxa || self.taxon_id_calculator.taxon_valid(protein.taxon_id))
+            .count()
+    }
+
+    /// Aggregates the taxa of all the proteins that match the search string, without retrieving these proteins
+    /// The taxa are aggregated with the aggregation method of the taxonomy, like `retrieve_lca` does for the retrieved proteins
+    /// For the suffix array intervals where every suffix is a match, the aggregation is taken from the taxon LCA index
+    /// Only the suffixes that have to be checked (sparse suffix array or I and L not equalized) are enumerated
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide being searched
+    /// * `equalize_i_and_l` - If set to true, I and L are equalized during search
+    /// * `clean_taxa` - If set to true, only the taxa which are stored as "valid" are used
+    ///
+    /// # Returns
+    ///
+    /// Returns the taxonomic analysis result for all matches of the search string, or None if there are no matches
+    pub fn search_lca(&self, search_string: &[u8], equalize_i_and_l: bool, clean_taxa: bool) -> Option<TaxonId> {
+        let il_locations = Self::il_locations(search_string);
+        let mut summary = TaxonSummary::EMPTY;
+
+        let mut skip: usize = 0;
+        while skip < self.sparseness_factor as usize && skip < search_string.len() {
+            let il_locations_current_suffix = Self::il_locations_from(&il_locations, skip);
+            for (min_bound, max_bound) in self.search_bound_intervals(&search_string[skip..]) {
+                if skip == 0 && (equalize_i_and_l || il_locations_current_suffix.is_empty()) {
+                    // every suffix in this interval is a match
+                    let interval_summary = self.taxon_lca_index().range_lca(
+                        min_bound,
+                        max_bound,
+                        clean_taxa,
+                        &self.taxon_id_calculator,
+                    );
+                    summary = self.taxon_id_calculator.join(summary, interval_summary);
+                    continue;
+                }
+
+                for sa_index in min_bound..max_bound {
+                    let suffix = self.sa.get(sa_index) as usize;
+                    let taxon = self.taxon_lca_index().taxon(sa_index, clean_taxa, &self.taxon_id_calculator);
+                    if taxon != TaxonSummary::EMPTY
+                        && self.check_match(search_string, suffix, skip, il_locations_current_suffix, equalize_i_and_l)
+                    {
+                        summary = self.taxon_id_calculator.join(summary, taxon);
+                    }
+                }
+            }
+            skip += 1;
+        }
+
+        match summary.taxon() {
+            0 => None,
+            taxon => Some(self.taxon_id_calculator.snap_taxon(taxon)),
+        }
+    }
+}
+
+/// State of the incremental search of a batch of search strings in the suffix array
+///
+/// # Arguments
+/// * `search_string` - The previous search string, with L and J replaced by I like in the suffix array
+/// * `bounds` - `bounds[depth]` is the interval in the suffix array of the suffixes that match the first `depth` characters of `search_string`
+struct IncrementalSearch {
+    search_string: Vec<u8>,
+    bounds: Vec<(usize, usize)>,
+}
+
+impl IncrementalSearch {
+    /// Creates the state of an incremental search that did not search any string yet
+    ///
+    /// # Arguments
+    /// * `sa_len` - The number of entries in the suffix array
+    ///
+    /// # Returns
+    ///
+    /// Returns the state in which the empty prefix matches the whole suffix array
+    fn new(sa_len: usize) -> Self {
+        IncrementalSearch {
+            search_string: vec![],
+            bounds: vec![(0, sa_len)],
+        }
+    }
+}
+
+/// Lazy iterator over the suffixes matching a search string in the suffix array
+/// The suffix array intervals are visited per skip offset, every suffix in an interval is only checked when the next match is requested
+///
+/// # Arguments
+/// * `searcher` - The Searcher in which the search string is searched
+/// * `search_string` - The string/peptide we are searching in the suffix array
+/// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+/// * `il_locations` - The locations of the I's and L's in the search string
+/// * `skip` - The current skip offset
+/// * `intervals` - The matching intervals in the suffix array for the current skip offset
+/// * `interval` - The index of the current interval in `intervals`
+/// * `sa_index` - The next index in the suffix array that is checked
+/// * `found_intervals` - The matching intervals for every skip offset if they were already searched, otherwise empty
+pub struct SuffixArrayMatches<'a> {
+    searcher: &'a Searcher,
+    search_string: Vec<u8>,
+    equalize_i_and_l: bool,
+    il_locations: Vec<usize>,
+    skip: usize,
+    intervals: Vec<(usize, usize)>,
+    interval: usize,
+    sa_index: usize,
+    found_intervals: Vec<Vec<(usize, usize)>>,
+}
+
+impl<'a> SuffixArrayMatches<'a> {
+
+    /// Creates a new iterator that continues from the given cursor
+    ///
+    /// # Arguments
+    /// * `searcher` - The Searcher in which the search string is searched
+    /// * `search_string` - The string/peptide we are searching in the suffix array
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    /// * `cursor` - The position from where the iteration starts
+    /// * `found_intervals` - The matching intervals for every skip offset if they were already searched, otherwise empty
+    ///
+    /// # Returns
+    ///
+    /// Returns the iterator over the matches from `cursor` on
+    fn new(
+        searcher: &'a Searcher,
+        search_string: &[u8],
+        equalize_i_and_l: bool,
+        cursor: MatchCursor,
+        found_intervals: Vec<Vec<(usize, usize)>>,
+    ) -> Self {
+        let mut matches = Self {
+            searcher,
+            search_string: search_string.to_vec(),
+            equalize_i_and_l,
+            il_locations: Searcher::il_locations(search_string),
+            skip: cursor.skip,
+            intervals: vec![],
+            interval: cursor.interval,
+            sa_index: cursor.position,
+            found_intervals,
+        };
+        matches.load_intervals();
+        if let Some(&(min_bound, _)) = matches.intervals.get(matches.interval) {
+            matches.sa_index = matches.sa_index.max(min_bound);
+        }
+        matches
+    }
+
+    /// Searches the matching intervals in the suffix array for the current skip offset
+    fn load_intervals(&mut self) {
+        self.intervals = if let Some(found_intervals) = self.found_intervals.get_mut(self.skip) {
+            std::mem::take(found_intervals)
+        } else if self.skip < self.searcher.sparseness_factor as usize && self.skip < self.search_string.len() {
+            self.searcher.search_bound_intervals(&self.search_string[self.skip..])
+        } else {
+            vec![]
+        };
+    }
+}
+
+impl Iterator for SuffixArrayMatches<'_> {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        while self.skip < self.searcher.sparseness_factor as usize {
+            let Some(&(_, max_bound)) = self.intervals.get(self.interval) else {
+                // all intervals of this skip offset are visited
+                self.skip += 1;
+                self.load_intervals();
+                self.interval = 0;
+                self.sa_index = self.intervals.first().map_or(0, |&(min_bound, _)| min_bound);
+                continue;
+            };
+
+            if self.sa_index >= max_bound {
+                self.interval += 1;
+                if let Some(&(min_bound, _)) = self.intervals.get(self.interval) {
+                    self.sa_index = min_bound;
+                }
+                continue;
+            }
+
+            let suffix = self.searcher.sa.get(self.sa_index) as usize;
+            self.sa_index += 1;
+            let il_locations_current_suffix = Searcher::il_locations_from(&self.il_locations, self.skip);
+            if self.searcher.check_match(&self.search_string, suffix, self.skip, il_locations_current_suffix, self.equalize_i_and_l) {
+                return Some((suffix - self.skip) as i64);
+            }
+        }
+        None
+    }
+}
+
+impl MatchIterator for SuffixArrayMatches<'_> {
+    fn cursor(&self) -> MatchCursor {
+        MatchCursor {
+            skip: self.skip,
+            interval: self.interval,
+            position: self.sa_index,
+            protein: 0,
+        }
+    }
+}
+
+impl PeptideIndex for Searcher {
+    fn min_peptide_length(&self) -> usize {
+        self.sparseness_factor as usize
+    }
+
+    fn matches<'a>(&'a self, peptide: &[u8], equalize_i_and_l: bool, cursor: MatchCursor) -> Box<dyn MatchIterator + 'a> {
+        Box::new(self.matching_suffixes(peptide, equalize_i_and_l, cursor))
+    }
+
+    fn matches_batch<'a>(&'a self, peptides: &[&[u8]], equalize_i_and_l: bool) -> Vec<Box<dyn MatchIterator + 'a>> {
+        self.matching_suffixes_batch(peptides, equalize_i_and_l)
+            .into_iter()
+            .map(|matches| Box::new(matches) as Box<dyn MatchIterator + 'a>)
+            .collect()
+    }
+
+    fn count(&self, peptide: &[u8], equalize_i_and_l: bool) -> usize {
+        self.count_matching_suffixes(peptide, equalize_i_and_l)
+    }
+
+    fn count_proteins(&self, peptide: &[u8], equalize_i_and_l: bool, clean_taxa: bool) -> usize {
+        self.count_matching_proteins(peptide, equalize_i_and_l, clean_taxa)
+    }
+
+    fn retrieve_proteins(&self, suffixes: &[i64]) -> Vec<Protein<'_>> {
+        Searcher::retrieve_proteins(self, suffixes)
+    }
+
+    fn match_proteins(&self, suffix: i64) -> ProteinIter<'_> {
+        Searcher::match_proteins(self, suffix)
+    }
+
+    fn search_lca(&self, peptide: &[u8], equalize_i_and_l: bool, clean_taxa: bool) -> Option<TaxonId> {
+        Searcher::search_lca(self, peptide, equalize_i_and_l, clean_taxa)
+    }
+
+    fn taxon_aggregator(&self) -> &TaxonAggregator {
+        &self.taxon_id_calculator
+    }
+
+    fn function_aggregator(&self) -> &FunctionAggregator {
+        &self.function_aggregator
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use sa_mappings::functionality::FunctionAggregator;
+    use sa_mappings::proteins::{Protein, Proteins, SEPARATION_CHARACTER, TERMINATION_CHARACTER};
+    use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+    use suffixarray_builder::fm_index::FmIndex;
+    use suffixarray_builder::suffix_array::BitPackedSuffixArray;
+    use suffixarray_builder::taxon_lca_index::TaxonLcaIndex;
+    use suffixarray_builder::{build_sa_with_lcp_lr, SAConstructionAlgorithm};
+    use crate::peptide_index::{MatchCursor, MatchIterator, PeptideIndex};
+    use crate::residue_equivalence::residue_matches;
+    use crate::sa_searcher::{
+        ApproximateMatch, BoundSearchResult, SearchAllApproximateSuffixesResult,
+        SearchAllSuffixesResult, Searcher,
+    };
+    use crate::suffix_to_protein_index::SparseSuffixToProtein;
+
+    fn get_example_proteins() -> Proteins {
+        get_example_proteins_with_taxa([0, 0, 0, 0])
+    }
+
+    fn get_example_proteins_with_taxa(taxa: [usize; 4]) -> Proteins {
+        let text = "AI-BLACVAA-AC-KCRLZ$".to_string().into_bytes();
+        Proteins::new(text, taxa.map(|taxon_id| Protein { uniprot_id: "", taxon_id, functional_annotations: &[] })).unwrap()
+    }
+
+    #[test]
+    fn test_search_simple() {
+        let proteins = get_example_proteins();
+        let sa = vec![
+            19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18,
+        ];
+
+        let searcher = Searcher::new(
+            Box::new(sa),
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        // search bounds 'A'
+        let bounds_res = searcher.search_bounds(&[b'A']);
+        assert_eq!(bounds_res, BoundSearchResult::SearchResult((4, 9)));
+
+        // search bounds '$'
+        let bounds_res = searcher.search_bounds(&[b'$']);
+        assert_eq!(bounds_res, BoundSearchResult::SearchResult((0, 1)));
+
+        // search bounds 'AC'
+        let bounds_res = searcher.search_bounds(&[b'A', b'C']);
+        assert_eq!(bounds_res, BoundSearchResult::SearchResult((6, 8)));
+    }
+
+    #[test]
+    fn test_search_sparse() {
+        let proteins = get_example_proteins();
+        let sa = vec![9, 0, 3, 12, 15, 6, 18];
+
+        let searcher = Searcher::new(
+            Box::new(sa),
+            3,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        // search suffix 'VAA'
+        let found_suffixes =
+            searcher.search_matching_suffixes(&[b'V', b'A', b'A'], usize::MAX, false);
+        assert_eq!(
+            found_suffixes,
+            SearchAllSuffixesResult::SearchResult(vec![7])
+        );
+
+        // search suffix 'AC'
+        let found_suffixes = searcher.search_matching_suffixes(&[b'A', b'C'], usize::MAX, false);
+        assert_eq!(
+            found_suffixes,
+            SearchAllSuffixesResult::SearchResult(vec![5, 11])
+        );
+    }
+
+    #[test]
+    fn test_search_bit_packed() {
+        let proteins = get_example_proteins();
+        let sa = BitPackedSuffixArray::new(&[9, 0, 3, 12, 15, 6, 18], 5).unwrap();
+
         let searcher = Searcher::new(
-            sa,
+            Box::new(sa),
             3,
             Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
             proteins,
@@ -604,6 +1629,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_search_fm_index() {
+        let proteins = get_example_proteins();
+        let sa = vec![
+            19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18,
+        ];
+        // the FM-index is built on the text where every L is replaced by an I
+        let fm_index = FmIndex::new(b"AI-BIACVAA-AC-KCRIZ$", &sa, 4).unwrap();
+
+        let searcher = Searcher::new(
+            Box::new(fm_index),
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        // search bounds 'A'
+        let bounds_res = searcher.search_bounds(&[b'A']);
+        assert_eq!(bounds_res, BoundSearchResult::SearchResult((4, 9)));
+
+        // search suffix 'AC'
+        let found_suffixes = searcher.search_matching_suffixes(&[b'A', b'C'], usize::MAX, false);
+        assert_eq!(
+            found_suffixes,
+            SearchAllSuffixesResult::SearchResult(vec![5, 11])
+        );
+
+        // search suffix 'RIZ' with I and L equalized
+        let found_suffixes = searcher.search_matching_suffixes(&[b'R', b'I', b'Z'], usize::MAX, true);
+        assert_eq!(
+            found_suffixes,
+            SearchAllSuffixesResult::SearchResult(vec![16])
+        );
+
+        // search suffix 'RIZ' without equalizing I and L
+        let found_suffixes = searcher.search_matching_suffixes(&[b'R', b'I', b'Z'], usize::MAX, false);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::NoMatches);
+    }
+
     #[test]
     fn test_il_equality() {
         let proteins = get_example_proteins();
@@ -612,7 +1678,7 @@ mod tests {
         ];
 
         let searcher = Searcher::new(
-            sa,
+            Box::new(sa),
             1,
             Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
             proteins,
@@ -634,7 +1700,7 @@ mod tests {
         let sa = vec![9, 0, 3, 12, 15, 6, 18];
 
         let searcher = Searcher::new(
-            sa,
+            Box::new(sa),
             3,
             Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
             proteins,
@@ -661,18 +1727,11 @@ mod tests {
     fn test_l_first_index_in_sa() {
         let text = "LMOXZ$".to_string().into_bytes();
 
-        let proteins = Proteins {
-            input_string: text,
-            proteins: vec![Protein {
-                uniprot_id: String::new(),
-                taxon_id: 0,
-                functional_annotations: vec![],
-            }],
-        };
+        let proteins = Proteins::new(text, [Protein { uniprot_id: "", taxon_id: 0, functional_annotations: &[] }]).unwrap();
 
         let sparse_sa = vec![0, 2, 4];
         let searcher = Searcher::new(
-            sparse_sa,
+            Box::new(sparse_sa),
             2,
             Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
             proteins,
@@ -692,18 +1751,11 @@ mod tests {
     fn test_il_missing_matches() {
         let text = "AAILLL$".to_string().into_bytes();
 
-        let proteins = Proteins {
-            input_string: text,
-            proteins: vec![Protein {
-                uniprot_id: String::new(),
-                taxon_id: 0,
-                functional_annotations: vec![],
-            }],
-        };
+        let proteins = Proteins::new(text, [Protein { uniprot_id: "", taxon_id: 0, functional_annotations: &[] }]).unwrap();
 
         let sparse_sa = vec![6, 0, 1, 5, 4, 3, 2];
         let searcher = Searcher::new(
-            sparse_sa,
+            Box::new(sparse_sa),
             1,
             Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
             proteins,
@@ -722,18 +1774,11 @@ mod tests {
     fn test_il_duplication() {
         let text = "IIIILL$".to_string().into_bytes();
 
-        let proteins = Proteins {
-            input_string: text,
-            proteins: vec![Protein {
-                uniprot_id: String::new(),
-                taxon_id: 0,
-                functional_annotations: vec![],
-            }],
-        };
+        let proteins = Proteins::new(text, [Protein { uniprot_id: "", taxon_id: 0, functional_annotations: &[] }]).unwrap();
 
         let sparse_sa = vec![6, 5, 4, 3, 2, 1, 0];
         let searcher = Searcher::new(
-            sparse_sa,
+            Box::new(sparse_sa),
             1,
             Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
             proteins,
@@ -752,18 +1797,11 @@ mod tests {
     fn test_il_suffix_check() {
         let text = "IIIILL$".to_string().into_bytes();
 
-        let proteins = Proteins {
-            input_string: text,
-            proteins: vec![Protein {
-                uniprot_id: String::new(),
-                taxon_id: 0,
-                functional_annotations: vec![],
-            }],
-        };
+        let proteins = Proteins::new(text, [Protein { uniprot_id: "", taxon_id: 0, functional_annotations: &[] }]).unwrap();
 
         let sparse_sa = vec![6, 4, 2, 0];
         let searcher = Searcher::new(
-            sparse_sa,
+            Box::new(sparse_sa),
             2,
             Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
             proteins,
@@ -784,18 +1822,11 @@ mod tests {
     fn test_il_duplication2() {
         let text = "IILLLL$".to_string().into_bytes();
 
-        let proteins = Proteins {
-            input_string: text,
-            proteins: vec![Protein {
-                uniprot_id: String::new(),
-                taxon_id: 0,
-                functional_annotations: vec![],
-            }],
-        };
+        let proteins = Proteins::new(text, [Protein { uniprot_id: "", taxon_id: 0, functional_annotations: &[] }]).unwrap();
 
         let sparse_sa = vec![6, 5, 4, 3, 2, 1, 0];
         let searcher = Searcher::new(
-            sparse_sa,
+            Box::new(sparse_sa),
             1,
             Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
             proteins,
@@ -810,4 +1841,560 @@ mod tests {
             SearchAllSuffixesResult::SearchResult(vec![0, 1, 2, 3, 4])
         );
     }
-}
\ No newline at end of file
+
+    /// Returns the matches sorted on their suffix, so the result does not depend on the order of the suffix array
+    fn sorted_matches(result: SearchAllApproximateSuffixesResult) -> Vec<(i64, usize)> {
+        let mut matches: Vec<(i64, usize)> = match result {
+            SearchAllApproximateSuffixesResult::NoMatches => vec![],
+            SearchAllApproximateSuffixesResult::MaxMatches(matches)
+            | SearchAllApproximateSuffixesResult::SearchResult(matches) => matches
+                .iter()
+                .map(|&ApproximateMatch { suffix, mismatches }| (suffix, mismatches))
+                .collect(),
+        };
+        matches.sort();
+        matches
+    }
+
+    #[test]
+    fn test_search_with_mismatches() {
+        let proteins = get_example_proteins();
+        let sa = vec![
+            19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18,
+        ];
+
+        let searcher = Searcher::new(
+            Box::new(sa),
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        // without mismatches, only the exact matches are found
+        let found_suffixes = searcher.search_matching_suffixes_with_mismatches(&[b'A', b'C'], 0, usize::MAX, false);
+        assert_eq!(sorted_matches(found_suffixes), vec![(5, 0), (11, 0)]);
+
+        // matches may not cross the border of a protein
+        let found_suffixes = searcher.search_matching_suffixes_with_mismatches(&[b'A', b'C'], 1, usize::MAX, false);
+        assert_eq!(
+            sorted_matches(found_suffixes),
+            vec![(0, 1), (5, 0), (8, 1), (11, 0), (14, 1)]
+        );
+
+        let found_suffixes = searcher.search_matching_suffixes_with_mismatches(&[b'A', b'C', b'V'], 2, usize::MAX, false);
+        assert_eq!(sorted_matches(found_suffixes), vec![(5, 0), (14, 2)]);
+    }
+
+    #[test]
+    fn test_search_with_mismatches_sparse() {
+        let proteins = get_example_proteins();
+        let sa = vec![9, 0, 3, 12, 15, 6, 18];
+
+        let searcher = Searcher::new(
+            Box::new(sa),
+            3,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        let found_suffixes = searcher.search_matching_suffixes_with_mismatches(&[b'A', b'C', b'V'], 1, usize::MAX, false);
+        assert_eq!(sorted_matches(found_suffixes), vec![(5, 0)]);
+
+        let found_suffixes = searcher.search_matching_suffixes_with_mismatches(&[b'A', b'C', b'V'], 2, usize::MAX, false);
+        assert_eq!(sorted_matches(found_suffixes), vec![(5, 0), (14, 2)]);
+    }
+
+    #[test]
+    fn test_search_with_mismatches_il_equality() {
+        let proteins = get_example_proteins();
+        let sa = vec![9, 0, 3, 12, 15, 6, 18];
+
+        let searcher = Searcher::new(
+            Box::new(sa),
+            3,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        // 'RIZ' only matches 'RLZ' when I and L are equalized, otherwise the I counts as a mismatch
+        let found_suffixes = searcher.search_matching_suffixes_with_mismatches(&[b'R', b'I', b'Z'], 0, usize::MAX, true);
+        assert_eq!(sorted_matches(found_suffixes), vec![(16, 0)]);
+
+        let found_suffixes = searcher.search_matching_suffixes_with_mismatches(&[b'R', b'I', b'Z'], 0, usize::MAX, false);
+        assert!(sorted_matches(found_suffixes).is_empty());
+
+        let found_suffixes = searcher.search_matching_suffixes_with_mismatches(&[b'R', b'I', b'Z'], 1, usize::MAX, false);
+        assert_eq!(sorted_matches(found_suffixes), vec![(16, 1)]);
+    }
+
+    #[test]
+    fn test_search_with_edits() {
+        let proteins = get_example_proteins();
+        let sa = vec![
+            19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18,
+        ];
+
+        let searcher = Searcher::new(
+            Box::new(sa),
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        // 'BLCVAA' misses the A of 'BLACVAA', which takes a single insertion but more than one substitution
+        let found_suffixes = searcher.search_matching_suffixes_with_mismatches(&[b'B', b'L', b'C', b'V', b'A', b'A'], 1, usize::MAX, false);
+        assert!(sorted_matches(found_suffixes).is_empty());
+
+        let found_suffixes = searcher.search_matching_suffixes_with_edits(&[b'B', b'L', b'C', b'V', b'A', b'A'], 1, usize::MAX, false);
+        assert_eq!(sorted_matches(found_suffixes), vec![(3, 1)]);
+
+        // every start is reported with the lowest edit distance of a match starting there
+        let found_suffixes = searcher.search_matching_suffixes_with_edits(&[b'A', b'C', b'V', b'A'], 1, usize::MAX, false);
+        assert_eq!(sorted_matches(found_suffixes), vec![(4, 1), (5, 0), (6, 1)]);
+
+        // a search string that can be deleted completely would match everywhere
+        let found_suffixes = searcher.search_matching_suffixes_with_edits(&[b'A', b'C'], 2, usize::MAX, false);
+        assert!(sorted_matches(found_suffixes).is_empty());
+    }
+
+    /// Computes the lowest edit distance between `search_string` and a part of the protein in `text` that starts at `start`
+    fn naive_edit_distance(text: &[u8], start: usize, search_string: &[u8], equalize_i_and_l: bool) -> usize {
+        let protein_end = text[start..]
+            .iter()
+            .position(|&character| character == SEPARATION_CHARACTER || character == TERMINATION_CHARACTER)
+            .map_or(text.len(), |offset| start + offset);
+        let protein = &text[start..protein_end];
+
+        // distances[i][j] is the edit distance between the first i characters of the protein and the first j characters of the search string
+        let mut distances = vec![vec![0; search_string.len() + 1]; protein.len() + 1];
+        for i in 0..=protein.len() {
+            for j in 0..=search_string.len() {
+                distances[i][j] = if i == 0 || j == 0 {
+                    i + j
+                } else {
+                    let substitution = usize::from(!residue_matches(search_string[j - 1], protein[i - 1], equalize_i_and_l));
+                    (distances[i - 1][j - 1] + substitution).min(distances[i - 1][j] + 1).min(distances[i][j - 1] + 1)
+                };
+            }
+        }
+        distances.iter().map(|row| row[search_string.len()]).min().unwrap()
+    }
+
+    #[test]
+    fn test_search_with_edits_sparse() {
+        let text = "AI-BLACVAA-AC-KCRLZ-ACAC-LAC$";
+        for sparseness_factor in [1, 2, 3] {
+            let (searcher, _) = create_searchers_with_and_without_lcp_lr(text, sparseness_factor);
+
+            for peptide in ["ACV", "BLCVAA", "LACA", "KCRL", "ACACI", "CVAAC"] {
+                for max_edits in 0..=2 {
+                    // shorter matches do not always contain a sampled suffix
+                    if peptide.len() < sparseness_factor as usize + max_edits {
+                        continue;
+                    }
+                    for equalize_i_and_l in [false, true] {
+                        let found_suffixes = searcher.search_matching_suffixes_with_edits(
+                            peptide.as_bytes(),
+                            max_edits,
+                            usize::MAX,
+                            equalize_i_and_l,
+                        );
+
+                        let expected: Vec<(i64, usize)> = (0..text.len())
+                            .filter(|&start| text.as_bytes()[start] != SEPARATION_CHARACTER && text.as_bytes()[start] != TERMINATION_CHARACTER)
+                            .map(|start| (start as i64, naive_edit_distance(text.as_bytes(), start, peptide.as_bytes(), equalize_i_and_l)))
+                            .filter(|&(_, edits)| edits <= max_edits)
+                            .collect();
+                        assert_eq!(sorted_matches(found_suffixes), expected, "{peptide} with {max_edits} edits and sparseness {sparseness_factor}");
+                    }
+                }
+            }
+        }
+    }
+
+    fn get_ambiguity_proteins() -> Proteins {
+        let text = "DAEK-NAQK-DAQL$".to_string().into_bytes();
+        Proteins::new(text, [Protein { uniprot_id: "", taxon_id: 0, functional_annotations: &[] }; 3]).unwrap()
+    }
+
+    #[test]
+    fn test_ambiguity_codes() {
+        let proteins = get_ambiguity_proteins();
+        let sa = vec![14, 9, 4, 1, 11, 6, 0, 10, 2, 13, 8, 3, 5, 12, 7];
+
+        let searcher = Searcher::new(
+            Box::new(sa),
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        // B matches D and N, Z matches E and Q
+        let found_suffixes = searcher.search_matching_suffixes(&[b'B', b'A', b'Z', b'K'], usize::MAX, false);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::SearchResult(vec![0, 5]));
+
+        // J matches I and L, even if I and L are not equalized
+        let found_suffixes = searcher.search_matching_suffixes(&[b'B', b'A', b'Z', b'J'], usize::MAX, false);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::SearchResult(vec![10]));
+
+        // X matches every residue
+        let found_suffixes = searcher.search_matching_suffixes(&[b'X', b'A', b'Q'], usize::MAX, false);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::SearchResult(vec![5, 10]));
+
+        // X never matches the separation character
+        let found_suffixes = searcher.search_matching_suffixes(&[b'K', b'X', b'N'], usize::MAX, false);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::NoMatches);
+    }
+
+    #[test]
+    fn test_ambiguity_codes_sparse() {
+        let proteins = get_ambiguity_proteins();
+        let sa = vec![14, 4, 6, 0, 10, 2, 8, 12];
+
+        let searcher = Searcher::new(
+            Box::new(sa),
+            2,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        let found_suffixes = searcher.search_matching_suffixes(&[b'B', b'A', b'Z', b'K'], usize::MAX, false);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::SearchResult(vec![0, 5]));
+
+        let found_suffixes = searcher.search_matching_suffixes(&[b'B', b'A', b'Z', b'J'], usize::MAX, false);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::SearchResult(vec![10]));
+
+        // the ambiguity code is in the unchecked prefix for the match at 5
+        let found_suffixes = searcher.search_matching_suffixes(&[b'X', b'A', b'Q'], usize::MAX, false);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::SearchResult(vec![5, 10]));
+    }
+
+    #[test]
+    fn test_search_lca() {
+        let proteins = get_example_proteins_with_taxa([7, 9, 13, 14]);
+        let sa = vec![
+            19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18,
+        ];
+
+        let searcher = Searcher::new(
+            Box::new(sa),
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        assert_eq!(searcher.search_lca(&[b'A'], false, false), Some(6));
+        assert_eq!(searcher.search_lca(&[b'A', b'C'], false, false), Some(6));
+        assert_eq!(searcher.search_lca(&[b'K', b'C'], false, false), Some(14));
+        assert_eq!(searcher.search_lca(&[b'W'], false, false), None);
+    }
+
+    #[test]
+    fn test_search_lca_aggregation_method() {
+        for (method, expected) in [(AggregationMethod::Lca, 17), (AggregationMethod::LcaStar, 20)] {
+            let proteins = get_example_proteins_with_taxa([17, 19, 20, 14]);
+            let sa = vec![
+                19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18,
+            ];
+
+            let searcher = Searcher::new(
+                Box::new(sa),
+                1,
+                Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+                proteins,
+                TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", method).unwrap(),
+                FunctionAggregator {}
+            );
+
+            // the taxa 17, 19 and 20 are on a single lineage, so the LCA* is the deepest taxon
+            assert_eq!(searcher.search_lca(&[b'A'], false, false), Some(expected));
+            for peptide in ["A", "AC", "C", "KC", "W"] {
+                let proteins = searcher.search_proteins_for_peptide(peptide.as_bytes(), false);
+                assert_eq!(searcher.search_lca(peptide.as_bytes(), false, false), searcher.retrieve_lca(&proteins));
+            }
+        }
+    }
+
+    #[test]
+    fn test_with_taxon_lca_index() {
+        let proteins = get_example_proteins();
+        let sa: Vec<i64> = vec![19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18];
+        let taxonomy = |method| TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", method).unwrap();
+        let taxon_index = |method| TaxonLcaIndex::new(&sa, &proteins, &taxonomy(method));
+        let searcher = || {
+            Searcher::new(
+                Box::new(sa.clone()),
+                1,
+                Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+                get_example_proteins(),
+                taxonomy(AggregationMethod::LcaStar),
+                FunctionAggregator {}
+            )
+        };
+
+        // the loaded index gives the same results as the index that is built when it is first used
+        let loaded = searcher().with_taxon_lca_index(taxon_index(AggregationMethod::LcaStar)).unwrap();
+        let built = searcher();
+        for peptide in ["A", "AC", "C", "KC", "W"] {
+            assert_eq!(loaded.search_lca(peptide.as_bytes(), false, false), built.search_lca(peptide.as_bytes(), false, false));
+        }
+
+        // an index built with another aggregation method or over another suffix array is refused
+        assert!(searcher().with_taxon_lca_index(taxon_index(AggregationMethod::Lca)).is_err());
+        let other_index = TaxonLcaIndex::new(&sa[..10].to_vec(), &proteins, &taxonomy(AggregationMethod::LcaStar));
+        assert!(searcher().with_taxon_lca_index(other_index).is_err());
+    }
+
+    #[test]
+    fn test_search_lca_sparse() {
+        let proteins = get_example_proteins_with_taxa([7, 9, 13, 14]);
+        let sa = vec![9, 0, 3, 12, 15, 6, 18];
+
+        let searcher = Searcher::new(
+            Box::new(sa),
+            3,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {}
+        );
+
+        assert_eq!(searcher.search_lca(&[b'V', b'A', b'A'], false, false), Some(9));
+        assert_eq!(searcher.search_lca(&[b'R', b'I', b'Z'], true, false), Some(14));
+        assert_eq!(searcher.search_lca(&[b'R', b'I', b'Z'], false, false), None);
+    }
+
+    #[test]
+    fn test_search_deduplicated() {
+        let create_searcher = |deduplicate: bool| {
+            let uniprot_ids: Vec<String> = (0..5).map(|index| format!("P{}", index)).collect();
+            let mut proteins = Proteins::new(
+                b"AI-BLACVAA-AI-KCRLZ-BLACVAA$".to_vec(),
+                uniprot_ids.iter().zip([7, 9, 13, 14, 19]).map(|(uniprot_id, taxon_id)| Protein {
+                    uniprot_id,
+                    taxon_id,
+                    functional_annotations: &[],
+                }),
+            )
+            .unwrap();
+            if deduplicate {
+                proteins.deduplicate();
+            }
+            let (sa, _) =
+                build_sa_with_lcp_lr(&mut proteins.input_string.clone(), &SAConstructionAlgorithm::LibSais, 1, 1).unwrap();
+            Searcher::new(
+                Box::new(sa),
+                1,
+                Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+                proteins,
+                TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+                FunctionAggregator {}
+            )
+        };
+        let searcher = create_searcher(false);
+        let deduplicated_searcher = create_searcher(true);
+        assert_eq!(deduplicated_searcher.proteins.input_string, b"AI-BLACVAA-KCRLZ$".to_vec());
+
+        // the deduplicated index returns the same proteins, counts and LCAs
+        let uniprot_ids = |searcher: &Searcher, peptide: &str| {
+            let mut uniprot_ids: Vec<String> = searcher
+                .search_proteins_for_peptide(peptide.as_bytes(), false)
+                .iter()
+                .map(|protein| protein.uniprot_id.to_string())
+                .collect();
+            uniprot_ids.sort();
+            uniprot_ids
+        };
+        for peptide in ["AI", "BLA", "AC", "KC", "W"] {
+            assert_eq!(uniprot_ids(&deduplicated_searcher, peptide), uniprot_ids(&searcher, peptide));
+            for clean_taxa in [false, true] {
+                assert_eq!(
+                    deduplicated_searcher.count_matching_proteins(peptide.as_bytes(), false, clean_taxa),
+                    searcher.count_matching_proteins(peptide.as_bytes(), false, clean_taxa)
+                );
+                assert_eq!(
+                    deduplicated_searcher.search_lca(peptide.as_bytes(), false, clean_taxa),
+                    searcher.search_lca(peptide.as_bytes(), false, clean_taxa)
+                );
+            }
+        }
+        assert_eq!(uniprot_ids(&deduplicated_searcher, "AI"), vec!["P0", "P2"]);
+        assert_eq!(deduplicated_searcher.count_matching_proteins(b"AC", false, false), 2);
+        assert_eq!(deduplicated_searcher.search_lca(b"AI", false, false), Some(6));
+    }
+
+    fn get_proteins_for_text(text: &str) -> Proteins {
+        let number_of_proteins = text.bytes().filter(|&character| character == b'-').count() + 1;
+        let uniprot_ids: Vec<String> = (0..number_of_proteins).map(|index| format!("P{}", index)).collect();
+        Proteins::new(
+            text.to_string().into_bytes(),
+            uniprot_ids.iter().map(|uniprot_id| Protein { uniprot_id, taxon_id: 0, functional_annotations: &[] }),
+        )
+        .unwrap()
+    }
+
+    fn create_searchers_with_and_without_lcp_lr(text: &str, sparseness_factor: u8) -> (Searcher, Searcher) {
+        let (sa, lcp_lr) = build_sa_with_lcp_lr(
+            &mut text.to_string().into_bytes(),
+            &SAConstructionAlgorithm::LibSais,
+            sparseness_factor,
+            1,
+        )
+        .unwrap();
+
+        let create_searcher = |sa: Vec<i64>| {
+            let proteins = get_proteins_for_text(text);
+            Searcher::new(
+                Box::new(sa),
+                sparseness_factor,
+                Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+                proteins,
+                TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+                FunctionAggregator {}
+            )
+        };
+        let searcher = create_searcher(sa.clone());
+        let lcp_lr_searcher = create_searcher(sa).with_lcp_lr(lcp_lr).unwrap();
+        (searcher, lcp_lr_searcher)
+    }
+
+    #[test]
+    fn test_search_bounds_lcp_lr() {
+        // long repeats make sure that the LCP values are larger than the stored cap of 255
+        let text = format!("{}C-{}LC-{}D-AI-BLACVAA-AC-KCRLZ$", "A".repeat(300), "A".repeat(290), "AC".repeat(150));
+        let text_bytes = text.as_bytes();
+
+        for sparseness_factor in [1, 3] {
+            let (searcher, lcp_lr_searcher) = create_searchers_with_and_without_lcp_lr(&text, sparseness_factor);
+
+            for start in 0..text_bytes.len() {
+                for length in [1, 2, 3, 7, 100, 254, 255, 256, 257, 292, 301] {
+                    if start + length >= text_bytes.len() {
+                        break;
+                    }
+                    let mut search_string = text_bytes[start..start + length].to_vec();
+                    assert_eq!(searcher.search_bounds(&search_string), lcp_lr_searcher.search_bounds(&search_string));
+
+                    // also search strings that do not occur, but share a long prefix with the text
+                    for last_character in [b'A', b'C', b'D', b'Y'] {
+                        search_string[length - 1] = last_character;
+                        assert_eq!(searcher.search_bounds(&search_string), lcp_lr_searcher.search_bounds(&search_string));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_lcp_lr() {
+        let (_, searcher) = create_searchers_with_and_without_lcp_lr("AI-BLACVAA-AC-KCRLZ$", 3);
+
+        let found_suffixes = searcher.search_matching_suffixes(&[b'V', b'A', b'A'], usize::MAX, false);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::SearchResult(vec![7]));
+
+        let found_suffixes = searcher.search_matching_suffixes(&[b'R', b'I', b'Z'], usize::MAX, true);
+        assert_eq!(found_suffixes, SearchAllSuffixesResult::SearchResult(vec![16]));
+    }
+
+    #[test]
+    fn test_count_matching() {
+        let text = "AI-BLACVAA-AC-KCRLZ-ACAC-LAC$";
+        for sparseness_factor in [1, 3] {
+            let (searcher, _) = create_searchers_with_and_without_lcp_lr(text, sparseness_factor);
+
+            for peptide in ["AC", "LAC", "IAC", "CAC", "XC", "BLA", "W"] {
+                for equalize_i_and_l in [false, true] {
+                    let suffixes = match searcher.search_matching_suffixes(peptide.as_bytes(), usize::MAX, equalize_i_and_l) {
+                        SearchAllSuffixesResult::SearchResult(suffixes) => suffixes,
+                        _ => vec![],
+                    };
+                    let mut proteins: Vec<&str> = searcher
+                        .retrieve_proteins(&suffixes)
+                        .into_iter()
+                        .map(|protein| protein.uniprot_id)
+                        .collect();
+                    proteins.sort();
+                    proteins.dedup();
+
+                    assert_eq!(searcher.count_matching_suffixes(peptide.as_bytes(), equalize_i_and_l), suffixes.len());
+                    assert_eq!(searcher.count_matching_proteins(peptide.as_bytes(), equalize_i_and_l, false), proteins.len());
+                }
+            }
+        }
+
+        let (searcher, _) = create_searchers_with_and_without_lcp_lr(text, 1);
+        // AC occurs twice in the fifth protein
+        assert_eq!(searcher.count_matching_suffixes(b"AC", false), 5);
+        assert_eq!(searcher.count_matching_proteins(b"AC", false, false), 4);
+    }
+
+    #[test]
+    fn test_matching_suffixes_resume() {
+        let text = "AI-BLACVAA-AC-KCRLZ-ACAC-LAC$";
+        for sparseness_factor in [1, 3] {
+            let (searcher, _) = create_searchers_with_and_without_lcp_lr(text, sparseness_factor);
+
+            for peptide in ["AC", "LAC", "XC", "A"] {
+                let all_matches: Vec<i64> = searcher
+                    .matching_suffixes(peptide.as_bytes(), true, MatchCursor::default())
+                    .collect();
+                assert_eq!(all_matches.len(), searcher.count_matching_suffixes(peptide.as_bytes(), true));
+
+                // retrieve the matches in pages of 2, resuming from the cursor every time
+                let mut paged_matches = vec![];
+                let mut cursor = MatchCursor::default();
+                loop {
+                    let mut matches = searcher.matching_suffixes(peptide.as_bytes(), true, cursor);
+                    let page: Vec<i64> = matches.by_ref().take(2).collect();
+                    cursor = matches.cursor();
+                    if page.is_empty() {
+                        break;
+                    }
+                    paged_matches.extend(page);
+                }
+                assert_eq!(paged_matches, all_matches);
+            }
+        }
+    }
+
+    #[test]
+    fn test_matching_suffixes_batch() {
+        let text = "AI-BLACVAA-AC-KCRLZ-ACAC-LAC$";
+        let mut peptides = vec![
+            "AC", "ACA", "ACAC", "ACC", "AI", "AL", "BLA", "CRI", "CRL", "LAC", "XC", "AC", "VAA", "KCRLZ", "Q",
+        ];
+        peptides.sort();
+
+        for sparseness_factor in [1, 3] {
+            let (searcher, _) = create_searchers_with_and_without_lcp_lr(text, sparseness_factor);
+            let search_strings: Vec<&[u8]> = peptides.iter().map(|peptide| peptide.as_bytes()).collect();
+
+            for equalize_i_and_l in [false, true] {
+                let batch = searcher.matching_suffixes_batch(&search_strings, equalize_i_and_l);
+                assert_eq!(batch.len(), search_strings.len());
+
+                for (&search_string, matches) in search_strings.iter().zip(batch) {
+                    let batch_matches: Vec<i64> = matches.collect();
+                    let expected_matches: Vec<i64> = searcher
+                        .matching_suffixes(search_string, equalize_i_and_l, MatchCursor::default())
+                        .collect();
+                    assert_eq!(batch_matches, expected_matches);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use rand::rngs::StdRng;
+use rand::seq::index;
+use rand::{Rng, SeedableRng};
+use sa_mappings::proteins::Protein;
+use serde::{Deserialize, Serialize};
+use umgap::taxon::TaxonId;
+
+use crate::peptide_index::{MatchCursor, PeptideIndex};
+
+/// Enum that represents the policies used to select the matching proteins that are kept when the cutoff is reached
+/// - First: keep the first proteins in the order of the index
+/// - Random: keep a uniform random sample, chosen from the exact number of matching proteins
+/// - Reservoir: keep a uniform random sample, chosen with reservoir sampling in a single pass over all matches
+/// - Stratified: keep a random sample in which every taxon has the same share as in all matching proteins
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplingPolicy {
+    #[default]
+    First,
+    Random,
+    Reservoir,
+    Stratified,
+}
+
+/// Struct representing how the matching proteins are sampled when the cutoff is reached
+///
+/// # Arguments
+/// * `policy` - The policy used to select the proteins
+/// * `seed` - The seed of the random generator, so the same sample is returned for the same query
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sampling {
+    pub policy: SamplingPolicy,
+    pub seed: u64,
+}
+
+/// Selects `sample_size` matching proteins of the peptide according to the sampling policy
+/// Every protein of a match is a candidate, so a match of a deduplicated sequence gives a candidate for every protein with that sequence
+/// Only the proteins from the cursor on are candidates, so the proteins of earlier pages are never sampled again
+///
+/// # Arguments
+/// * `searcher` - The index which contains the protein database
+/// * `peptide` - The peptide that is being searched in the index
+/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
+/// * `cursor` - The position in the matches from where the candidates start, `MatchCursor::default()` to start at the first match
+/// * `sample_size` - The number of proteins that are selected
+/// * `sampling` - The sampling policy and seed
+///
+/// # Returns
+///
+/// Returns the selected proteins, all matching proteins if there are at most `sample_size` of them
+pub fn sample_proteins<'a>(
+    searcher: &'a dyn PeptideIndex,
+    peptide: &[u8],
+    equalize_i_and_l: bool,
+    cursor: MatchCursor,
+    sample_size: usize,
+    sampling: &Sampling,
+) -> Vec<Protein<'a>> {
+    let mut rng = StdRng::seed_from_u64(sampling.seed);
+    match sampling.policy {
+        SamplingPolicy::First => matching_proteins(searcher, peptide, equalize_i_and_l, cursor).take(sample_size).collect(),
+        SamplingPolicy::Random => sample_random(searcher, peptide, equalize_i_and_l, cursor, sample_size, &mut rng),
+        SamplingPolicy::Reservoir => sample_reservoir(searcher, peptide, equalize_i_and_l, cursor, sample_size, &mut rng),
+        SamplingPolicy::Stratified => sample_stratified(searcher, peptide, equalize_i_and_l, cursor, sample_size, &mut rng),
+    }
+}
+
+/// Iterates over the proteins of the matches of the peptide from the cursor on, in the order of the index
+///
+/// # Arguments
+/// * `searcher` - The index which contains the protein database
+/// * `peptide` - The peptide that is being searched in the index
+/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
+/// * `cursor` - The position in the matches from where the iteration starts
+///
+/// # Returns
+///
+/// Returns an iterator over the proteins of every match, all proteins with the sequence of a match if the sequences are deduplicated
+fn matching_proteins<'a>(
+    searcher: &'a dyn PeptideIndex,
+    peptide: &[u8],
+    equalize_i_and_l: bool,
+    cursor: MatchCursor,
+) -> impl Iterator<Item = Protein<'a>> + 'a {
+    // the proteins of the first match that were already returned all come before the other proteins
+    searcher
+        .matches(peptide, equalize_i_and_l, cursor)
+        .flat_map(move |suffix| searcher.match_proteins(suffix))
+        .skip(cursor.protein)
+}
+
+/// Selects a uniform random sample of the matching proteins by choosing random ranks out of the exact number of proteins
+/// The proteins are counted in a first pass, and only visited until the last chosen rank in the second pass
+///
+/// # Arguments
+/// * `searcher` - The index which contains the protein database
+/// * `peptide` - The peptide that is being searched in the index
+/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
+/// * `cursor` - The position in the matches from where the candidates start
+/// * `sample_size` - The number of proteins that are selected
+/// * `rng` - The random generator
+///
+/// # Returns
+///
+/// Returns the selected proteins in the order of the index
+fn sample_random<'a>(
+    searcher: &'a dyn PeptideIndex,
+    peptide: &[u8],
+    equalize_i_and_l: bool,
+    cursor: MatchCursor,
+    sample_size: usize,
+    rng: &mut StdRng,
+) -> Vec<Protein<'a>> {
+    let count = matching_proteins(searcher, peptide, equalize_i_and_l, cursor).count();
+    let mut ranks = index::sample(rng, count, sample_size.min(count)).into_vec();
+    ranks.sort_unstable();
+
+    let mut sample = Vec::with_capacity(ranks.len());
+    let mut proteins = matching_proteins(searcher, peptide, equalize_i_and_l, cursor);
+    let mut current_rank = 0;
+    for rank in ranks {
+        // nth skips all proteins before the chosen rank
+        match proteins.nth(rank - current_rank) {
+            Some(protein) => sample.push(protein),
+            None => break,
+        }
+        current_rank = rank + 1;
+    }
+    sample
+}
+
+/// Selects a uniform random sample of the matching proteins with reservoir sampling (algorithm R) in a single pass over all matches
+///
+/// # Arguments
+/// * `searcher` - The index which contains the protein database
+/// * `peptide` - The peptide that is being searched in the index
+/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
+/// * `cursor` - The position in the matches from where the candidates start
+/// * `sample_size` - The number of proteins that are selected
+/// * `rng` - The random generator
+///
+/// # Returns
+///
+/// Returns the selected proteins in an arbitrary order
+fn sample_reservoir<'a>(
+    searcher: &'a dyn PeptideIndex,
+    peptide: &[u8],
+    equalize_i_and_l: bool,
+    cursor: MatchCursor,
+    sample_size: usize,
+    rng: &mut StdRng,
+) -> Vec<Protein<'a>> {
+    let mut reservoir = Vec::with_capacity(sample_size);
+    for (seen, protein) in matching_proteins(searcher, peptide, equalize_i_and_l, cursor).enumerate() {
+        if seen < sample_size {
+            reservoir.push(protein);
+        } else {
+            let replaced = rng.gen_range(0..=seen);
+            if replaced < sample_size {
+                reservoir[replaced] = protein;
+            }
+        }
+    }
+    reservoir
+}
+
+/// Selects a random sample of the matching proteins that is stratified by their taxon
+/// Every taxon gets a share of the sample proportional to its number of proteins, the remaining places go to the taxa with the largest remainders
+/// The matches are visited twice: once to count the proteins per taxon, and once to pick the chosen ranks within every taxon
+///
+/// # Arguments
+/// * `searcher` - The index which contains the protein database
+/// * `peptide` - The peptide that is being searched in the index
+/// * `equalize_i_and_l` - Boolean indicating if we want to equate I and L during search
+/// * `cursor` - The position in the matches from where the candidates start
+/// * `sample_size` - The number of proteins that are selected
+/// * `rng` - The random generator
+///
+/// # Returns
+///
+/// Returns the selected proteins in the order of the index
+fn sample_stratified<'a>(
+    searcher: &'a dyn PeptideIndex,
+    peptide: &[u8],
+    equalize_i_and_l: bool,
+    cursor: MatchCursor,
+    sample_size: usize,
+    rng: &mut StdRng,
+) -> Vec<Protein<'a>> {
+    // a BTreeMap is used so the taxa are always visited in the same order for the same seed
+    let mut counts: BTreeMap<TaxonId, usize> = BTreeMap::new();
+    for protein in matching_proteins(searcher, peptide, equalize_i_and_l, cursor) {
+        *counts.entry(protein.taxon_id).or_insert(0) += 1;
+    }
+    let total: usize = counts.values().sum();
+    if total == 0 {
+        return vec![];
+    }
+    let sample_size = sample_size.min(total);
+
+    // largest remainder allocation of the sample over the taxa
+    let mut allocation: BTreeMap<TaxonId, usize> = BTreeMap::new();
+    let mut remainders: Vec<(usize, TaxonId)> = vec![];
+    for (&taxon, &count) in &counts {
+        allocation.insert(taxon, count * sample_size / total);
+        remainders.push((count * sample_size % total, taxon));
+    }
+    let allocated: usize = allocation.values().sum();
+    remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    for &(_, taxon) in remainders.iter().take(sample_size - allocated) {
+        *allocation.get_mut(&taxon).unwrap() += 1;
+    }
+
+    // choose the ranks of the selected proteins within every taxon
+    let mut chosen_ranks: BTreeMap<TaxonId, Vec<usize>> = BTreeMap::new();
+    for (&taxon, &taxon_sample_size) in &allocation {
+        let mut ranks = index::sample(rng, counts[&taxon], taxon_sample_size).into_vec();
+        // the ranks are popped from the back, so they are sorted in descending order
+        ranks.sort_unstable_by(|a, b| b.cmp(a));
+        chosen_ranks.insert(taxon, ranks);
+    }
+
+    let mut sample = Vec::with_capacity(sample_size);
+    let mut current_ranks: BTreeMap<TaxonId, usize> = BTreeMap::new();
+    for protein in matching_proteins(searcher, peptide, equalize_i_and_l, cursor) {
+        if sample.len() == sample_size {
+            break;
+        }
+        let current_rank = current_ranks.entry(protein.taxon_id).or_insert(0);
+        let ranks = chosen_ranks.get_mut(&protein.taxon_id).unwrap();
+        if ranks.last() == Some(&*current_rank) {
+            ranks.pop();
+            sample.push(protein);
+        }
+        *current_rank += 1;
+    }
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use sa_mappings::functionality::FunctionAggregator;
+    use sa_mappings::proteins::{Protein, ProteinArrays, Proteins};
+    use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+
+    use crate::peptide_index::{MatchCursor, PeptideIndex};
+    use crate::sa_searcher::Searcher;
+    use crate::sampling::{sample_proteins, Sampling, SamplingPolicy};
+    use crate::suffix_to_protein_index::SparseSuffixToProtein;
+
+    fn create_searcher(taxa: &[usize], entry_starts: Vec<u32>) -> Searcher {
+        let text = "AI-BLACVAA-AC-KCRLZ$".to_string().into_bytes();
+        let uniprot_ids: Vec<String> = (0..taxa.len()).map(|i| format!("P{}", i)).collect();
+        let arrays = ProteinArrays::from_proteins(uniprot_ids.iter().zip(taxa).map(|(uniprot_id, &taxon_id)| Protein {
+            uniprot_id,
+            taxon_id,
+            functional_annotations: &[],
+        }))
+        .unwrap();
+        let proteins = Proteins::from_arrays(text, arrays, entry_starts).unwrap();
+        let sa: Vec<i64> = vec![19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18];
+        Searcher::new(
+            Box::new(sa),
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap(),
+            FunctionAggregator {},
+        )
+    }
+
+    fn sample_ids(searcher: &Searcher, sample_size: usize, sampling: &Sampling) -> Vec<String> {
+        sample_ids_from(searcher, MatchCursor::default(), sample_size, sampling)
+    }
+
+    fn sample_ids_from(searcher: &Searcher, cursor: MatchCursor, sample_size: usize, sampling: &Sampling) -> Vec<String> {
+        let mut ids: Vec<String> = sample_proteins(searcher, b"A", false, cursor, sample_size, sampling)
+            .iter()
+            .map(|protein| protein.uniprot_id.to_string())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_sample_uniform() {
+        let searcher = create_searcher(&[7, 9, 9, 13], vec![]);
+        // A occurs at 0 in P0, at 5, 8 and 9 in P1 and at 11 in P2
+        let all_proteins = ["P0", "P1", "P1", "P1", "P2"];
+
+        for policy in [SamplingPolicy::First, SamplingPolicy::Random, SamplingPolicy::Reservoir, SamplingPolicy::Stratified] {
+            for seed in [0, 1, 42] {
+                let sampling = Sampling { policy, seed };
+                let sample = sample_ids(&searcher, 3, &sampling);
+                assert_eq!(sample, sample_ids(&searcher, 3, &sampling));
+                assert_eq!(sample.len(), 3);
+                for id in &sample {
+                    let in_sample = sample.iter().filter(|other| *other == id).count();
+                    let in_all = all_proteins.iter().filter(|other| *other == id).count();
+                    assert!(in_sample <= in_all);
+                }
+
+                // a sample larger than the number of matching proteins contains all matching proteins
+                assert_eq!(sample_ids(&searcher, 10, &sampling), all_proteins);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_stratified() {
+        let searcher = create_searcher(&[7, 9, 9, 13], vec![]);
+        // P0 is part of taxon 7, the other 4 matching proteins are part of taxon 9
+        for seed in [0, 1, 42] {
+            let sampling = Sampling { policy: SamplingPolicy::Stratified, seed };
+            assert!(!sample_ids(&searcher, 2, &sampling).contains(&"P0".to_string()));
+            assert!(sample_ids(&searcher, 3, &sampling).contains(&"P0".to_string()));
+        }
+    }
+
+    #[test]
+    fn test_sample_deduplicated() {
+        // the sequence AC is shared by P2 up to P6, of which P2 is part of taxon 9 and the others are part of taxon 7
+        let searcher = create_searcher(&[9, 9, 9, 7, 7, 7, 7, 13], vec![0, 1, 2, 7, 8]);
+        let all_proteins = ["P0", "P1", "P1", "P1", "P2", "P3", "P4", "P5", "P6"];
+
+        // in the order of the index, the matches are at 9 and 8 in P1 and at 11 in P2 up to P6
+        let sampling = Sampling { policy: SamplingPolicy::First, seed: 0 };
+        assert_eq!(sample_ids(&searcher, 6, &sampling), ["P1", "P1", "P2", "P3", "P4", "P5"]);
+        assert_eq!(sample_ids(&searcher, 10, &sampling), all_proteins);
+
+        // 4 of the 9 matching proteins are part of taxon 7, although the first protein of their sequence is not
+        for seed in [0, 1, 42] {
+            let sampling = Sampling { policy: SamplingPolicy::Stratified, seed };
+            let sample = sample_ids(&searcher, 2, &sampling);
+            assert_eq!(sample.iter().filter(|id| ["P3", "P4", "P5", "P6"].contains(&id.as_str())).count(), 1);
+            assert_eq!(sample_ids(&searcher, 9, &sampling), all_proteins);
+        }
+    }
+
+    #[test]
+    fn test_sample_from_cursor() {
+        let searcher = create_searcher(&[9, 9, 9, 7, 7, 7, 7, 13], vec![0, 1, 2, 7, 8]);
+        // the matches at 9 and 8 in P1 and the first 2 proteins of the match at 11 were already retrieved
+        let mut matches = searcher.matches(b"A", false, MatchCursor::default());
+        matches.nth(1);
+        let cursor = MatchCursor { protein: 2, ..matches.cursor() };
+        let remaining_proteins = ["P0", "P1", "P4", "P5", "P6"];
+
+        for policy in [SamplingPolicy::First, SamplingPolicy::Random, SamplingPolicy::Reservoir, SamplingPolicy::Stratified] {
+            for seed in [0, 1, 42] {
+                let sampling = Sampling { policy, seed };
+                let sample = sample_ids_from(&searcher, cursor, 2, &sampling);
+                assert_eq!(sample.len(), 2);
+                assert!(sample.iter().all(|id| remaining_proteins.contains(&id.as_str())));
+                assert_eq!(sample_ids_from(&searcher, cursor, 10, &sampling), remaining_proteins);
+            }
+        }
+    }
+}
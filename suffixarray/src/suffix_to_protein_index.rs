@@ -13,6 +13,7 @@ pub enum SuffixToProteinMappingStyle {
 pub trait SuffixToProteinIndex: Send + Sync {
     
     /// Returns the index of the protein in the protein list for the given suffix
+    /// If the sequences are deduplicated, this is the index of the entry in the text, see `Proteins::entry`
     ///
     /// # Arguments
     /// * `suffix` - The suffix of which we want to know of which protein it is a part
@@ -0,0 +1,330 @@
+use std::collections::HashSet;
+
+use sa_mappings::functionality::FunctionAggregator;
+use sa_mappings::proteins::{Protein, ProteinIter, Proteins};
+use sa_mappings::taxonomy::{TaxonAggregator, TaxonSummary};
+use suffixtree::tree::{NodeIndex, Nullable as TreeNullable, Tree};
+use suffixtree::tree_builder::{TreeBuilder, UkkonenBuilder};
+use umgap::taxon::TaxonId;
+
+use crate::peptide_index::{MatchCursor, MatchIterator, PeptideIndex};
+use crate::residue_equivalence::{fold_text_character, residue_matches, residue_matches_folded};
+use crate::suffix_to_protein_index::{SparseSuffixToProtein, SuffixToProteinIndex};
+use crate::Nullable;
+
+/// Index that searches peptides in a suffix tree instead of a suffix array
+/// The tree is built over the text with every L translated to an I, in the same way as the suffix array
+/// The search does not need a cursor, so the index can be shared between threads
+///
+/// # Arguments
+/// * `tree` - The suffix tree built over the proteins
+/// * `suffix_index_to_protein` - Mapping from a suffix to the proteins to know which a suffix is part of
+/// * `proteins` - List of all the proteins where the suffix tree is build on
+/// * `taxon_id_calculator` - Object representing the used taxonomy and that calculates the taxonomic analysis provided by Unipept
+/// * `function_aggregator` - Object used to retrieve the functional annotations and to calculate the functional analysis provided by Unipept
+pub struct SuffixTreeIndex {
+    tree: Tree,
+    suffix_index_to_protein: SparseSuffixToProtein,
+    proteins: Proteins,
+    taxon_id_calculator: TaxonAggregator,
+    function_aggregator: FunctionAggregator,
+}
+
+impl SuffixTreeIndex {
+
+    /// Creates a new SuffixTreeIndex by building the suffix tree over the proteins
+    ///
+    /// # Arguments
+    /// * `proteins` - List of all the proteins where the suffix tree is build on
+    /// * `taxon_id_calculator` - Object representing the used taxonomy and that calculates the taxonomic analysis provided by Unipept
+    /// * `function_aggregator` - Object used to retrieve the functional annotations and to calculate the functional analysis provided by Unipept
+    ///
+    /// # Returns
+    ///
+    /// Returns a new SuffixTreeIndex
+    pub fn new(proteins: Proteins, taxon_id_calculator: TaxonAggregator, function_aggregator: FunctionAggregator) -> Self {
+        let folded_text: Vec<u8> = proteins.input_string.iter().map(|&character| fold_text_character(character)).collect();
+        let tree = Tree::new(&folded_text, UkkonenBuilder::new());
+
+        Self {
+            tree,
+            suffix_index_to_protein: SparseSuffixToProtein::new(&proteins.input_string),
+            proteins,
+            taxon_id_calculator,
+            function_aggregator,
+        }
+    }
+
+    /// Walks down the tree along every path that matches the search string when I and L are equalized
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide we are searching in the tree
+    /// * `node` - The node where the walk continues
+    /// * `depth` - The length of the path from the root to `node`, which is the number of characters of the search string that are already matched
+    /// * `loci` - The nodes of which every leaf in the subtree matches the search string are added to this list, together with the length of the path to their parent
+    fn search_loci(&self, search_string: &[u8], node: NodeIndex, depth: usize, loci: &mut Vec<(NodeIndex, usize)>) {
+        let text = &self.proteins.input_string;
+        for &child in self.tree.arena[node].children.iter() {
+            if child.is_null() {
+                continue;
+            }
+
+            let range = &self.tree.arena[child].range;
+            let compared = range.length().min(search_string.len() - depth);
+            let edge_matches = (0..compared).all(|i| {
+                residue_matches_folded(search_string[depth + i], fold_text_character(text[range.start + i]))
+            });
+            if !edge_matches {
+                continue;
+            }
+
+            if depth + compared == search_string.len() {
+                loci.push((child, depth));
+            } else {
+                self.search_loci(search_string, child, depth + compared, loci);
+            }
+        }
+    }
+
+    /// Returns a lazy iterator over the start positions of the occurrences of the search string in the text
+    ///
+    /// # Arguments
+    /// * `search_string` - The string/peptide we are searching in the tree
+    /// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+    ///
+    /// # Returns
+    ///
+    /// Returns an iterator over all matches
+    pub fn matching_suffixes(&self, search_string: &[u8], equalize_i_and_l: bool) -> SuffixTreeMatches<'_> {
+        let mut loci = vec![];
+        if !search_string.is_empty() {
+            self.search_loci(search_string, 0, 0, &mut loci);
+        }
+
+        SuffixTreeMatches {
+            index: self,
+            search_string: search_string.to_vec(),
+            equalize_i_and_l,
+            stack: loci,
+            visited: 0,
+        }
+    }
+}
+
+/// Lazy iterator over the leaves of the subtrees that match a search string
+///
+/// # Arguments
+/// * `index` - The SuffixTreeIndex in which the search string is searched
+/// * `search_string` - The string/peptide we are searching in the tree
+/// * `equalize_i_and_l` - True if we want to equate I and L during search, otherwise false
+/// * `stack` - The nodes that still have to be visited, together with the length of the path to their parent
+/// * `visited` - The number of matches that are already returned
+pub struct SuffixTreeMatches<'a> {
+    index: &'a SuffixTreeIndex,
+    search_string: Vec<u8>,
+    equalize_i_and_l: bool,
+    stack: Vec<(NodeIndex, usize)>,
+    visited: usize,
+}
+
+impl Iterator for SuffixTreeMatches<'_> {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        let text = &self.index.proteins.input_string;
+        while let Some((node, parent_depth)) = self.stack.pop() {
+            let current_node = &self.index.tree.arena[node];
+            if current_node.suffix_index.is_null() {
+                let depth = parent_depth + current_node.range.length();
+                self.stack.extend(current_node.children.iter().filter(|child| !child.is_null()).map(|&child| (child, depth)));
+                continue;
+            }
+
+            // the suffix of a leaf starts the length of the path to its parent before the start of its edge
+            let suffix = current_node.range.start - parent_depth;
+            // the tree is built with I == L, so matches where I was wrongfully equalized to L are filtered away
+            let is_match = self.equalize_i_and_l
+                || self
+                    .search_string
+                    .iter()
+                    .zip(&text[suffix..])
+                    .all(|(&query_character, &text_character)| residue_matches(query_character, text_character, false));
+            if is_match {
+                self.visited += 1;
+                return Some(suffix as i64);
+            }
+        }
+        None
+    }
+}
+
+impl MatchIterator for SuffixTreeMatches<'_> {
+    /// The tree is always traversed in the same order, so the position is the number of matches already returned
+    fn cursor(&self) -> MatchCursor {
+        MatchCursor {
+            skip: 0,
+            interval: 0,
+            position: self.visited,
+            protein: 0,
+        }
+    }
+}
+
+impl PeptideIndex for SuffixTreeIndex {
+    fn min_peptide_length(&self) -> usize {
+        1
+    }
+
+    fn matches<'a>(&'a self, peptide: &[u8], equalize_i_and_l: bool, cursor: MatchCursor) -> Box<dyn MatchIterator + 'a> {
+        let mut matches = self.matching_suffixes(peptide, equalize_i_and_l);
+        // skip the matches that were already returned before the cursor
+        if cursor.position > 0 {
+            matches.nth(cursor.position - 1);
+        }
+        Box::new(matches)
+    }
+
+    fn count_proteins(&self, peptide: &[u8], equalize_i_and_l: bool, clean_taxa: bool) -> usize {
+        let mut protein_indices: HashSet<u32> = HashSet::new();
+        for suffix in self.matching_suffixes(peptide, equalize_i_and_l) {
+            let protein_index = self.suffix_index_to_protein.suffix_to_protein(suffix);
+            if !protein_index.is_null() {
+                protein_indices.insert(protein_index);
+            }
+        }
+        // every entry in the text contains all proteins with its sequence
+        protein_indices
+            .into_iter()
+            .flat_map(|protein_index| self.proteins.entry(protein_index as usize))
+            .filter(|protein| !clean_taxa || self.taxon_id_calculator.taxon_valid(protein.taxon_id))
+            .count()
+    }
+
+    fn retrieve_proteins(&self, suffixes: &[i64]) -> Vec<Protein<'_>> {
+        suffixes.iter().flat_map(|&suffix| self.match_proteins(suffix)).collect()
+    }
+
+    fn match_proteins(&self, suffix: i64) -> ProteinIter<'_> {
+        let protein_index = self.suffix_index_to_protein.suffix_to_protein(suffix);
+        if protein_index.is_null() {
+            return self.proteins.range(0..0);
+        }
+        self.proteins.entry(protein_index as usize)
+    }
+
+    fn search_lca(&self, peptide: &[u8], equalize_i_and_l: bool, clean_taxa: bool) -> Option<TaxonId> {
+        let mut summary = TaxonSummary::EMPTY;
+        for suffix in self.matching_suffixes(peptide, equalize_i_and_l) {
+            let protein_index = self.suffix_index_to_protein.suffix_to_protein(suffix);
+            if protein_index.is_null() {
+                continue;
+            }
+            for protein in self.proteins.entry(protein_index as usize) {
+                if !clean_taxa || self.taxon_id_calculator.taxon_valid(protein.taxon_id) {
+                    summary = self.taxon_id_calculator.join(summary, TaxonSummary::lineage(protein.taxon_id));
+                }
+            }
+        }
+
+        match summary.taxon() {
+            0 => None,
+            taxon => Some(self.taxon_id_calculator.snap_taxon(taxon)),
+        }
+    }
+
+    fn taxon_aggregator(&self) -> &TaxonAggregator {
+        &self.taxon_id_calculator
+    }
+
+    fn function_aggregator(&self) -> &FunctionAggregator {
+        &self.function_aggregator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sa_mappings::functionality::FunctionAggregator;
+    use sa_mappings::proteins::{Protein, Proteins};
+    use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+
+    use crate::peptide_index::{MatchCursor, PeptideIndex};
+    use crate::sa_searcher::{SearchAllSuffixesResult, Searcher};
+    use crate::suffix_to_protein_index::SparseSuffixToProtein;
+    use crate::suffix_tree_index::SuffixTreeIndex;
+
+    fn get_example_proteins() -> Proteins {
+        let text = "AI-BLACVAA-AC-KCRLZ$".to_string().into_bytes();
+        Proteins::new(
+            text,
+            [1, 2, 6, 7].map(|taxon_id| Protein { uniprot_id: "", taxon_id, functional_annotations: &[] }),
+        )
+        .unwrap()
+    }
+
+    fn get_taxon_aggregator() -> TaxonAggregator {
+        TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap()
+    }
+
+    #[test]
+    fn test_locate() {
+        let index = SuffixTreeIndex::new(get_example_proteins(), get_taxon_aggregator(), FunctionAggregator {});
+
+        assert_eq!(index.locate(b"AC", usize::MAX, false), SearchAllSuffixesResult::SearchResult(vec![5, 11]));
+        assert_eq!(index.locate(b"IA", usize::MAX, false), SearchAllSuffixesResult::NoMatches);
+        assert_eq!(index.locate(b"IA", usize::MAX, true), SearchAllSuffixesResult::SearchResult(vec![4]));
+        assert_eq!(index.locate(b"JA", usize::MAX, false), SearchAllSuffixesResult::SearchResult(vec![4]));
+        assert_eq!(index.locate(b"XC", usize::MAX, false), SearchAllSuffixesResult::SearchResult(vec![5, 11, 14]));
+        assert_eq!(index.locate(b"A-", usize::MAX, false), SearchAllSuffixesResult::SearchResult(vec![9]));
+        assert_eq!(index.count(b"A", false), 5);
+        assert!(matches!(index.locate(b"A", 2, false), SearchAllSuffixesResult::MaxMatches(suffixes) if suffixes.len() == 2));
+    }
+
+    #[test]
+    fn test_same_results_as_suffix_array() {
+        let proteins = get_example_proteins();
+        let sa: Vec<i64> = vec![19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18];
+        let searcher = Searcher::new(
+            Box::new(sa),
+            1,
+            Box::new(SparseSuffixToProtein::new(&proteins.input_string)),
+            proteins,
+            get_taxon_aggregator(),
+            FunctionAggregator {},
+        );
+        let index = SuffixTreeIndex::new(get_example_proteins(), get_taxon_aggregator(), FunctionAggregator {});
+
+        for peptide in ["A", "AC", "CRL", "RIZ", "BL", "KXR", "VAA", "W"] {
+            for equalize_i_and_l in [false, true] {
+                assert_eq!(
+                    index.locate(peptide.as_bytes(), usize::MAX, equalize_i_and_l),
+                    searcher.locate(peptide.as_bytes(), usize::MAX, equalize_i_and_l)
+                );
+                assert_eq!(
+                    index.search_lca(peptide.as_bytes(), equalize_i_and_l, false),
+                    searcher.search_lca(peptide.as_bytes(), equalize_i_and_l, false)
+                );
+                assert_eq!(
+                    index.count(peptide.as_bytes(), equalize_i_and_l),
+                    searcher.count(peptide.as_bytes(), equalize_i_and_l)
+                );
+                assert_eq!(
+                    index.count_proteins(peptide.as_bytes(), equalize_i_and_l, false),
+                    searcher.count_proteins(peptide.as_bytes(), equalize_i_and_l, false)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_resume() {
+        let index = SuffixTreeIndex::new(get_example_proteins(), get_taxon_aggregator(), FunctionAggregator {});
+
+        let all_matches: Vec<i64> = index.matches(b"A", false, MatchCursor::default()).collect();
+        let mut matches = index.matches(b"A", false, MatchCursor::default());
+        let first_page: Vec<i64> = matches.by_ref().take(3).collect();
+        let second_page: Vec<i64> = index.matches(b"A", false, matches.cursor()).collect();
+
+        assert_eq!(all_matches.len(), 5);
+        assert_eq!([first_page, second_page].concat(), all_matches);
+    }
+}
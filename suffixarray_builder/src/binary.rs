@@ -1,12 +1,84 @@
 use std::cmp::min;
 use std::error::Error;
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+
+use memmap2::Mmap;
+use sa_mappings::filter::ProteinFilter;
+use sa_mappings::proteins::SEPARATION_CHARACTER;
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
+
+use crate::fm_index::FmIndex;
+use crate::lcp_lr::LcpLr;
+use crate::suffix_array::{check_bits_per_value, pack, read_packed, BitPackedSuffixArray, SuffixArray, SuffixEntry};
+use crate::taxon_lca_index::{Layout, Summaries, TaxonLcaIndex};
 
 const ONE_GIB: usize = 2usize.pow(30);
 
+/// The number of entries that are packed and written at once, which is a multiple of 8, so every packed part ends at a byte boundary
+const WRITE_PART_ENTRIES: usize = ONE_GIB / 8;
+
+/// The version of the index file format, files with another version can not be loaded
+pub const FORMAT_VERSION: u16 = 3;
+
+/// Flag in the header that is set if every L in the text was replaced by an I before building the suffix array
+const FLAG_IL_FOLDED: u8 = 1;
+
+/// Flag in the header of a taxon index that is set if the taxa are aggregated with the LCA* instead of the LCA
+const FLAG_LCA_STAR: u8 = 2;
+
+/// The size of the header of an index file without protein filters, see `write_suffix_array` for its layout
+const HEADER_SIZE: usize = 69;
+
+/// The position in the header of the length of the serialized protein filters, which are stored directly after it
+const FILTER_LENGTH_OFFSET: usize = 57;
+
+/// The number of payload bytes covered by a single checksum
+const CHECKSUM_BLOCK_SIZE: usize = 1 << 26;
+
+/// The kinds of files that store an index over a protein database, or a part of it
+/// Every kind starts with its own magic bytes, followed by the same header, see `write_suffix_array` for its layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IndexFileKind {
+    SuffixArray,
+    FmIndex,
+    LcpLr,
+    TaxonIndex,
+}
+
+impl IndexFileKind {
+    /// All the kinds of index files
+    const ALL: [IndexFileKind; 4] =
+        [IndexFileKind::SuffixArray, IndexFileKind::FmIndex, IndexFileKind::LcpLr, IndexFileKind::TaxonIndex];
+
+    /// Returns the magic bytes at the start of a file of this kind
+    fn magic(self) -> &'static [u8; 4] {
+        match self {
+            IndexFileKind::SuffixArray => b"UPSA",
+            IndexFileKind::FmIndex => b"UPFM",
+            IndexFileKind::LcpLr => b"UPLR",
+            IndexFileKind::TaxonIndex => b"UPTX",
+        }
+    }
+
+    /// Returns the name of this kind that is used in error messages
+    fn name(self) -> &'static str {
+        match self {
+            IndexFileKind::SuffixArray => "suffix array index",
+            IndexFileKind::FmIndex => "FM-index",
+            IndexFileKind::LcpLr => "LCP-LR file",
+            IndexFileKind::TaxonIndex => "taxon index",
+        }
+    }
+
+    /// Returns the kind of the file that starts with the given magic bytes, or None if it is not an index file
+    fn from_magic(magic: &[u8]) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.magic() == magic)
+    }
+}
+
 /// Trait implemented by structs that are binary serializable
-/// In our case this is will be a [i64] since the suffix array is a Vec<i64>
+/// In our case this is will be a slice of suffix array entries, which are serialized as i64
 pub trait Serializable {
 
     /// Serializes self into a vector of bytes
@@ -17,11 +89,11 @@ pub trait Serializable {
     fn serialize(&self) -> Vec<u8>;
 }
 
-impl Serializable for [i64] {
+impl<T: SuffixEntry> Serializable for [T] {
     fn serialize(&self) -> Vec<u8> {
         let mut res = vec![];
-        self.iter().for_each(|entry|
-            res.extend_from_slice(&entry.to_le_bytes())
+        self.iter().for_each(|&entry|
+            res.extend_from_slice(&Into::<i64>::into(entry).to_le_bytes())
         );
         res
     }
@@ -46,10 +118,292 @@ fn deserialize_sa(data: &[u8]) -> Vec<i64> {
     res
 }
 
+/// Fingerprint of the protein database an index is built on
+/// An index is only loaded together with the database that has the same fingerprint
+///
+/// # Arguments
+/// * `text_length` - The length of the text with all the concatenated proteins
+/// * `protein_count` - The number of proteins in the text, which is the number of distinct sequences if they are deduplicated
+/// * `hash` - The hash of the text, together with the contents of the database file and the taxonomy file that were used to create it
+/// * `filter` - The filters that selected the proteins of the database file that are in the text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseFingerprint {
+    pub text_length: u64,
+    pub protein_count: u64,
+    pub hash: u64,
+    pub filter: ProteinFilter,
+}
+
+impl DatabaseFingerprint {
+    /// Calculates the fingerprint of the text with the concatenated proteins
+    /// The text that is already in memory is hashed together with the contents of the files,
+    /// so every change to the accessions or the taxa of the proteins is noticed, even if it does not change the text
+    ///
+    /// # Arguments
+    /// * `text` - The text with all the proteins, separated by the separation character
+    /// * `filenames` - The database file and the taxonomy file used to create the text
+    /// * `filter` - The filters that selected the proteins of the database file that are in the text
+    ///
+    /// # Returns
+    ///
+    /// Returns the fingerprint of the database
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the files could not be read
+    pub fn new(text: &[u8], filenames: &[&str], filter: &ProteinFilter) -> Result<Self, Box<dyn Error>> {
+        let mut writer = FingerprintWriter::new(std::io::sink());
+        writer.write_all(text)?;
+        Ok(writer.finish(filenames, filter)?.0)
+    }
+}
+
+/// Writer that calculates the fingerprint of the text that is written to it, so the text never has to be in memory completely
+///
+/// # Arguments
+/// * `inner` - The writer the text is written to
+/// * `hasher` - The hasher of the text
+/// * `text_length` - The number of bytes of the text that were written so far
+/// * `separators` - The number of separators in the text that was written so far
+pub struct FingerprintWriter<W: Write> {
+    inner: W,
+    hasher: Xxh3,
+    text_length: u64,
+    separators: u64,
+}
+
+impl<W: Write> FingerprintWriter<W> {
+    /// Creates a new FingerprintWriter that writes to `inner`
+    pub fn new(inner: W) -> Self {
+        FingerprintWriter {
+            inner,
+            hasher: Xxh3::new(),
+            text_length: 0,
+            separators: 0,
+        }
+    }
+
+    /// Finishes the fingerprint of the text that was written, see `DatabaseFingerprint::new`
+    ///
+    /// # Arguments
+    /// * `filenames` - The database file and the taxonomy file used to create the text
+    /// * `filter` - The filters that selected the proteins of the database file that are in the text
+    ///
+    /// # Returns
+    ///
+    /// Returns the fingerprint of the database and the inner writer
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the files could not be read
+    pub fn finish(mut self, filenames: &[&str], filter: &ProteinFilter) -> Result<(DatabaseFingerprint, W), Box<dyn Error>> {
+        for filename in filenames {
+            hash_file(&mut self.hasher, filename)?;
+        }
+
+        // the text always ends with the termination character, so every protein is followed by one separator
+        let protein_count = if self.text_length > 1 { self.separators + 1 } else { 0 };
+
+        let fingerprint = DatabaseFingerprint {
+            text_length: self.text_length,
+            protein_count,
+            hash: self.hasher.digest(),
+            filter: filter.clone(),
+        };
+        Ok((fingerprint, self.inner))
+    }
+}
+
+impl<W: Write> Write for FingerprintWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.text_length += written as u64;
+        self.separators += buf[..written].iter().filter(|&&character| character == SEPARATION_CHARACTER).count() as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Adds the contents of a file to a hash, followed by the length of the file so the boundaries between the files are part of the hash
+/// The file is read in parts, so it never has to be in memory completely
+///
+/// # Arguments
+/// * `hasher` - The hasher the contents are added to
+/// * `filename` - The name of the file that is hashed
+///
+/// # Errors
+///
+/// Returns an error if the file could not be read
+fn hash_file(hasher: &mut Xxh3, filename: &str) -> Result<(), Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(filename)?);
+    let mut length = 0_u64;
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+        hasher.update(buffer);
+        let count = buffer.len();
+        length += count as u64;
+        reader.consume(count);
+    }
+    hasher.update(&length.to_le_bytes());
+    Ok(())
+}
+
+/// Writer that calculates a checksum for every `CHECKSUM_BLOCK_SIZE` bytes that are written
+///
+/// # Arguments
+/// * `inner` - The writer the bytes are written to
+/// * `hasher` - The hasher of the current block
+/// * `block_length` - The number of bytes in the current block
+/// * `checksums` - The checksums of the finished blocks
+struct ChecksumWriter<W: Write> {
+    inner: W,
+    hasher: Xxh3,
+    block_length: usize,
+    checksums: Vec<u64>,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    /// Creates a new ChecksumWriter that writes to `inner`
+    fn new(inner: W) -> Self {
+        ChecksumWriter {
+            inner,
+            hasher: Xxh3::new(),
+            block_length: 0,
+            checksums: vec![],
+        }
+    }
+
+    /// Returns the number of bytes that were written so far
+    fn written(&self) -> usize {
+        self.checksums.len() * CHECKSUM_BLOCK_SIZE + self.block_length
+    }
+
+    /// Finishes the last block
+    ///
+    /// # Returns
+    ///
+    /// Returns the inner writer and the checksum of every block
+    fn finish(mut self) -> (W, Vec<u64>) {
+        if self.block_length > 0 {
+            self.checksums.push(self.hasher.digest());
+        }
+        (self.inner, self.checksums)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // never write past the end of the current block, so every block gets its own checksum
+        let length = min(buf.len(), CHECKSUM_BLOCK_SIZE - self.block_length);
+        let written = self.inner.write(&buf[..length])?;
+        self.hasher.update(&buf[..written]);
+        self.block_length += written;
+        if self.block_length == CHECKSUM_BLOCK_SIZE {
+            self.checksums.push(self.hasher.digest());
+            self.hasher.reset();
+            self.block_length = 0;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader of the payload of an index file that verifies the checksum of every block as soon as it is read completely
+///
+/// # Arguments
+/// * `inner` - The reader the payload is read from
+/// * `kind` - The kind of the index, used in the error messages
+/// * `hasher` - The hasher of the current block
+/// * `block_size` - The number of payload bytes covered by a single checksum
+/// * `block_length` - The number of bytes read from the current block
+/// * `block_index` - The index of the current block
+/// * `remaining` - The number of payload bytes that are not read yet
+/// * `checksums` - The checksums of all blocks, as stored at the end of the file
+struct ChecksumReader<R: Read> {
+    inner: R,
+    kind: IndexFileKind,
+    hasher: Xxh3,
+    block_size: usize,
+    block_length: usize,
+    block_index: usize,
+    remaining: usize,
+    checksums: Vec<u8>,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    /// Creates a new ChecksumReader that reads `payload_size` bytes from `inner`
+    fn new(inner: R, kind: IndexFileKind, block_size: usize, payload_size: usize, checksums: Vec<u8>) -> Self {
+        ChecksumReader {
+            inner,
+            kind,
+            hasher: Xxh3::new(),
+            block_size,
+            block_length: 0,
+            block_index: 0,
+            remaining: payload_size,
+            checksums,
+        }
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // never read past the end of the current block, so every block is verified on its own
+        let length = min(buf.len(), min(self.block_size - self.block_length, self.remaining));
+        if length == 0 {
+            return Ok(0);
+        }
+        let count = self.inner.read(&mut buf[..length])?;
+        if count == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        self.hasher.update(&buf[..count]);
+        self.block_length += count;
+        self.remaining -= count;
+
+        if self.block_length == self.block_size || self.remaining == 0 {
+            let offset = self.block_index * 8;
+            if offset + 8 > self.checksums.len() || self.hasher.digest() != u64_at(&self.checksums, offset) {
+                let message = format!("Block {} of the {} is corrupt (checksum mismatch)", self.block_index, self.kind.name());
+                return Err(std::io::Error::new(ErrorKind::InvalidData, message));
+            }
+            self.hasher.reset();
+            self.block_length = 0;
+            self.block_index += 1;
+        }
+        Ok(count)
+    }
+}
+
 /// Writes the given suffix array with the `sparseness_factor` factor to the given file
+/// The file starts with a header of `HEADER_SIZE` bytes, all integers are stored in little endian:
+/// - the magic bytes (4 bytes), `UPSA` for a suffix array, `UPFM` for an FM-index, `UPLR` for LCP-LR arrays and `UPTX` for a taxon index
+/// - the format version (2 bytes)
+/// - the flags (1 byte), of which the lowest bit indicates that I and L were folded and the next bit that a taxon index uses the LCA*
+/// - the sparseness factor (1 byte) and the number of bits per entry (1 byte)
+/// - the number of entries (8 bytes)
+/// - the length of the text (8 bytes), the number of proteins (8 bytes) and the hash of the database (8 bytes)
+/// - the number of bytes per checksum block (8 bytes) and the number of bytes of the payload (8 bytes)
+/// - the length of the serialized protein filters (4 bytes), followed by the filters themselves, see `ProteinFilter::serialize`
+/// - the checksum of the previous bytes of the header (8 bytes)
+///
+/// The header is followed by the payload, which are the entries of the suffix array, packed in `bits_per_value` bits each
+/// The file ends with a checksum (8 bytes) for every block of `CHECKSUM_BLOCK_SIZE` bytes of the payload
 ///
 /// # Arguments
 /// * `sparseness_factor` - The sparseness factor of the suffix array
+/// * `bits_per_value` - The number of bits used to store every entry of the suffix array
+/// * `database` - The fingerprint of the database the suffix array is built on
 /// * `suffix_array` - The suffix array
 /// * `filename` - The name of the file we want to write the suffix array to
 ///
@@ -59,30 +413,520 @@ fn deserialize_sa(data: &[u8]) -> Vec<i64> {
 ///
 /// # Errors
 ///
-/// Returns an io::Error if writing away the suffix array failed
-pub fn write_suffix_array(sparseness_factor: u8, suffix_array: &[i64], filename: &str) -> Result<(), std::io::Error> {
+/// Returns an io::Error if writing away the suffix array failed or if the entries do not fit in `bits_per_value` bits
+pub fn write_suffix_array<T: SuffixEntry>(
+    sparseness_factor: u8,
+    bits_per_value: u8,
+    database: &DatabaseFingerprint,
+    suffix_array: &[T],
+    filename: &str,
+) -> Result<(), std::io::Error> {
     // create the file
-    let mut f = OpenOptions::new()
+    let f = OpenOptions::new()
         .create(true)
         .write(true)
         .truncate(true) // if the file already exists, empty the file
         .open(filename)?;
-    f.write_all(&[sparseness_factor])?; // write the sample rate as the first byte
 
-    // write 1 GiB at a time, to minimize extra used memory since we need to translate i64 to [u8; 8]
-    let sa_len = suffix_array.len();
-    for start_index in (0..sa_len).step_by(ONE_GIB/8) {
-        let end_index = min(start_index + ONE_GIB/8, sa_len);
-        f.write_all(&suffix_array[start_index..end_index].serialize())?;
+    write_suffix_array_to(f, sparseness_factor, bits_per_value, database, suffix_array)?;
+    Ok(())
+}
+
+/// Writes the given suffix array in the format of `write_suffix_array` to the given writer
+///
+/// # Arguments
+/// * `f` - The writer to which the suffix array is written
+/// * `sparseness_factor` - The sparseness factor of the suffix array
+/// * `bits_per_value` - The number of bits used to store every entry of the suffix array, the entries must fit
+/// * `database` - The fingerprint of the database the suffix array is built on
+/// * `suffix_array` - The suffix array
+///
+/// # Returns
+///
+/// Returns the writer after the suffix array was written
+///
+/// # Errors
+///
+/// Returns an io::Error if writing away the suffix array failed, or if the entries do not fit in `bits_per_value` bits
+pub(crate) fn write_suffix_array_to<W: Write, T: SuffixEntry>(
+    f: W,
+    sparseness_factor: u8,
+    bits_per_value: u8,
+    database: &DatabaseFingerprint,
+    suffix_array: &[T],
+) -> Result<W, std::io::Error> {
+    check_bits_per_value(suffix_array, bits_per_value)
+        .map_err(|err| std::io::Error::new(ErrorKind::InvalidInput, err))?;
+
+    let mut writer = SuffixArrayWriter::new(f, sparseness_factor, bits_per_value, database, suffix_array.len())?;
+    writer.write_entries(suffix_array)?;
+    writer.finish()
+}
+
+/// Writer that writes a suffix array in the format of `write_suffix_array` while its entries are produced in order
+/// Only a part of the entries is buffered, so the complete suffix array never has to be in memory
+///
+/// # Arguments
+/// * `writer` - The writer of the file, which calculates the checksums of the entries
+/// * `bits_per_value` - The number of bits used to store every entry
+/// * `len` - The number of entries in the suffix array, which is stored in the header
+/// * `written` - The number of entries that were written so far
+/// * `buffer` - The entries that are not packed yet
+/// * `part_entries` - The number of entries that are packed and written at once, a multiple of 8
+pub struct SuffixArrayWriter<W: Write> {
+    writer: ChecksumWriter<W>,
+    bits_per_value: u8,
+    len: usize,
+    written: usize,
+    buffer: Vec<i64>,
+    part_entries: usize,
+}
+
+impl<W: Write> SuffixArrayWriter<W> {
+
+    /// Creates a new SuffixArrayWriter and writes the header of the suffix array
+    ///
+    /// # Arguments
+    /// * `f` - The writer to which the suffix array is written
+    /// * `sparseness_factor` - The sparseness factor of the suffix array
+    /// * `bits_per_value` - The number of bits used to store every entry of the suffix array
+    /// * `database` - The fingerprint of the database the suffix array is built on
+    /// * `len` - The number of entries that will be written
+    ///
+    /// # Returns
+    ///
+    /// Returns the SuffixArrayWriter that expects `len` entries
+    ///
+    /// # Errors
+    ///
+    /// Returns an io::Error if writing the header failed, or if `bits_per_value` is not between 1 and 64
+    pub fn new(
+        mut f: W,
+        sparseness_factor: u8,
+        bits_per_value: u8,
+        database: &DatabaseFingerprint,
+        len: usize,
+    ) -> Result<Self, std::io::Error> {
+        check_bits_per_value::<i64>(&[], bits_per_value).map_err(|err| std::io::Error::new(ErrorKind::InvalidInput, err))?;
+
+        let header = IndexHeader {
+            kind: IndexFileKind::SuffixArray,
+            version: FORMAT_VERSION,
+            il_folded: true,
+            lca_star: false,
+            sparseness_factor,
+            bits_per_value,
+            len,
+            database: database.clone(),
+            block_size: CHECKSUM_BLOCK_SIZE,
+            payload_size: payload_size(bits_per_value, len),
+        };
+        f.write_all(&header.serialize())?;
+
+        Ok(SuffixArrayWriter {
+            writer: ChecksumWriter::new(f),
+            bits_per_value,
+            len,
+            written: 0,
+            buffer: vec![],
+            part_entries: WRITE_PART_ENTRIES,
+        })
+    }
+
+    /// Limits the number of entries that are buffered before they are packed and written
+    ///
+    /// # Arguments
+    /// * `entries` - The maximum number of buffered entries, rounded down to a multiple of 8
+    ///
+    /// # Returns
+    ///
+    /// Returns the SuffixArrayWriter with the new limit
+    pub fn with_buffer_size(mut self, entries: usize) -> Self {
+        self.part_entries = (entries / 8).max(1) * 8;
+        self
+    }
+
+    /// Writes the next entries of the suffix array
+    ///
+    /// # Arguments
+    /// * `entries` - The next entries, in suffix array order
+    ///
+    /// # Errors
+    ///
+    /// Returns an io::Error if writing failed, if an entry does not fit in `bits_per_value` bits or if more than `len` entries are written
+    pub fn write_entries<T: SuffixEntry>(&mut self, entries: &[T]) -> Result<(), std::io::Error> {
+        if self.written + self.buffer.len() + entries.len() > self.len {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "More entries were written than stored in the header"));
+        }
+
+        // every part contains a multiple of 8 entries, so every packed part ends at a byte boundary
+        let mut entries = entries;
+        while !entries.is_empty() {
+            if self.buffer.is_empty() && entries.len() >= self.part_entries {
+                self.write_part(&entries[..self.part_entries])?;
+                entries = &entries[self.part_entries..];
+            } else {
+                let length = min(self.part_entries - self.buffer.len(), entries.len());
+                self.buffer.extend(entries[..length].iter().map(|&entry| Into::<i64>::into(entry)));
+                entries = &entries[length..];
+                if self.buffer.len() == self.part_entries {
+                    self.flush_buffer()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the remaining entries and the checksums
+    ///
+    /// # Returns
+    ///
+    /// Returns the writer after the suffix array was written
+    ///
+    /// # Errors
+    ///
+    /// Returns an io::Error if writing failed, or if not all `len` entries were written
+    pub fn finish(mut self) -> Result<W, std::io::Error> {
+        self.flush_buffer()?;
+        if self.written != self.len {
+            return Err(std::io::Error::new(ErrorKind::InvalidInput, "Fewer entries were written than stored in the header"));
+        }
+
+        let (mut f, checksums) = self.writer.finish();
+        let checksums: Vec<u8> = checksums.iter().flat_map(|checksum| checksum.to_le_bytes()).collect();
+        f.write_all(&checksums)?;
+        Ok(f)
+    }
+
+    /// Packs and writes the buffered entries
+    fn flush_buffer(&mut self) -> Result<(), std::io::Error> {
+        let buffer = std::mem::take(&mut self.buffer);
+        self.write_part(&buffer)?;
+        self.buffer = buffer;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Packs and writes a part of the entries
+    fn write_part<T: SuffixEntry>(&mut self, part: &[T]) -> Result<(), std::io::Error> {
+        check_bits_per_value(part, self.bits_per_value)
+            .map_err(|err| std::io::Error::new(ErrorKind::InvalidInput, err))?;
+        if self.bits_per_value == 64 {
+            self.writer.write_all(&part.serialize())?;
+        } else {
+            self.writer.write_all(&pack(part, self.bits_per_value))?;
+        }
+        self.written += part.len();
+        Ok(())
+    }
+}
+
+/// The header of an index file, see `write_suffix_array` for its layout
+///
+/// # Arguments
+/// * `kind` - The kind of index stored in the file
+/// * `version` - The version of the file format
+/// * `il_folded` - True if every L in the text was replaced by an I before building the index
+/// * `lca_star` - True if the taxa of a taxon index are aggregated with the LCA*, always false for the other kinds
+/// * `sparseness_factor` - The sparseness factor of the suffix array
+/// * `bits_per_value` - The number of bits used to store every entry
+/// * `len` - The number of entries in the suffix array, or the length of the text for an FM-index
+/// * `database` - The fingerprint of the database the index is built on
+/// * `block_size` - The number of payload bytes covered by a single checksum
+/// * `payload_size` - The number of bytes between the header and the checksums
+#[derive(Debug, PartialEq)]
+struct IndexHeader {
+    kind: IndexFileKind,
+    version: u16,
+    il_folded: bool,
+    lca_star: bool,
+    sparseness_factor: u8,
+    bits_per_value: u8,
+    len: usize,
+    database: DatabaseFingerprint,
+    block_size: usize,
+    payload_size: usize,
+}
+
+impl IndexHeader {
+    /// Serializes the header into `HEADER_SIZE` bytes, followed by the serialized protein filters
+    fn serialize(&self) -> Vec<u8> {
+        let filter = self.database.filter.serialize();
+        let mut header = Vec::with_capacity(HEADER_SIZE + filter.len());
+        header.extend_from_slice(self.kind.magic());
+        header.extend_from_slice(&self.version.to_le_bytes());
+        let il_folded = if self.il_folded { FLAG_IL_FOLDED } else { 0 };
+        let lca_star = if self.lca_star { FLAG_LCA_STAR } else { 0 };
+        header.push(il_folded | lca_star);
+        header.push(self.sparseness_factor);
+        header.push(self.bits_per_value);
+        for value in [
+            self.len as u64,
+            self.database.text_length,
+            self.database.protein_count,
+            self.database.hash,
+            self.block_size as u64,
+            self.payload_size as u64,
+        ] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        header.extend_from_slice(&(filter.len() as u32).to_le_bytes());
+        header.extend_from_slice(filter.as_bytes());
+        let checksum = xxh3_64(&header);
+        header.extend_from_slice(&checksum.to_le_bytes());
+        header
+    }
+
+    /// Returns the size of the header, including the serialized protein filters
+    fn size(&self) -> usize {
+        HEADER_SIZE + self.database.filter.serialize().len()
+    }
+
+    /// Returns the number of checksums at the end of the file
+    fn number_of_blocks(&self) -> usize {
+        self.payload_size.div_ceil(self.block_size)
+    }
+
+    /// Returns the size of the complete file described by this header
+    fn file_size(&self) -> usize {
+        self.size() + self.payload_size + 8 * self.number_of_blocks()
+    }
+}
+
+/// Reads a little endian u64 from `bytes` at `offset`
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Returns the size of the header that starts with the given bytes, which contain at least the bytes before the protein filters
+fn header_size(header: &[u8]) -> usize {
+    let filter_length = u32::from_le_bytes(header[FILTER_LENGTH_OFFSET..FILTER_LENGTH_OFFSET + 4].try_into().unwrap());
+    HEADER_SIZE + filter_length as usize
+}
+
+/// Reads the header of an index file written by `write_suffix_array`, `write_fm_index` or `write_lcp_lr`
+///
+/// # Arguments
+/// * `header` - The bytes of the file, starting with the header
+///
+/// # Returns
+///
+/// Returns the parsed header
+///
+/// # Errors
+///
+/// Returns an error if the file is not an index file, if it has another version or if the header is corrupt
+fn parse_header(header: &[u8]) -> Result<IndexHeader, Box<dyn Error>> {
+    if header.len() < HEADER_SIZE - 8 {
+        return Err("Could not read the header from the binary file".into());
+    }
+    let kind = IndexFileKind::from_magic(&header[..4])
+        .ok_or("The binary file is not an index file, or it was written by an older version that has no header")?;
+    let version = u16::from_le_bytes(header[4..6].try_into().unwrap());
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "The {} has format version {}, but only version {} is supported. Rebuild the index",
+            kind.name(), version, FORMAT_VERSION
+        )
+        .into());
+    }
+    let size = header_size(header);
+    if header.len() < size {
+        return Err("Could not read the header from the binary file".into());
+    }
+    if xxh3_64(&header[..size - 8]) != u64_at(header, size - 8) {
+        return Err(format!("The header of the {} is corrupt", kind.name()).into());
+    }
+
+    let bits_per_value = header[8];
+    if !(1..=64).contains(&bits_per_value) {
+        return Err(format!("Invalid number of bits per value in the binary file: {}", bits_per_value).into());
+    }
+    // the entries of a block are deserialized together, so a block has to contain a whole number of 64 bit entries
+    let block_size = u64_at(header, 41) as usize;
+    if block_size == 0 || !block_size.is_multiple_of(8) {
+        return Err(format!("The header of the {} is corrupt", kind.name()).into());
+    }
+
+    Ok(IndexHeader {
+        kind,
+        version,
+        il_folded: header[6] & FLAG_IL_FOLDED != 0,
+        lca_star: header[6] & FLAG_LCA_STAR != 0,
+        sparseness_factor: header[7],
+        bits_per_value,
+        len: u64_at(header, 9) as usize,
+        database: DatabaseFingerprint {
+            text_length: u64_at(header, 17),
+            protein_count: u64_at(header, 25),
+            hash: u64_at(header, 33),
+            filter: ProteinFilter::deserialize(std::str::from_utf8(&header[HEADER_SIZE - 8..size - 8])?)?,
+        },
+        block_size,
+        payload_size: u64_at(header, 49) as usize,
+    })
+}
+
+/// Reads the header of the index that is stored in the format of `write_suffix_array` in a file
+///
+/// # Arguments
+/// * `file` - The file in which the index is stored
+/// * `offset` - The position in the file where the index starts
+///
+/// # Returns
+///
+/// Returns the parsed header
+///
+/// # Errors
+///
+/// Returns an error if the header could not be read, or if it is not a valid header
+fn read_header(mut file: &File, offset: u64) -> Result<IndexHeader, Box<dyn Error>> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut header = vec![0_u8; HEADER_SIZE - 8];
+    file.read_exact(&mut header).map_err(|_| "Could not read the header from the binary file")?;
+    // the protein filters and the checksum follow the fixed part of the header, which has another layout in other versions
+    let remaining = (header_size(&header) - header.len()) as u64;
+    let supported = IndexFileKind::from_magic(&header[..4]).is_some() && header[4..6] == FORMAT_VERSION.to_le_bytes();
+    if supported && offset + header.len() as u64 + remaining <= file.metadata()?.len() {
+        file.take(remaining).read_to_end(&mut header)?;
+    }
+    parse_header(&header)
+}
+
+/// Reads the filters that selected the proteins of the database a suffix array or an FM-index was built on
+/// The database has to be loaded with the same filters to load the index
+///
+/// # Arguments
+/// * `filename` - The filename of the file where the index is stored
+///
+/// # Returns
+///
+/// Returns the protein filters stored in the header of the index
+///
+/// # Errors
+///
+/// Returns an error if the file could not be read, or if it does not start with a valid header
+pub fn read_protein_filter(filename: &str) -> Result<ProteinFilter, Box<dyn Error>> {
+    read_protein_filter_at(&File::open(filename)?, 0)
+}
+
+/// Reads the protein filters from the header of the suffix array that is stored in the format of `write_suffix_array` in a file
+///
+/// # Arguments
+/// * `file` - The file in which the suffix array is stored
+/// * `offset` - The position in the file where the suffix array starts
+///
+/// # Returns
+///
+/// Returns the protein filters stored in the header of the suffix array
+///
+/// # Errors
+///
+/// Returns an error if the header could not be read, or if it is not a valid header
+pub(crate) fn read_protein_filter_at(file: &File, offset: u64) -> Result<ProteinFilter, Box<dyn Error>> {
+    Ok(read_header(file, offset)?.database.filter)
+}
+
+/// Checks that the index described by the header is of the expected kind and can be used to search the given database
+///
+/// # Arguments
+/// * `header` - The header of the index file
+/// * `kind` - The kind of index that is loaded
+/// * `database` - The fingerprint of the database the index is loaded with
+///
+/// # Returns
+///
+/// Returns () if the index was built on the database
+///
+/// # Errors
+///
+/// Returns an error describing the difference if the file contains another kind of index, or if the index was built on another database or without folding I and L
+fn check_header(header: &IndexHeader, kind: IndexFileKind, database: &DatabaseFingerprint) -> Result<(), Box<dyn Error>> {
+    let name = kind.name();
+    if header.kind != kind {
+        return Err(format!("The binary file contains another kind of index: expected {}, found {}", name, header.kind.name()).into());
+    }
+    if kind == IndexFileKind::SuffixArray && header.payload_size != payload_size(header.bits_per_value, header.len) {
+        return Err(format!("The header of the {} is corrupt", name).into());
+    }
+    if !header.il_folded {
+        return Err(format!("The {} was built without replacing L by I, so it can not be searched", name).into());
     }
+    if header.database.text_length != database.text_length || header.database.protein_count != database.protein_count {
+        return Err(format!(
+            "The {} was built on a database with {} proteins ({} characters), but the provided database has {} proteins ({} characters)",
+            name, header.database.protein_count, header.database.text_length, database.protein_count, database.text_length
+        )
+        .into());
+    }
+    if header.database.hash != database.hash {
+        return Err(format!("The {} was built on another database or taxonomy file than the ones provided", name).into());
+    }
+    if header.database.filter != database.filter {
+        return Err(format!(
+            "The {} was built on the proteins selected by {}, but the provided database was loaded with {}",
+            name, header.database.filter, database.filter
+        )
+        .into());
+    }
+    Ok(())
+}
 
+/// Checks the checksums of the blocks of packed entries
+///
+/// # Arguments
+/// * `payload` - Consecutive blocks of packed entries, only the last block can be smaller than `block_size`
+/// * `first_block` - The index of the first block in `payload`
+/// * `block_size` - The number of payload bytes covered by a single checksum
+/// * `checksums` - The checksums of all blocks, as stored at the end of the file
+///
+/// # Returns
+///
+/// Returns () if all blocks match their checksum
+///
+/// # Errors
+///
+/// Returns an error with the index of the first block that does not match its checksum
+fn verify_blocks(payload: &[u8], first_block: usize, block_size: usize, checksums: &[u8]) -> Result<(), Box<dyn Error>> {
+    for (index, block) in payload.chunks(block_size).enumerate() {
+        let block_index = first_block + index;
+        if block_index * 8 + 8 > checksums.len() || xxh3_64(block) != u64_at(checksums, block_index * 8) {
+            return Err(format!("Block {} of the index is corrupt (checksum mismatch)", block_index).into());
+        }
+    }
     Ok(())
 }
 
+/// Returns the number of bytes needed to store `len` entries of `bits_per_value` bits
+fn payload_size(bits_per_value: u8, len: usize) -> usize {
+    (len * bits_per_value as usize).div_ceil(8)
+}
+
 /// Loads the suffix array from the file with the given `filename`
+/// The header and the checksums are verified, and the suffix array must be built on the given database
 ///
 /// # Arguments
 /// * `filename` - The filename of the file where the suffix array is stored
+/// * `database` - The fingerprint of the database the suffix array is loaded with
+///
+/// # Returns
+///
+/// Returns the sample rate of the suffix array, together with the suffix array
+/// A suffix array stored with 64 bits per entry is loaded as a Vec<i64>, otherwise the entries are kept bit-packed in memory
+///
+/// # Errors
+///
+/// Returns any error from opening the file or reading the file, or if the file is corrupt or was built on another database
+pub fn load_suffix_array(filename: &str, database: &DatabaseFingerprint) -> Result<(u8, Box<dyn SuffixArray>), Box<dyn Error>> {
+    read_suffix_array(&File::open(filename)?, 0, database)
+}
+
+/// Reads the suffix array that is stored in the format of `write_suffix_array` at the end of a file
+///
+/// # Arguments
+/// * `file` - The file in which the suffix array is stored
+/// * `offset` - The position in the file where the suffix array starts
+/// * `database` - The fingerprint of the database the suffix array is loaded with
 ///
 /// # Returns
 ///
@@ -90,31 +934,590 @@ pub fn write_suffix_array(sparseness_factor: u8, suffix_array: &[i64], filename:
 ///
 /// # Errors
 ///
-/// Returns any error from opening the file or reading the file
-pub fn load_suffix_array(filename: &str) -> Result<(u8, Vec<i64>), Box<dyn Error>> {
-    let mut file = &File::open(filename)?;
-    let mut sparseness_factor_buffer = [0_u8; 1];
-    file.read_exact(&mut sparseness_factor_buffer).map_err(|_| "Could not read the sample rate from the binary file")?;
-    let sparseness_factor = sparseness_factor_buffer[0];
+/// Returns any error from reading the file, or if the file is corrupt or was built on another database
+pub(crate) fn read_suffix_array(
+    mut file: &File,
+    offset: u64,
+    database: &DatabaseFingerprint,
+) -> Result<(u8, Box<dyn SuffixArray>), Box<dyn Error>> {
+    let header = read_header(file, offset)?;
+    check_header(&header, IndexFileKind::SuffixArray, database)?;
+    let IndexHeader { sparseness_factor, bits_per_value, len, block_size, .. } = header;
+
+    if file.metadata()?.len() != offset + header.file_size() as u64 {
+        return Err("The size of the binary file does not match the number of entries in the suffix array".into());
+    }
+
+    // the checksums are stored after the entries
+    let payload_start = offset + header.size() as u64;
+    let mut checksums = vec![0_u8; 8 * header.number_of_blocks()];
+    file.seek(SeekFrom::Start(payload_start + header.payload_size as u64))?;
+    file.read_exact(&mut checksums)?;
+    file.seek(SeekFrom::Start(payload_start))?;
+    let mut payload = file.take(header.payload_size as u64);
+
+    if bits_per_value != 64 {
+        let mut data = Vec::with_capacity(header.payload_size);
+        payload.read_to_end(&mut data)?;
+        verify_blocks(&data, 0, block_size, &checksums)?;
+        return Ok((sparseness_factor, Box::new(BitPackedSuffixArray::from_packed(data, bits_per_value, len))));
+    }
 
-    let mut sa = vec![];
+    // every part of 1 GiB contains a whole number of checksum blocks
+    let blocks_per_part = ONE_GIB.div_ceil(block_size);
+    let mut sa = Vec::with_capacity(len);
+    let mut first_block = 0;
     loop {
         let mut buffer = vec![];
         // use take in combination with read_to_end to ensure that the buffer will be completely filled (except when the file is smaller than the buffer)
-        let count = file.take(ONE_GIB as u64).read_to_end(&mut buffer)?;
+        let count = (&mut payload).take((blocks_per_part * block_size) as u64).read_to_end(&mut buffer)?;
         if count == 0 {
             break;
         }
+        verify_blocks(&buffer[..count], first_block, block_size, &checksums)?;
         sa.extend_from_slice(&deserialize_sa(&buffer[..count]));
+        first_block += blocks_per_part;
+    }
+    if sa.len() != len {
+        return Err("The size of the binary file does not match the number of entries in the suffix array".into());
+    }
+
+    Ok((sparseness_factor, Box::new(sa)))
+}
+
+/// Suffix array that is memory mapped from a file written by `write_suffix_array`
+/// The entries are read directly from the mapped file, so the suffix array is never copied into memory
+/// Multiple processes that map the same file share the pages of the file in the page cache
+///
+/// # Arguments
+/// * `mmap` - The memory mapped file, which contains the header followed by the suffix array
+/// * `payload_start` - The position in the file of the first entry, directly after the header
+/// * `bits_per_value` - The number of bits used to store every entry
+/// * `len` - The number of entries in the suffix array
+pub struct MmapSuffixArray {
+    mmap: Mmap,
+    payload_start: usize,
+    bits_per_value: u8,
+    len: usize,
+}
+
+impl SuffixArray for MmapSuffixArray {
+    #[inline]
+    fn get(&self, index: usize) -> i64 {
+        // skip the header, the checksums after the entries are never read
+        if self.bits_per_value == 64 {
+            let start = self.payload_start + index * 8;
+            i64::from_le_bytes(self.mmap[start..start + 8].try_into().unwrap())
+        } else {
+            read_packed(&self.mmap[self.payload_start..], self.bits_per_value, index)
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Memory maps the suffix array from the file with the given `filename`, instead of loading it into memory
+/// The header is verified and the suffix array must be built on the given database
+/// The checksums are only verified if asked, because that reads the complete file, while mapping it only reads the header
+///
+/// # Arguments
+/// * `filename` - The filename of the file where the suffix array is stored
+/// * `database` - The fingerprint of the database the suffix array is loaded with
+/// * `verify_checksums` - If true, the checksums of all the entries are verified before the suffix array is returned
+///
+/// # Returns
+///
+/// Returns the sample rate of the suffix array, together with the memory mapped suffix array
+///
+/// # Errors
+///
+/// Returns any error from opening or mapping the file, or if the file is corrupt or was built on another database
+pub fn map_suffix_array(
+    filename: &str,
+    database: &DatabaseFingerprint,
+    verify_checksums: bool,
+) -> Result<(u8, MmapSuffixArray), Box<dyn Error>> {
+    map_suffix_array_at(&File::open(filename)?, 0, database, verify_checksums)
+}
+
+/// Memory maps the suffix array that is stored in the format of `write_suffix_array` at the end of a file
+///
+/// # Arguments
+/// * `file` - The file in which the suffix array is stored
+/// * `offset` - The position in the file where the suffix array starts
+/// * `database` - The fingerprint of the database the suffix array is loaded with
+/// * `verify_checksums` - If true, the checksums of all the entries are verified before the suffix array is returned
+///
+/// # Returns
+///
+/// Returns the sample rate of the suffix array, together with the memory mapped suffix array
+///
+/// # Errors
+///
+/// Returns any error from mapping the file, or if the file is corrupt or was built on another database
+pub(crate) fn map_suffix_array_at(
+    file: &File,
+    offset: usize,
+    database: &DatabaseFingerprint,
+    verify_checksums: bool,
+) -> Result<(u8, MmapSuffixArray), Box<dyn Error>> {
+    // safety: the file is only read, the index files are not expected to be changed while they are in use
+    let mmap = unsafe { Mmap::map(file)? };
+    if mmap.len() < offset {
+        return Err("Could not read the header from the binary file".into());
+    }
+    let header = parse_header(&mmap[offset..])?;
+    check_header(&header, IndexFileKind::SuffixArray, database)?;
+    if mmap.len() != offset + header.file_size() {
+        return Err("The size of the binary file does not match the number of entries in the suffix array".into());
+    }
+    let payload_start = offset + header.size();
+    let payload_end = payload_start + header.payload_size;
+    if verify_checksums {
+        verify_blocks(&mmap[payload_start..payload_end], 0, header.block_size, &mmap[payload_end..])?;
+    }
+
+    let IndexHeader { sparseness_factor, bits_per_value, len, .. } = header;
+    Ok((sparseness_factor, MmapSuffixArray { mmap, payload_start, bits_per_value, len }))
+}
+
+/// Writes an index file: the header, the payload and the checksums of the payload, see `write_suffix_array` for the layout
+///
+/// # Arguments
+/// * `filename` - The name of the file we want to write the index to
+/// * `header` - The header of the file, which describes the payload
+/// * `write_payload` - Function that writes the `header.payload_size` bytes of the payload
+///
+/// # Returns
+///
+/// Returns () if writing away the index succeeded
+///
+/// # Errors
+///
+/// Returns an io::Error if writing away the index failed, or if the size of the written payload does not match the header
+fn write_index_file(
+    filename: &str,
+    header: &IndexHeader,
+    write_payload: impl FnOnce(&mut ChecksumWriter<BufWriter<File>>) -> Result<(), std::io::Error>,
+) -> Result<(), std::io::Error> {
+    let f = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true) // if the file already exists, empty the file
+        .open(filename)?;
+    let mut f = BufWriter::new(f);
+    f.write_all(&header.serialize())?;
+
+    let mut writer = ChecksumWriter::new(f);
+    write_payload(&mut writer)?;
+    if writer.written() != header.payload_size {
+        return Err(std::io::Error::new(ErrorKind::InvalidInput, "The size of the written payload does not match the header"));
+    }
+    let (mut f, checksums) = writer.finish();
+    let checksums: Vec<u8> = checksums.iter().flat_map(|checksum| checksum.to_le_bytes()).collect();
+    f.write_all(&checksums)?;
+    f.flush()
+}
+
+/// Reader of the payload of an index file opened by `open_index_file`
+type PayloadReader = ChecksumReader<BufReader<File>>;
+
+/// Opens an index file written by `write_index_file`, and checks its header and size
+///
+/// # Arguments
+/// * `filename` - The filename of the file where the index is stored
+/// * `kind` - The kind of index that is expected in the file
+/// * `database` - The fingerprint of the database the index is loaded with
+///
+/// # Returns
+///
+/// Returns the header, together with a reader of the payload that verifies the checksum of every block that is read
+///
+/// # Errors
+///
+/// Returns any error from opening or reading the file, or if the header is corrupt or does not match the kind and the database
+fn open_index_file(
+    filename: &str,
+    kind: IndexFileKind,
+    database: &DatabaseFingerprint,
+) -> Result<(IndexHeader, PayloadReader), Box<dyn Error>> {
+    let mut file = File::open(filename)?;
+    let header = read_header(&file, 0)?;
+    check_header(&header, kind, database)?;
+    if file.metadata()?.len() != header.file_size() as u64 {
+        return Err(format!("The size of the binary file does not match the header of the {}", kind.name()).into());
+    }
+
+    // the checksums are stored after the payload
+    let payload_start = header.size() as u64;
+    let mut checksums = vec![0_u8; 8 * header.number_of_blocks()];
+    file.seek(SeekFrom::Start(payload_start + header.payload_size as u64))?;
+    file.read_exact(&mut checksums)?;
+    file.seek(SeekFrom::Start(payload_start))?;
+
+    let reader = ChecksumReader::new(BufReader::new(file), kind, header.block_size, header.payload_size, checksums);
+    Ok((header, reader))
+}
+
+/// Returns the number of payload bytes of an FM-index file
+///
+/// # Arguments
+/// * `len` - The length of the text
+/// * `sample_rate` - The sample rate of the suffix array values
+/// * `bits_per_value` - The number of bits used to store every sample
+fn fm_index_payload_size(len: usize, sample_rate: usize, bits_per_value: u8) -> usize {
+    // a sample is stored for every suffix that starts at a multiple of the sample rate
+    8 + len + len.div_ceil(64) * 8 + payload_size(bits_per_value, len.div_ceil(sample_rate))
+}
+
+/// Writes the given FM-index to the given file
+/// The file has the header of `write_suffix_array`, the number of entries is the length of the text and the number of bits is the one of the samples
+/// The payload contains the sample rate (8 bytes), the BWT, the bitvector of the marked rows and the bit-packed samples
+///
+/// # Arguments
+/// * `fm_index` - The FM-index
+/// * `database` - The fingerprint of the database the FM-index is built on
+/// * `filename` - The name of the file we want to write the FM-index to
+///
+/// # Returns
+///
+/// Returns () if writing away the FM-index succeeded
+///
+/// # Errors
+///
+/// Returns an io::Error if writing away the FM-index failed
+pub fn write_fm_index(fm_index: &FmIndex, database: &DatabaseFingerprint, filename: &str) -> Result<(), std::io::Error> {
+    let samples = fm_index.samples();
+    let header = IndexHeader {
+        kind: IndexFileKind::FmIndex,
+        version: FORMAT_VERSION,
+        il_folded: true,
+        lca_star: false,
+        sparseness_factor: 1,
+        bits_per_value: samples.bits_per_value(),
+        len: fm_index.len(),
+        database: database.clone(),
+        block_size: CHECKSUM_BLOCK_SIZE,
+        payload_size: fm_index_payload_size(fm_index.len(), fm_index.sample_rate(), samples.bits_per_value()),
+    };
+
+    write_index_file(filename, &header, |writer| {
+        writer.write_all(&(fm_index.sample_rate() as u64).to_le_bytes())?;
+        writer.write_all(fm_index.bwt())?;
+        let marked: Vec<u8> = fm_index.marked().iter().flat_map(|word| word.to_le_bytes()).collect();
+        writer.write_all(&marked)?;
+        writer.write_all(samples.packed_data())
+    })
+}
+
+/// Reads a little endian u64 from the reader
+///
+/// # Arguments
+/// * `reader` - The reader to read from
+///
+/// # Returns
+///
+/// Returns the read value
+///
+/// # Errors
+///
+/// Returns an error if there are less than 8 bytes left
+fn read_u64(reader: &mut impl Read) -> Result<u64, Box<dyn Error>> {
+    let mut buffer = [0_u8; 8];
+    reader.read_exact(&mut buffer).map_err(|_| "Could not read the FM-index from the binary file")?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+/// Loads the FM-index from the file with the given `filename`
+/// The header and the checksums are verified, and the FM-index must be built on the given database
+///
+/// # Arguments
+/// * `filename` - The filename of the file where the FM-index is stored
+/// * `database` - The fingerprint of the database the FM-index is loaded with
+///
+/// # Returns
+///
+/// Returns the FM-index
+///
+/// # Errors
+///
+/// Returns any error from opening the file or reading the file, or if the file is corrupt or was built on another database
+pub fn load_fm_index(filename: &str, database: &DatabaseFingerprint) -> Result<FmIndex, Box<dyn Error>> {
+    let (header, mut reader) = open_index_file(filename, IndexFileKind::FmIndex, database)?;
+    let IndexHeader { len, bits_per_value, .. } = header;
+    let sample_rate = read_u64(&mut reader)? as usize;
+    if sample_rate == 0 || header.payload_size != fm_index_payload_size(len, sample_rate, bits_per_value) {
+        return Err("The size of the binary file does not match the sample rate of the FM-index".into());
     }
 
-    Ok((sparseness_factor, sa))
+    let mut bwt = vec![0_u8; len];
+    reader.read_exact(&mut bwt)?;
+
+    let mut marked_bytes = vec![0_u8; len.div_ceil(64) * 8];
+    reader.read_exact(&mut marked_bytes)?;
+    let marked: Vec<u64> = marked_bytes
+        .chunks_exact(8)
+        .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+        .collect();
+    drop(marked_bytes);
+
+    let number_of_samples = len.div_ceil(sample_rate);
+    let mut packed_samples = vec![0_u8; payload_size(bits_per_value, number_of_samples)];
+    reader.read_exact(&mut packed_samples)?;
+    let samples = BitPackedSuffixArray::from_packed(packed_samples, bits_per_value, number_of_samples);
+
+    Ok(FmIndex::from_parts(bwt, sample_rate, marked, samples))
+}
+
+/// Writes the given LCP-LR arrays to the given file
+/// The file has the header of `write_suffix_array`, with the number of entries of the suffix array and 16 bits per entry
+/// The payload contains the `left` array, directly followed by the `right` array
+///
+/// # Arguments
+/// * `lcp_lr` - The LCP-LR arrays of the suffix array
+/// * `sparseness_factor` - The sparseness factor of the suffix array
+/// * `database` - The fingerprint of the database the suffix array is built on
+/// * `filename` - The name of the file we want to write the LCP-LR arrays to
+///
+/// # Returns
+///
+/// Returns () if writing away the LCP-LR arrays succeeded
+///
+/// # Errors
+///
+/// Returns an io::Error if writing away the LCP-LR arrays failed
+pub fn write_lcp_lr(
+    lcp_lr: &LcpLr,
+    sparseness_factor: u8,
+    database: &DatabaseFingerprint,
+    filename: &str,
+) -> Result<(), std::io::Error> {
+    let header = IndexHeader {
+        kind: IndexFileKind::LcpLr,
+        version: FORMAT_VERSION,
+        il_folded: true,
+        lca_star: false,
+        sparseness_factor,
+        bits_per_value: 16,
+        len: lcp_lr.left.len(),
+        database: database.clone(),
+        block_size: CHECKSUM_BLOCK_SIZE,
+        payload_size: lcp_lr.left.len() + lcp_lr.right.len(),
+    };
+
+    write_index_file(filename, &header, |writer| {
+        writer.write_all(&lcp_lr.left)?;
+        writer.write_all(&lcp_lr.right)
+    })
+}
+
+/// Loads the LCP-LR arrays from the file with the given `filename`
+/// The header and the checksums are verified, and the arrays must be built on the given database
+///
+/// # Arguments
+/// * `filename` - The filename of the file where the LCP-LR arrays are stored
+/// * `sparseness_factor` - The sparseness factor of the suffix array the arrays are loaded with
+/// * `database` - The fingerprint of the database the suffix array is loaded with
+///
+/// # Returns
+///
+/// Returns the LCP-LR arrays
+///
+/// # Errors
+///
+/// Returns any error from opening the file or reading the file, or if the file is corrupt or was built for another suffix array
+pub fn load_lcp_lr(filename: &str, sparseness_factor: u8, database: &DatabaseFingerprint) -> Result<LcpLr, Box<dyn Error>> {
+    let (header, mut reader) = open_index_file(filename, IndexFileKind::LcpLr, database)?;
+    if header.payload_size != 2 * header.len {
+        return Err("The LCP-LR file does not contain two arrays of equal length".into());
+    }
+    if header.sparseness_factor != sparseness_factor {
+        return Err(format!(
+            "The LCP-LR arrays were built for a suffix array with sparseness factor {}, but the suffix array has sparseness factor {}",
+            header.sparseness_factor, sparseness_factor
+        )
+        .into());
+    }
+
+    let mut left = vec![0_u8; header.len];
+    reader.read_exact(&mut left)?;
+    let mut right = vec![0_u8; header.len];
+    reader.read_exact(&mut right)?;
+    Ok(LcpLr { left, right })
+}
+
+/// Writes the given taxon index to the given file
+/// The file has the header of `write_suffix_array`, with the number of entries of the suffix array and 32 bits per summary
+/// The payload contains all summaries of the index in the order of their `Layout`
+///
+/// # Arguments
+/// * `taxon_index` - The taxon index built over the suffix array
+/// * `sparseness_factor` - The sparseness factor of the suffix array
+/// * `database` - The fingerprint of the database the suffix array is built on
+/// * `filename` - The name of the file we want to write the taxon index to
+///
+/// # Returns
+///
+/// Returns () if writing away the taxon index succeeded
+///
+/// # Errors
+///
+/// Returns an io::Error if writing away the taxon index failed
+pub fn write_taxon_index(
+    taxon_index: &TaxonLcaIndex,
+    sparseness_factor: u8,
+    database: &DatabaseFingerprint,
+    filename: &str,
+) -> Result<(), std::io::Error> {
+    let number_of_summaries = taxon_index.number_of_summaries();
+    let header = IndexHeader {
+        kind: IndexFileKind::TaxonIndex,
+        version: FORMAT_VERSION,
+        il_folded: true,
+        lca_star: taxon_index.is_lca_star(),
+        sparseness_factor,
+        bits_per_value: 32,
+        len: taxon_index.len(),
+        database: database.clone(),
+        block_size: CHECKSUM_BLOCK_SIZE,
+        payload_size: 4 * number_of_summaries,
+    };
+
+    write_index_file(filename, &header, |writer| {
+        // the summaries are written in parts, to avoid a copy of the complete index in memory
+        let mut buffer = Vec::with_capacity(4 * min(number_of_summaries, WRITE_PART_ENTRIES));
+        for start in (0..number_of_summaries).step_by(WRITE_PART_ENTRIES) {
+            buffer.clear();
+            for index in start..min(start + WRITE_PART_ENTRIES, number_of_summaries) {
+                buffer.extend_from_slice(&taxon_index.summary_bits(index).to_le_bytes());
+            }
+            writer.write_all(&buffer)?;
+        }
+        Ok(())
+    })
+}
+
+/// Checks that the header of a taxon index belongs to the suffix array it is loaded with, and calculates the layout of its payload
+///
+/// # Arguments
+/// * `header` - The header of the taxon index file
+/// * `sparseness_factor` - The sparseness factor of the suffix array the taxon index is loaded with
+///
+/// # Returns
+///
+/// Returns the layout of the summaries in the payload
+///
+/// # Errors
+///
+/// Returns an error if the taxon index was built for a suffix array with another sparseness factor, or if its size does not match the header
+fn taxon_index_layout(header: &IndexHeader, sparseness_factor: u8) -> Result<Layout, Box<dyn Error>> {
+    if header.sparseness_factor != sparseness_factor {
+        return Err(format!(
+            "The taxon index was built for a suffix array with sparseness factor {}, but the suffix array has sparseness factor {}",
+            header.sparseness_factor, sparseness_factor
+        )
+        .into());
+    }
+    // the summaries of the valid taxa per suffix are only stored if the sequences are deduplicated
+    [false, true]
+        .into_iter()
+        .map(|deduplicated| Layout::new(header.len, deduplicated))
+        .find(|layout| header.bits_per_value == 32 && 4 * layout.size == header.payload_size)
+        .ok_or_else(|| "The header of the taxon index is corrupt".into())
+}
+
+/// Loads the taxon index from the file with the given `filename`
+/// The header and the checksums are verified, and the taxon index must be built on the given database
+///
+/// # Arguments
+/// * `filename` - The filename of the file where the taxon index is stored
+/// * `sparseness_factor` - The sparseness factor of the suffix array the taxon index is loaded with
+/// * `database` - The fingerprint of the database the suffix array is loaded with
+///
+/// # Returns
+///
+/// Returns the taxon index
+///
+/// # Errors
+///
+/// Returns any error from opening the file or reading the file, or if the file is corrupt or was built for another suffix array
+pub fn load_taxon_index(
+    filename: &str,
+    sparseness_factor: u8,
+    database: &DatabaseFingerprint,
+) -> Result<TaxonLcaIndex, Box<dyn Error>> {
+    let (header, mut reader) = open_index_file(filename, IndexFileKind::TaxonIndex, database)?;
+    let layout = taxon_index_layout(&header, sparseness_factor)?;
+
+    let mut summaries = Vec::with_capacity(layout.size);
+    let mut buffer = vec![0_u8; 4 * min(layout.size, WRITE_PART_ENTRIES)];
+    while summaries.len() < layout.size {
+        let count = min(layout.size - summaries.len(), WRITE_PART_ENTRIES);
+        reader.read_exact(&mut buffer[..4 * count])?;
+        summaries.extend(buffer[..4 * count].chunks_exact(4).map(|summary| u32::from_le_bytes(summary.try_into().unwrap())));
+    }
+
+    Ok(TaxonLcaIndex::from_parts(Summaries::Memory(summaries), layout, header.len, header.lca_star))
 }
 
+/// Memory maps the taxon index from the file with the given `filename`, instead of loading it into memory
+/// The header is verified and the taxon index must be built on the given database
+/// The checksums are only verified if asked, because that reads the complete file, while mapping it only reads the header
+///
+/// # Arguments
+/// * `filename` - The filename of the file where the taxon index is stored
+/// * `sparseness_factor` - The sparseness factor of the suffix array the taxon index is loaded with
+/// * `database` - The fingerprint of the database the suffix array is loaded with
+/// * `verify_checksums` - If true, the checksums of all the summaries are verified before the taxon index is returned
+///
+/// # Returns
+///
+/// Returns the memory mapped taxon index
+///
+/// # Errors
+///
+/// Returns any error from opening or mapping the file, or if the file is corrupt or was built for another suffix array
+pub fn map_taxon_index(
+    filename: &str,
+    sparseness_factor: u8,
+    database: &DatabaseFingerprint,
+    verify_checksums: bool,
+) -> Result<TaxonLcaIndex, Box<dyn Error>> {
+    // safety: the file is only read, the index files are not expected to be changed while they are in use
+    let mmap = unsafe { Mmap::map(&File::open(filename)?)? };
+    let header = parse_header(&mmap)?;
+    check_header(&header, IndexFileKind::TaxonIndex, database)?;
+    let layout = taxon_index_layout(&header, sparseness_factor)?;
+    if mmap.len() != header.file_size() {
+        return Err("The size of the binary file does not match the header of the taxon index".into());
+    }
+    let start = header.size();
+    let end = start + header.payload_size;
+    if verify_checksums {
+        verify_blocks(&mmap[start..end], 0, header.block_size, &mmap[end..])?;
+    }
+
+    Ok(TaxonLcaIndex::from_parts(Summaries::Mapped { mmap, start }, layout, header.len, header.lca_star))
+}
 
 #[cfg(test)]
 mod tests {
-    use crate::binary::{deserialize_sa, Serializable};
+    use sa_mappings::filter::ProteinFilter;
+    use sa_mappings::proteins::{Protein, Proteins};
+    use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+    use tempdir::TempDir;
+
+    use crate::binary::{
+        deserialize_sa, load_fm_index, load_lcp_lr, load_suffix_array, load_taxon_index, map_suffix_array,
+        map_taxon_index, read_protein_filter, write_fm_index, write_lcp_lr, write_suffix_array, write_taxon_index,
+        DatabaseFingerprint, Serializable, HEADER_SIZE,
+    };
+    use crate::fm_index::FmIndex;
+    use crate::lcp_lr::LcpLr;
+    use crate::taxon_lca_index::TaxonLcaIndex;
+    use crate::suffix_array::SuffixArray;
 
     #[test]
     fn test_serialize_deserialize() {
@@ -131,4 +1534,217 @@ mod tests {
         let deserialized = deserialize_sa(serialized.as_ref());
         assert_eq!(data, deserialized);
     }
-}
\ No newline at end of file
+
+    const DATABASE: DatabaseFingerprint = DatabaseFingerprint {
+        text_length: 2165487363,
+        protein_count: 4,
+        hash: 42,
+        filter: ProteinFilter::new(),
+    };
+
+    #[test]
+    fn test_database_fingerprint() {
+        let tmp_dir = TempDir::new("test_database_fingerprint").unwrap();
+        let database_path = tmp_dir.path().join("database.tsv");
+        let taxonomy_path = tmp_dir.path().join("taxonomy.tsv");
+        let database_file = database_path.to_str().unwrap();
+        let taxonomy_file = taxonomy_path.to_str().unwrap();
+        std::fs::write(database_file, "P12345\t2\tAI\tGO:0009279\nP54321\t2\tKC\tGO:0009279\n").unwrap();
+        std::fs::write(taxonomy_file, "1\troot\tno rank\t1\t\x01\n2\tBacteria\tsuperkingdom\t1\t\x01\n").unwrap();
+
+        let text = b"AI-KC$";
+        let database = DatabaseFingerprint::new(text, &[database_file, taxonomy_file], &ProteinFilter::new()).unwrap();
+        assert_eq!(database.text_length, 6);
+        assert_eq!(database.protein_count, 2);
+        assert_eq!(database, DatabaseFingerprint::new(text, &[database_file, taxonomy_file], &ProteinFilter::new()).unwrap());
+
+        // another accession of the same length does not change the text or the size of the database file
+        std::fs::write(database_file, "P12346\t2\tAI\tGO:0009279\nP54321\t2\tKC\tGO:0009279\n").unwrap();
+        let changed_database = DatabaseFingerprint::new(text, &[database_file, taxonomy_file], &ProteinFilter::new()).unwrap();
+        assert_ne!(changed_database.hash, database.hash);
+    }
+
+    #[test]
+    fn test_write_load_map_suffix_array() {
+        let tmp_dir = TempDir::new("test_write_load_map_suffix_array").unwrap();
+        let data: Vec<i64> = vec![5, 2165487362, 0, 12315135, 7, 1, 19];
+        for bits_per_value in [32, 35, 64] {
+            let path = tmp_dir.path().join(format!("test_write_load_map_suffix_array_{}.bin", bits_per_value));
+            let filename = path.to_str().unwrap();
+            write_suffix_array(3, bits_per_value, &DATABASE, &data, filename).unwrap();
+
+            let (sparseness_factor, loaded_sa) = load_suffix_array(filename, &DATABASE).unwrap();
+            let (mapped_sparseness_factor, mapped_sa) = map_suffix_array(filename, &DATABASE, true).unwrap();
+            assert_eq!(sparseness_factor, 3);
+            assert_eq!(mapped_sparseness_factor, 3);
+            assert_eq!(loaded_sa.len(), data.len());
+            assert_eq!(mapped_sa.len(), data.len());
+            for (index, &suffix) in data.iter().enumerate() {
+                assert_eq!(loaded_sa.get(index), suffix);
+                assert_eq!(mapped_sa.get(index), suffix);
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_suffix_array_too_few_bits() {
+        let tmp_dir = TempDir::new("test_write_suffix_array_too_few_bits").unwrap();
+        let data: Vec<i64> = vec![5, 2165487362];
+        let path = tmp_dir.path().join("test_write_suffix_array_too_few_bits.bin");
+        assert!(write_suffix_array(1, 31, &DATABASE, &data, path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_load_suffix_array_other_database() {
+        let tmp_dir = TempDir::new("test_load_suffix_array_other_database").unwrap();
+        let data: Vec<i64> = vec![5, 2165487362, 0, 12315135, 7, 1, 19];
+        let path = tmp_dir.path().join("test_load_suffix_array_other_database.bin");
+        let filename = path.to_str().unwrap();
+        write_suffix_array(1, 35, &DATABASE, &data, filename).unwrap();
+
+        for database in [
+            DatabaseFingerprint { text_length: 2165487364, ..DATABASE },
+            DatabaseFingerprint { protein_count: 5, ..DATABASE },
+            DatabaseFingerprint { hash: 43, ..DATABASE },
+            DatabaseFingerprint { filter: ProteinFilter { min_length: Some(5), ..ProteinFilter::new() }, ..DATABASE },
+        ] {
+            assert!(load_suffix_array(filename, &database).is_err());
+            assert!(map_suffix_array(filename, &database, false).is_err());
+        }
+    }
+
+    #[test]
+    fn test_write_load_protein_filter() {
+        let tmp_dir = TempDir::new("test_write_load_protein_filter").unwrap();
+        let data: Vec<i64> = vec![5, 2165487362, 0, 12315135, 7, 1, 19];
+        let path = tmp_dir.path().join("test_write_load_protein_filter.bin");
+        let filename = path.to_str().unwrap();
+        let database = DatabaseFingerprint {
+            filter: ProteinFilter { include_taxa: vec![2], max_length: Some(1000), ..ProteinFilter::new() },
+            ..DATABASE
+        };
+        write_suffix_array(1, 35, &database, &data, filename).unwrap();
+
+        assert_eq!(read_protein_filter(filename).unwrap(), database.filter);
+        let (_, loaded_sa) = load_suffix_array(filename, &database).unwrap();
+        let (_, mapped_sa) = map_suffix_array(filename, &database, false).unwrap();
+        assert_eq!(loaded_sa.get(1), 2165487362);
+        assert_eq!(mapped_sa.get(6), 19);
+        assert!(load_suffix_array(filename, &DATABASE).is_err());
+    }
+
+    #[test]
+    fn test_load_suffix_array_corrupt() {
+        let tmp_dir = TempDir::new("test_load_suffix_array_corrupt").unwrap();
+        let data: Vec<i64> = vec![5, 2165487362, 0, 12315135, 7, 1, 19];
+        let path = tmp_dir.path().join("test_load_suffix_array_corrupt.bin");
+        let filename = path.to_str().unwrap();
+        write_suffix_array(1, 64, &DATABASE, &data, filename).unwrap();
+        let original = std::fs::read(filename).unwrap();
+
+        // flip a bit in the magic bytes, the version, the header fields, an entry and a checksum
+        for position in [0, 4, 7, 20, HEADER_SIZE + 3, original.len() - 1] {
+            let mut corrupted = original.clone();
+            corrupted[position] ^= 1;
+            std::fs::write(filename, &corrupted).unwrap();
+
+            assert!(load_suffix_array(filename, &DATABASE).is_err());
+            assert!(map_suffix_array(filename, &DATABASE, true).is_err());
+            // without verifying the checksums, mapping only checks the header
+            assert_eq!(map_suffix_array(filename, &DATABASE, false).is_err(), position < HEADER_SIZE);
+        }
+
+        // a truncated file is refused as well
+        std::fs::write(filename, &original[..original.len() - 4]).unwrap();
+        assert!(load_suffix_array(filename, &DATABASE).is_err());
+        assert!(map_suffix_array(filename, &DATABASE, false).is_err());
+    }
+
+    #[test]
+    fn test_write_load_fm_index() {
+        let tmp_dir = TempDir::new("test_write_load_fm_index").unwrap();
+        let text = b"AI-BIACVAA-AC-KCRIZ$";
+        let sa: Vec<i64> = vec![19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18];
+        let fm_index = FmIndex::new(text, &sa, 3).unwrap();
+
+        let path = tmp_dir.path().join("test_write_load_fm_index.bin");
+        let filename = path.to_str().unwrap();
+        write_fm_index(&fm_index, &DATABASE, filename).unwrap();
+        let loaded_fm_index = load_fm_index(filename, &DATABASE).unwrap();
+
+        assert_eq!(loaded_fm_index.len(), sa.len());
+        assert_eq!(loaded_fm_index.sample_rate(), 3);
+        for (row, &suffix) in sa.iter().enumerate() {
+            assert_eq!(loaded_fm_index.get(row), suffix);
+        }
+        assert_eq!(loaded_fm_index.backward_search(b"AC"), Some((6, 8)));
+
+        // the FM-index is refused with another database, as another kind of index or when it is corrupt
+        let other_database = DatabaseFingerprint { hash: 43, ..DATABASE };
+        assert!(load_fm_index(filename, &other_database).is_err());
+        assert!(load_suffix_array(filename, &DATABASE).is_err());
+        let mut corrupt = std::fs::read(filename).unwrap();
+        corrupt[HEADER_SIZE + 10] ^= 1;
+        std::fs::write(filename, &corrupt).unwrap();
+        assert!(load_fm_index(filename, &DATABASE).is_err());
+    }
+
+    #[test]
+    fn test_write_load_lcp_lr() {
+        let tmp_dir = TempDir::new("test_write_load_lcp_lr").unwrap();
+        let lcp_lr = LcpLr { left: vec![0, 1, 2, 3, 4], right: vec![5, 6, 7, 8, 9] };
+
+        let path = tmp_dir.path().join("test_write_load_lcp_lr.bin");
+        let filename = path.to_str().unwrap();
+        write_lcp_lr(&lcp_lr, 2, &DATABASE, filename).unwrap();
+        let loaded_lcp_lr = load_lcp_lr(filename, 2, &DATABASE).unwrap();
+        assert_eq!(loaded_lcp_lr.left, lcp_lr.left);
+        assert_eq!(loaded_lcp_lr.right, lcp_lr.right);
+
+        // the arrays are refused for a suffix array with another sparseness factor or database
+        let other_database = DatabaseFingerprint { protein_count: 5, ..DATABASE };
+        assert!(load_lcp_lr(filename, 1, &DATABASE).is_err());
+        assert!(load_lcp_lr(filename, 2, &other_database).is_err());
+        assert!(load_fm_index(filename, &DATABASE).is_err());
+
+        let mut corrupt = std::fs::read(filename).unwrap();
+        let last_entry = corrupt.len() - 9;
+        corrupt[last_entry] ^= 1;
+        std::fs::write(filename, &corrupt).unwrap();
+        assert!(load_lcp_lr(filename, 2, &DATABASE).is_err());
+    }
+
+    #[test]
+    fn test_write_load_map_taxon_index() {
+        let tmp_dir = TempDir::new("test_write_load_map_taxon_index").unwrap();
+        let proteins = Proteins::new(
+            b"AC-KC-W$".to_vec(),
+            [13, 14, 19].map(|taxon_id| Protein { uniprot_id: "", taxon_id, functional_annotations: &[] }),
+        )
+        .unwrap();
+        let sa: Vec<i64> = vec![7, 2, 5, 0, 1, 4, 3, 6];
+        let taxon_aggregator =
+            TaxonAggregator::try_from_taxonomy_file("../testfiles/small_taxonomy.tsv", AggregationMethod::LcaStar).unwrap();
+        let taxon_index = TaxonLcaIndex::new(&sa, &proteins, &taxon_aggregator);
+
+        let path = tmp_dir.path().join("test_write_load_map_taxon_index.bin");
+        let filename = path.to_str().unwrap();
+        write_taxon_index(&taxon_index, 1, &DATABASE, filename).unwrap();
+        let loaded_index = load_taxon_index(filename, 1, &DATABASE).unwrap();
+        let mapped_index = map_taxon_index(filename, 1, &DATABASE, true).unwrap();
+        for index in [&loaded_index, &mapped_index] {
+            assert_eq!(index.len(), sa.len());
+            assert!(index.is_lca_star());
+            for sa_index in 0..sa.len() {
+                assert_eq!(index.taxon(sa_index, false, &taxon_aggregator), taxon_index.taxon(sa_index, false, &taxon_aggregator));
+            }
+            assert_eq!(index.range_lca(0, sa.len(), false, &taxon_aggregator).taxon(), 10);
+        }
+
+        // the taxon index is refused for a suffix array with another sparseness factor or database
+        let other_database = DatabaseFingerprint { hash: 43, ..DATABASE };
+        assert!(load_taxon_index(filename, 3, &DATABASE).is_err());
+        assert!(map_taxon_index(filename, 1, &other_database, false).is_err());
+        assert!(load_lcp_lr(filename, 1, &DATABASE).is_err());
+    }
+}
@@ -0,0 +1,451 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::ops::Range;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+use sa_mappings::filter::ProteinFilter;
+use sa_mappings::proteins::{ProteinArrays, ProteinSection, Proteins};
+use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::binary::{map_suffix_array_at, read_protein_filter_at, read_suffix_array, write_suffix_array_to, DatabaseFingerprint};
+use crate::suffix_array::{SuffixArray, SuffixEntry};
+
+/// The magic bytes at the start of every bundle file
+const MAGIC: &[u8; 4] = b"UPBN";
+
+/// The version of the bundle file format, files with another version can not be loaded
+pub const BUNDLE_VERSION: u16 = 2;
+
+/// Flag in the header that is set if the taxonomy is embedded in the bundle
+const FLAG_TAXONOMY: u8 = 1;
+
+/// The size of the header of a bundle file, see `write_bundle` for its layout
+const BUNDLE_HEADER_SIZE: usize = 39;
+
+/// Everything that is needed to search in the index, loaded from a single bundle file
+///
+/// # Arguments
+/// * `proteins` - The proteins in the database, together with the concatenated text
+/// * `sparseness_factor` - The sparseness factor of the suffix array
+/// * `suffix_array` - The suffix array built over the text
+/// * `taxonomy` - The taxonomy in the binary form of `taxonomy_file_to_binary`, if it is embedded in the bundle
+/// * `database` - The fingerprint of the database the suffix array is built on, to load the files that belong to the bundle
+pub struct IndexBundle {
+    pub proteins: Proteins,
+    pub sparseness_factor: u8,
+    pub suffix_array: Box<dyn SuffixArray>,
+    pub taxonomy: Option<Vec<u8>>,
+    pub database: DatabaseFingerprint,
+}
+
+impl IndexBundle {
+
+    /// Loads the taxonomy used to search in the bundle
+    ///
+    /// # Arguments
+    /// * `taxonomy_file` - The taxonomy file that is used, or None to use the taxonomy embedded in the bundle
+    /// * `method` - The aggregation method used by the taxonomy
+    ///
+    /// # Returns
+    ///
+    /// Returns the taxonomy from the file if it was provided, otherwise the embedded taxonomy
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no taxonomy file was provided and no taxonomy is embedded in the bundle, or if the taxonomy could not be read
+    pub fn taxon_aggregator(&self, taxonomy_file: Option<&str>, method: AggregationMethod) -> Result<TaxonAggregator, Box<dyn Error>> {
+        match (taxonomy_file, &self.taxonomy) {
+            (Some(taxonomy_file), _) => TaxonAggregator::try_from_taxonomy_file(taxonomy_file, method),
+            (None, Some(taxonomy)) => TaxonAggregator::try_from_taxonomy_binary(taxonomy, method),
+            (None, None) => Err("The bundle does not contain a taxonomy, so a taxonomy file has to be provided".into()),
+        }
+    }
+}
+
+/// Writes the proteins, the suffix array and optionally the taxonomy to a single bundle file
+/// The file starts with a header of `BUNDLE_HEADER_SIZE` bytes, all integers are stored in little endian:
+/// - the magic bytes `UPBN` and the format version (u16)
+/// - the flags (u8), where `FLAG_TAXONOMY` is set if the taxonomy is embedded
+/// - the text length, the number of entries in the text and the hash of the database fingerprint (3 x u64)
+/// - the xxh3 checksum of the previous bytes of the header (u64)
+///
+/// The header is followed by the sections below, every section is stored as its length in bytes (u64), the bytes and their xxh3 checksum (u64):
+/// - the concatenated protein text
+/// - the index of the first protein of every entry in the text, followed by the number of proteins (u32), empty if every protein has its own entry
+/// - the taxon of every protein (u32)
+/// - the start of the accession of every protein in the accession bytes, followed by the total length (u64)
+/// - the accessions of all proteins, concatenated
+/// - the start of the annotations of every protein in the annotation bytes, followed by the total length (u64)
+/// - the encoded functional annotations of all proteins, concatenated
+/// - the taxonomy in the binary form of `taxonomy_file_to_binary`, only if `FLAG_TAXONOMY` is set
+///
+/// The file ends with the suffix array, in the format of `write_suffix_array`
+///
+/// # Arguments
+/// * `proteins` - The proteins in the database, together with the concatenated text
+/// * `taxonomy` - The binary form of the taxonomy that is embedded, or None to not embed the taxonomy
+/// * `sparseness_factor` - The sparseness factor of the suffix array
+/// * `bits_per_value` - The number of bits used to store every entry of the suffix array, the entries must fit
+/// * `database` - The fingerprint of the database the suffix array is built on
+/// * `suffix_array` - The suffix array
+/// * `filename` - The name of the file the bundle will be written to
+///
+/// # Errors
+///
+/// Returns an error if writing away the bundle failed
+pub fn write_bundle<T: SuffixEntry>(
+    proteins: &Proteins,
+    taxonomy: Option<&[u8]>,
+    sparseness_factor: u8,
+    bits_per_value: u8,
+    database: &DatabaseFingerprint,
+    suffix_array: &[T],
+    filename: &str,
+) -> Result<(), Box<dyn Error>> {
+    let entry_starts: Vec<u8> = proteins.entry_starts.iter().flat_map(|start| start.to_le_bytes()).collect();
+    let entry_count = proteins.entry_starts.len().checked_sub(1).unwrap_or(proteins.len());
+
+    let mut header = Vec::with_capacity(BUNDLE_HEADER_SIZE);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&BUNDLE_VERSION.to_le_bytes());
+    header.push(if taxonomy.is_some() { FLAG_TAXONOMY } else { 0 });
+    header.extend_from_slice(&(proteins.input_string.len() as u64).to_le_bytes());
+    header.extend_from_slice(&(entry_count as u64).to_le_bytes());
+    header.extend_from_slice(&database.hash.to_le_bytes());
+    header.extend_from_slice(&xxh3_64(&header).to_le_bytes());
+
+    // create the file
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true) // if the file already exists, empty the file
+        .open(filename)?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(&header)?;
+    // the flat arrays of the proteins are written as they are stored
+    let arrays = proteins.arrays();
+    write_section(&mut writer, &proteins.input_string)?;
+    write_section(&mut writer, &entry_starts)?;
+    for section in [&arrays.taxa, &arrays.accession_offsets, &arrays.accessions, &arrays.annotation_offsets, &arrays.annotations] {
+        write_section(&mut writer, (**section).as_ref())?;
+    }
+    if let Some(taxonomy) = taxonomy {
+        write_section(&mut writer, taxonomy)?;
+    }
+
+    let mut writer = write_suffix_array_to(writer, sparseness_factor, bits_per_value, database, suffix_array)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Loads a bundle written by `write_bundle`
+/// The proteins are kept in the flat arrays of the bundle, so the database does not have to be parsed again and no protein is copied out of the arrays
+///
+/// # Arguments
+/// * `filename` - The name of the bundle file
+/// * `memory_map` - If true, the suffix array and the arrays of the proteins are memory mapped instead of loaded into memory
+/// * `verify_checksums` - If true, the checksums of the memory mapped parts are verified, which reads them completely
+///
+/// # Returns
+///
+/// Returns the proteins, the suffix array and the embedded taxonomy stored in the bundle
+///
+/// # Errors
+///
+/// Returns any error from reading the file, or if the file is not a valid bundle or is corrupt
+pub fn load_bundle(filename: &str, memory_map: bool, verify_checksums: bool) -> Result<IndexBundle, Box<dyn Error>> {
+    let file = File::open(filename)?;
+    let mut reader = BufReader::new(&file);
+
+    let mut header = [0_u8; BUNDLE_HEADER_SIZE];
+    reader.read_exact(&mut header).map_err(|_| "Could not read the header from the bundle file")?;
+    if &header[0..4] != MAGIC {
+        return Err("The file is not an index bundle".into());
+    }
+    let version = u16::from_le_bytes([header[4], header[5]]);
+    if version != BUNDLE_VERSION {
+        return Err(format!("The bundle has format version {}, but only version {} is supported", version, BUNDLE_VERSION).into());
+    }
+    if xxh3_64(&header[..BUNDLE_HEADER_SIZE - 8]) != u64_at(&header, BUNDLE_HEADER_SIZE - 8) {
+        return Err("The header of the bundle file is corrupt".into());
+    }
+    let has_taxonomy = header[6] & FLAG_TAXONOMY != 0;
+    let mut database = DatabaseFingerprint {
+        text_length: u64_at(&header, 7),
+        protein_count: u64_at(&header, 15),
+        hash: u64_at(&header, 23),
+        filter: ProteinFilter::new(),
+    };
+
+    let input_string = read_section(&mut reader)?;
+    let entry_starts = read_section(&mut reader)?;
+    let entry_starts: Vec<u32> = entry_starts.chunks_exact(4).map(|start| u32::from_le_bytes(start.try_into().unwrap())).collect();
+
+    // safety: the file is only read, the index files are not expected to be changed while they are in use
+    let mmap = if memory_map { Some(Arc::new(unsafe { Mmap::map(&file)? })) } else { None };
+    let mut protein_section = || -> Result<ProteinSection, Box<dyn Error>> {
+        match &mmap {
+            Some(mmap) => Ok(Box::new(map_section(&mut reader, mmap, verify_checksums)?)),
+            None => Ok(Box::new(read_section(&mut reader)?)),
+        }
+    };
+    let arrays = ProteinArrays {
+        taxa: protein_section()?,
+        accession_offsets: protein_section()?,
+        accessions: protein_section()?,
+        annotation_offsets: protein_section()?,
+        annotations: protein_section()?,
+    };
+    let taxonomy = if has_taxonomy { Some(read_section(&mut reader)?) } else { None };
+
+    let proteins = Proteins::from_arrays(input_string, arrays, entry_starts)?;
+    let entry_count = proteins.entry_starts.len().checked_sub(1).unwrap_or(proteins.len());
+    if proteins.input_string.len() as u64 != database.text_length || entry_count as u64 != database.protein_count {
+        return Err("The sections of the bundle file do not match its header".into());
+    }
+
+    // the suffix array is stored after the last section, the proteins in the bundle were selected by the filters stored in its header
+    let offset = reader.stream_position()?;
+    database.filter = read_protein_filter_at(&file, offset)?;
+    let (sparseness_factor, suffix_array): (u8, Box<dyn SuffixArray>) = if memory_map {
+        let (sparseness_factor, sa) = map_suffix_array_at(&file, offset as usize, &database, verify_checksums)?;
+        (sparseness_factor, Box::new(sa))
+    } else {
+        read_suffix_array(&file, offset, &database)?
+    };
+
+    Ok(IndexBundle {
+        proteins,
+        sparseness_factor,
+        suffix_array,
+        taxonomy,
+        database,
+    })
+}
+
+/// Writes a section of the bundle: its length, its bytes and their checksum
+///
+/// # Arguments
+/// * `writer` - The writer to which the section is written
+/// * `data` - The bytes of the section
+///
+/// # Errors
+///
+/// Returns an io::Error if writing away the section failed
+fn write_section(writer: &mut impl Write, data: &[u8]) -> Result<(), std::io::Error> {
+    writer.write_all(&(data.len() as u64).to_le_bytes())?;
+    writer.write_all(data)?;
+    writer.write_all(&xxh3_64(data).to_le_bytes())
+}
+
+/// Reads a section of the bundle written by `write_section` and verifies its checksum
+///
+/// # Arguments
+/// * `reader` - The reader from which the section is read
+///
+/// # Returns
+///
+/// Returns the bytes of the section
+///
+/// # Errors
+///
+/// Returns an error if reading the section failed, or if the section is truncated or corrupt
+fn read_section(reader: &mut impl Read) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut length = [0_u8; 8];
+    reader.read_exact(&mut length).map_err(|_| "The bundle file is truncated")?;
+    let length = u64::from_le_bytes(length);
+
+    // use take in combination with read_to_end, so a corrupt length does not allocate a huge buffer up front
+    let mut data = vec![];
+    if reader.take(length).read_to_end(&mut data)? as u64 != length {
+        return Err("The bundle file is truncated".into());
+    }
+    let mut checksum = [0_u8; 8];
+    reader.read_exact(&mut checksum).map_err(|_| "The bundle file is truncated")?;
+    if xxh3_64(&data) != u64::from_le_bytes(checksum) {
+        return Err("A section of the bundle file is corrupt".into());
+    }
+
+    Ok(data)
+}
+
+/// Maps a section of the bundle written by `write_section`, instead of reading it into memory
+///
+/// # Arguments
+/// * `reader` - The reader of the bundle file, which is moved past the section
+/// * `mmap` - The memory mapped bundle file
+/// * `verify_checksum` - If true, the checksum of the section is verified, which reads the complete section
+///
+/// # Returns
+///
+/// Returns the section in the memory mapped file
+///
+/// # Errors
+///
+/// Returns an error if the section is truncated, or if the checksum is verified and the section is corrupt
+fn map_section(reader: &mut (impl Read + Seek), mmap: &Arc<Mmap>, verify_checksum: bool) -> Result<MappedSection, Box<dyn Error>> {
+    let mut length = [0_u8; 8];
+    reader.read_exact(&mut length).map_err(|_| "The bundle file is truncated")?;
+    let length = u64::from_le_bytes(length);
+
+    let start = reader.stream_position()?;
+    let end = start.checked_add(length).filter(|&end| end + 8 <= mmap.len() as u64).ok_or("The bundle file is truncated")?;
+    let range = start as usize..end as usize;
+    if verify_checksum && xxh3_64(&mmap[range.clone()]) != u64_at(mmap, range.end) {
+        return Err("A section of the bundle file is corrupt".into());
+    }
+    reader.seek(std::io::SeekFrom::Start(end + 8))?;
+
+    Ok(MappedSection { mmap: Arc::clone(mmap), range })
+}
+
+/// A section of a memory mapped bundle file, which is read directly from the file
+/// All sections share the same memory mapped file
+///
+/// # Arguments
+/// * `mmap` - The memory mapped bundle file
+/// * `range` - The position of the bytes of the section in the file
+struct MappedSection {
+    mmap: Arc<Mmap>,
+    range: Range<usize>,
+}
+
+impl AsRef<[u8]> for MappedSection {
+    fn as_ref(&self) -> &[u8] {
+        &self.mmap[self.range.clone()]
+    }
+}
+
+/// Reads a little endian u64 from the given position in a slice
+fn u64_at(data: &[u8], position: usize) -> u64 {
+    u64::from_le_bytes(data[position..position + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use sa_mappings::filter::ProteinFilter;
+    use sa_mappings::proteins::{Protein, Proteins};
+    use sa_mappings::taxonomy::{taxonomy_file_to_binary, AggregationMethod};
+    use tempdir::TempDir;
+
+    use crate::binary::DatabaseFingerprint;
+    use crate::bundle::{load_bundle, write_bundle, BUNDLE_HEADER_SIZE};
+
+    fn create_proteins() -> Proteins {
+        Proteins::new(
+            "AI-BLACVAA-AC-KCRLZ$".to_string().into_bytes(),
+            [("P1", 7, &[][..]), ("P12345", 9, &[0xD1, 0x11]), ("", 9, &[0x27]), ("Q9", 13, &[])].map(
+                |(uniprot_id, taxon_id, functional_annotations)| Protein { uniprot_id, taxon_id, functional_annotations },
+            ),
+        )
+        .unwrap()
+    }
+
+    const DATABASE: DatabaseFingerprint = DatabaseFingerprint {
+        text_length: 20,
+        protein_count: 4,
+        hash: 42,
+        filter: ProteinFilter::new(),
+    };
+
+    const SA: [i64; 7] = [19, 10, 9, 0, 12, 15, 6];
+
+    #[test]
+    fn test_write_load_bundle() {
+        let tmp_dir = TempDir::new("test_write_load_bundle").unwrap();
+        let proteins = create_proteins();
+        let taxonomy_path = tmp_dir.path().join("test_write_load_bundle_taxonomy.tsv");
+        std::fs::write(&taxonomy_path, "1\troot\tno rank\t1\t\x01\n2\tBacteria\tsuperkingdom\t1\t\x01\n").unwrap();
+        let binary_taxonomy = taxonomy_file_to_binary(taxonomy_path.to_str().unwrap()).unwrap();
+        let filtered_database = DatabaseFingerprint { filter: ProteinFilter { exclude_taxa: vec![2], ..ProteinFilter::new() }, ..DATABASE };
+        for (taxonomy, memory_map, database) in [
+            (None, false, DATABASE),
+            (Some(binary_taxonomy.as_slice()), true, filtered_database),
+        ] {
+            let path = tmp_dir.path().join(format!("test_write_load_bundle_{}.bin", memory_map));
+            let filename = path.to_str().unwrap();
+            write_bundle(&proteins, taxonomy, 3, 5, &database, &SA, filename).unwrap();
+
+            let bundle = load_bundle(filename, memory_map, true).unwrap();
+            assert_eq!(bundle.proteins.input_string, proteins.input_string);
+            assert_eq!(bundle.proteins.len(), proteins.len());
+            assert!(bundle.proteins.iter().eq(proteins.iter()));
+            assert_eq!(bundle.sparseness_factor, 3);
+            assert_eq!(bundle.suffix_array.len(), SA.len());
+            for (index, &suffix) in SA.iter().enumerate() {
+                assert_eq!(bundle.suffix_array.get(index), suffix);
+            }
+            assert_eq!(bundle.taxonomy.as_deref(), taxonomy);
+            let taxon_aggregator = bundle.taxon_aggregator(None, AggregationMethod::LcaStar);
+            assert_eq!(taxon_aggregator.is_ok(), taxonomy.is_some());
+            if let Ok(taxon_aggregator) = taxon_aggregator {
+                assert!(taxon_aggregator.taxon_exists(2));
+                assert!(!taxon_aggregator.taxon_exists(3));
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_load_deduplicated_bundle() {
+        let tmp_dir = TempDir::new("test_write_load_deduplicated_bundle").unwrap();
+        let mut proteins = create_proteins();
+        proteins.input_string = b"AI-BLACVAA-AI-BLACVAA$".to_vec();
+        proteins.deduplicate();
+        let database = DatabaseFingerprint {
+            text_length: 11,
+            protein_count: 2,
+            filter: ProteinFilter { deduplicate: true, ..ProteinFilter::new() },
+            ..DATABASE
+        };
+        let path = tmp_dir.path().join("test_write_load_deduplicated_bundle.bin");
+        let filename = path.to_str().unwrap();
+        write_bundle(&proteins, None, 1, 64, &database, &[10, 2, 9, 8, 5, 0, 3, 6, 1, 4, 7], filename).unwrap();
+
+        let bundle = load_bundle(filename, false, false).unwrap();
+        assert_eq!(bundle.proteins.input_string, b"AI-BLACVAA$".to_vec());
+        assert_eq!(bundle.proteins.entry_starts, vec![0, 2, 4]);
+        let uniprot_ids: Vec<&str> = bundle.proteins.entry(1).map(|protein| protein.uniprot_id).collect();
+        assert_eq!(uniprot_ids, vec!["P12345", "Q9"]);
+    }
+
+    #[test]
+    fn test_load_bundle_corrupt() {
+        let tmp_dir = TempDir::new("test_load_bundle_corrupt").unwrap();
+        let path = tmp_dir.path().join("test_load_bundle_corrupt.bin");
+        let filename = path.to_str().unwrap();
+        write_bundle(&create_proteins(), None, 1, 64, &DATABASE, &SA, filename).unwrap();
+        let original = std::fs::read(filename).unwrap();
+
+        // every section is stored as its length, its bytes and their checksum, see `write_bundle`
+        // the sections before the annotation offsets are the text, the entries, the taxa, the accession offsets and the accessions
+        let proteins = create_proteins();
+        let accessions_size = proteins.iter().map(|protein| protein.uniprot_id.len()).sum::<usize>();
+        let section_sizes = [proteins.input_string.len(), 0, 4 * proteins.len(), 8 * (proteins.len() + 1), accessions_size];
+        let accessions = BUNDLE_HEADER_SIZE + section_sizes[..4].iter().map(|size| size + 16).sum::<usize>() + 8;
+        let annotation_offsets = accessions + accessions_size + 16;
+
+        // flip a bit in the header, the text, the annotation offsets and the suffix array
+        for position in [0, 10, BUNDLE_HEADER_SIZE + 9, annotation_offsets + 8, original.len() - 1] {
+            let mut corrupt = original.clone();
+            corrupt[position] ^= 1;
+            std::fs::write(filename, &corrupt).unwrap();
+            assert!(load_bundle(filename, false, false).is_err());
+            assert!(load_bundle(filename, true, true).is_err());
+        }
+
+        // without verifying the checksums, a mapped bundle is still refused if its offsets or accessions are invalid
+        for (position, byte) in [(annotation_offsets + 8, 0xFF), (annotation_offsets + 15, 0x01), (accessions, 0xFF)] {
+            let mut corrupt = original.clone();
+            corrupt[position] = byte;
+            std::fs::write(filename, &corrupt).unwrap();
+            assert!(load_bundle(filename, true, false).is_err());
+        }
+
+        std::fs::write(filename, &original[..original.len() - 3]).unwrap();
+        assert!(load_bundle(filename, true, true).is_err());
+    }
+}
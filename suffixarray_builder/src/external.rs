@@ -0,0 +1,393 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use memmap2::Mmap;
+use sa_mappings::filter::ProteinFilter;
+use sa_mappings::proteins::Proteins;
+use sa_mappings::taxonomy::TaxonAggregator;
+
+use crate::binary::{DatabaseFingerprint, FingerprintWriter, SuffixArrayWriter};
+use crate::suffix_array::required_bits_per_value;
+use crate::translate_l_to_i;
+
+/// The smallest buffer used to read a sorted run during the merge
+const MIN_RUN_BUFFER_SIZE: usize = 1 << 16;
+
+/// The number of characters of every suffix that are stored next to it in a sorted run, so most suffixes are merged without reading the text
+const KEY_LENGTH: usize = 16;
+
+/// The number of bytes of every suffix in a sorted run: its position followed by its key
+const RUN_ENTRY_SIZE: usize = 8 + KEY_LENGTH;
+
+/// Files in the scratch directory that are removed when the build finishes, also when the build fails
+struct ScratchFiles {
+    paths: Vec<PathBuf>,
+}
+
+impl ScratchFiles {
+
+    /// Creates a new file in the scratch directory, which is removed together with the other scratch files
+    ///
+    /// # Arguments
+    /// * `scratch_dir` - The directory in which the file is created
+    /// * `name` - The name of the file, which is prefixed to not clash with other builds in the same directory
+    ///
+    /// # Returns
+    ///
+    /// Returns the path and the opened file
+    ///
+    /// # Errors
+    ///
+    /// Returns an io::Error if the file could not be created
+    fn create(&mut self, scratch_dir: &Path, name: &str) -> Result<(PathBuf, File), std::io::Error> {
+        let path = scratch_dir.join(format!("suffixarray_builder_{}_{}", std::process::id(), name));
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)?;
+        self.paths.push(path.clone());
+        Ok((path, file))
+    }
+}
+
+impl Drop for ScratchFiles {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            // the files are only used during the build, failing to remove them does not make the build fail
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Writer that translates every L to an I before the text is written to the inner writer
+///
+/// # Arguments
+/// * `inner` - The writer the translated text is written to
+/// * `buffer` - The buffer in which the text is translated
+struct FoldingWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Write for FoldingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(buf);
+        translate_l_to_i(&mut self.buffer);
+        self.inner.write_all(&self.buffer)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The smallest remaining suffix of a sorted run, ordered so the BinaryHeap returns the smallest suffix first
+///
+/// # Arguments
+/// * `key` - The first `KEY_LENGTH` characters of the suffix, see `suffix_key`
+/// * `position` - The start position of the suffix in the text
+/// * `run` - The index of the run the suffix was read from
+/// * `text` - The text, which is only read if the keys of two suffixes are equal
+struct RunHead<'a> {
+    key: u128,
+    position: i64,
+    run: usize,
+    text: &'a [u8],
+}
+
+impl PartialEq for RunHead<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.position == other.position
+    }
+}
+
+impl Eq for RunHead<'_> {}
+
+impl PartialOrd for RunHead<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RunHead<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // the BinaryHeap is a max-heap, so the order of the suffixes is reversed
+        compare_keyed_suffixes(self.text, (self.key, self.position), (other.key, other.position)).reverse()
+    }
+}
+
+/// The files and the resources used to build a suffix array on disk
+///
+/// # Arguments
+/// * `database_file` - The database file the suffix array is built on
+/// * `taxonomy` - The taxonomy file, which is part of the fingerprint of the database
+/// * `scratch_dir` - The directory where the intermediate files are stored
+/// * `memory_budget` - The number of bytes used to sort a run and to buffer the runs during the merge
+pub struct ExternalBuild<'a> {
+    pub database_file: &'a str,
+    pub taxonomy: &'a str,
+    pub scratch_dir: &'a str,
+    pub memory_budget: usize,
+}
+
+/// Builds the sparse suffix array over the text of the database on disk and writes it to the output file in the format of `write_suffix_array`
+/// The text is written to the scratch directory while the database is read and memory mapped, so it is never completely in memory.
+/// The sampled suffixes are sorted in runs that fit in the memory budget, every sorted run is written to the scratch directory
+/// and the runs are merged into the output file. Every suffix in a run is stored together with its first characters,
+/// so the text is only read during the merge to order suffixes with the same first characters.
+/// The suffixes are ordered over the complete text, so the output file is identical to the one written after building the suffix array in memory.
+///
+/// # Arguments
+/// * `build` - The database, the scratch directory and the resources used to build the suffix array
+/// * `taxon_aggregator` - The taxonomy used to read the database file
+/// * `filter` - The filters that select the proteins of the database file that are in the text
+/// * `sparseness_factor` - The sparseness factor used on the suffix array
+/// * `bits_per_value` - The number of bits used to store every entry of the suffix array, None to use the minimum number of bits
+/// * `output` - The name of the file the suffix array will be written to
+///
+/// # Returns
+///
+/// Returns the fingerprint of the database
+///
+/// # Errors
+///
+/// Returns an error if the filters deduplicate the sequences,
+/// or any error that occurred while reading the database or while writing or reading the intermediate files or the output file
+pub fn build_sa_external(
+    build: &ExternalBuild,
+    taxon_aggregator: &TaxonAggregator,
+    filter: &ProteinFilter,
+    sparseness_factor: u8,
+    bits_per_value: Option<u8>,
+    output: &str,
+) -> Result<DatabaseFingerprint, Box<dyn Error>> {
+    if sparseness_factor == 0 {
+        return Err("The sparseness factor must be at least 1".into());
+    }
+    // the identical sequences can only be found if all sequences are in memory
+    if filter.deduplicate {
+        return Err("The suffix array can not be built on disk with deduplicated sequences".into());
+    }
+    let scratch_dir = Path::new(build.scratch_dir);
+    let mut scratch_files = ScratchFiles { paths: vec![] };
+
+    // write the text to the scratch directory with all L's translated to an I, the fingerprint is calculated over the original text
+    let (text_path, text_file) = scratch_files.create(scratch_dir, "text.bin")?;
+    let mut text_writer = FingerprintWriter::new(FoldingWriter { inner: BufWriter::new(text_file), buffer: vec![] });
+    Proteins::write_database_text(build.database_file, taxon_aggregator, filter, &mut text_writer)?;
+    let (database, mut text_writer) = text_writer.finish(&[build.database_file, build.taxonomy], filter)?;
+    text_writer.flush()?;
+    drop(text_writer);
+    // safety: the text file is created by this build and is not changed while it is mapped
+    let text = unsafe { Mmap::map(&File::open(text_path)?)? };
+    let text = &text[..];
+
+    // sort the sampled suffixes in runs of at most `run_entries` suffixes, together with their keys
+    let sparseness_factor = sparseness_factor as usize;
+    let len = text.len().div_ceil(sparseness_factor);
+    let run_entries = (build.memory_budget / size_of::<(u128, i64)>()).max(1);
+    let mut runs = vec![];
+    for (run_index, run_start) in (0..len).step_by(run_entries).enumerate() {
+        let run_end = (run_start + run_entries).min(len);
+        let mut run: Vec<(u128, i64)> = (run_start..run_end)
+            .map(|entry| entry * sparseness_factor)
+            .map(|position| (suffix_key(text, position), position as i64))
+            .collect();
+        run.sort_unstable_by(|&a, &b| compare_keyed_suffixes(text, a, b));
+
+        let (path, run_file) = scratch_files.create(scratch_dir, &format!("run_{}.bin", run_index))?;
+        let mut writer = BufWriter::new(run_file);
+        for (key, position) in run {
+            writer.write_all(&position.to_le_bytes())?;
+            writer.write_all(&key.to_le_bytes())?;
+        }
+        writer.flush()?;
+        runs.push(path);
+    }
+
+    // merge the sorted runs, the memory budget is divided over the buffers of the runs and the output
+    let buffer_size = (build.memory_budget / (runs.len() + 1)).max(MIN_RUN_BUFFER_SIZE);
+    let mut readers = runs
+        .iter()
+        .map(|path| Ok(BufReader::with_capacity(buffer_size, File::open(path)?)))
+        .collect::<Result<Vec<_>, std::io::Error>>()?;
+    let mut heads = BinaryHeap::with_capacity(readers.len());
+    for (run, reader) in readers.iter_mut().enumerate() {
+        if let Some((position, key)) = read_run_entry(reader)? {
+            heads.push(RunHead { key, position, run, text });
+        }
+    }
+
+    let output_file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true) // if the file already exists, empty the file
+        .open(output)?;
+    let bits_per_value = bits_per_value.unwrap_or_else(|| required_bits_per_value(text.len()));
+    let mut writer = SuffixArrayWriter::new(
+        BufWriter::with_capacity(buffer_size, output_file),
+        sparseness_factor as u8,
+        bits_per_value,
+        &database,
+        len,
+    )?
+    .with_buffer_size(buffer_size / 8);
+    while let Some(RunHead { position, run, .. }) = heads.pop() {
+        writer.write_entries(&[position])?;
+        if let Some((position, key)) = read_run_entry(&mut readers[run])? {
+            heads.push(RunHead { key, position, run, text });
+        }
+    }
+    writer.finish()?.flush()?;
+
+    Ok(database)
+}
+
+/// Returns the first `KEY_LENGTH` characters of a suffix as a number, in which the characters after the end of the text are 0
+/// Two keys are ordered like their suffixes are ordered, as long as the suffixes differ in their first `KEY_LENGTH` characters
+///
+/// # Arguments
+/// * `text` - The text, which ends with the termination character
+/// * `suffix` - The start of the suffix
+///
+/// # Returns
+///
+/// Returns the key of the suffix
+fn suffix_key(text: &[u8], suffix: usize) -> u128 {
+    let mut key = [0_u8; KEY_LENGTH];
+    for (key_character, &character) in key.iter_mut().zip(&text[suffix..]) {
+        *key_character = character;
+    }
+    u128::from_be_bytes(key)
+}
+
+/// Compares two suffixes of the text by their keys, and by the rest of the text if their keys are equal
+/// The termination character only occurs at the end of the text, so two different suffixes are never equal
+/// and they are ordered like in the suffix array that is built in memory
+///
+/// # Arguments
+/// * `text` - The text, which ends with the termination character
+/// * `a` - The key and the start of the first suffix
+/// * `b` - The key and the start of the second suffix
+///
+/// # Returns
+///
+/// Returns the order of the suffixes
+fn compare_keyed_suffixes(text: &[u8], (key_a, a): (u128, i64), (key_b, b): (u128, i64)) -> Ordering {
+    key_a.cmp(&key_b).then_with(|| {
+        // equal keys can only contain the termination character if the suffixes are the same, so both continue after the key
+        let (a, b) = (a as usize, b as usize);
+        text[(a + KEY_LENGTH).min(text.len())..].cmp(&text[(b + KEY_LENGTH).min(text.len())..])
+    })
+}
+
+/// Reads the next suffix of a sorted run
+///
+/// # Arguments
+/// * `reader` - The reader of the run
+///
+/// # Returns
+///
+/// Returns the position and the key of the next suffix, or None if all suffixes of the run were read
+///
+/// # Errors
+///
+/// Returns an io::Error if reading the run failed
+fn read_run_entry(reader: &mut impl Read) -> Result<Option<(i64, u128)>, std::io::Error> {
+    let mut entry = [0_u8; RUN_ENTRY_SIZE];
+    match reader.read_exact(&mut entry) {
+        Ok(()) => Ok(Some((
+            i64::from_le_bytes(entry[..8].try_into().unwrap()),
+            u128::from_le_bytes(entry[8..].try_into().unwrap()),
+        ))),
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use std::io::Write;
+
+    use sa_mappings::filter::ProteinFilter;
+    use sa_mappings::proteins::Proteins;
+    use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+    use tempdir::TempDir;
+
+    use crate::binary::{write_suffix_array, DatabaseFingerprint};
+    use crate::external::{build_sa_external, suffix_key, ExternalBuild};
+    use crate::suffix_array::required_bits_per_value;
+    use crate::{build_sa, SAConstructionAlgorithm};
+
+    const TAXONOMY: &str = "../testfiles/small_taxonomy.tsv";
+
+    fn create_database_file(tmp_dir: &TempDir) -> String {
+        let sequences = ["AI", "BLACVAA", "AC", "KCRLZ", "AAAAAAAAAAAAAAAAAAAA", "AAAAAAAAAAAAAAAAAAAAC", "AACA", "LLIIL", "ILIL", "AAAAAAAAAAAAAAAAAAAA"];
+        let database_file = tmp_dir.path().join("database.tsv");
+        let mut file = std::fs::File::create(&database_file).unwrap();
+        for (i, sequence) in sequences.iter().enumerate() {
+            writeln!(file, "P{}\t{}\t{}\t", i, [1, 2, 6, 7, 9][i % 5], sequence).unwrap();
+        }
+        database_file.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_suffix_key() {
+        let text = b"AIC-AICDEFGHIKMNPQRST-AIC-AIC$";
+        assert_eq!(suffix_key(text, 0).cmp(&suffix_key(text, 4)), Ordering::Less);
+        assert_eq!(suffix_key(text, 26).cmp(&suffix_key(text, 0)), Ordering::Less);
+        assert_eq!(suffix_key(text, 4).to_be_bytes(), *b"AICDEFGHIKMNPQRS");
+        assert_eq!(suffix_key(text, 26).to_be_bytes()[..5], *b"AIC$\0");
+    }
+
+    #[test]
+    fn test_build_sa_external() {
+        let tmp_dir = TempDir::new("test_build_sa_external").unwrap();
+        let database_file = create_database_file(&tmp_dir);
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(TAXONOMY, AggregationMethod::LcaStar).unwrap();
+
+        let text = Proteins::try_from_database_file_without_annotations(&database_file, &taxon_aggregator, &ProteinFilter::new()).unwrap();
+        let expected_database = DatabaseFingerprint::new(&text, &[&database_file, TAXONOMY], &ProteinFilter::new()).unwrap();
+
+        for (sparseness_factor, memory_budget) in [(1, 32), (2, 96), (3, 1 << 20)] {
+            // the suffix array built on disk is identical to the one built in memory, byte for byte
+            let expected_path = tmp_dir.path().join(format!("expected_{}.bin", sparseness_factor));
+            let sa = build_sa(&mut text.clone(), &SAConstructionAlgorithm::LibSais, sparseness_factor, 1).unwrap().to_vec();
+            write_suffix_array(sparseness_factor, required_bits_per_value(text.len()), &expected_database, &sa, expected_path.to_str().unwrap()).unwrap();
+
+            let path = tmp_dir.path().join(format!("external_{}.bin", sparseness_factor));
+            let build = ExternalBuild {
+                database_file: &database_file,
+                taxonomy: TAXONOMY,
+                scratch_dir: tmp_dir.path().to_str().unwrap(),
+                memory_budget,
+            };
+            let database = build_sa_external(&build, &taxon_aggregator, &ProteinFilter::new(), sparseness_factor, None, path.to_str().unwrap()).unwrap();
+
+            assert_eq!(database, expected_database);
+            assert_eq!(std::fs::read(&path).unwrap(), std::fs::read(&expected_path).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_build_sa_external_deduplicated() {
+        let tmp_dir = TempDir::new("test_build_sa_external_deduplicated").unwrap();
+        let database_file = create_database_file(&tmp_dir);
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(TAXONOMY, AggregationMethod::LcaStar).unwrap();
+
+        let filter = ProteinFilter { deduplicate: true, ..ProteinFilter::new() };
+        let path = tmp_dir.path().join("external.bin");
+        let build = ExternalBuild { database_file: &database_file, taxonomy: TAXONOMY, scratch_dir: tmp_dir.path().to_str().unwrap(), memory_budget: 32 };
+        assert!(build_sa_external(&build, &taxon_aggregator, &filter, 1, None, path.to_str().unwrap()).is_err());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,331 @@
+use crate::suffix_array::{required_bits_per_value, BitPackedSuffixArray, SuffixArray};
+
+/// The number of characters of the BWT between two stored occurrence counts
+const OCCURRENCE_SAMPLE_RATE: usize = 256;
+
+/// The number of words of the marked rows bitvector between two stored rank values
+const MARKED_RANK_SAMPLE_RATE: usize = 8;
+
+/// FM-index over the protein text, used as a compressed alternative for the suffix array
+/// The index consists of the Burrows-Wheeler transform (BWT) of the text, a rank structure over the BWT and a sampled suffix array
+/// The suffix array value of every row is retrieved by following the LF-mapping until a sampled row is reached
+///
+/// # Arguments
+/// * `bwt` - The Burrows-Wheeler transform of the text
+/// * `symbol_codes` - Maps every character to its rank in the alphabet of the text, `u8::MAX` if the character does not occur
+/// * `symbols` - The characters of the alphabet of the text, in sorted order
+/// * `counts` - `counts[code]` is the number of characters in the text that are smaller than the character with `code`
+/// * `occurrences` - `occurrences[block * alphabet size + code]` is the number of occurrences of `code` in the BWT before `block * OCCURRENCE_SAMPLE_RATE`
+/// * `sample_rate` - The suffix array value is stored for every row of which the suffix starts at a multiple of `sample_rate`
+/// * `marked` - Bitvector indicating which rows of the suffix array are sampled
+/// * `marked_ranks` - The number of marked rows before every block of `MARKED_RANK_SAMPLE_RATE` words of `marked`
+/// * `samples` - The suffix array values of the marked rows, in row order
+pub struct FmIndex {
+    bwt: Vec<u8>,
+    symbol_codes: [u8; 256],
+    symbols: Vec<u8>,
+    counts: Vec<u64>,
+    occurrences: Vec<u64>,
+    sample_rate: usize,
+    marked: Vec<u64>,
+    marked_ranks: Vec<u64>,
+    samples: BitPackedSuffixArray,
+}
+
+impl FmIndex {
+    /// Creates a new FmIndex from the text and its suffix array
+    ///
+    /// # Arguments
+    /// * `text` - The text the index is built on, this text has to end with a unique character that is smaller than all other characters
+    /// * `sa` - The complete (non-sparse) suffix array built over `text`
+    /// * `sample_rate` - The suffix array value is stored for every suffix that starts at a multiple of `sample_rate`
+    ///
+    /// # Returns
+    ///
+    /// Returns the FM-index built over the text
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the suffix array does not match the text, if the text is not correctly terminated or if the sample rate is 0
+    pub fn new(text: &[u8], sa: &[i64], sample_rate: usize) -> Result<Self, String> {
+        if text.len() != sa.len() {
+            return Err("The suffix array does not match the text".to_string());
+        }
+        if sample_rate == 0 {
+            return Err("The sample rate of the FM-index must be at least 1".to_string());
+        }
+        if let Some((&terminator, rest)) = text.split_last() {
+            if rest.iter().any(|&character| character <= terminator) {
+                return Err("The text must end with a unique character that is smaller than all other characters".to_string());
+            }
+        }
+
+        let n = text.len();
+        let bwt: Vec<u8> = sa.iter().map(|&suffix| text[(suffix as usize + n - 1) % n]).collect();
+
+        let mut marked = vec![0_u64; n.div_ceil(64)];
+        let mut samples = vec![];
+        for (row, &suffix) in sa.iter().enumerate() {
+            if (suffix as usize).is_multiple_of(sample_rate) {
+                marked[row / 64] |= 1 << (row % 64);
+                samples.push(suffix);
+            }
+        }
+        let samples = BitPackedSuffixArray::new(&samples, required_bits_per_value(n))?;
+
+        Ok(Self::from_parts(bwt, sample_rate, marked, samples))
+    }
+
+    /// Creates a new FmIndex from its stored parts, the rank structures are calculated from these parts
+    ///
+    /// # Arguments
+    /// * `bwt` - The Burrows-Wheeler transform of the text
+    /// * `sample_rate` - The sample rate used for the sampled suffix array
+    /// * `marked` - Bitvector indicating which rows of the suffix array are sampled
+    /// * `samples` - The suffix array values of the marked rows, in row order
+    ///
+    /// # Returns
+    ///
+    /// Returns the FM-index
+    pub(crate) fn from_parts(bwt: Vec<u8>, sample_rate: usize, marked: Vec<u64>, samples: BitPackedSuffixArray) -> Self {
+        let mut character_counts = [0_u64; 256];
+        for &character in &bwt {
+            character_counts[character as usize] += 1;
+        }
+
+        let mut symbol_codes = [u8::MAX; 256];
+        let mut symbols = vec![];
+        let mut counts = vec![];
+        let mut total = 0;
+        for character in 0..=u8::MAX {
+            if character_counts[character as usize] > 0 {
+                symbol_codes[character as usize] = symbols.len() as u8;
+                symbols.push(character);
+                counts.push(total);
+                total += character_counts[character as usize];
+            }
+        }
+
+        // store the occurrences of every character before each block of the BWT, including the end of the BWT
+        let alphabet_size = symbols.len();
+        let mut occurrences = Vec::with_capacity((bwt.len() / OCCURRENCE_SAMPLE_RATE + 1) * alphabet_size);
+        let mut current = vec![0_u64; alphabet_size];
+        for block in bwt.chunks(OCCURRENCE_SAMPLE_RATE) {
+            occurrences.extend_from_slice(&current);
+            for &character in block {
+                current[symbol_codes[character as usize] as usize] += 1;
+            }
+        }
+        if bwt.len().is_multiple_of(OCCURRENCE_SAMPLE_RATE) {
+            occurrences.extend_from_slice(&current);
+        }
+
+        let mut marked_ranks = vec![];
+        let mut marked_count = 0;
+        for block in marked.chunks(MARKED_RANK_SAMPLE_RATE) {
+            marked_ranks.push(marked_count);
+            marked_count += block.iter().map(|word| word.count_ones() as u64).sum::<u64>();
+        }
+
+        FmIndex {
+            bwt,
+            symbol_codes,
+            symbols,
+            counts,
+            occurrences,
+            sample_rate,
+            marked,
+            marked_ranks,
+            samples,
+        }
+    }
+
+    /// Returns the Burrows-Wheeler transform of the text
+    pub(crate) fn bwt(&self) -> &[u8] {
+        &self.bwt
+    }
+
+    /// Returns the sample rate used for the sampled suffix array
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate
+    }
+
+    /// Returns the bitvector indicating which rows of the suffix array are sampled
+    pub(crate) fn marked(&self) -> &[u64] {
+        &self.marked
+    }
+
+    /// Returns the suffix array values of the marked rows
+    pub(crate) fn samples(&self) -> &BitPackedSuffixArray {
+        &self.samples
+    }
+
+    /// Returns the number of occurrences of the character with `code` in the BWT before `index`
+    ///
+    /// # Arguments
+    /// * `code` - The code of the character in the alphabet
+    /// * `index` - The index in the BWT (exclusive)
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of occurrences of the character in `bwt[..index]`
+    #[inline]
+    fn rank(&self, code: usize, index: usize) -> u64 {
+        let block = index / OCCURRENCE_SAMPLE_RATE;
+        let symbol = self.symbols[code];
+        let in_block = self.bwt[block * OCCURRENCE_SAMPLE_RATE..index]
+            .iter()
+            .filter(|&&character| character == symbol)
+            .count();
+        self.occurrences[block * self.symbols.len() + code] + in_block as u64
+    }
+
+    /// Returns the row of the suffix that starts one position before the suffix in `row` (the LF-mapping)
+    ///
+    /// # Arguments
+    /// * `row` - The row in the suffix array
+    ///
+    /// # Returns
+    ///
+    /// Returns the row of the suffix that starts one position earlier in the text, cyclically
+    #[inline]
+    fn lf(&self, row: usize) -> usize {
+        let code = self.symbol_codes[self.bwt[row] as usize] as usize;
+        (self.counts[code] + self.rank(code, row)) as usize
+    }
+
+    /// Returns true if the suffix array value of `row` is stored
+    #[inline]
+    fn is_marked(&self, row: usize) -> bool {
+        self.marked[row / 64] & (1 << (row % 64)) != 0
+    }
+
+    /// Returns the number of marked rows before `row`
+    #[inline]
+    fn marked_rank(&self, row: usize) -> usize {
+        let word = row / 64;
+        let block = word / MARKED_RANK_SAMPLE_RATE;
+        let full_words: u64 = self.marked[block * MARKED_RANK_SAMPLE_RATE..word]
+            .iter()
+            .map(|word| word.count_ones() as u64)
+            .sum();
+        let partial_word = (self.marked[word] & ((1 << (row % 64)) - 1)).count_ones() as u64;
+        (self.marked_ranks[block] + full_words + partial_word) as usize
+    }
+}
+
+impl SuffixArray for FmIndex {
+    fn get(&self, index: usize) -> i64 {
+        let mut row = index;
+        let mut steps = 0;
+        // every LF step moves one position to the left in the text, a sampled position is reached within `sample_rate` steps
+        while !self.is_marked(row) {
+            row = self.lf(row);
+            steps += 1;
+        }
+        self.samples.get(self.marked_rank(row)) + steps
+    }
+
+    fn len(&self) -> usize {
+        self.bwt.len()
+    }
+
+    fn backward_search(&self, search_string: &[u8]) -> Option<(usize, usize)> {
+        let mut min_bound = 0;
+        let mut max_bound = self.bwt.len();
+        for &character in search_string.iter().rev() {
+            let code = self.symbol_codes[character as usize];
+            if code == u8::MAX {
+                return Some((0, 0));
+            }
+            let code = code as usize;
+            min_bound = (self.counts[code] + self.rank(code, min_bound)) as usize;
+            max_bound = (self.counts[code] + self.rank(code, max_bound)) as usize;
+            if min_bound >= max_bound {
+                return Some((0, 0));
+            }
+        }
+        Some((min_bound, max_bound))
+    }
+
+    fn for_each_suffix(&self, f: &mut dyn FnMut(usize, i64)) {
+        if self.bwt.is_empty() {
+            return;
+        }
+        // the last suffix only contains the smallest character, so it is the first row
+        let mut row = 0;
+        for suffix in (0..self.bwt.len()).rev() {
+            f(row, suffix as i64);
+            row = self.lf(row);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fm_index::FmIndex;
+    use crate::suffix_array::SuffixArray;
+
+    fn get_example_fm_index(sample_rate: usize) -> (FmIndex, Vec<i64>) {
+        let text = b"AI-BIACVAA-AC-KCRIZ$";
+        let sa: Vec<i64> = vec![19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18];
+        (FmIndex::new(text, &sa, sample_rate).unwrap(), sa)
+    }
+
+    #[test]
+    fn test_locate() {
+        for sample_rate in [1, 3, 32] {
+            let (fm_index, sa) = get_example_fm_index(sample_rate);
+            assert_eq!(fm_index.len(), sa.len());
+            for (row, &suffix) in sa.iter().enumerate() {
+                assert_eq!(fm_index.get(row), suffix);
+            }
+        }
+    }
+
+    #[test]
+    fn test_for_each_suffix() {
+        let (fm_index, sa) = get_example_fm_index(4);
+        let mut found = vec![-1; sa.len()];
+        fm_index.for_each_suffix(&mut |row, suffix| found[row] = suffix);
+        assert_eq!(found, sa);
+    }
+
+    #[test]
+    fn test_backward_search() {
+        let (fm_index, _) = get_example_fm_index(3);
+        // the suffixes starting with 'AC' are at rows 6 and 7
+        assert_eq!(fm_index.backward_search(b"AC"), Some((6, 8)));
+        assert_eq!(fm_index.backward_search(b"VAA"), Some((18, 19)));
+        assert_eq!(fm_index.backward_search(b"I"), Some((13, 16)));
+        assert_eq!(fm_index.backward_search(b"CA"), Some((0, 0)));
+        assert_eq!(fm_index.backward_search(b"W"), Some((0, 0)));
+    }
+
+    #[test]
+    fn test_long_text() {
+        // long enough to span multiple occurrence blocks and marked rank blocks
+        let mut text: Vec<u8> = (0..5000_u32).map(|i| b"ACDEI-"[((i * 7 + i / 13) % 6) as usize]).collect();
+        text.push(b'$');
+        let mut sa: Vec<i64> = (0..text.len() as i64).collect();
+        sa.sort_by_key(|&suffix| &text[suffix as usize..]);
+
+        let fm_index = FmIndex::new(&text, &sa, 5).unwrap();
+        for row in (0..sa.len()).step_by(7) {
+            assert_eq!(fm_index.get(row), sa[row]);
+        }
+
+        let pattern = &text[1234..1240];
+        let (min_bound, max_bound) = fm_index.backward_search(pattern).unwrap();
+        let expected: Vec<usize> = (0..sa.len()).filter(|&row| text[sa[row] as usize..].starts_with(pattern)).collect();
+        assert_eq!((min_bound..max_bound).collect::<Vec<usize>>(), expected);
+    }
+
+    #[test]
+    fn test_invalid_text() {
+        let sa: Vec<i64> = vec![1, 0];
+        assert!(FmIndex::new(b"AB", &sa, 1).is_err());
+        assert!(FmIndex::new(b"A$", &sa, 0).is_err());
+        assert!(FmIndex::new(b"A$", &sa[..1], 1).is_err());
+    }
+}
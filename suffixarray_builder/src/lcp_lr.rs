@@ -0,0 +1,184 @@
+use std::cmp::min;
+use std::error::Error;
+
+/// The maximum LCP value that is stored, larger values are capped to this value
+/// A stored value equal to `LCP_LR_CAP` means that the longest common prefix is at least `LCP_LR_CAP` long
+pub const LCP_LR_CAP: usize = u8::MAX as usize;
+
+/// The LCP-LR arrays used to speed up the binary search in the (sparse) suffix array
+/// The binary search visits the suffix array as an implicit binary tree: the search window (`left`, `right`) starting at (0, n) is split at `center` = (`left` + `right`) / 2
+/// Every index is the center of at most one search window, so the longest common prefixes of the center with both bounds of its window can be stored per center
+///
+/// # Arguments
+/// * `left` - `left[center]` is the length of the longest common prefix of the suffixes at `left` and `center` in the suffix array, capped to `LCP_LR_CAP`
+/// * `right` - `right[center]` is the length of the longest common prefix of the suffixes at `center` and `right` in the suffix array, capped to `LCP_LR_CAP`, 0 if `right` is the end of the suffix array
+#[derive(Debug, PartialEq)]
+pub struct LcpLr {
+    pub left: Vec<u8>,
+    pub right: Vec<u8>,
+}
+
+impl LcpLr {
+    /// Returns the number of suffix array entries the LCP-LR arrays are built for
+    pub fn len(&self) -> usize {
+        self.left.len()
+    }
+
+    /// Returns true if the LCP-LR arrays are built for an empty suffix array
+    pub fn is_empty(&self) -> bool {
+        self.left.is_empty()
+    }
+
+    /// Builds the LCP-LR arrays from the LCP array of a (sparse) suffix array
+    ///
+    /// # Arguments
+    /// * `lcp` - `lcp[i]` is the length of the longest common prefix of the suffixes at `i - 1` and `i` in the suffix array
+    ///
+    /// # Returns
+    ///
+    /// Returns the LCP-LR arrays for the binary search over the suffix array
+    pub fn from_lcp(lcp: &[i64]) -> Self {
+        let mut lcp_lr = LcpLr {
+            left: vec![0; lcp.len()],
+            right: vec![0; lcp.len()],
+        };
+        lcp_lr.fill(lcp, 0, lcp.len());
+        lcp_lr
+    }
+
+    /// Fills in the LCP-LR values of all centers in the search window (`left`, `right`)
+    ///
+    /// # Arguments
+    /// * `lcp` - The LCP array of the suffix array
+    /// * `left` - The left bound of the search window
+    /// * `right` - The right bound of the search window
+    ///
+    /// # Returns
+    ///
+    /// Returns the length of the longest common prefix of the suffixes at `left` and `right`, or `usize::MAX` if `right` is the end of the suffix array
+    fn fill(&mut self, lcp: &[i64], left: usize, right: usize) -> usize {
+        if right - left <= 1 {
+            return if right == lcp.len() { usize::MAX } else { lcp[right] as usize };
+        }
+
+        let center = (left + right) / 2;
+        let lcp_left_center = self.fill(lcp, left, center);
+        let lcp_center_right = self.fill(lcp, center, right);
+        self.left[center] = min(lcp_left_center, LCP_LR_CAP) as u8;
+        // the end of the suffix array is not a suffix, so there is no common prefix with it
+        self.right[center] = if right == lcp.len() { 0 } else { min(lcp_center_right, LCP_LR_CAP) as u8 };
+
+        min(lcp_left_center, lcp_center_right)
+    }
+}
+
+/// Builds the LCP-LR arrays for the sparse suffix array
+///
+/// # Arguments
+/// * `text` - The text the suffix array is built on, with every L translated to an I
+/// * `sa` - The complete (non-sparse) suffix array built over `text`
+/// * `sparseness_factor` - The sparseness factor used on the suffix array
+/// * `threads` - The number of threads used by libsais, 0 to use all available cores
+///
+/// # Returns
+///
+/// Returns the LCP-LR arrays for the binary search over the sparse suffix array
+///
+/// # Errors
+///
+/// Returns an error if building the LCP array failed
+pub fn build_lcp_lr(text: &[u8], sa: &[i64], sparseness_factor: u8, threads: usize) -> Result<LcpLr, Box<dyn Error>> {
+    let plcp = libsais64_rs::plcp64_omp(text, sa, threads).map_err(|err| format!("Building the PLCP array failed: {}", err))?;
+    let lcp = libsais64_rs::lcp64_omp(&plcp, sa, threads).map_err(|err| format!("Building the LCP array failed: {}", err))?;
+    drop(plcp);
+
+    Ok(LcpLr::from_lcp(&sample_lcp(&lcp, sa, sparseness_factor)))
+}
+
+/// Calculates the LCP array of the sparse suffix array from the LCP array of the complete suffix array
+/// The longest common prefix of 2 sampled suffixes is the minimum of the LCP values of all suffixes in between
+///
+/// # Arguments
+/// * `lcp` - The LCP array of the complete suffix array
+/// * `sa` - The complete suffix array
+/// * `sparseness_factor` - The sparseness factor used on the suffix array
+///
+/// # Returns
+///
+/// Returns the LCP array of the sparse suffix array
+fn sample_lcp(lcp: &[i64], sa: &[i64], sparseness_factor: u8) -> Vec<i64> {
+    let mut sampled_lcp = vec![];
+    let mut current_min = i64::MAX;
+    for (&suffix, &lcp_value) in sa.iter().zip(lcp) {
+        current_min = min(current_min, lcp_value);
+        if suffix % sparseness_factor as i64 == 0 {
+            // the first sampled suffix has no predecessor
+            sampled_lcp.push(if sampled_lcp.is_empty() { 0 } else { current_min });
+            current_min = i64::MAX;
+        }
+    }
+    sampled_lcp
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lcp_lr::{sample_lcp, LcpLr, LCP_LR_CAP};
+
+    /// Builds the LCP-LR arrays by walking over the binary search and comparing the suffixes directly
+    fn brute_force_lcp_lr(suffixes: &[&[u8]]) -> LcpLr {
+        fn common_prefix(a: &[u8], b: &[u8]) -> u8 {
+            a.iter().zip(b).take_while(|(x, y)| x == y).count().min(LCP_LR_CAP) as u8
+        }
+        fn visit(suffixes: &[&[u8]], left: usize, right: usize, lcp_lr: &mut LcpLr) {
+            if right - left <= 1 {
+                return;
+            }
+            let center = (left + right) / 2;
+            lcp_lr.left[center] = common_prefix(suffixes[left], suffixes[center]);
+            if right < suffixes.len() {
+                lcp_lr.right[center] = common_prefix(suffixes[center], suffixes[right]);
+            }
+            visit(suffixes, left, center, lcp_lr);
+            visit(suffixes, center, right, lcp_lr);
+        }
+
+        let mut lcp_lr = LcpLr {
+            left: vec![0; suffixes.len()],
+            right: vec![0; suffixes.len()],
+        };
+        visit(suffixes, 0, suffixes.len(), &mut lcp_lr);
+        lcp_lr
+    }
+
+    #[test]
+    fn test_lcp_lr_from_lcp() {
+        let text = b"AI-BIACVAA-AC-KCRIZ$";
+        let sa: Vec<i64> = vec![19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18];
+        let suffixes: Vec<&[u8]> = sa.iter().map(|&suffix| &text[suffix as usize..]).collect();
+        let lcp: Vec<i64> = (0..sa.len())
+            .map(|i| if i == 0 { 0 } else { suffixes[i - 1].iter().zip(suffixes[i]).take_while(|(a, b)| a == b).count() as i64 })
+            .collect();
+
+        assert_eq!(LcpLr::from_lcp(&lcp), brute_force_lcp_lr(&suffixes));
+    }
+
+    #[test]
+    fn test_lcp_lr_capped() {
+        let text = [b'A'; 600];
+        let suffixes: Vec<&[u8]> = (0..text.len()).rev().map(|suffix| &text[suffix..]).collect();
+        let lcp: Vec<i64> = (0..text.len() as i64).collect();
+
+        let lcp_lr = LcpLr::from_lcp(&lcp);
+        assert_eq!(lcp_lr, brute_force_lcp_lr(&suffixes));
+        assert_eq!(lcp_lr.left[450], LCP_LR_CAP as u8);
+    }
+
+    #[test]
+    fn test_sample_lcp() {
+        let sa: Vec<i64> = vec![19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18];
+        let lcp: Vec<i64> = vec![0, 0, 1, 1, 0, 1, 1, 2, 1, 0, 0, 1, 1, 0, 1, 1, 0, 0, 0, 0];
+        assert_eq!(sample_lcp(&lcp, &sa, 1), lcp);
+        // sampled suffixes: 9, 0, 3, 12, 15, 6, 18
+        assert_eq!(sample_lcp(&lcp, &sa, 3), vec![0, 1, 0, 0, 1, 1, 0]);
+    }
+}
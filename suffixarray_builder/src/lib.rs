@@ -1,12 +1,38 @@
 pub mod binary;
+pub mod bundle;
+pub mod external;
+pub mod fm_index;
+pub mod lcp_lr;
+pub mod suffix_array;
+pub mod subindex;
+pub mod taxon_lca_index;
+pub mod update;
+pub mod verify;
 
+use std::cmp::min;
+use std::collections::BTreeSet;
 use std::error::Error;
+use std::num::NonZeroUsize;
 use clap::{Parser, ValueEnum};
+use libsais64_rs::SaisError;
+use sa_mappings::filter::{read_accessions_file, ProteinFilter};
+
+use crate::fm_index::FmIndex;
+use crate::lcp_lr::{build_lcp_lr, LcpLr};
+use crate::suffix_array::{BuiltSuffixArray, SuffixEntry};
+
+/// The length of the longest text of which the suffix array is built and stored with 32-bit entries
+const MAX_32_BIT_TEXT_LENGTH: usize = i32::MAX as usize;
+
+/// A libsais routine that builds the suffix array over a text of integers, given the alphabet size and the number of threads
+type IntegerSort<T> = fn(&mut [T], T, usize) -> Result<Vec<T>, SaisError>;
 
 /// Enum that represents all possible commandline arguments
 #[derive(Parser, Debug)]
+#[command(after_help = "Run `suffixarray_builder verify --help` to check an existing index against its database, or `suffixarray_builder sub-index --help` to derive an index over the proteins of a taxon subtree")]
 pub struct Arguments {
     /// File with the proteins used to build the suffix tree. All the proteins are expected to be concatenated using a `#`.
+    /// Both tsv database files and UniProt FASTA files are accepted, the taxon of a protein in a FASTA file is read from the `OX=` field of its header.
     #[arg(short, long)]
     pub database_file: String,
     #[arg(short, long)]
@@ -16,10 +42,160 @@ pub struct Arguments {
     #[arg(short, long)]
     pub output: String,
     /// The sparseness_factor used on the suffix array (default value 1, which means every value in the SA is used)
+    /// With libsais, the sampled suffixes are sorted directly, so the memory needed during construction shrinks with the sparseness factor
     #[arg(long, default_value_t = 1)]
     pub sparseness_factor: u8,
     #[arg(short, long, value_enum, default_value_t = SAConstructionAlgorithm::LibSais)]
     pub construction_algorithm: SAConstructionAlgorithm,
+    /// Output file to store the LCP-LR arrays used to speed up the search. The LCP-LR arrays are only built if this file is provided.
+    #[arg(long)]
+    pub lcp_lr_output: Option<String>,
+    /// Output file to store the taxon index, which aggregates the taxa of the matches of a peptide without retrieving the proteins. The taxa are aggregated with the LCA*, like the search does.
+    #[arg(long)]
+    pub taxon_index_output: Option<String>,
+    /// The number of bits used to store every entry of the suffix array (default: the minimum number of bits needed for the input text, use 64 to store plain integers)
+    #[arg(long)]
+    pub bits_per_value: Option<u8>,
+    /// The kind of index that is built. The FM-index uses less memory than the suffix array, but is slower to search.
+    #[arg(long, value_enum, default_value_t = IndexType::SuffixArray)]
+    pub index_type: IndexType,
+    /// The FM-index stores the suffix array value of every suffix starting at a multiple of this sample rate
+    #[arg(long, default_value_t = 32)]
+    pub fm_sample_rate: usize,
+    /// The number of threads used by libsais to build the suffix array (default: all available cores)
+    #[arg(long)]
+    pub threads: Option<NonZeroUsize>,
+    /// Store a single bundle file with the proteins and the suffix array as output, so the database does not have to be parsed when the index is loaded
+    #[arg(long)]
+    pub bundle: bool,
+    /// Also embed the taxonomy in the bundle, so the index can be loaded without the taxonomy file
+    #[arg(long, requires = "bundle")]
+    pub bundle_taxonomy: bool,
+    /// Tsv file with the accession and the functional annotations of a protein on every line, used for the proteins of a FASTA database file that are stored in the bundle
+    #[arg(long, requires = "bundle")]
+    pub annotations_file: Option<String>,
+    /// Build the suffix array on disk instead of in memory, storing the text and the intermediate files in this directory. The output is identical to the one of the in-memory build.
+    #[arg(long)]
+    pub scratch_dir: Option<String>,
+    /// The memory (in MiB) used to sort the suffixes and to merge the sorted parts when the suffix array is built on disk
+    #[arg(long, default_value_t = 4096, requires = "scratch_dir")]
+    pub memory_budget: usize,
+    /// Update this existing suffix array, built over the database file, instead of building a new one. Only the suffixes of the added proteins are sorted, the sparseness factor of the existing suffix array is kept.
+    #[arg(long, requires = "updated_database")]
+    pub update_index: Option<String>,
+    /// Database file with the proteins that are added to the database when the index is updated
+    #[arg(long, requires = "update_index")]
+    pub added_proteins: Option<String>,
+    /// File with the accessions of the proteins that are removed from the database when the index is updated, one accession per line
+    #[arg(long, requires = "update_index")]
+    pub removed_accessions: Option<String>,
+    /// The database of the updated index is written to this file. The updated index can only be loaded together with this database file.
+    #[arg(long, requires = "update_index")]
+    pub updated_database: Option<String>,
+    /// Only index the proteins of which the taxon is in the subtree of one of these taxa, separated by commas. The filters are stored in the index.
+    #[arg(long, value_delimiter = ',')]
+    pub include_taxa: Vec<usize>,
+    /// Do not index the proteins of which the taxon is in the subtree of one of these taxa, separated by commas
+    #[arg(long, value_delimiter = ',')]
+    pub exclude_taxa: Vec<usize>,
+    /// Do not index the proteins with a shorter sequence
+    #[arg(long)]
+    pub min_length: Option<usize>,
+    /// Do not index the proteins with a longer sequence
+    #[arg(long)]
+    pub max_length: Option<usize>,
+    /// File with the accessions of the only proteins that are indexed, one accession per line
+    #[arg(long)]
+    pub allowed_accessions: Option<String>,
+    /// File with the accessions of the proteins that are not indexed, one accession per line
+    #[arg(long)]
+    pub denied_accessions: Option<String>,
+    /// Store the proteins with identical sequences as one entry in the text, which makes the index smaller without changing the search results
+    #[arg(long)]
+    pub deduplicate: bool,
+}
+
+impl Arguments {
+
+    /// Returns the filters that select the proteins of the database file that are indexed, and if their sequences are deduplicated
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the accession files could not be read
+    pub fn protein_filter(&self) -> Result<ProteinFilter, Box<dyn Error>> {
+        Ok(ProteinFilter {
+            include_taxa: self.include_taxa.clone(),
+            exclude_taxa: self.exclude_taxa.clone(),
+            min_length: self.min_length,
+            max_length: self.max_length,
+            allowed_accessions: self.allowed_accessions.as_deref().map(read_accessions_file).transpose()?,
+            denied_accessions: match &self.denied_accessions {
+                Some(denied_accessions) => read_accessions_file(denied_accessions)?,
+                None => BTreeSet::new(),
+            },
+            deduplicate: self.deduplicate,
+        })
+    }
+}
+
+/// Enum that represents the commandline arguments of `suffixarray_builder verify`, which checks an existing suffix array against its database
+#[derive(Parser, Debug)]
+#[command(name = "suffixarray_builder verify")]
+pub struct VerifyArguments {
+    /// File with the proteins the suffix array was built on
+    #[arg(short, long)]
+    pub database_file: String,
+    /// The taxonomy the suffix array was built with, as a tsv file
+    #[arg(short, long)]
+    pub taxonomy: String,
+    /// File with the suffix array that is checked
+    #[arg(short, long)]
+    pub index_file: String,
+    /// Only check the order of this many evenly spread pairs of adjacent suffixes (default: check all suffixes)
+    #[arg(long)]
+    pub sample: Option<usize>,
+    /// The suffix array is only built again with both construction algorithms and compared if the text is at most this long
+    #[arg(long, default_value_t = 1 << 20)]
+    pub compare_max_length: usize,
+    /// The number of threads used by libsais to build the suffix arrays that are compared (default: all available cores)
+    #[arg(long)]
+    pub threads: Option<NonZeroUsize>,
+}
+
+/// Enum that represents the commandline arguments of `suffixarray_builder sub-index`, which derives an index over the proteins of a taxon subtree from an existing suffix array
+#[derive(Parser, Debug)]
+#[command(name = "suffixarray_builder sub-index")]
+pub struct SubIndexArguments {
+    /// File with the proteins the existing suffix array was built on, as a tsv file
+    #[arg(short, long)]
+    pub database_file: String,
+    /// The taxonomy the existing suffix array was built with, as a tsv file
+    #[arg(short, long)]
+    pub taxonomy: String,
+    /// File with the existing suffix array
+    #[arg(short, long)]
+    pub index_file: String,
+    /// Only the proteins of which the taxon is in the subtree of this taxon are kept in the sub-index
+    #[arg(long)]
+    pub taxon: usize,
+    /// The suffix array of the sub-index is written to this file
+    #[arg(short, long)]
+    pub output: String,
+    /// The database of the sub-index is written to this file. The sub-index can only be loaded together with this database file, which contains empty proteins where the removed proteins are not a multiple of the sparseness factor long.
+    #[arg(long)]
+    pub sub_database: String,
+    /// The number of bits used to store every entry of the suffix array of the sub-index (default: the minimum number of bits)
+    #[arg(long)]
+    pub bits_per_value: Option<u8>,
+}
+
+/// Enum representing the kinds of index that can be built over the proteins
+/// The suffix tree is always built in memory when the proteins are loaded, it can not be stored in a file
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+pub enum IndexType {
+    SuffixArray,
+    FmIndex,
+    SuffixTree,
 }
 
 /// Enum representing the two possible algorithms to construct the suffix array
@@ -29,12 +205,15 @@ pub enum SAConstructionAlgorithm {
     LibSais,
 }
 
-/// Gets the current time in ms
+/// Builds the sparse suffix array over the text
+/// With libsais, only the sampled suffixes are sorted, so the complete suffix array is never kept in memory.
+/// If every position in the text fits in an i32, libsais builds and stores the suffix array with 32-bit entries, which halves its memory.
 ///
 /// # Arguments
 /// * `data` - The text on which we want to build the suffix array
 /// * `construction_algorithm` - The algorithm used during construction
 /// * `sparseness_factor` - The sparseness factor used on the suffix array
+/// * `threads` - The number of threads used by libsais, 0 to use all available cores
 /// 
 /// # Returns
 ///
@@ -43,22 +222,176 @@ pub enum SAConstructionAlgorithm {
 /// # Errors
 ///
 /// The errors that occurred during the building of the suffix array itself
-pub fn build_sa(data: &mut Vec<u8>, construction_algorithm: &SAConstructionAlgorithm, sparseness_factor: u8) -> Result<Vec<i64>, Box<dyn Error>> {
-    
-    // translate all L's to a I
+pub fn build_sa(data: &mut Vec<u8>, construction_algorithm: &SAConstructionAlgorithm, sparseness_factor: u8, threads: usize) -> Result<BuiltSuffixArray, Box<dyn Error>> {
+    if *construction_algorithm == SAConstructionAlgorithm::LibDivSufSort {
+        let mut sa = build_complete_sa(data, construction_algorithm, threads)?;
+        sample_sa(&mut sa, sparseness_factor);
+        return Ok(BuiltSuffixArray::Entries64(sa));
+    }
+
+    translate_l_to_i(data);
+    // libsais can sort the sampled suffixes directly, so the complete suffix array is never built
+    if data.len() <= MAX_32_BIT_TEXT_LENGTH {
+        let sa = match sparseness_factor {
+            1 => libsais64_rs::sais32_omp(data, threads)
+                .map_err(|err| format!("Building suffix array failed: {}", err))?,
+            _ => build_sparse_sa(data, sparseness_factor, threads, libsais64_rs::sais32_int_omp)?,
+        };
+        Ok(BuiltSuffixArray::Entries32(sa))
+    } else {
+        let sa = match sparseness_factor {
+            1 => libsais64_rs::sais64_omp(data, threads)
+                .map_err(|err| format!("Building suffix array failed: {}", err))?,
+            _ => build_sparse_sa(data, sparseness_factor, threads, libsais64_rs::sais64_long_omp)?,
+        };
+        Ok(BuiltSuffixArray::Entries64(sa))
+    }
+}
+
+/// Builds the sparse suffix array over the text, together with the LCP-LR arrays used to speed up the search
+///
+/// # Arguments
+/// * `data` - The text on which we want to build the suffix array
+/// * `construction_algorithm` - The algorithm used during construction
+/// * `sparseness_factor` - The sparseness factor used on the suffix array
+/// * `threads` - The number of threads used by libsais, 0 to use all available cores
+///
+/// # Returns
+///
+/// Returns the constructed suffix array and the LCP-LR arrays for this suffix array
+///
+/// # Errors
+///
+/// The errors that occurred during the building of the suffix array or the LCP array
+pub fn build_sa_with_lcp_lr(data: &mut Vec<u8>, construction_algorithm: &SAConstructionAlgorithm, sparseness_factor: u8, threads: usize) -> Result<(Vec<i64>, LcpLr), Box<dyn Error>> {
+    let mut sa = build_complete_sa(data, construction_algorithm, threads)?;
+    // the LCP values are calculated on the complete suffix array, before making it sparse
+    let lcp_lr = build_lcp_lr(data, &sa, sparseness_factor, threads)?;
+    sample_sa(&mut sa, sparseness_factor);
+    Ok((sa, lcp_lr))
+}
+
+/// Builds the FM-index over the text
+///
+/// # Arguments
+/// * `data` - The text on which we want to build the FM-index
+/// * `construction_algorithm` - The algorithm used during construction of the suffix array
+/// * `sample_rate` - The suffix array value is stored for every suffix starting at a multiple of `sample_rate`
+/// * `threads` - The number of threads used by libsais, 0 to use all available cores
+///
+/// # Returns
+///
+/// Returns the constructed FM-index
+///
+/// # Errors
+///
+/// The errors that occurred during the building of the suffix array or the FM-index
+pub fn build_fm_index(data: &mut Vec<u8>, construction_algorithm: &SAConstructionAlgorithm, sample_rate: usize, threads: usize) -> Result<FmIndex, Box<dyn Error>> {
+    let sa = build_complete_sa(data, construction_algorithm, threads)?;
+    Ok(FmIndex::new(data, &sa, sample_rate)?)
+}
+
+/// Builds the complete (non-sparse) suffix array over the text
+/// Every L in the text is translated to an I before construction
+///
+/// # Arguments
+/// * `data` - The text on which we want to build the suffix array
+/// * `construction_algorithm` - The algorithm used during construction
+/// * `threads` - The number of threads used by libsais, 0 to use all available cores
+///
+/// # Returns
+///
+/// Returns the constructed suffix array
+///
+/// # Errors
+///
+/// The errors that occurred during the building of the suffix array itself
+fn build_complete_sa(data: &mut Vec<u8>, construction_algorithm: &SAConstructionAlgorithm, threads: usize) -> Result<Vec<i64>, Box<dyn Error>> {
+    translate_l_to_i(data);
+
+    let sa = match construction_algorithm {
+        SAConstructionAlgorithm::LibSais => libsais64_rs::sais64_omp(data, threads)
+            .map_err(|err| format!("Building suffix array failed: {}", err))?,
+        SAConstructionAlgorithm::LibDivSufSort => {
+            libdivsufsort_rs::divsufsort64(data).ok_or("Building suffix array failed")?
+        }
+    };
+
+    Ok(sa)
+}
+
+/// Builds the sparse suffix array over the text without building the complete suffix array first
+/// The text is split in blocks of `sparseness_factor` characters, and every block is replaced by its rank among all blocks.
+/// Sorting the suffixes of this reduced text sorts the suffixes of the text that start at a multiple of `sparseness_factor`,
+/// since a shorter last block is smaller than all blocks that extend it. The peak memory is two entries per sampled suffix, together with the text.
+///
+/// # Arguments
+/// * `data` - The text on which we want to build the suffix array
+/// * `sparseness_factor` - The sparseness factor used on the suffix array
+/// * `threads` - The number of threads used by libsais, 0 to use all available cores
+/// * `sort` - The libsais routine that builds the suffix array over a text of integers, with the entry type of the suffix array
+///
+/// # Returns
+///
+/// Returns the suffix array containing the suffixes that start at a multiple of `sparseness_factor`
+///
+/// # Errors
+///
+/// The errors that occurred during the building of the suffix array of the reduced text
+fn build_sparse_sa<T: SuffixEntry>(
+    data: &[u8],
+    sparseness_factor: u8,
+    threads: usize,
+    sort: IntegerSort<T>,
+) -> Result<Vec<T>, Box<dyn Error>> {
+    let sparseness_factor = sparseness_factor as usize;
+    let block = |start: T| {
+        let start = Into::<i64>::into(start) as usize;
+        &data[start..min(start + sparseness_factor, data.len())]
+    };
+
+    // sort the start positions of the blocks, to give every distinct block its rank
+    let mut block_starts: Vec<T> = (0..data.len()).step_by(sparseness_factor).map(T::from_position).collect();
+    block_starts.sort_unstable_by(|&a, &b| block(a).cmp(block(b)));
+
+    let mut reduced_text = vec![T::default(); block_starts.len()];
+    let mut rank = 0;
+    for (index, &start) in block_starts.iter().enumerate() {
+        if index > 0 && block(block_starts[index - 1]) != block(start) {
+            rank += 1;
+        }
+        reduced_text[Into::<i64>::into(start) as usize / sparseness_factor] = T::from_position(rank);
+    }
+    drop(block_starts);
+
+    let mut sa = sort(&mut reduced_text, T::from_position(rank + 1), threads)
+        .map_err(|err| format!("Building suffix array failed: {}", err))?;
+    drop(reduced_text);
+    for suffix in sa.iter_mut() {
+        *suffix = T::from_position(Into::<i64>::into(*suffix) as usize * sparseness_factor);
+    }
+
+    Ok(sa)
+}
+
+/// Translates every L in the text to an I, since I and L are equalized in the index
+///
+/// # Arguments
+/// * `data` - The text, which is translated in place
+pub(crate) fn translate_l_to_i(data: &mut [u8]) {
     for character in data.iter_mut() {
         if *character == b'L' {
             *character = b'I'
         }
     }
-    
-    let mut sa = match construction_algorithm {
-        SAConstructionAlgorithm::LibSais => libsais64_rs::sais64(data),
-        SAConstructionAlgorithm::LibDivSufSort => {
-            libdivsufsort_rs::divsufsort64(data)
-        }
-    }.ok_or("Building suffix array failed")?;
+}
 
+/// Makes the suffix array sparse by only keeping the suffixes starting at a multiple of `sparseness_factor`
+///
+/// # Arguments
+/// * `sa` - The complete suffix array, which is made sparse in place
+/// * `sparseness_factor` - The sparseness factor used on the suffix array
+fn sample_sa(sa: &mut Vec<i64>, sparseness_factor: u8) {
     // make the SA sparse and decrease the vector size if we have sampling (== sampling_rate > 1)
     if sparseness_factor > 1 {
         let mut current_sampled_index = 0;
@@ -72,6 +405,40 @@ pub fn build_sa(data: &mut Vec<u8>, construction_algorithm: &SAConstructionAlgor
         // make shorter
         sa.resize(current_sampled_index, 0);
     }
+}
 
-    Ok(sa)
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use crate::suffix_array::BuiltSuffixArray;
+    use crate::{build_sa, SAConstructionAlgorithm};
+
+    #[test]
+    fn test_build_sa_32_bit() {
+        let text = b"AI-BLACVAA-AC-KCRLZ$";
+        let expected = vec![19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18];
+        let sa = build_sa(&mut text.to_vec(), &SAConstructionAlgorithm::LibSais, 1, 1).unwrap();
+        assert_eq!(sa, BuiltSuffixArray::Entries32(expected.clone()));
+        let sa = build_sa(&mut text.to_vec(), &SAConstructionAlgorithm::LibDivSufSort, 1, 1).unwrap();
+        assert_eq!(sa, BuiltSuffixArray::Entries64(expected.iter().map(|&suffix| suffix as i64).collect()));
+    }
+
+    #[test]
+    fn test_build_sparse_sa() {
+        let text = b"AI-BLACVAA-AC-KCRLZ-AAAAAAA-AAC-AACA$";
+        let complete_sa = build_sa(&mut text.to_vec(), &SAConstructionAlgorithm::LibSais, 1, 1).unwrap().to_vec();
+
+        for sparseness_factor in 2..=5 {
+            let expected: Vec<i64> = complete_sa
+                .iter()
+                .copied()
+                .filter(|suffix| suffix % sparseness_factor as i64 == 0)
+                .collect();
+            for threads in [0, 1, 2] {
+                let sa = build_sa(&mut text.to_vec(), &SAConstructionAlgorithm::LibSais, sparseness_factor, threads).unwrap();
+                assert_eq!(sa.to_vec(), expected);
+            }
+            let sa = build_sa(&mut text.to_vec(), &SAConstructionAlgorithm::LibDivSufSort, sparseness_factor, 1).unwrap();
+            assert_eq!(sa.to_vec(), expected);
+        }
+    }
+}
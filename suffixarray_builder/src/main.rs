@@ -1,12 +1,48 @@
+use std::error::Error;
+use std::num::NonZeroUsize;
+
 use clap::Parser;
 use sa_mappings::proteins::Proteins;
-use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
-use suffixarray_builder::{Arguments, build_sa};
-use suffixarray_builder::binary::write_suffix_array;
+use sa_mappings::taxonomy::{taxonomy_file_to_binary, AggregationMethod, TaxonAggregator};
+use suffixarray_builder::{Arguments, build_fm_index, build_sa, build_sa_with_lcp_lr, IndexType, SubIndexArguments, VerifyArguments};
+use suffixarray_builder::binary::{map_suffix_array, read_protein_filter, write_fm_index, write_lcp_lr, write_suffix_array, write_taxon_index, DatabaseFingerprint};
+use suffixarray_builder::bundle::write_bundle;
+use suffixarray_builder::external::{build_sa_external, ExternalBuild};
+use suffixarray_builder::subindex::{write_sub_index, SubIndex};
+use suffixarray_builder::update::{merge_suffix_array, IndexUpdate};
+use suffixarray_builder::suffix_array::{required_bits_per_value, BuiltSuffixArray, SuffixArray, SuffixEntry};
+use suffixarray_builder::taxon_lca_index::TaxonLcaIndex;
+use suffixarray_builder::verify::verify_index;
 
 fn main() {
+    // the verify and sub-index commands have their own arguments, the build arguments are parsed otherwise
+    match std::env::args().nth(1).as_deref() {
+        Some("verify") => {
+            verify(VerifyArguments::parse_from(std::env::args().skip(1)));
+            return;
+        }
+        Some("sub-index") => {
+            sub_index(SubIndexArguments::parse_from(std::env::args().skip(1)));
+            return;
+        }
+        _ => {}
+    }
+
     let args = Arguments::parse();
-    let Arguments { database_file, taxonomy, output, sparseness_factor, construction_algorithm } = args;
+    let filter = args.protein_filter();
+    if let Err(err) = filter {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let filter = filter.unwrap();
+    let Arguments { database_file, taxonomy, output, sparseness_factor, construction_algorithm, lcp_lr_output, taxon_index_output, bits_per_value, index_type, fm_sample_rate, threads, bundle, bundle_taxonomy, annotations_file, scratch_dir, memory_budget, update_index, added_proteins, removed_accessions, updated_database, .. } = args;
+    // libsais uses all available cores if the number of threads is 0
+    let threads = threads.map_or(0, NonZeroUsize::get);
+    if index_type == IndexType::SuffixTree {
+        eprintln!("The suffix tree can not be stored in a file, it is built when the proteins are loaded");
+        std::process::exit(1);
+    }
+
     let taxon_id_calculator = TaxonAggregator::try_from_taxonomy_file(&taxonomy, AggregationMethod::LcaStar);  
     if let Err(err) = taxon_id_calculator {
         eprintln!("{}", err);
@@ -14,25 +50,323 @@ fn main() {
     }
     
     let taxon_id_calculator = taxon_id_calculator.unwrap();
+
+    // an updated index keeps the filters of the existing index
+    let filter = match &update_index {
+        Some(update_index) if filter.is_empty() => read_protein_filter(update_index),
+        Some(_) => Err("An updated index keeps the filters of the existing index, so no other filters can be given".into()),
+        None => Ok(filter),
+    };
+    if let Err(err) = filter {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let filter = filter.unwrap();
     
-    // read input
-    let data = Proteins::try_from_database_file_without_annotations(&database_file, &taxon_id_calculator);
+    // the external build streams the text to the scratch directory and the suffix array to the output file, so neither is ever completely in memory
+    if let Some(scratch_dir) = &scratch_dir {
+        if index_type == IndexType::FmIndex || update_index.is_some() || lcp_lr_output.is_some() || taxon_index_output.is_some() || bundle {
+            eprintln!("The suffix array built on disk can not be an FM-index or an updated index, or be stored with LCP-LR arrays, a taxon index or in a bundle");
+            std::process::exit(1);
+        }
+        let build = ExternalBuild { database_file: &database_file, taxonomy: &taxonomy, scratch_dir, memory_budget: memory_budget << 20 };
+        if let Err(err) = build_sa_external(&build, &taxon_id_calculator, &filter, sparseness_factor, bits_per_value, &output) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // read input, the bundle also contains the proteins themselves and the taxon index needs their taxa
+    let proteins = if bundle || taxon_index_output.is_some() {
+        Proteins::try_from_database_file_with_annotations(&database_file, annotations_file.as_deref(), &taxon_id_calculator, &filter).map(Some)
+    } else {
+        Ok(None)
+    };
+    if let Err(err) = proteins {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let proteins = proteins.unwrap();
+    let data = match &proteins {
+        // the text is translated during construction, so the proteins keep the original text
+        Some(proteins) => Ok(proteins.input_string.clone()),
+        None => Proteins::try_from_database_file_without_annotations(&database_file, &taxon_id_calculator, &filter),
+    };
     if let Err(err) = data {
         eprintln!("{}", err);
         std::process::exit(1);
     }
     let mut data = data.unwrap();
-    // calculate sa
-    let sa = build_sa(&mut data, &construction_algorithm, sparseness_factor);
+
+    // the fingerprint is stored in the index (computed before the text is translated during construction), so the index is only loaded together with the same database
+    let database_fingerprint = DatabaseFingerprint::new(&data, &[&database_file, &taxonomy], &filter);
+    if let Err(err) = database_fingerprint {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let database_fingerprint = database_fingerprint.unwrap();
+
+    if index_type == IndexType::FmIndex {
+        if bundle || update_index.is_some() {
+            eprintln!("The FM-index can not be stored in a bundle or updated");
+            std::process::exit(1);
+        }
+        // the FM-index always represents the complete suffix array
+        if sparseness_factor != 1 || lcp_lr_output.is_some() {
+            eprintln!("The FM-index can not be built with a sparseness factor or LCP-LR arrays");
+            std::process::exit(1);
+        }
+        let fm_index = build_fm_index(&mut data, &construction_algorithm, fm_sample_rate, threads);
+        if let Err(err) = fm_index {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        let fm_index = fm_index.unwrap();
+        if let Err(err) = write_fm_index(&fm_index, &database_fingerprint, &output) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        if let (Some(proteins), Some(taxon_index_output)) = (&proteins, &taxon_index_output) {
+            if let Err(err) = build_taxon_index(&fm_index, proteins, &taxon_id_calculator, 1, &database_fingerprint, taxon_index_output) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // only the suffixes of the added proteins are sorted when an existing index is updated
+    if let (Some(update_index), Some(updated_database)) = (&update_index, &updated_database) {
+        if lcp_lr_output.is_some() || taxon_index_output.is_some() || bundle {
+            eprintln!("An updated index can not be stored with LCP-LR arrays, a taxon index or in a bundle");
+            std::process::exit(1);
+        }
+        let update = IndexUpdate {
+            old_index: update_index,
+            database_file: &database_file,
+            added_proteins: added_proteins.as_deref(),
+            removed_accessions: removed_accessions.as_deref(),
+            updated_database,
+        };
+        if let Err(err) = update_suffix_array(&update, &taxonomy, &taxon_id_calculator, &data, bits_per_value, threads, &output) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let bits_per_value = bits_per_value.unwrap_or_else(|| required_bits_per_value(data.len()));
+
+    // calculate sa, and the LCP-LR arrays if they need to be stored
+    let sa = match &lcp_lr_output {
+        Some(_) => build_sa_with_lcp_lr(&mut data, &construction_algorithm, sparseness_factor, threads)
+            .map(|(sa, lcp_lr)| (BuiltSuffixArray::Entries64(sa), Some(lcp_lr))),
+        None => build_sa(&mut data, &construction_algorithm, sparseness_factor, threads).map(|sa| (sa, None)),
+    };
     if let Err(err) = sa {
         eprintln!("{}", err);
         std::process::exit(1);
     }
-    let sa = sa.unwrap();
+    let (sa, lcp_lr) = sa.unwrap();
+
+    // output the built LCP-LR arrays
+    if let (Some(lcp_lr), Some(lcp_lr_output)) = (&lcp_lr, &lcp_lr_output) {
+        if let Err(err) = write_lcp_lr(lcp_lr, sparseness_factor, &database_fingerprint, lcp_lr_output) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+    
+    // output the taxon index of the built SA
+    if let (Some(proteins), Some(taxon_index_output)) = (&proteins, &taxon_index_output) {
+        let sa: &dyn SuffixArray = match &sa {
+            BuiltSuffixArray::Entries32(sa) => sa,
+            BuiltSuffixArray::Entries64(sa) => sa,
+        };
+        if let Err(err) = build_taxon_index(sa, proteins, &taxon_id_calculator, sparseness_factor, &database_fingerprint, taxon_index_output) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
     
-    // output the build SA
-    if let Err(err) = write_suffix_array(sparseness_factor, &sa, &output) {
+    // output the build SA, together with the proteins if a bundle is built
+    let proteins = proteins.filter(|_| bundle);
+    let embedded_taxonomy = if bundle_taxonomy { taxonomy_file_to_binary(&taxonomy).map(Some) } else { Ok(None) };
+    if let Err(err) = embedded_taxonomy {
         eprintln!("{}", err);
         std::process::exit(1);
+    }
+    let embedded_taxonomy = embedded_taxonomy.unwrap();
+    let written = match &sa {
+        BuiltSuffixArray::Entries32(sa) => write_output(proteins.as_ref(), embedded_taxonomy.as_deref(), sparseness_factor, bits_per_value, &database_fingerprint, sa, &output),
+        BuiltSuffixArray::Entries64(sa) => write_output(proteins.as_ref(), embedded_taxonomy.as_deref(), sparseness_factor, bits_per_value, &database_fingerprint, sa, &output),
     };
-}
\ No newline at end of file
+    if let Err(err) = written {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    };
+}
+
+/// Builds the taxon index over the built suffix array (or FM-index) and writes it to the output file
+///
+/// # Arguments
+/// * `sa` - The built suffix array (or FM-index)
+/// * `proteins` - The proteins the suffix array is built on
+/// * `taxon_aggregator` - The taxonomy used to aggregate the taxa
+/// * `sparseness_factor` - The sparseness factor of the suffix array
+/// * `database` - The fingerprint of the database the suffix array is built on
+/// * `output` - The name of the file the taxon index is written to
+///
+/// # Errors
+///
+/// Returns an error if writing away the taxon index failed
+fn build_taxon_index(
+    sa: &dyn SuffixArray,
+    proteins: &Proteins,
+    taxon_aggregator: &TaxonAggregator,
+    sparseness_factor: u8,
+    database: &DatabaseFingerprint,
+    output: &str,
+) -> Result<(), Box<dyn Error>> {
+    let taxon_index = TaxonLcaIndex::new(sa, proteins, taxon_aggregator);
+    Ok(write_taxon_index(&taxon_index, sparseness_factor, database, output)?)
+}
+
+/// Writes the built suffix array to the output file, as a bundle together with the proteins if they are given
+///
+/// # Arguments
+/// * `proteins` - The proteins stored in the bundle, or None to only store the suffix array
+/// * `taxonomy` - The binary form of the taxonomy that is embedded in the bundle, or None to not embed the taxonomy
+/// * `sparseness_factor` - The sparseness factor of the suffix array
+/// * `bits_per_value` - The number of bits used to store every entry of the suffix array
+/// * `database` - The fingerprint of the database the suffix array is built on
+/// * `sa` - The suffix array
+/// * `output` - The name of the output file
+///
+/// # Errors
+///
+/// Returns an error if writing away the suffix array or the bundle failed
+fn write_output<T: SuffixEntry>(
+    proteins: Option<&Proteins>,
+    taxonomy: Option<&[u8]>,
+    sparseness_factor: u8,
+    bits_per_value: u8,
+    database: &DatabaseFingerprint,
+    sa: &[T],
+    output: &str,
+) -> Result<(), Box<dyn Error>> {
+    match proteins {
+        Some(proteins) => write_bundle(proteins, taxonomy, sparseness_factor, bits_per_value, database, sa, output),
+        None => Ok(write_suffix_array(sparseness_factor, bits_per_value, database, sa, output)?),
+    }
+}
+
+/// Updates an existing suffix array with the added and removed proteins, and writes the database and the suffix array of the updated index
+/// The proteins of the updated database are selected by the filters of the existing index
+///
+/// # Arguments
+/// * `update` - The existing index and the files with the changes to its database
+/// * `taxonomy` - The taxonomy file, which is part of the fingerprint of the database
+/// * `taxon_aggregator` - The taxonomy used to read the database files
+/// * `old_text` - The text of the database the existing index was built on
+/// * `bits_per_value` - The number of bits used to store every entry of the updated suffix array, None to use the minimum number of bits
+/// * `threads` - The number of threads used by libsais, 0 to use all available cores
+/// * `output` - The name of the file the updated suffix array is written to
+///
+/// # Errors
+///
+/// Returns an error if the existing index has deduplicated sequences or does not match its database, or if reading, merging or writing failed
+fn update_suffix_array(
+    update: &IndexUpdate,
+    taxonomy: &str,
+    taxon_aggregator: &TaxonAggregator,
+    old_text: &[u8],
+    bits_per_value: Option<u8>,
+    threads: usize,
+    output: &str,
+) -> Result<(), Box<dyn Error>> {
+    let filter = read_protein_filter(update.old_index)?;
+    // the proteins of a deduplicated text can not be removed one by one
+    if filter.deduplicate {
+        return Err("An index with deduplicated sequences can not be updated".into());
+    }
+    let old_fingerprint = DatabaseFingerprint::new(old_text, &[update.database_file, taxonomy], &filter)?;
+    let (sparseness_factor, old_sa) = map_suffix_array(update.old_index, &old_fingerprint, true)?;
+
+    let kept = update.update_database(taxon_aggregator, &filter)?;
+    let mut new_text = Proteins::try_from_database_file_without_annotations(update.updated_database, taxon_aggregator, &filter)?;
+    let database_fingerprint = DatabaseFingerprint::new(&new_text, &[update.updated_database, taxonomy], &filter)?;
+
+    let sa = merge_suffix_array(old_text, &kept, &old_sa, sparseness_factor, &mut new_text, threads)?;
+    let bits_per_value = bits_per_value.unwrap_or_else(|| required_bits_per_value(new_text.len()));
+    write_suffix_array(sparseness_factor, bits_per_value, &database_fingerprint, &sa, output)?;
+    Ok(())
+}
+
+/// Checks an existing suffix array against its database, prints the report and exits with a non-zero code if a check failed
+///
+/// # Arguments
+/// * `args` - The commandline arguments of the verify command
+fn verify(args: VerifyArguments) {
+    let VerifyArguments { database_file, taxonomy, index_file, sample, compare_max_length, threads } = args;
+
+    let taxon_id_calculator = TaxonAggregator::try_from_taxonomy_file(&taxonomy, AggregationMethod::LcaStar);
+    if let Err(err) = taxon_id_calculator {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let taxon_id_calculator = taxon_id_calculator.unwrap();
+
+    // the database is loaded with the filters stored in the index
+    let filter = read_protein_filter(&index_file);
+    if let Err(err) = filter {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let filter = filter.unwrap();
+
+    let data = Proteins::try_from_database_file_without_annotations(&database_file, &taxon_id_calculator, &filter);
+    if let Err(err) = data {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let mut data = data.unwrap();
+
+    let database_fingerprint = DatabaseFingerprint::new(&data, &[&database_file, &taxonomy], &filter);
+    if let Err(err) = database_fingerprint {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let database_fingerprint = database_fingerprint.unwrap();
+
+    let report = verify_index(&mut data, &database_fingerprint, &index_file, sample, compare_max_length, threads.map_or(0, NonZeroUsize::get));
+    println!("{}", report);
+    if !report.passed() {
+        std::process::exit(1);
+    }
+}
+
+/// Derives the sub-index over the proteins of a taxon subtree from an existing suffix array, and exits with a non-zero code if it failed
+///
+/// # Arguments
+/// * `args` - The commandline arguments of the sub-index command
+fn sub_index(args: SubIndexArguments) {
+    let SubIndexArguments { database_file, taxonomy, index_file, taxon, output, sub_database, bits_per_value } = args;
+
+    let taxon_id_calculator = TaxonAggregator::try_from_taxonomy_file(&taxonomy, AggregationMethod::LcaStar);
+    if let Err(err) = taxon_id_calculator {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let taxon_id_calculator = taxon_id_calculator.unwrap();
+
+    let sub_index = SubIndex { index_file: &index_file, database_file: &database_file, taxon_id: taxon, sub_database: &sub_database };
+    let database_fingerprint = write_sub_index(&sub_index, &taxonomy, &taxon_id_calculator, bits_per_value, &output);
+    if let Err(err) = database_fingerprint {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    let database_fingerprint = database_fingerprint.unwrap();
+    println!("The sub-index contains {} proteins", database_fingerprint.protein_count);
+}
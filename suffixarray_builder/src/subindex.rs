@@ -0,0 +1,322 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::str::from_utf8;
+
+use sa_mappings::filter::ProteinFilter;
+use sa_mappings::proteins::{is_fasta_file, Proteins};
+use sa_mappings::taxonomy::TaxonAggregator;
+
+use crate::binary::{map_suffix_array, read_protein_filter, write_suffix_array, DatabaseFingerprint};
+use crate::suffix_array::{required_bits_per_value, SuffixArray};
+use crate::update::ProteinShifts;
+
+/// The files used to derive a sub-index that only contains the proteins of a taxon subtree from an existing index
+///
+/// # Arguments
+/// * `index_file` - The suffix array that was built over `database_file`
+/// * `database_file` - The database file the existing index was built on
+/// * `taxon_id` - The root of the taxon subtree of which the proteins are kept
+/// * `sub_database` - The file the database of the sub-index is written to
+pub struct SubIndex<'a> {
+    pub index_file: &'a str,
+    pub database_file: &'a str,
+    pub taxon_id: usize,
+    pub sub_database: &'a str,
+}
+
+impl SubIndex<'_> {
+
+    /// Writes the database file of the sub-index, which contains the proteins of the existing index that are in the taxon subtree, in the same order
+    /// Every gap left by the removed proteins is padded with empty proteins until the removed length is a multiple of the sparseness factor, see `ProteinShifts::padded`.
+    /// An empty protein has the accession and the taxon of the kept protein after it, so it passes the same filters, and it never matches a peptide.
+    ///
+    /// # Arguments
+    /// * `taxon_aggregator` - The taxonomy used to read the database file
+    /// * `filter` - The filters that selected the proteins of the database file that are in the text of the existing index
+    /// * `sparseness_factor` - The sparseness factor of the existing index
+    ///
+    /// # Returns
+    ///
+    /// Returns for every protein in the text of the existing index if it is kept in the sub-index
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the files could not be read or written, if the database file is a FASTA file, or if it contains an invalid line
+    pub fn write_sub_database(&self, taxon_aggregator: &TaxonAggregator, filter: &ProteinFilter, sparseness_factor: u8) -> Result<Vec<bool>, Box<dyn Error>> {
+        // the lines of the database are copied, so the database has to be a tsv file
+        if is_fasta_file(self.database_file)? {
+            return Err("Only indexes built on a tsv database file can be restricted to a taxon".into());
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true) // if the file already exists, empty the file
+            .open(self.sub_database)?;
+        let mut writer = BufWriter::new(file);
+        let mut kept = vec![];
+        // the length of the proteins in the text that were removed since the last kept protein
+        let mut gap = 0;
+        for line in BufReader::new(File::open(self.database_file)?).split(b'\n') {
+            let line = line?;
+            let mut fields = line.split(|&character| character == b'\t');
+            let accession = fields.next().unwrap_or_default();
+            let taxon_id = fields
+                .next()
+                .and_then(|taxon_id| from_utf8(taxon_id).ok())
+                .and_then(|taxon_id| taxon_id.parse::<usize>().ok())
+                .ok_or("The database file contains a line without a valid taxon id")?;
+            let sequence_length = fields.next().map_or(0, <[u8]>::len);
+
+            // only the proteins in the text of the existing index can be kept
+            if !taxon_aggregator.taxon_exists(taxon_id) || !filter.keeps(from_utf8(accession)?, taxon_id, sequence_length, taxon_aggregator) {
+                continue;
+            }
+            let keep = taxon_aggregator.in_subtree(taxon_id, self.taxon_id);
+            kept.push(keep);
+            if keep {
+                for _ in 0..gap % sparseness_factor as usize {
+                    writer.write_all(accession)?;
+                    writeln!(writer, "\t{}\t\t", taxon_id)?;
+                }
+                gap = 0;
+                writer.write_all(&line)?;
+                writer.write_all(b"\n")?;
+            } else {
+                gap += sequence_length + 1;
+            }
+        }
+        writer.flush()?;
+
+        Ok(kept)
+    }
+}
+
+/// Derives the sub-index of an existing suffix array, and writes the database and the suffix array of the sub-index
+/// The sub-index is built in one pass over the existing suffix array, the proteins are not sorted again.
+/// The database of the sub-index contains empty proteins where the removed proteins would otherwise shift the kept proteins by a length that is not a multiple of the sparseness factor.
+/// The sub-index keeps the filters of the existing index, and only includes the part of the included taxa that is in the taxon subtree.
+///
+/// # Arguments
+/// * `sub_index` - The existing index, the taxon and the file the database of the sub-index is written to
+/// * `taxonomy` - The taxonomy file, which is part of the fingerprint of the database
+/// * `taxon_aggregator` - The taxonomy used to read the database files
+/// * `bits_per_value` - The number of bits used to store every entry of the sub-index, None to use the minimum number of bits
+/// * `output` - The name of the file the suffix array of the sub-index is written to
+///
+/// # Returns
+///
+/// Returns the fingerprint of the database of the sub-index
+///
+/// # Errors
+///
+/// Returns an error if the taxon is not in the taxonomy or shares no subtree with the taxa included in the existing index, if the existing index has deduplicated sequences or does not match its database, or if reading, restricting or writing failed
+pub fn write_sub_index(
+    sub_index: &SubIndex,
+    taxonomy: &str,
+    taxon_aggregator: &TaxonAggregator,
+    bits_per_value: Option<u8>,
+    output: &str,
+) -> Result<DatabaseFingerprint, Box<dyn Error>> {
+    if !taxon_aggregator.taxon_exists(sub_index.taxon_id) {
+        return Err(format!("The taxon {} is not in the taxonomy", sub_index.taxon_id).into());
+    }
+
+    let filter = read_protein_filter(sub_index.index_file)?;
+    // an entry of a deduplicated text can contain proteins from inside and outside the taxon subtree
+    if filter.deduplicate {
+        return Err("No sub-index can be derived from an index with deduplicated sequences".into());
+    }
+    let old_text = Proteins::try_from_database_file_without_annotations(sub_index.database_file, taxon_aggregator, &filter)?;
+    let old_fingerprint = DatabaseFingerprint::new(&old_text, &[sub_index.database_file, taxonomy], &filter)?;
+    let (sparseness_factor, old_sa) = map_suffix_array(sub_index.index_file, &old_fingerprint, true)?;
+
+    let kept = sub_index.write_sub_database(taxon_aggregator, &filter, sparseness_factor)?;
+    let sub_filter = filter
+        .restrict_to_subtree(sub_index.taxon_id, taxon_aggregator)
+        .ok_or_else(|| format!("The taxon {} is not in the subtree of a taxon included in the index", sub_index.taxon_id))?;
+    let new_text = Proteins::try_from_database_file_without_annotations(sub_index.sub_database, taxon_aggregator, &sub_filter)?;
+    let database_fingerprint = DatabaseFingerprint::new(&new_text, &[sub_index.sub_database, taxonomy], &sub_filter)?;
+
+    let sa = restrict_suffix_array(&old_text, &kept, &old_sa, sparseness_factor)?;
+    let bits_per_value = bits_per_value.unwrap_or_else(|| required_bits_per_value(new_text.len()));
+    write_suffix_array(sparseness_factor, bits_per_value, &database_fingerprint, &sa, output)?;
+    Ok(database_fingerprint)
+}
+
+/// Builds the suffix array over the text with only the kept proteins, by leaving out the suffixes of the removed proteins from the old suffix array
+/// Removing proteins does not change the order of the other suffixes up to the end of their protein, so the kept suffixes only shift by the length of the removed proteins before them.
+/// Every gap left by the removed proteins is padded with empty proteins, so the kept suffixes shift by a multiple of the sparseness factor, see `ProteinShifts::padded`.
+/// The separators of the empty proteins are equal to all other separators up to the end of their protein, so they directly follow the terminator.
+/// Suffixes that are equal up to the end of their protein can be ordered differently than after a full build, which does not change the search results.
+///
+/// # Arguments
+/// * `old_text` - The text the old suffix array was built on
+/// * `kept` - For every protein in the old text if it is kept in the new text
+/// * `old_sa` - The suffix array built over the old text
+/// * `sparseness_factor` - The sparseness factor of the old suffix array, which is also used for the new suffix array
+///
+/// # Returns
+///
+/// Returns the suffix array over the text with only the kept proteins and the padding
+///
+/// # Errors
+///
+/// Returns an error if `kept` does not match the proteins in the old text, or if no protein is kept
+pub fn restrict_suffix_array(
+    old_text: &[u8],
+    kept: &[bool],
+    old_sa: &dyn SuffixArray,
+    sparseness_factor: u8,
+) -> Result<Vec<i64>, Box<dyn Error>> {
+    let sparseness_factor = sparseness_factor as usize;
+    let shifts = ProteinShifts::padded(old_text, kept, sparseness_factor)?;
+    if shifts.kept_length == 0 {
+        return Err("None of the proteins of the index are kept".into());
+    }
+
+    // the separator after the last kept protein becomes the terminator, which is the smallest suffix
+    let terminator = shifts.kept_length - 1;
+    let mut sa = Vec::with_capacity(shifts.kept_length.div_ceil(sparseness_factor));
+    if terminator.is_multiple_of(sparseness_factor) {
+        sa.push(terminator as i64);
+    }
+    for (protein, &padding) in shifts.padding.iter().enumerate().filter(|(_, &padding)| padding > 0) {
+        // only kept proteins are preceded by padding
+        let padding_end = shifts.starts[protein] - shifts.shifts[protein].unwrap_or_default();
+        sa.extend((padding_end - padding..padding_end).filter(|suffix| suffix.is_multiple_of(sparseness_factor)).map(|suffix| suffix as i64));
+    }
+    for index in 0..old_sa.len() {
+        if let Some(suffix) = shifts.shift(old_sa.get(index) as usize).filter(|&suffix| suffix < terminator) {
+            sa.push(suffix as i64);
+        }
+    }
+
+    Ok(sa)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use std::io::Write;
+
+    use sa_mappings::filter::ProteinFilter;
+    use sa_mappings::proteins::Proteins;
+    use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+    use tempdir::TempDir;
+
+    use crate::binary::{write_suffix_array, DatabaseFingerprint};
+    use crate::subindex::{restrict_suffix_array, write_sub_index, SubIndex};
+    use crate::update::compare_suffixes;
+    use crate::verify::verify_index;
+    use crate::{build_sa, translate_l_to_i, SAConstructionAlgorithm};
+
+    fn check_restricted(new_text: &[u8], sa: &[i64], sparseness_factor: u8) {
+        let mut new_text = new_text.to_vec();
+        let built = build_sa(&mut new_text.clone(), &SAConstructionAlgorithm::LibSais, sparseness_factor, 1).unwrap().to_vec();
+        translate_l_to_i(&mut new_text);
+        assert_eq!(sa.len(), built.len());
+        for (&suffix, &built_suffix) in sa.iter().zip(&built) {
+            assert_eq!(compare_suffixes(&new_text, suffix as usize, built_suffix as usize), Ordering::Equal);
+        }
+    }
+
+    /// Returns the text with only the kept proteins, in which every gap is padded with empty proteins until its length is a multiple of the sparseness factor
+    fn padded_text(proteins: &[&[u8]], kept: &[bool], sparseness_factor: usize) -> Vec<u8> {
+        let mut text = vec![];
+        let mut gap = 0;
+        for (&protein, &keep) in proteins.iter().zip(kept) {
+            if keep {
+                text.extend(std::iter::repeat_n(b'-', gap % sparseness_factor));
+                text.extend_from_slice(protein);
+                text.push(b'-');
+                gap = 0;
+            } else {
+                gap += protein.len() + 1;
+            }
+        }
+        text.pop();
+        text.push(b'$');
+        text
+    }
+
+    #[test]
+    fn test_restrict_suffix_array() {
+        let old_text = b"AI-BLACVAA-AC-KCRLZ$";
+        let old_sa = build_sa(&mut old_text.to_vec(), &SAConstructionAlgorithm::LibSais, 1, 1).unwrap().to_vec();
+
+        // a protein in the middle is removed
+        let sa = restrict_suffix_array(old_text, &[true, false, true, true], &old_sa, 1).unwrap();
+        check_restricted(b"AI-AC-KCRLZ$", &sa, 1);
+
+        // the last protein is removed, so the separator before it becomes the terminator
+        let sa = restrict_suffix_array(old_text, &[true, true, false, false], &old_sa, 1).unwrap();
+        assert_eq!(sa[0], 10);
+        check_restricted(b"AI-BLACVAA$", &sa, 1);
+
+        assert!(restrict_suffix_array(old_text, &[false; 4], &old_sa, 1).is_err());
+        assert!(restrict_suffix_array(old_text, &[true; 3], &old_sa, 1).is_err());
+    }
+
+    #[test]
+    fn test_restrict_sparse_suffix_array() {
+        let old_text = b"AI-BLACVAA-AC-KCRLZ$";
+        let proteins: Vec<&[u8]> = old_text[..old_text.len() - 1].split(|&character| character == b'-').collect();
+
+        // removing the third protein shifts the last protein by 3, so one empty protein pads the gap
+        let old_sa = build_sa(&mut old_text.to_vec(), &SAConstructionAlgorithm::LibSais, 2, 1).unwrap().to_vec();
+        let kept = [true, true, false, true];
+        assert_eq!(padded_text(&proteins, &kept, 2), b"AI-BLACVAA--KCRLZ$");
+        let sa = restrict_suffix_array(old_text, &kept, &old_sa, 2).unwrap();
+        check_restricted(b"AI-BLACVAA--KCRLZ$", &sa, 2);
+
+        // every combination of kept proteins
+        for sparseness_factor in 2..=4 {
+            let old_sa = build_sa(&mut old_text.to_vec(), &SAConstructionAlgorithm::LibSais, sparseness_factor, 1).unwrap().to_vec();
+            for mask in 1..(1 << proteins.len()) {
+                let kept: Vec<bool> = (0..proteins.len()).map(|protein| mask & (1 << protein) != 0).collect();
+                let sa = restrict_suffix_array(old_text, &kept, &old_sa, sparseness_factor).unwrap();
+                check_restricted(&padded_text(&proteins, &kept, sparseness_factor as usize), &sa, sparseness_factor);
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_sub_index() {
+        let tmp_dir = TempDir::new("test_write_sub_index").unwrap();
+        let database_file = tmp_dir.path().join("database.tsv");
+        let mut file = std::fs::File::create(&database_file).unwrap();
+        for (accession, taxon_id, sequence) in [("P1", 7, "AI"), ("P2", 2, "BLACVAA"), ("P3", 9, "AC"), ("P4", 2, "KCR"), ("P5", 7, "KCRLZ"), ("P6", 9, "LAAC")] {
+            writeln!(file, "{}\t{}\t{}\t", accession, taxon_id, sequence).unwrap();
+        }
+        let database_file = database_file.to_str().unwrap();
+        let taxonomy = "../testfiles/small_taxonomy.tsv";
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(taxonomy, AggregationMethod::LcaStar).unwrap();
+
+        let mut text = Proteins::try_from_database_file_without_annotations(database_file, &taxon_aggregator, &ProteinFilter::new()).unwrap();
+        let database = DatabaseFingerprint::new(&text, &[database_file, taxonomy], &ProteinFilter::new()).unwrap();
+        let sa = build_sa(&mut text, &SAConstructionAlgorithm::LibSais, 3, 1).unwrap().to_vec();
+        let index_file = tmp_dir.path().join("index.bin");
+        write_suffix_array(3, 64, &database, &sa, index_file.to_str().unwrap()).unwrap();
+
+        let sub_database = tmp_dir.path().join("sub_database.tsv");
+        let output = tmp_dir.path().join("sub_index.bin");
+        let sub_index = SubIndex {
+            index_file: index_file.to_str().unwrap(),
+            database_file,
+            taxon_id: 6,
+            sub_database: sub_database.to_str().unwrap(),
+        };
+        let sub_database_fingerprint = write_sub_index(&sub_index, taxonomy, &taxon_aggregator, None, output.to_str().unwrap()).unwrap();
+
+        // the gaps of 8 and 4 characters are padded with 2 and 1 empty proteins
+        let sub_filter = ProteinFilter { include_taxa: vec![6], ..ProteinFilter::new() };
+        let mut sub_text = Proteins::try_from_database_file_without_annotations(sub_index.sub_database, &taxon_aggregator, &sub_filter).unwrap();
+        assert_eq!(sub_text, b"AI---AC--KCRLZ-LAAC$".to_vec());
+        assert_eq!(sub_database_fingerprint.filter, sub_filter);
+
+        let report = verify_index(&mut sub_text, &sub_database_fingerprint, output.to_str().unwrap(), None, 1 << 20, 1);
+        assert!(report.checks.iter().all(|(_, failure)| failure.is_none()));
+    }
+}
@@ -0,0 +1,332 @@
+use std::cmp::min;
+
+/// Trait implemented by all representations of a (sparse) suffix array
+/// This allows the searcher to use the suffix array without knowing if it is stored in memory or mapped from a file
+pub trait SuffixArray: Send + Sync {
+
+    /// Returns the suffix stored at `index` in the suffix array
+    ///
+    /// # Arguments
+    /// * `index` - The index in the suffix array
+    ///
+    /// # Returns
+    ///
+    /// Returns the start position in the text of the suffix at `index`
+    fn get(&self, index: usize) -> i64;
+
+    /// Returns the number of entries in the suffix array
+    fn len(&self) -> usize;
+
+    /// Returns true if the suffix array contains no entries
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Searches the interval of suffixes that start with `search_string` without a binary search
+    /// Only representations that support backward search (like an FM-index) implement this
+    ///
+    /// # Arguments
+    /// * `search_string` - The string we are searching, with every L translated to an I
+    ///
+    /// # Returns
+    ///
+    /// Returns the minimum (inclusive) and maximum (exclusive) bound of the suffixes starting with `search_string`, an empty interval if there are none
+    /// Returns None if backward search is not supported by this representation
+    fn backward_search(&self, _search_string: &[u8]) -> Option<(usize, usize)> {
+        None
+    }
+
+    /// Calls `f` with the index and the suffix of every entry of the suffix array, in an unspecified order
+    /// Representations where `get` is expensive can visit all entries faster than calling `get` for every index
+    ///
+    /// # Arguments
+    /// * `f` - The function called with every index and suffix
+    fn for_each_suffix(&self, f: &mut dyn FnMut(usize, i64)) {
+        for index in 0..self.len() {
+            f(index, self.get(index));
+        }
+    }
+}
+
+/// Integer type in which the entries of a suffix array are stored in memory
+/// Suffix arrays over texts of at most `i32::MAX` characters are stored in 32-bit entries, which halves their memory
+pub trait SuffixEntry: Copy + Default + Ord + Into<i64> + Send + Sync + 'static {
+
+    /// Converts a position in the text to an entry
+    ///
+    /// # Arguments
+    /// * `position` - The position in the text, which fits in the entry
+    ///
+    /// # Returns
+    ///
+    /// Returns the entry that stores `position`
+    fn from_position(position: usize) -> Self;
+}
+
+impl SuffixEntry for i32 {
+    #[inline]
+    fn from_position(position: usize) -> Self {
+        position as i32
+    }
+}
+
+impl SuffixEntry for i64 {
+    #[inline]
+    fn from_position(position: usize) -> Self {
+        position as i64
+    }
+}
+
+impl<T: SuffixEntry> SuffixArray for Vec<T> {
+    #[inline]
+    fn get(&self, index: usize) -> i64 {
+        self[index].into()
+    }
+
+    fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+}
+
+/// Suffix array built in memory, of which the entries are stored in 32 bits if the text is short enough
+#[derive(Debug, PartialEq)]
+pub enum BuiltSuffixArray {
+    Entries32(Vec<i32>),
+    Entries64(Vec<i64>),
+}
+
+impl BuiltSuffixArray {
+    /// Returns a copy of the suffix array with 64-bit entries
+    pub fn to_vec(&self) -> Vec<i64> {
+        match self {
+            BuiltSuffixArray::Entries32(sa) => sa.iter().map(|&suffix| suffix as i64).collect(),
+            BuiltSuffixArray::Entries64(sa) => sa.clone(),
+        }
+    }
+}
+
+impl SuffixArray for BuiltSuffixArray {
+    #[inline]
+    fn get(&self, index: usize) -> i64 {
+        match self {
+            BuiltSuffixArray::Entries32(sa) => sa[index] as i64,
+            BuiltSuffixArray::Entries64(sa) => sa[index],
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            BuiltSuffixArray::Entries32(sa) => sa.len(),
+            BuiltSuffixArray::Entries64(sa) => sa.len(),
+        }
+    }
+}
+
+/// Suffix array of which every entry is stored in `bits_per_value` bits instead of 64 bits
+/// The entries are packed after each other in little endian order, so an entry can span multiple bytes
+///
+/// # Arguments
+/// * `data` - The packed entries of the suffix array
+/// * `bits_per_value` - The number of bits used to store every entry
+/// * `len` - The number of entries in the suffix array
+pub struct BitPackedSuffixArray {
+    data: Vec<u8>,
+    bits_per_value: u8,
+    len: usize,
+}
+
+impl BitPackedSuffixArray {
+    /// Creates a new BitPackedSuffixArray from the given suffix array
+    ///
+    /// # Arguments
+    /// * `sa` - The suffix array
+    /// * `bits_per_value` - The number of bits used to store every entry
+    ///
+    /// # Returns
+    ///
+    /// Returns the bit-packed suffix array
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an entry of the suffix array can not be stored in `bits_per_value` bits
+    pub fn new<T: SuffixEntry>(sa: &[T], bits_per_value: u8) -> Result<Self, String> {
+        check_bits_per_value(sa, bits_per_value)?;
+        Ok(Self::from_packed(pack(sa, bits_per_value), bits_per_value, sa.len()))
+    }
+
+    /// Creates a new BitPackedSuffixArray from entries that are already packed
+    ///
+    /// # Arguments
+    /// * `data` - The entries packed with `pack`
+    /// * `bits_per_value` - The number of bits used to store every entry
+    /// * `len` - The number of entries in the suffix array
+    ///
+    /// # Returns
+    ///
+    /// Returns the bit-packed suffix array
+    pub(crate) fn from_packed(data: Vec<u8>, bits_per_value: u8, len: usize) -> Self {
+        BitPackedSuffixArray {
+            data,
+            bits_per_value,
+            len,
+        }
+    }
+
+    /// Returns the number of bits used to store every entry
+    pub fn bits_per_value(&self) -> u8 {
+        self.bits_per_value
+    }
+
+    /// Returns the packed entries
+    pub(crate) fn packed_data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl SuffixArray for BitPackedSuffixArray {
+    #[inline]
+    fn get(&self, index: usize) -> i64 {
+        read_packed(&self.data, self.bits_per_value, index)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// Returns the minimum number of bits needed to store every suffix of a text
+///
+/// # Arguments
+/// * `text_length` - The length of the text the suffix array is built on
+///
+/// # Returns
+///
+/// Returns the number of bits needed to store the largest suffix, with a minimum of 1
+pub fn required_bits_per_value(text_length: usize) -> u8 {
+    let largest_suffix = text_length.saturating_sub(1) as u64;
+    (u64::BITS - largest_suffix.leading_zeros()).max(1) as u8
+}
+
+/// Checks if all entries of the suffix array can be stored in `bits_per_value` bits
+///
+/// # Arguments
+/// * `sa` - The suffix array
+/// * `bits_per_value` - The number of bits used to store every entry
+///
+/// # Returns
+///
+/// Returns () if all entries can be stored in `bits_per_value` bits
+///
+/// # Errors
+///
+/// Returns an error message if `bits_per_value` is not between 1 and 64, or if an entry does not fit
+pub(crate) fn check_bits_per_value<T: SuffixEntry>(sa: &[T], bits_per_value: u8) -> Result<(), String> {
+    if !(1..=64).contains(&bits_per_value) {
+        return Err(format!("The number of bits per value must be between 1 and 64, got {}", bits_per_value));
+    }
+    let largest_suffix: i64 = sa.iter().copied().max().unwrap_or_default().into();
+    if sa.iter().any(|&suffix| suffix < T::default()) || required_bits_per_value(largest_suffix as usize + 1) > bits_per_value {
+        return Err(format!("The suffix array can not be stored with {} bits per value", bits_per_value));
+    }
+    Ok(())
+}
+
+/// Packs the entries of the suffix array in `bits_per_value` bits each
+/// If the number of entries is a multiple of 8, the packed entries fill a whole number of bytes, so multiple packed parts can be concatenated
+///
+/// # Arguments
+/// * `sa` - The suffix array, of which every entry fits in `bits_per_value` bits
+/// * `bits_per_value` - The number of bits used to store every entry
+///
+/// # Returns
+///
+/// Returns the packed entries as bytes
+pub(crate) fn pack<T: SuffixEntry>(sa: &[T], bits_per_value: u8) -> Vec<u8> {
+    let bits_per_value = bits_per_value as usize;
+    let mut data = vec![0_u8; (sa.len() * bits_per_value).div_ceil(8)];
+    for (index, &suffix) in sa.iter().enumerate() {
+        let suffix: i64 = suffix.into();
+        let bit_start = index * bits_per_value;
+        let mut byte_index = bit_start / 8;
+        let mut value = (suffix as u128) << (bit_start % 8);
+        while value != 0 {
+            data[byte_index] |= value as u8;
+            value >>= 8;
+            byte_index += 1;
+        }
+    }
+    data
+}
+
+/// Reads the entry at `index` from the packed entries
+///
+/// # Arguments
+/// * `data` - The entries packed with `pack`
+/// * `bits_per_value` - The number of bits used to store every entry
+/// * `index` - The index of the entry
+///
+/// # Returns
+///
+/// Returns the entry at `index`
+#[inline]
+pub(crate) fn read_packed(data: &[u8], bits_per_value: u8, index: usize) -> i64 {
+    let bit_start = index * bits_per_value as usize;
+    let byte_start = bit_start / 8;
+    // an entry of at most 64 bits spans at most 9 bytes, which always fit in a u128
+    let byte_end = min(byte_start + 16, data.len());
+    let mut buffer = [0_u8; 16];
+    buffer[..byte_end - byte_start].copy_from_slice(&data[byte_start..byte_end]);
+
+    let value = u128::from_le_bytes(buffer) >> (bit_start % 8);
+    (value & ((1_u128 << bits_per_value) - 1)) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::suffix_array::{pack, read_packed, required_bits_per_value, BitPackedSuffixArray, SuffixArray};
+
+    #[test]
+    fn test_required_bits_per_value() {
+        assert_eq!(required_bits_per_value(0), 1);
+        assert_eq!(required_bits_per_value(2), 1);
+        assert_eq!(required_bits_per_value(3), 2);
+        assert_eq!(required_bits_per_value(1 << 32), 32);
+        assert_eq!(required_bits_per_value((1 << 32) + 1), 33);
+        assert_eq!(required_bits_per_value(20_000_000_000), 35);
+    }
+
+    #[test]
+    fn test_pack_read() {
+        let sa: Vec<i64> = vec![19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18];
+        for bits_per_value in [5, 7, 8, 13, 35, 64] {
+            let data = pack(&sa, bits_per_value);
+            assert_eq!(data.len(), (sa.len() * bits_per_value as usize).div_ceil(8));
+            for (index, &suffix) in sa.iter().enumerate() {
+                assert_eq!(read_packed(&data, bits_per_value, index), suffix);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pack_large_values() {
+        let sa: Vec<i64> = vec![(1 << 35) - 1, 0, 1 << 34, 12345678901, i64::MAX];
+        let data = pack(&sa, 64);
+        for (index, &suffix) in sa.iter().enumerate() {
+            assert_eq!(read_packed(&data, 64, index), suffix);
+        }
+
+        let sa = BitPackedSuffixArray::new(&sa[..4], 35).unwrap();
+        assert_eq!(sa.len(), 4);
+        assert_eq!(sa.get(0), (1 << 35) - 1);
+        assert_eq!(sa.get(2), 1 << 34);
+        assert_eq!(sa.get(3), 12345678901);
+    }
+
+    #[test]
+    fn test_bit_packed_too_small() {
+        assert!(BitPackedSuffixArray::new(&[0, 1, 8], 3).is_err());
+        assert!(BitPackedSuffixArray::new(&[0, 1, 7], 3).is_ok());
+        assert!(BitPackedSuffixArray::new(&[0, -1], 64).is_err());
+        assert!(BitPackedSuffixArray::new(&[0, 1], 0).is_err());
+    }
+}
@@ -0,0 +1,422 @@
+use memmap2::Mmap;
+use sa_mappings::proteins::{Proteins, SEPARATION_CHARACTER, TERMINATION_CHARACTER};
+use sa_mappings::taxonomy::{TaxonAggregator, TaxonSummary};
+
+use crate::suffix_array::SuffixArray;
+
+/// The number of suffix array entries that are combined in one block
+const BLOCK_SIZE: usize = 64;
+
+/// Index that aggregates the taxa of all suffixes in an interval of the suffix array, without retrieving the proteins
+/// The taxa are aggregated with the aggregation method of the taxonomy, so the result is the same as aggregating the taxa of the retrieved proteins
+/// The taxa are stored per suffix in suffix array order, and a sparse table over blocks of `BLOCK_SIZE` suffixes is used for range queries
+/// All summaries are stored in a single flat array, so the index can be written to a file and memory mapped from it, see `Layout`
+///
+/// # Arguments
+/// * `summaries` - The flat array with all the summaries of the index
+/// * `layout` - The positions of the parts of the index in `summaries`
+/// * `len` - The number of entries in the suffix array the index is built on
+/// * `lca_star` - True if the taxa are aggregated with the LCA*, false for the LCA
+pub struct TaxonLcaIndex {
+    summaries: Summaries,
+    layout: Layout,
+    len: usize,
+    lca_star: bool,
+}
+
+/// The flat array with the summaries of a TaxonLcaIndex, either in memory or memory mapped from a file
+pub(crate) enum Summaries {
+    /// The summaries stored in memory
+    Memory(Vec<u32>),
+    /// The summaries read directly from the file, as little endian u32 values starting at `start`
+    Mapped { mmap: Mmap, start: usize },
+}
+
+impl Summaries {
+    /// Returns the summary at `index` in the flat array
+    #[inline]
+    fn get(&self, index: usize) -> TaxonSummary {
+        match self {
+            Summaries::Memory(summaries) => TaxonSummary::from_bits(summaries[index]),
+            Summaries::Mapped { mmap, start } => {
+                let position = start + 4 * index;
+                TaxonSummary::from_bits(u32::from_le_bytes(mmap[position..position + 4].try_into().unwrap()))
+            }
+        }
+    }
+}
+
+/// The positions of the parts of a TaxonLcaIndex in its flat array of summaries, in the order in which they are stored
+///
+/// # Arguments
+/// * `valid_taxa` - The start of the summaries of the valid taxa per suffix, only stored if the sequences are deduplicated
+/// * `block_lcas` - The start of every level of the sparse table over the blocks
+/// * `valid_block_lcas` - The start of every level of the sparse table over the blocks, only taking the valid taxa into account
+/// * `size` - The total number of summaries
+#[derive(Debug, PartialEq)]
+pub(crate) struct Layout {
+    valid_taxa: Option<usize>,
+    block_lcas: Vec<usize>,
+    valid_block_lcas: Vec<usize>,
+    pub(crate) size: usize,
+}
+
+impl Layout {
+    /// Calculates the layout of the index over a suffix array with `len` entries
+    /// The summaries of the taxa per suffix are stored first, level `level` of the sparse tables summarizes 2^level blocks
+    ///
+    /// # Arguments
+    /// * `len` - The number of entries in the suffix array
+    /// * `deduplicated` - True if the sequences of the text are deduplicated
+    ///
+    /// # Returns
+    ///
+    /// Returns the layout of the index
+    pub(crate) fn new(len: usize, deduplicated: bool) -> Self {
+        let mut size = len;
+        let valid_taxa = if deduplicated {
+            size += len;
+            Some(len)
+        } else {
+            None
+        };
+
+        let number_of_blocks = len.div_ceil(BLOCK_SIZE);
+        let mut sparse_table = || {
+            let mut levels = vec![];
+            let mut width = 1;
+            while width <= number_of_blocks {
+                levels.push(size);
+                size += number_of_blocks - width + 1;
+                width *= 2;
+            }
+            levels
+        };
+        let block_lcas = sparse_table();
+        let valid_block_lcas = sparse_table();
+
+        Layout { valid_taxa, block_lcas, valid_block_lcas, size }
+    }
+}
+
+impl TaxonLcaIndex {
+
+    /// Creates a new TaxonLcaIndex
+    ///
+    /// # Arguments
+    /// * `sa` - The sparse suffix array representing the protein database
+    /// * `proteins` - List of all the proteins where the suffix array is build on
+    /// * `taxon_aggregator` - The taxonomy used to aggregate the taxa
+    ///
+    /// # Returns
+    ///
+    /// Returns a new TaxonLcaIndex built over the suffix array
+    pub fn new(sa: &dyn SuffixArray, proteins: &Proteins, taxon_aggregator: &TaxonAggregator) -> Self {
+        let join = |summary1: u32, summary2: u32| {
+            taxon_aggregator.join(TaxonSummary::from_bits(summary1), TaxonSummary::from_bits(summary2)).to_bits()
+        };
+        let summary = |taxon: usize| TaxonSummary::lineage(taxon).to_bits();
+
+        // the start of every entry in the text, used to find the entry a suffix is part of
+        let text = &proteins.input_string;
+        let mut entry_starts: Vec<i64> = vec![0];
+        for (index, &character) in text.iter().enumerate() {
+            if character == SEPARATION_CHARACTER || character == TERMINATION_CHARACTER {
+                entry_starts.push(index as i64 + 1);
+            }
+        }
+
+        // an entry of a deduplicated text can have both valid and invalid taxa, so the summary of its valid taxa is stored separately
+        let deduplicated = !proteins.entry_starts.is_empty();
+        let layout = Layout::new(sa.len(), deduplicated);
+        let mut taxa: Vec<u32> = vec![0; sa.len()];
+        let mut valid_taxa: Vec<u32> = if deduplicated { vec![0; sa.len()] } else { vec![] };
+        sa.for_each_suffix(&mut |sa_index, suffix| {
+            // a suffix that starts with a separation character is not part of a protein
+            let character = text[suffix as usize];
+            if character == SEPARATION_CHARACTER || character == TERMINATION_CHARACTER {
+                return;
+            }
+            let entry = entry_starts.partition_point(|&start| start <= suffix) - 1;
+            for protein in proteins.entry(entry) {
+                taxa[sa_index] = join(taxa[sa_index], summary(protein.taxon_id));
+                if deduplicated && taxon_aggregator.taxon_valid(protein.taxon_id) {
+                    valid_taxa[sa_index] = join(valid_taxa[sa_index], summary(protein.taxon_id));
+                }
+            }
+        });
+
+        let block_lcas = Self::build_sparse_table(&taxa, |_| true, join);
+        let valid_block_lcas = if deduplicated {
+            Self::build_sparse_table(&valid_taxa, |_| true, join)
+        } else {
+            Self::build_sparse_table(
+                &taxa,
+                |summary| taxon_aggregator.taxon_valid(TaxonSummary::from_bits(summary).taxon()),
+                join,
+            )
+        };
+
+        let mut summaries = taxa;
+        summaries.reserve_exact(layout.size - summaries.len());
+        summaries.append(&mut valid_taxa);
+        summaries.extend(block_lcas.into_iter().flatten());
+        summaries.extend(valid_block_lcas.into_iter().flatten());
+        debug_assert_eq!(summaries.len(), layout.size);
+
+        TaxonLcaIndex {
+            summaries: Summaries::Memory(summaries),
+            layout,
+            len: sa.len(),
+            lca_star: taxon_aggregator.is_lca_star(),
+        }
+    }
+
+    /// Creates a TaxonLcaIndex from summaries that were written to a file
+    ///
+    /// # Arguments
+    /// * `summaries` - The flat array with all the summaries of the index
+    /// * `layout` - The positions of the parts of the index in `summaries`, which has to match the size of `summaries`
+    /// * `len` - The number of entries in the suffix array the index is built on
+    /// * `lca_star` - True if the taxa are aggregated with the LCA*, false for the LCA
+    ///
+    /// # Returns
+    ///
+    /// Returns the TaxonLcaIndex
+    pub(crate) fn from_parts(summaries: Summaries, layout: Layout, len: usize, lca_star: bool) -> Self {
+        TaxonLcaIndex { summaries, layout, len, lca_star }
+    }
+
+    /// Builds the sparse table over the blocks of the taxa
+    ///
+    /// # Arguments
+    /// * `taxa` - The summaries of the taxa in suffix array order
+    /// * `include` - Function that returns true if a summary has to be used in the aggregation
+    /// * `join` - Function that joins two summaries
+    ///
+    /// # Returns
+    ///
+    /// Returns the sparse table where `table[level][block]` summarizes the taxa of the 2^level blocks starting at `block`
+    fn build_sparse_table(
+        taxa: &[u32],
+        include: impl Fn(u32) -> bool,
+        join: impl Fn(u32, u32) -> u32,
+    ) -> Vec<Vec<u32>> {
+        let first_level: Vec<u32> = taxa
+            .chunks(BLOCK_SIZE)
+            .map(|block| {
+                block.iter().fold(0, |current, &summary| {
+                    if include(summary) {
+                        join(current, summary)
+                    } else {
+                        current
+                    }
+                })
+            })
+            .collect();
+
+        let number_of_blocks = first_level.len();
+        let mut table = vec![first_level];
+        let mut width = 1;
+        while 2 * width <= number_of_blocks {
+            let previous_level = &table[table.len() - 1];
+            let level: Vec<u32> = (0..=number_of_blocks - 2 * width)
+                .map(|block| join(previous_level[block], previous_level[block + width]))
+                .collect();
+            table.push(level);
+            width *= 2;
+        }
+
+        // the layout has no levels if there are no blocks
+        if number_of_blocks == 0 {
+            table.clear();
+        }
+        table
+    }
+
+    /// Returns the number of entries in the suffix array the index is built on
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the index is built on an empty suffix array
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if the taxa are aggregated with the LCA*, false if they are aggregated with the LCA
+    pub fn is_lca_star(&self) -> bool {
+        self.lca_star
+    }
+
+    /// Returns the number of summaries in the flat array of the index
+    pub(crate) fn number_of_summaries(&self) -> usize {
+        self.layout.size
+    }
+
+    /// Returns the summary at `index` in the flat array of the index, as it is stored in a file
+    pub(crate) fn summary_bits(&self, index: usize) -> u32 {
+        self.summaries.get(index).to_bits()
+    }
+
+    /// Returns the summary of the taxon of the protein of which the suffix at `sa_index` is a part
+    ///
+    /// # Arguments
+    /// * `sa_index` - The index in the suffix array
+    /// * `clean_taxa` - If true, only the taxa which are stored as "valid" are used
+    /// * `taxon_aggregator` - The taxonomy used to check if a taxon is valid
+    ///
+    /// # Returns
+    ///
+    /// Returns the summary of the taxon of the suffix, of the taxa of all proteins with its sequence if the sequences are deduplicated,
+    /// or an empty summary if the suffix is not part of a protein or has no valid taxon
+    #[inline]
+    pub fn taxon(&self, sa_index: usize, clean_taxa: bool, taxon_aggregator: &TaxonAggregator) -> TaxonSummary {
+        match self.layout.valid_taxa {
+            Some(valid_taxa) if clean_taxa => self.summaries.get(valid_taxa + sa_index),
+            _ => {
+                let summary = self.summaries.get(sa_index);
+                if clean_taxa && !taxon_aggregator.taxon_valid(summary.taxon()) {
+                    TaxonSummary::EMPTY
+                } else {
+                    summary
+                }
+            }
+        }
+    }
+
+    /// Aggregates the taxa of all suffixes in the interval [`min_bound`, `max_bound`) of the suffix array
+    ///
+    /// # Arguments
+    /// * `min_bound` - The start of the interval in the suffix array (inclusive)
+    /// * `max_bound` - The end of the interval in the suffix array (exclusive)
+    /// * `clean_taxa` - If true, only the taxa which are stored as "valid" are used
+    /// * `taxon_aggregator` - The taxonomy used to aggregate the taxa
+    ///
+    /// # Returns
+    ///
+    /// Returns the summary of all the taxa in the interval, which is empty if there are no taxa in the interval
+    pub fn range_lca(
+        &self,
+        min_bound: usize,
+        max_bound: usize,
+        clean_taxa: bool,
+        taxon_aggregator: &TaxonAggregator,
+    ) -> TaxonSummary {
+        let table = if clean_taxa { &self.layout.valid_block_lcas } else { &self.layout.block_lcas };
+        let join_range = |start: usize, end: usize| {
+            (start..end).fold(TaxonSummary::EMPTY, |current, sa_index| {
+                taxon_aggregator.join(current, self.taxon(sa_index, clean_taxa, taxon_aggregator))
+            })
+        };
+
+        let first_full_block = min_bound.div_ceil(BLOCK_SIZE);
+        let end_full_blocks = max_bound / BLOCK_SIZE;
+        if first_full_block >= end_full_blocks {
+            return join_range(min_bound, max_bound);
+        }
+
+        // the parts before and after the full blocks are calculated directly
+        let mut summary = taxon_aggregator.join(
+            join_range(min_bound, first_full_block * BLOCK_SIZE),
+            join_range(end_full_blocks * BLOCK_SIZE, max_bound),
+        );
+
+        // the full blocks are covered by two (overlapping) ranges in the sparse table, joining a taxon twice does not change the summary
+        let level = (end_full_blocks - first_full_block).ilog2() as usize;
+        summary = taxon_aggregator.join(summary, self.summaries.get(table[level] + first_full_block));
+        taxon_aggregator.join(summary, self.summaries.get(table[level] + end_full_blocks - (1 << level)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sa_mappings::proteins::{Protein, Proteins};
+    use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
+
+    use crate::taxon_lca_index::{Layout, TaxonLcaIndex};
+
+    fn create_index(taxa: &[usize]) -> (TaxonLcaIndex, TaxonAggregator) {
+        // every protein is a single character, so every suffix that does not start with a separation character is part of a protein
+        let mut text: Vec<u8> = taxa.iter().flat_map(|_| [b'A', b'-']).collect();
+        text.pop();
+        text.push(b'$');
+
+        let proteins = Proteins::new(
+            text,
+            taxa.iter().map(|&taxon_id| Protein { uniprot_id: "", taxon_id, functional_annotations: &[] }),
+        )
+        .unwrap();
+        let sa: Vec<i64> = (0..taxa.len() as i64).map(|protein| protein * 2).collect();
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            "../testfiles/small_taxonomy.tsv",
+            AggregationMethod::LcaStar,
+        )
+        .unwrap();
+        let index = TaxonLcaIndex::new(&sa, &proteins, &taxon_aggregator);
+        (index, taxon_aggregator)
+    }
+
+    #[test]
+    fn test_range_lca_small() {
+        let (index, taxon_aggregator) = create_index(&[7, 9, 13, 14, 19, 20]);
+        assert_eq!(index.taxon(2, false, &taxon_aggregator).taxon(), 13);
+        assert_eq!(index.range_lca(0, 2, false, &taxon_aggregator).taxon(), 6);
+        assert_eq!(index.range_lca(2, 4, false, &taxon_aggregator).taxon(), 10);
+        // the LCA* ignores taxon 19, which is an ancestor of taxon 20
+        assert_eq!(index.range_lca(4, 6, false, &taxon_aggregator).taxon(), 20);
+        assert_eq!(index.range_lca(1, 6, false, &taxon_aggregator).taxon(), 6);
+        assert_eq!(index.range_lca(3, 3, false, &taxon_aggregator).taxon(), 0);
+    }
+
+    #[test]
+    fn test_range_lca_blocks() {
+        // taxon 7 and 9 alternate, except for a single 2 at index 500
+        let mut taxa: Vec<usize> = (0..1000).map(|i| if i % 2 == 0 { 7 } else { 9 }).collect();
+        taxa[500] = 2;
+        let (index, taxon_aggregator) = create_index(&taxa);
+
+        assert_eq!(index.range_lca(0, 1, false, &taxon_aggregator).taxon(), 7);
+        assert_eq!(index.range_lca(0, 500, false, &taxon_aggregator).taxon(), 6);
+        assert_eq!(index.range_lca(501, 1000, false, &taxon_aggregator).taxon(), 6);
+        assert_eq!(index.range_lca(200, 501, false, &taxon_aggregator).taxon(), 1);
+        assert_eq!(index.range_lca(500, 501, false, &taxon_aggregator).taxon(), 2);
+        assert_eq!(index.range_lca(2, 998, false, &taxon_aggregator).taxon(), 1);
+        assert_eq!(index.range_lca(130, 140, false, &taxon_aggregator).taxon(), 6);
+    }
+
+    #[test]
+    fn test_range_lca_deduplicated() {
+        let mut proteins = Proteins::new(
+            b"A-C-A-C-A$".to_vec(),
+            [7, 13, 9, 14, 7].map(|taxon_id| Protein { uniprot_id: "", taxon_id, functional_annotations: &[] }),
+        )
+        .unwrap();
+        proteins.deduplicate();
+        let sa: Vec<i64> = vec![0, 2];
+        let taxon_aggregator = TaxonAggregator::try_from_taxonomy_file(
+            "../testfiles/small_taxonomy.tsv",
+            AggregationMethod::LcaStar,
+        )
+        .unwrap();
+        let index = TaxonLcaIndex::new(&sa, &proteins, &taxon_aggregator);
+
+        // the first entry has taxa 7, 9 and 7, the second entry has taxa 13 and 14
+        assert_eq!(index.taxon(0, false, &taxon_aggregator).taxon(), 6);
+        assert_eq!(index.taxon(1, true, &taxon_aggregator).taxon(), 10);
+        assert_eq!(index.range_lca(0, 2, true, &taxon_aggregator).taxon(), 6);
+    }
+
+    #[test]
+    fn test_layout() {
+        // 130 entries are divided into 3 blocks, so the sparse tables have a level of 3 blocks and a level of 2 blocks
+        let expected = Layout { valid_taxa: None, block_lcas: vec![130, 133], valid_block_lcas: vec![135, 138], size: 140 };
+        assert_eq!(Layout::new(130, false), expected);
+        let expected = Layout { valid_taxa: Some(130), block_lcas: vec![260, 263], valid_block_lcas: vec![265, 268], size: 270 };
+        assert_eq!(Layout::new(130, true), expected);
+        assert_eq!(Layout::new(0, true).size, 0);
+
+        let (index, _) = create_index(&[7; 130]);
+        assert_eq!(index.number_of_summaries(), 140);
+    }
+}
@@ -0,0 +1,457 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::str::from_utf8;
+
+use sa_mappings::filter::ProteinFilter;
+use sa_mappings::proteins::{is_fasta_file, SEPARATION_CHARACTER, TERMINATION_CHARACTER};
+use sa_mappings::taxonomy::TaxonAggregator;
+
+use crate::suffix_array::SuffixArray;
+use crate::translate_l_to_i;
+
+/// The files used to update an existing index with the proteins that were added to and removed from its database
+///
+/// # Arguments
+/// * `old_index` - The suffix array that was built over `database_file`
+/// * `database_file` - The database file the existing index was built on
+/// * `added_proteins` - Database file with the proteins that are added, or None if no proteins are added
+/// * `removed_accessions` - File with the accessions of the removed proteins, one accession per line, or None if no proteins are removed
+/// * `updated_database` - The file the database of the updated index is written to
+pub struct IndexUpdate<'a> {
+    pub old_index: &'a str,
+    pub database_file: &'a str,
+    pub added_proteins: Option<&'a str>,
+    pub removed_accessions: Option<&'a str>,
+    pub updated_database: &'a str,
+}
+
+impl IndexUpdate<'_> {
+
+    /// Writes the database file of the updated index
+    /// The updated database contains the proteins of the old database without the removed accessions, followed by the added proteins.
+    /// Since the kept proteins stay in the same order, the text of the updated database starts with the text of the kept proteins.
+    ///
+    /// # Arguments
+    /// * `taxon_aggregator` - The taxonomy used to read the database files
+    /// * `filter` - The filters that selected the proteins of the old database that are in its text
+    ///
+    /// # Returns
+    ///
+    /// Returns for every protein in the text of the old database if it is kept in the updated database
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the files could not be read or written, if one of the database files is a FASTA file, or if the old database file contains an invalid line
+    pub fn update_database(&self, taxon_aggregator: &TaxonAggregator, filter: &ProteinFilter) -> Result<Vec<bool>, Box<dyn Error>> {
+        // the lines of the old database are copied, so both database files have to be tsv files
+        if is_fasta_file(self.database_file)? || self.added_proteins.map(is_fasta_file).transpose()?.unwrap_or(false) {
+            return Err("Only indexes built on a tsv database file can be updated with proteins from a tsv database file".into());
+        }
+
+        let mut removed_accessions = HashSet::new();
+        if let Some(removed_accessions_file) = self.removed_accessions {
+            for line in BufReader::new(File::open(removed_accessions_file)?).lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    removed_accessions.insert(line.trim().as_bytes().to_vec());
+                }
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true) // if the file already exists, empty the file
+            .open(self.updated_database)?;
+        let mut writer = BufWriter::new(file);
+        let mut kept = vec![];
+        for line in BufReader::new(File::open(self.database_file)?).split(b'\n') {
+            let line = line?;
+            let mut fields = line.split(|&character| character == b'\t');
+            let accession = fields.next().unwrap_or_default();
+            let taxon_id = fields
+                .next()
+                .and_then(|taxon_id| from_utf8(taxon_id).ok())
+                .and_then(|taxon_id| taxon_id.parse::<usize>().ok())
+                .ok_or("The database file contains a line without a valid taxon id")?;
+            let sequence_length = fields.next().map_or(0, <[u8]>::len);
+
+            let removed = removed_accessions.contains(accession);
+            // proteins of which the taxon is not in the taxonomy, or that do not pass the filters, are not part of the text
+            if taxon_aggregator.taxon_exists(taxon_id) && filter.keeps(from_utf8(accession)?, taxon_id, sequence_length, taxon_aggregator) {
+                kept.push(!removed);
+            }
+            if !removed {
+                writer.write_all(&line)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        if let Some(added_proteins) = self.added_proteins {
+            std::io::copy(&mut BufReader::new(File::open(added_proteins)?), &mut writer)?;
+        }
+        writer.flush()?;
+
+        Ok(kept)
+    }
+}
+
+/// Builds the suffix array over the text of the updated database by merging the suffix array over the old text with the suffixes of the added proteins
+/// The suffixes of the kept proteins stay in the same order, they only shift by the length of the removed proteins before them.
+/// Only the suffixes starting in the added proteins are sorted, after which both sorted lists are merged.
+/// The suffixes of the kept proteins that shift by a length that is not a multiple of the sparseness factor are not in the old suffix array,
+/// so only the sampled suffixes of those proteins are sorted again. Suffixes that are equal up to the end of their protein are ordered by the text after it,
+/// so the suffix array is identical to the one of a full build over the updated text.
+///
+/// # Arguments
+/// * `old_text` - The text of the old database
+/// * `kept` - For every protein in the old text if it is kept in the updated database, as returned by `IndexUpdate::update_database`
+/// * `old_sa` - The suffix array built over the old text
+/// * `sparseness_factor` - The sparseness factor of the old suffix array, which is also used for the updated suffix array
+/// * `new_text` - The text of the updated database, in which every L is translated to an I
+/// * `threads` - The number of threads used by libsais, 0 to use all available cores
+///
+/// # Returns
+///
+/// Returns the suffix array over the text of the updated database
+///
+/// # Errors
+///
+/// Returns an error if `kept` does not match the proteins in the old text, or if building the suffix array of the added proteins failed
+pub fn merge_suffix_array(
+    old_text: &[u8],
+    kept: &[bool],
+    old_sa: &dyn SuffixArray,
+    sparseness_factor: u8,
+    new_text: &mut [u8],
+    threads: usize,
+) -> Result<Vec<i64>, Box<dyn Error>> {
+    let sparseness_factor = sparseness_factor as usize;
+    let shifts = ProteinShifts::new(old_text, kept, sparseness_factor)?;
+
+    // the suffixes from the separator after the last kept protein on are sorted again, since the text after that separator changed
+    let added_start = shifts.kept_length.saturating_sub(1);
+    if new_text.len() <= added_start {
+        return Err("The text of the updated database does not start with the kept proteins".into());
+    }
+    translate_l_to_i(new_text);
+    let new_text = &*new_text;
+    let added_sa = libsais64_rs::sais64_omp(&new_text[added_start..], threads)
+        .map_err(|err| format!("Building suffix array failed: {}", err))?;
+
+    // the kept suffixes that are equal up to the end of their protein are next to each other, but the text after their protein changed
+    let mut kept_suffixes: Vec<usize> = (0..old_sa.len())
+        .filter_map(|index| shifts.shift(old_sa.get(index) as usize).filter(|&suffix| suffix < added_start))
+        .collect();
+    for equal_suffixes in kept_suffixes.chunk_by_mut(|&a, &b| equal_in_protein(new_text, a, b)) {
+        equal_suffixes.sort_unstable_by(|&a, &b| new_text[a..].cmp(&new_text[b..]));
+    }
+
+    let mut shifted_suffixes: Vec<usize> = shifts
+        .misaligned()
+        .flat_map(|protein| protein.filter(|suffix| suffix.is_multiple_of(sparseness_factor)))
+        .filter(|&suffix| suffix < added_start)
+        .collect();
+    shifted_suffixes.sort_unstable_by(|&a, &b| new_text[a..].cmp(&new_text[b..]));
+
+    let added_suffixes = added_sa
+        .into_iter()
+        .map(|suffix| suffix as usize + added_start)
+        .filter(|suffix| suffix.is_multiple_of(sparseness_factor));
+
+    let kept_suffixes = merge_sorted(new_text, kept_suffixes.into_iter(), shifted_suffixes.into_iter());
+    Ok(merge_sorted(new_text, kept_suffixes, added_suffixes).map(|suffix| suffix as i64).collect())
+}
+
+/// Merges two sorted lists of suffixes of the text into one sorted list
+///
+/// # Arguments
+/// * `text` - The text, which ends with the termination character
+/// * `first` - The first sorted list of suffixes
+/// * `second` - The second sorted list of suffixes
+///
+/// # Returns
+///
+/// Returns an iterator over the suffixes of both lists, in sorted order
+fn merge_sorted<'a>(
+    text: &'a [u8],
+    first: impl Iterator<Item = usize> + 'a,
+    second: impl Iterator<Item = usize> + 'a,
+) -> impl Iterator<Item = usize> + 'a {
+    let mut first = first.peekable();
+    let mut second = second.peekable();
+    std::iter::from_fn(move || match (first.peek(), second.peek()) {
+        (Some(&a), Some(&b)) => {
+            if text[a..] > text[b..] {
+                second.next()
+            } else {
+                first.next()
+            }
+        }
+        (Some(_), None) => first.next(),
+        (None, _) => second.next(),
+    })
+}
+
+/// Where the proteins of an old text end up in the text that only contains the kept proteins, in the same order
+///
+/// # Arguments
+/// * `starts` - The start of every protein in the old text
+/// * `shifts` - The distance every protein shifts to the left in the new text, None if the protein is removed
+/// * `padding` - The number of empty proteins that are inserted in the new text right before every protein, always 0 for a removed protein
+/// * `kept_length` - The length of the kept proteins and the padding in the new text, including the separator or terminator after every protein
+/// * `sparseness_factor` - The sparseness factor of the suffix array over the old text
+pub(crate) struct ProteinShifts {
+    pub starts: Vec<usize>,
+    pub shifts: Vec<Option<usize>>,
+    pub padding: Vec<usize>,
+    pub kept_length: usize,
+    pub sparseness_factor: usize,
+}
+
+impl ProteinShifts {
+    /// Computes the shift of every protein of the old text
+    ///
+    /// # Arguments
+    /// * `old_text` - The old text, which ends with the termination character
+    /// * `kept` - For every protein in the old text if it is kept
+    /// * `sparseness_factor` - The sparseness factor of the suffix array over the old text
+    ///
+    /// # Returns
+    ///
+    /// Returns the start and the shift of every protein
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `kept` does not match the proteins in the old text
+    pub(crate) fn new(old_text: &[u8], kept: &[bool], sparseness_factor: usize) -> Result<Self, Box<dyn Error>> {
+        Self::compute(old_text, kept, sparseness_factor, false)
+    }
+
+    /// Computes the shift of every protein of the old text, when every gap left by the removed proteins is padded with empty proteins
+    /// until the length that is removed is a multiple of the sparseness factor, so every kept protein shifts by a multiple of the sparseness factor
+    ///
+    /// # Arguments
+    /// * `old_text` - The old text, which ends with the termination character
+    /// * `kept` - For every protein in the old text if it is kept
+    /// * `sparseness_factor` - The sparseness factor of the suffix array over the old text
+    ///
+    /// # Returns
+    ///
+    /// Returns the start, the shift and the padding of every protein
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `kept` does not match the proteins in the old text
+    pub(crate) fn padded(old_text: &[u8], kept: &[bool], sparseness_factor: usize) -> Result<Self, Box<dyn Error>> {
+        Self::compute(old_text, kept, sparseness_factor, true)
+    }
+
+    /// Computes the shift of every protein of the old text, see `new` and `padded`
+    fn compute(old_text: &[u8], kept: &[bool], sparseness_factor: usize, pad_gaps: bool) -> Result<Self, Box<dyn Error>> {
+        let old_proteins = old_text[..old_text.len() - 1].split(|&character| character == SEPARATION_CHARACTER);
+        if old_proteins.clone().count() != kept.len() {
+            return Err("The kept proteins do not match the proteins of the old index".into());
+        }
+
+        let mut starts = Vec::with_capacity(kept.len());
+        let mut shifts = Vec::with_capacity(kept.len());
+        let mut padding = Vec::with_capacity(kept.len());
+        let mut start = 0;
+        // the length of the removed proteins before the current protein, without the padding
+        let mut shift = 0;
+        let mut gap = 0;
+        for (protein, &keep) in old_proteins.zip(kept) {
+            starts.push(start);
+            if keep {
+                let protein_padding = if pad_gaps { gap % sparseness_factor } else { 0 };
+                shift -= protein_padding;
+                shifts.push(Some(shift));
+                padding.push(protein_padding);
+                gap = 0;
+            } else {
+                shifts.push(None);
+                padding.push(0);
+                shift += protein.len() + 1;
+                gap += protein.len() + 1;
+            }
+            start += protein.len() + 1;
+        }
+
+        Ok(ProteinShifts { starts, shifts, padding, kept_length: start - shift, sparseness_factor })
+    }
+
+    /// Returns the start of a suffix of the old text in the new text
+    ///
+    /// # Arguments
+    /// * `suffix` - The start of the suffix in the old text
+    ///
+    /// # Returns
+    ///
+    /// Returns the start of the suffix in the new text, or None if the suffix starts in a removed protein
+    /// or in a protein that shifts by a length that is not a multiple of the sparseness factor
+    pub(crate) fn shift(&self, suffix: usize) -> Option<usize> {
+        let protein = self.starts.partition_point(|&start| start <= suffix) - 1;
+        self.shifts[protein]
+            .filter(|shift| shift.is_multiple_of(self.sparseness_factor))
+            .map(|shift| suffix - shift)
+    }
+
+    /// Returns the kept proteins that shift by a length that is not a multiple of the sparseness factor,
+    /// of which the sampled suffixes in the new text are not in the old suffix array
+    ///
+    /// # Returns
+    ///
+    /// Returns the range of every such protein in the new text, including the separator or terminator after it
+    pub(crate) fn misaligned(&self) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.shifts.iter().enumerate().filter_map(|(protein, shift)| {
+            let shift = shift.filter(|shift| !shift.is_multiple_of(self.sparseness_factor))?;
+            // the last protein ends at the end of the old text, which is the kept length plus its shift
+            let end = self.starts.get(protein + 1).map_or(self.kept_length + shift, |&end| end);
+            Some(self.starts[protein] - shift..end - shift)
+        })
+    }
+}
+
+/// Returns if two suffixes of the text are equal up to the end of the protein they start in
+/// The separator and the terminator are both the end of a protein, so the last kept protein still equals the suffixes it was next to in the old suffix array
+///
+/// # Arguments
+/// * `text` - The text, which ends with the termination character
+/// * `a` - The start of the first suffix
+/// * `b` - The start of the second suffix
+///
+/// # Returns
+///
+/// Returns true if the suffixes have the same characters up to the end of their protein
+fn equal_in_protein(text: &[u8], a: usize, b: usize) -> bool {
+    let is_end = |character: u8| character == SEPARATION_CHARACTER || character == TERMINATION_CHARACTER;
+    for (&character_a, &character_b) in text[a..].iter().zip(&text[b..]) {
+        if is_end(character_a) || is_end(character_b) {
+            return is_end(character_a) && is_end(character_b);
+        }
+        if character_a != character_b {
+            return false;
+        }
+    }
+    false
+}
+
+/// Compares two suffixes of the text up to the end of the protein they start in
+/// Peptides never contain a separator, so the order of suffixes that are equal up to the end of their protein does not matter during search
+///
+/// # Arguments
+/// * `text` - The text, which ends with the termination character
+/// * `a` - The start of the first suffix
+/// * `b` - The start of the second suffix
+///
+/// # Returns
+///
+/// Returns the order of the suffixes, Equal if they are equal up to and including the end of their protein
+pub(crate) fn compare_suffixes(text: &[u8], a: usize, b: usize) -> Ordering {
+    for (&character_a, &character_b) in text[a..].iter().zip(&text[b..]) {
+        if character_a != character_b {
+            return character_a.cmp(&character_b);
+        }
+        if character_a == SEPARATION_CHARACTER || character_a == TERMINATION_CHARACTER {
+            return Ordering::Equal;
+        }
+    }
+    // only reached if the text does not end with the termination character, the shortest suffix is the smallest
+    b.cmp(&a)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use crate::update::{compare_suffixes, equal_in_protein, merge_suffix_array};
+    use crate::{build_sa, SAConstructionAlgorithm};
+
+    fn check_rebuilt(text: &[u8], sa: &[i64], sparseness_factor: u8) {
+        // the merged suffix array is identical to the one of a full build over the new text
+        let built = build_sa(&mut text.to_vec(), &SAConstructionAlgorithm::LibSais, sparseness_factor, 1).unwrap().to_vec();
+        assert_eq!(sa, built);
+    }
+
+    #[test]
+    fn test_compare_suffixes() {
+        let text = b"AIC-AID-AIC-AIE$";
+        assert_eq!(compare_suffixes(text, 0, 4), Ordering::Less);
+        assert_eq!(compare_suffixes(text, 0, 8), Ordering::Equal);
+        assert_eq!(compare_suffixes(text, 3, 15), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_equal_in_protein() {
+        let text = b"AIC-AID-AIC-IC$";
+        assert!(equal_in_protein(text, 0, 8));
+        assert!(!equal_in_protein(text, 0, 4));
+        assert!(!equal_in_protein(text, 1, 5));
+        // the terminator ends a protein like the separator
+        assert!(equal_in_protein(text, 1, 12));
+        assert!(equal_in_protein(text, 3, 14));
+    }
+
+    #[test]
+    fn test_merge_suffix_array() {
+        let old_text = b"AI-BLACVAA-AC-KCRLZ$";
+        let old_sa = build_sa(&mut old_text.to_vec(), &SAConstructionAlgorithm::LibSais, 1, 1).unwrap().to_vec();
+
+        // the second protein is removed, and two proteins are added
+        let new_text = b"AI-AC-KCRLZ-LAAC-CVAA$";
+        let mut folded_text = new_text.to_vec();
+        let sa = merge_suffix_array(old_text, &[true, false, true, true], &old_sa, 1, &mut folded_text, 1).unwrap();
+        assert_eq!(folded_text, b"AI-AC-KCRIZ-IAAC-CVAA$".to_vec());
+        check_rebuilt(new_text, &sa, 1);
+
+        // only proteins are added
+        let mut new_text = b"AI-BLACVAA-AC-KCRLZ-LAAC$".to_vec();
+        let sa = merge_suffix_array(old_text, &[true; 4], &old_sa, 1, &mut new_text, 1).unwrap();
+        check_rebuilt(&new_text, &sa, 1);
+
+        assert!(merge_suffix_array(old_text, &[true; 3], &old_sa, 1, &mut new_text, 1).is_err());
+    }
+
+    #[test]
+    fn test_merge_sparse_suffix_array() {
+        let old_text = b"AI-BLACVAA-AC-KCRLZ$";
+        let old_sa = build_sa(&mut old_text.to_vec(), &SAConstructionAlgorithm::LibSais, 2, 1).unwrap().to_vec();
+
+        // removing the third protein shifts the last protein by 3, which is not a multiple of the sparseness factor, so its suffixes are sorted again
+        let mut new_text = b"AI-BLACVAA-KCRLZ$".to_vec();
+        let sa = merge_suffix_array(old_text, &[true, true, false, true], &old_sa, 2, &mut new_text, 1).unwrap();
+        check_rebuilt(&new_text, &sa, 2);
+
+        let mut new_text = b"AI-BLACVAA-AC-KCRLZ-LAAC-CVA$".to_vec();
+        let sa = merge_suffix_array(old_text, &[true; 4], &old_sa, 2, &mut new_text, 1).unwrap();
+        check_rebuilt(&new_text, &sa, 2);
+    }
+
+    #[test]
+    fn test_merge_every_combination() {
+        // proteins that occur more than once are only ordered by the text after them
+        let old_text = b"AC-KCA-BLACVAA-AC-LAAC-KCA-AC$";
+        let proteins: Vec<&[u8]> = old_text[..old_text.len() - 1].split(|&character| character == b'-').collect();
+        let added: [&[u8]; 3] = [b"", b"-ACV-AC", b"-KCA"];
+
+        for sparseness_factor in 1..=3 {
+            let old_sa = build_sa(&mut old_text.to_vec(), &SAConstructionAlgorithm::LibSais, sparseness_factor, 1).unwrap().to_vec();
+            for mask in 1..(1 << proteins.len()) {
+                let kept: Vec<bool> = (0..proteins.len()).map(|protein| mask & (1 << protein) != 0).collect();
+                let kept_proteins: Vec<&[u8]> = proteins.iter().zip(&kept).filter(|(_, &keep)| keep).map(|(&protein, _)| protein).collect();
+                for added_proteins in added {
+                    let mut new_text = kept_proteins.join(&b'-');
+                    new_text.extend_from_slice(added_proteins);
+                    new_text.push(b'$');
+
+                    let sa = merge_suffix_array(old_text, &kept, &old_sa, sparseness_factor, &mut new_text.clone(), 1).unwrap();
+                    check_rebuilt(&new_text, &sa, sparseness_factor);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+use crate::binary::{load_suffix_array, DatabaseFingerprint};
+use crate::suffix_array::SuffixArray;
+use crate::update::compare_suffixes;
+use crate::{build_sa, translate_l_to_i, SAConstructionAlgorithm};
+
+/// Report of the checks done by `verify_index`
+///
+/// # Arguments
+/// * `checks` - The description of every check that was done, together with the reason it failed, or None if it passed
+#[derive(Debug, Default)]
+pub struct VerificationReport {
+    pub checks: Vec<(&'static str, Option<String>)>,
+}
+
+impl VerificationReport {
+    /// Returns true if all checks passed
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|(_, failure)| failure.is_none())
+    }
+
+    /// Adds the outcome of a check to the report
+    ///
+    /// # Arguments
+    /// * `description` - The description of the check
+    /// * `outcome` - The outcome of the check, with the reason it failed as error
+    fn add(&mut self, description: &'static str, outcome: Result<(), String>) {
+        self.checks.push((description, outcome.err()));
+    }
+}
+
+impl Display for VerificationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (description, failure) in &self.checks {
+            match failure {
+                None => writeln!(f, "OK      {}", description)?,
+                Some(reason) => writeln!(f, "FAILED  {}: {}", description, reason)?,
+            }
+        }
+        if self.passed() {
+            write!(f, "The index is correct for the database")
+        } else {
+            write!(f, "The index is not correct for the database")
+        }
+    }
+}
+
+/// Checks that the suffix array stored in `index_file` is correct for the database with the given text
+/// The file is loaded, which checks its format, its checksums, the fingerprint of the database and the folding of I and L.
+/// Afterwards the sampled suffixes and their order are checked, and for short texts the suffix array is compared with the ones built by both construction algorithms.
+///
+/// # Arguments
+/// * `text` - The text of the database, every L is translated to an I in place
+/// * `database` - The fingerprint of the database
+/// * `index_file` - The file with the suffix array
+/// * `sample` - The number of evenly spread pairs of adjacent suffixes of which the order is checked, None to check all suffixes
+/// * `compare_max_length` - The suffix array is only built with both construction algorithms if the text is at most this long
+/// * `threads` - The number of threads used by libsais, 0 to use all available cores
+///
+/// # Returns
+///
+/// Returns the report with the outcome of every check, the checks that depend on a check that failed are not done
+pub fn verify_index(
+    text: &mut [u8],
+    database: &DatabaseFingerprint,
+    index_file: &str,
+    sample: Option<usize>,
+    compare_max_length: usize,
+    threads: usize,
+) -> VerificationReport {
+    let mut report = VerificationReport::default();
+
+    let loaded = load_suffix_array(index_file, database);
+    report.add(
+        "the file is a complete suffix array index built on this database with I and L folded",
+        loaded.as_ref().map(|_| ()).map_err(|err| err.to_string()),
+    );
+    let Ok((sparseness_factor, sa)) = loaded else {
+        return report;
+    };
+
+    translate_l_to_i(text);
+    let sparseness = check_sparseness(text.len(), sparseness_factor, sa.as_ref());
+    let sparseness_passed = sparseness.is_ok();
+    report.add("every sampled suffix occurs exactly once", sparseness);
+    if !sparseness_passed {
+        return report;
+    }
+
+    report.add("the suffixes are sorted", check_order(text, sa.as_ref(), sample));
+
+    if text.len() <= compare_max_length {
+        report.add(
+            "the suffix array matches the ones built by libsais and libdivsufsort",
+            compare_construction_algorithms(text, sa.as_ref(), sparseness_factor, threads),
+        );
+    }
+
+    report
+}
+
+/// Checks that the suffix array contains every suffix starting at a multiple of the sparseness factor exactly once
+///
+/// # Arguments
+/// * `text_length` - The length of the text
+/// * `sparseness_factor` - The sparseness factor stored in the index
+/// * `sa` - The suffix array
+///
+/// # Returns
+///
+/// Returns () if the suffix array contains the expected suffixes
+///
+/// # Errors
+///
+/// Returns the reason why the suffix array does not contain the expected suffixes
+fn check_sparseness(text_length: usize, sparseness_factor: u8, sa: &dyn SuffixArray) -> Result<(), String> {
+    if sparseness_factor == 0 {
+        return Err("The sparseness factor of the index is 0".to_string());
+    }
+    let sparseness_factor = sparseness_factor as usize;
+    let expected_len = text_length.div_ceil(sparseness_factor);
+    if sa.len() != expected_len {
+        return Err(format!(
+            "The index contains {} suffixes, but a text of {} characters has {} suffixes with sparseness factor {}",
+            sa.len(), text_length, expected_len, sparseness_factor
+        ));
+    }
+
+    let mut seen = vec![false; expected_len];
+    for index in 0..sa.len() {
+        let suffix = sa.get(index);
+        if suffix < 0 || suffix as usize >= text_length {
+            return Err(format!("The suffix at index {} starts at {}, which is outside the text", index, suffix));
+        }
+        if !(suffix as usize).is_multiple_of(sparseness_factor) {
+            return Err(format!("The suffix at index {} starts at {}, which is not a multiple of the sparseness factor", index, suffix));
+        }
+        if std::mem::replace(&mut seen[suffix as usize / sparseness_factor], true) {
+            return Err(format!("The suffix starting at {} occurs more than once", suffix));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that adjacent suffixes in the suffix array are in order
+/// Suffixes are compared up to the end of the protein they start in, since an updated index only orders its suffixes up to there
+///
+/// # Arguments
+/// * `text` - The text, in which every L is translated to an I
+/// * `sa` - The suffix array
+/// * `sample` - The number of evenly spread pairs of adjacent suffixes that are checked, None to check all pairs
+///
+/// # Returns
+///
+/// Returns () if all checked pairs are in order
+///
+/// # Errors
+///
+/// Returns the first pair of suffixes that is not in order
+fn check_order(text: &[u8], sa: &dyn SuffixArray, sample: Option<usize>) -> Result<(), String> {
+    let pairs = sa.len().saturating_sub(1);
+    let step = match sample {
+        Some(sample) => (pairs / sample.max(1)).max(1),
+        None => 1,
+    };
+    for index in (0..pairs).step_by(step) {
+        let (first, second) = (sa.get(index), sa.get(index + 1));
+        if compare_suffixes(text, first as usize, second as usize) == Ordering::Greater {
+            return Err(format!(
+                "The suffix starting at {} (index {}) is larger than the suffix starting at {} (index {})",
+                first, index, second, index + 1
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Builds the suffix array with both construction algorithms and compares them with each other and with the suffix array of the index
+/// The suffix array of the index is only compared up to the end of the protein every suffix starts in, like in `check_order`
+///
+/// # Arguments
+/// * `text` - The text, in which every L is translated to an I
+/// * `sa` - The suffix array of the index
+/// * `sparseness_factor` - The sparseness factor of the index
+/// * `threads` - The number of threads used by libsais, 0 to use all available cores
+///
+/// # Returns
+///
+/// Returns () if all suffix arrays match
+///
+/// # Errors
+///
+/// Returns the reason why the suffix arrays do not match
+fn compare_construction_algorithms(text: &[u8], sa: &dyn SuffixArray, sparseness_factor: u8, threads: usize) -> Result<(), String> {
+    let libsais = build_sa(&mut text.to_vec(), &SAConstructionAlgorithm::LibSais, sparseness_factor, threads)
+        .map_err(|err| err.to_string())?
+        .to_vec();
+    let libdivsufsort = build_sa(&mut text.to_vec(), &SAConstructionAlgorithm::LibDivSufSort, sparseness_factor, threads)
+        .map_err(|err| err.to_string())?
+        .to_vec();
+
+    if let Some(index) = (0..libsais.len()).find(|&index| libsais[index] != libdivsufsort[index]) {
+        return Err(format!("libsais and libdivsufsort differ at index {}", index));
+    }
+    if libsais.len() != sa.len() {
+        return Err(format!("libsais built {} suffixes, but the index contains {} suffixes", libsais.len(), sa.len()));
+    }
+    for (index, &suffix) in libsais.iter().enumerate() {
+        if compare_suffixes(text, suffix as usize, sa.get(index) as usize) != Ordering::Equal {
+            return Err(format!("The index differs from the suffix array built by libsais at index {}", index));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sa_mappings::filter::ProteinFilter;
+    use tempdir::TempDir;
+
+    use crate::binary::{write_suffix_array, DatabaseFingerprint};
+    use crate::verify::verify_index;
+
+    const DATABASE: DatabaseFingerprint = DatabaseFingerprint { text_length: 20, protein_count: 4, hash: 42, filter: ProteinFilter::new() };
+
+    fn verify(name: &str, sparseness_factor: u8, sa: &[i64], database: &DatabaseFingerprint, sample: Option<usize>) -> Vec<bool> {
+        let tmp_dir = TempDir::new(&format!("test_verify_index_{}", name)).unwrap();
+        let path = tmp_dir.path().join("index.bin");
+        let filename = path.to_str().unwrap();
+        write_suffix_array(sparseness_factor, 64, &DATABASE, sa, filename).unwrap();
+        let report = verify_index(&mut b"AI-BLACVAA-AC-KCRLZ$".to_vec(), database, filename, sample, 1 << 20, 1);
+        report.checks.iter().map(|(_, failure)| failure.is_none()).collect()
+    }
+
+    #[test]
+    fn test_verify_index() {
+        let sa = [19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18];
+        assert_eq!(verify("complete", 1, &sa, &DATABASE, None), vec![true, true, true, true]);
+        assert_eq!(verify("sample", 1, &sa, &DATABASE, Some(3)), vec![true, true, true, true]);
+        let sparse_sa = [10, 2, 8, 0, 12, 6, 4, 14, 16, 18];
+        assert_eq!(verify("sparse", 2, &sparse_sa, &DATABASE, None), vec![true, true, true, true]);
+    }
+
+    #[test]
+    fn test_verify_index_failures() {
+        // built on another database
+        let sa = [19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18];
+        let other_database = DatabaseFingerprint { hash: 43, ..DATABASE };
+        assert_eq!(verify("other_database", 1, &sa, &other_database, None), vec![false]);
+
+        // a suffix is missing and another one occurs twice
+        let sa = [19, 10, 2, 13, 9, 8, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 7];
+        assert_eq!(verify("duplicate", 1, &sa, &DATABASE, None), vec![true, false]);
+
+        // the suffixes at index 4 and 5 are swapped
+        let sa = [19, 10, 2, 13, 8, 9, 11, 5, 0, 3, 12, 15, 6, 1, 4, 17, 14, 16, 7, 18];
+        assert_eq!(verify("unsorted", 1, &sa, &DATABASE, None), vec![true, true, false, false]);
+    }
+}
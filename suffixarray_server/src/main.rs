@@ -7,25 +7,58 @@ use axum::routing::{get, post};
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 
+use sa_mappings::filter::ProteinFilter;
 use sa_mappings::functionality::FunctionAggregator;
 use sa_mappings::proteins::Proteins;
 use sa_mappings::taxonomy::{AggregationMethod, TaxonAggregator};
-use suffixarray::peptide_search::{OutputData, analyse_all_peptides, SearchResultWithAnalysis, SearchOnlyResult, search_all_peptides};
+use suffixarray::peptide_search::{OutputData, analyse_all_peptides, analyse_all_peptides_batch, count_all_peptides, CountResult, SearchResultWithAnalysis, SearchOnlyResult, search_all_peptides};
+use suffixarray::peptide_index::{MatchCursor, PeptideIndex};
 use suffixarray::sa_searcher::Searcher;
+use suffixarray::sampling::{Sampling, SamplingPolicy};
 use suffixarray::suffix_to_protein_index::SparseSuffixToProtein;
-use suffixarray_builder::binary::load_suffix_array;
+use suffixarray::suffix_tree_index::SuffixTreeIndex;
+use suffixarray_builder::binary::{load_fm_index, load_lcp_lr, load_suffix_array, load_taxon_index, map_suffix_array, map_taxon_index, read_protein_filter, DatabaseFingerprint};
+use suffixarray_builder::bundle::load_bundle;
+use suffixarray_builder::IndexType;
+use suffixarray_builder::suffix_array::SuffixArray;
 
 /// Enum that represents all possible commandline arguments
 #[derive(Parser, Debug)]
 pub struct Arguments {
     /// File with the proteins used to build the suffix tree. All the proteins are expected to be concatenated using a `#`.
+    /// Both tsv database files and UniProt FASTA files are accepted, the taxon of a protein in a FASTA file is read from the `OX=` field of its header.
+    #[arg(short, long, required_unless_present = "bundle_file")]
+    database_file: Option<String>,
+    /// Tsv file with the accession and the functional annotations of a protein on every line, used for the proteins of a FASTA database file
+    #[arg(long, requires = "database_file")]
+    annotations_file: Option<String>,
+    /// File with the stored index, not used for the suffix tree which is built when the server starts
     #[arg(short, long)]
-    database_file: String,
-    #[arg(short, long)]
-    index_file: String,
+    index_file: Option<String>,
+    /// Bundle file written by the builder, which contains the proteins and the suffix array, so the database file does not have to be read
+    #[arg(long, conflicts_with_all = ["database_file", "index_file"])]
+    bundle_file: Option<String>,
+    /// Memory map the index file instead of reading it into memory, for a bundle the proteins are memory mapped as well. Processes that map the same index share its memory.
+    #[arg(long)]
+    memory_map: bool,
+    /// Verify the checksums of a memory mapped index when the server starts, which reads the complete index.
+    /// An index that is read into memory is always verified.
+    #[arg(long, requires = "memory_map")]
+    verify_checksums: bool,
+    /// The kind of index stored in the index file, or `suffix-tree` to build a suffix tree over the proteins instead
+    #[arg(long, value_enum, default_value_t = IndexType::SuffixArray)]
+    index_type: IndexType,
+    /// File with the LCP-LR arrays of the index, used to speed up the search
+    #[arg(long)]
+    lcp_lr_file: Option<String>,
+    /// File with the taxon index of the index, used to aggregate the taxa of the matches without retrieving the proteins.
+    /// It is memory mapped together with the index. The taxon index is built when it is first used if no file is provided.
+    #[arg(long)]
+    taxon_index_file: Option<String>,
     #[arg(short, long)]
     /// The taxonomy to be used as a tsv file. This is a preprocessed version of the NCBI taxonomy.
-    taxonomy: String,
+    /// Only optional if the taxonomy is embedded in the bundle file
+    taxonomy: Option<String>,
 }
 
 /// Function used by serde to place a default value in the cutoff field of the input
@@ -43,9 +76,13 @@ fn default_true() -> bool {
 /// 
 /// # Arguments
 /// * `peptides` - List of peptides we want to process
-/// * `cutoff` - The maximum amount of matches to process, default value 10000
+/// * `cutoff` - The maximum amount of proteins to retrieve, where a match of a deduplicated sequence counts once for every protein with that sequence, default value 10000
 /// * `equalize_I_and_L` - True if we want to equalize I and L during search
 /// * `clean_taxa` - True if we only want to use proteins marked as "valid"
+/// * `cursors` - The cursor from where the search continues for every peptide, taken from the `next_cursor` of a previous search result. Only used by `/search`
+/// * `sampling_policy` - The policy used to select the retained proteins when the cutoff is reached, default value `first`
+/// * `sampling_seed` - The seed used by the random sampling policies, default value 0
+/// * `batch` - True if the peptides are sorted and deduplicated before the search, so the search work for shared prefixes is reused. Only used by `/analyse`
 #[derive(Debug, Deserialize, Serialize)]
 #[allow(non_snake_case)]
 struct InputData {
@@ -57,6 +94,24 @@ struct InputData {
     equalize_I_and_L: bool,
     #[serde(default = "bool::default")] // default value is false
     clean_taxa: bool,
+    #[serde(default)] // default is an empty list, which starts the search at the first match
+    cursors: Vec<MatchCursor>,
+    #[serde(default)] // default is `first`, which keeps the first matches in the index
+    sampling_policy: SamplingPolicy,
+    #[serde(default)]
+    sampling_seed: u64,
+    #[serde(default = "bool::default")] // default value is false
+    batch: bool,
+}
+
+impl InputData {
+    /// Returns the sampling policy and seed provided by the user
+    fn sampling(&self) -> Sampling {
+        Sampling {
+            policy: self.sampling_policy,
+            seed: self.sampling_seed,
+        }
+    }
 }
 
 #[tokio::main]
@@ -76,51 +131,98 @@ async fn root() -> &'static str {
 /// Endpoint executed for peptide matching and taxonomic and functional analysis
 ///
 /// # Arguments
-/// * `state(searcher)` - The searcher object provided by the server
+/// * `state(searcher)` - The index provided by the server
 /// * `data` - InputData object provided by the user with the peptides to be searched and the config
 /// 
 /// # Returns
 ///
 /// Returns the search and analysis results from the index as a JSON
 async fn analyse(
-    State(searcher): State<Arc<Searcher>>,
+    State(searcher): State<Arc<dyn PeptideIndex>>,
     data: Json<InputData>,
 ) -> Result<Json<OutputData<SearchResultWithAnalysis>>, StatusCode> {
-    let search_result = analyse_all_peptides(
-        &searcher,
-        &data.peptides,
-        data.cutoff,
-        data.equalize_I_and_L,
-        data.clean_taxa,
-    );
+    let search_result = if data.batch {
+        analyse_all_peptides_batch(
+            searcher.as_ref(),
+            &data.peptides,
+            data.cutoff,
+            data.equalize_I_and_L,
+            data.clean_taxa,
+            &data.sampling(),
+        )
+    } else {
+        analyse_all_peptides(
+            searcher.as_ref(),
+            &data.peptides,
+            data.cutoff,
+            data.equalize_I_and_L,
+            data.clean_taxa,
+            &data.sampling(),
+        )
+    };
 
     Ok(Json(search_result))
 }
 
 /// Endpoint executed for peptide matching, without any analysis
+/// At most `cutoff` proteins are retrieved per peptide, the next page of a peptide is retrieved by passing its `next_cursor` in `cursors`
 ///
 /// # Arguments
-/// * `state(searcher)` - The searcher object provided by the server
+/// * `state(searcher)` - The index provided by the server
 /// * `data` - InputData object provided by the user with the peptides to be searched and the config
 ///
 /// # Returns
 ///
 /// Returns the search results from the index as a JSON
+///
+/// # Errors
+///
+/// Returns a bad request if cursors are provided, but not one for every peptide
 async fn search(
-    State(searcher): State<Arc<Searcher>>,
+    State(searcher): State<Arc<dyn PeptideIndex>>,
     data: Json<InputData>,
 ) -> Result<Json<OutputData<SearchOnlyResult>>, StatusCode> {
+    if !data.cursors.is_empty() && data.cursors.len() != data.peptides.len() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
     let search_result = search_all_peptides(
-        &searcher,
+        searcher.as_ref(),
         &data.peptides,
+        &data.cursors,
         data.cutoff,
         data.equalize_I_and_L,
         data.clean_taxa,
+        &data.sampling(),
     );
 
     Ok(Json(search_result))
 }
 
+/// Endpoint executed to count the matches of peptides, without retrieving the matching proteins
+/// The cutoff is not used, the counts are always exact
+///
+/// # Arguments
+/// * `state(searcher)` - The index provided by the server
+/// * `data` - InputData object provided by the user with the peptides to be counted and the config
+///
+/// # Returns
+///
+/// Returns the number of matches and matching proteins of every peptide as a JSON
+async fn count(
+    State(searcher): State<Arc<dyn PeptideIndex>>,
+    data: Json<InputData>,
+) -> Result<Json<OutputData<CountResult>>, StatusCode> {
+    let count_result = count_all_peptides(
+        searcher.as_ref(),
+        &data.peptides,
+        data.equalize_I_and_L,
+        data.clean_taxa,
+    );
+
+    Ok(Json(count_result))
+}
+
 /// Starts the server with the provided commandline arguments
 ///
 /// # Arguments
@@ -134,35 +236,58 @@ async fn search(
 /// 
 /// Returns any error occurring during the startup or uptime of the server
 async fn start_server(args: Arguments) -> Result<(), Box<dyn Error>> {
-    let Arguments {
-        database_file,
-        index_file,
-        taxonomy,
-    } = args;
-
-    eprintln!("Loading suffix array...");
-    let (sparseness_factor, sa) = load_suffix_array(&index_file)?;
+    if let Some(bundle_file) = &args.bundle_file {
+        if args.index_type != IndexType::SuffixArray {
+            return Err("A bundle always contains a suffix array".into());
+        }
+        let searcher = create_searcher_from_bundle(&args, bundle_file)?;
+        return serve(Arc::new(searcher)).await;
+    }
+    let Arguments { database_file, annotations_file, index_file, index_type, taxonomy, .. } = &args;
+    let database_file = database_file.as_deref().ok_or("A database file is required when no bundle file is used")?;
+    let taxonomy = taxonomy.as_deref().ok_or("A taxonomy file is required when no bundle file is used")?;
 
     eprintln!("Loading taxon file...");
     let taxon_id_calculator =
-        TaxonAggregator::try_from_taxonomy_file(&taxonomy, AggregationMethod::LcaStar)?;
+        TaxonAggregator::try_from_taxonomy_file(taxonomy, AggregationMethod::LcaStar)?;
 
     let function_aggregator = FunctionAggregator {};
 
+    // a loaded index only contains the proteins that pass the filters it was built with
+    let filter = match index_file {
+        Some(index_file) if *index_type != IndexType::SuffixTree => read_protein_filter(index_file)?,
+        _ => ProteinFilter::new(),
+    };
+
     eprintln!("Loading proteins...");
-    let proteins = Proteins::try_from_database_file(&database_file, &taxon_id_calculator)?;
-    let suffix_index_to_protein = Box::new(SparseSuffixToProtein::new(&proteins.input_string));
+    let proteins = Proteins::try_from_database_file_with_annotations(database_file, annotations_file.as_deref(), &taxon_id_calculator, &filter)?;
 
-    eprintln!("Creating searcher...");
-    let searcher = Arc::new(Searcher::new(
-        sa,
-        sparseness_factor,
-        suffix_index_to_protein,
-        proteins,
-        taxon_id_calculator,
-        function_aggregator,
-    ));
+    let searcher: Arc<dyn PeptideIndex> = if *index_type == IndexType::SuffixTree {
+        eprintln!("Building suffix tree...");
+        Arc::new(SuffixTreeIndex::new(proteins, taxon_id_calculator, function_aggregator))
+    } else {
+        let index_file = index_file.as_deref().ok_or("An index file is required for this index type")?;
+        let database_fingerprint =
+            DatabaseFingerprint::new(&proteins.input_string, &[database_file, taxonomy], &filter)?;
+        Arc::new(create_searcher(&args, index_file, &database_fingerprint, proteins, taxon_id_calculator)?)
+    };
+
+    serve(searcher).await
+}
 
+/// Serves the endpoints that search in the given index
+///
+/// # Arguments
+/// * `searcher` - The index which contains the protein database
+///
+/// # Returns
+///
+/// Returns Unit when the server stops
+///
+/// # Errors
+///
+/// Returns any error occurring while binding the address or serving the requests
+async fn serve(searcher: Arc<dyn PeptideIndex>) -> Result<(), Box<dyn Error>> {
     // build our application with a route
     let app = Router::new()
         // `GET /` goes to `root`
@@ -174,6 +299,10 @@ async fn start_server(args: Arguments) -> Result<(), Box<dyn Error>> {
         // `POST /search` goes to `search` and set max payload size to 5 MB
         .route("/search", post(search))
         .layer(DefaultBodyLimit::max(5 * 10_usize.pow(6)))
+        .with_state(searcher.clone())
+        // `POST /count` goes to `count` and set max payload size to 5 MB
+        .route("/count", post(count))
+        .layer(DefaultBodyLimit::max(5 * 10_usize.pow(6)))
         .with_state(searcher);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
@@ -182,3 +311,125 @@ async fn start_server(args: Arguments) -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+/// Loads the suffix array (or FM-index) and creates the Searcher over it
+///
+/// # Arguments
+/// * `args` - The arguments used to start the server, which select how the index is loaded
+/// * `index_file` - The file where the index is stored
+/// * `database_fingerprint` - The fingerprint of the database, which has to match the one stored in the index files
+/// * `proteins` - List of all the proteins where the index is build on
+/// * `taxon_id_calculator` - The taxonomy used by the searcher
+///
+/// # Returns
+///
+/// Returns the Searcher which contains the protein database
+///
+/// # Errors
+///
+/// Returns any error occurring while loading the index, or if the suffix array was built on another database
+fn create_searcher(
+    args: &Arguments,
+    index_file: &str,
+    database_fingerprint: &DatabaseFingerprint,
+    proteins: Proteins,
+    taxon_id_calculator: TaxonAggregator,
+) -> Result<Searcher, Box<dyn Error>> {
+    let (sparseness_factor, sa) = if args.index_type == IndexType::FmIndex {
+        eprintln!("Loading FM-index...");
+        // the FM-index always represents the complete suffix array
+        (1, Box::new(load_fm_index(index_file, database_fingerprint)?) as Box<dyn SuffixArray>)
+    } else if args.memory_map {
+        eprintln!("Mapping suffix array...");
+        let (sparseness_factor, sa) = map_suffix_array(index_file, database_fingerprint, args.verify_checksums)?;
+        (sparseness_factor, Box::new(sa) as Box<dyn SuffixArray>)
+    } else {
+        eprintln!("Loading suffix array...");
+        load_suffix_array(index_file, database_fingerprint)?
+    };
+
+    assemble_searcher(args, sa, sparseness_factor, database_fingerprint, proteins, taxon_id_calculator)
+}
+
+/// Loads the proteins and the suffix array from a bundle and creates the Searcher over them
+/// The taxonomy file is used if one was given, otherwise the taxonomy embedded in the bundle
+///
+/// # Arguments
+/// * `args` - The arguments used to start the server, which select how the bundle is loaded
+/// * `bundle_file` - The file where the bundle is stored
+///
+/// # Returns
+///
+/// Returns the Searcher which contains the protein database
+///
+/// # Errors
+///
+/// Returns any error occurring while loading the bundle, or if no taxonomy is available
+fn create_searcher_from_bundle(args: &Arguments, bundle_file: &str) -> Result<Searcher, Box<dyn Error>> {
+    eprintln!("Loading bundle...");
+    let bundle = load_bundle(bundle_file, args.memory_map, args.verify_checksums)?;
+    let taxon_id_calculator = bundle.taxon_aggregator(args.taxonomy.as_deref(), AggregationMethod::LcaStar)?;
+
+    assemble_searcher(
+        args,
+        bundle.suffix_array,
+        bundle.sparseness_factor,
+        &bundle.database,
+        bundle.proteins,
+        taxon_id_calculator,
+    )
+}
+
+/// Creates the Searcher over a loaded suffix array (or FM-index)
+///
+/// # Arguments
+/// * `args` - The arguments used to start the server, which select the LCP-LR arrays and the taxon index that are loaded
+/// * `sa` - The suffix array (or FM-index) built over the proteins
+/// * `sparseness_factor` - The sparseness factor of the suffix array
+/// * `database` - The fingerprint of the database, which has to match the one stored in the LCP-LR and taxon index files
+/// * `proteins` - List of all the proteins where the index is build on
+/// * `taxon_id_calculator` - The taxonomy used by the searcher
+///
+/// # Returns
+///
+/// Returns the Searcher which contains the protein database
+///
+/// # Errors
+///
+/// Returns any error occurring while loading the LCP-LR arrays or the taxon index
+fn assemble_searcher(
+    args: &Arguments,
+    sa: Box<dyn SuffixArray>,
+    sparseness_factor: u8,
+    database: &DatabaseFingerprint,
+    proteins: Proteins,
+    taxon_id_calculator: TaxonAggregator,
+) -> Result<Searcher, Box<dyn Error>> {
+    let suffix_index_to_protein = Box::new(SparseSuffixToProtein::new(&proteins.input_string));
+
+    eprintln!("Creating searcher...");
+    let mut searcher = Searcher::new(
+        sa,
+        sparseness_factor,
+        suffix_index_to_protein,
+        proteins,
+        taxon_id_calculator,
+        FunctionAggregator {},
+    );
+
+    if let Some(lcp_lr_file) = &args.lcp_lr_file {
+        eprintln!("Loading LCP-LR arrays...");
+        searcher = searcher.with_lcp_lr(load_lcp_lr(lcp_lr_file, sparseness_factor, database)?)?;
+    }
+    if let Some(taxon_index_file) = &args.taxon_index_file {
+        let taxon_index = if args.memory_map {
+            eprintln!("Mapping taxon index...");
+            map_taxon_index(taxon_index_file, sparseness_factor, database, args.verify_checksums)?
+        } else {
+            eprintln!("Loading taxon index...");
+            load_taxon_index(taxon_index_file, sparseness_factor, database)?
+        };
+        searcher = searcher.with_taxon_lca_index(taxon_index)?;
+    }
+    Ok(searcher)
+}
@@ -12,8 +12,8 @@ use crate::tree_taxon_id_calculator::TreeTaxonIdCalculator;
 use crate::tree::Tree;
 use crate::tree_builder::{TreeBuilder, UkkonenBuilder};
 
-mod tree_builder;
-mod tree;
+pub mod tree_builder;
+pub mod tree;
 mod cursor;
 mod search_cursor;
 mod searcher;